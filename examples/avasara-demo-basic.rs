@@ -7,17 +7,17 @@ fn main() {
 
     // or instead of all of this you could just get a Vec<u8> and wrap it in a cursor
     let mut src = vec![];
-    std::fs::File::open(&path)
+    std::fs::File::open(path)
         .expect("failed to open media")
         .read_to_end(&mut src)
         .unwrap();
     let src = Cursor::new(src);
 
-    let opus = compose_to_ogg(src, path, 0, -0.2, true);
+    let opus = compose_to_ogg(src, path, 0, -0.2, true).expect("failed to compose");
     println!(
-        "The encoded file is {} bytes and was saved to {}.",
+        "The encoded file is {} bytes and was saved to {}.ogg.",
         opus.len(),
-        format!("{}.ogg", path)
+        path
     );
     let mut output = std::fs::OpenOptions::new()
         .create(true)
@@ -1,11 +1,11 @@
 use avasara::{
-    analyze_pitch, decode, interleave_to_mono,
+    analyze_pitch, decode_file, interleave_to_mono,
     vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder},
 };
 #[allow(unused_imports)]
 use avasara::{OggToOgg, Remuxer};
 use std::{
-    io::{Cursor, Read, Write},
+    io::Write,
     num::{NonZeroU32, NonZeroU8},
 };
 
@@ -13,16 +13,16 @@ fn main() {
     let args: Vec<String> = std::env::args().collect();
     let path = args.get(1).expect("file path not provided");
 
-    // or instead of all of this you could just get a Vec<u8> and wrap it in a cursor
-    let mut src = vec![];
-    std::fs::File::open(&path)
-        .expect("failed to open media")
-        .read_to_end(&mut src)
-        .unwrap();
-    let src = Cursor::new(src);
-
+    // opens the file and decodes it as it's read, use `decode_audio` for audio that's already in
+    // memory (a Vec<u8> wrapped in a cursor) or coming from somewhere else
     println!("decoding {}...", path);
-    let (audio, sample_rate, channels) = decode(src);
+    let (audio, sample_rate, channels) = match decode_file(path) {
+        Ok(decoded) => decoded.into_parts(),
+        Err(err) => {
+            eprintln!("couldn't decode {}: {}", path, err);
+            std::process::exit(-1);
+        }
+    };
     if (sample_rate == 0) || (channels == 0) {
         eprintln!("there was an error trying to decode the metadata of the source, your audio file is most likely broken, exiting... (can't continue without knowing channel count and sample rate)");
         std::process::exit(-1);
@@ -34,14 +34,18 @@ fn main() {
 
     println!("doing pitch analysis...");
     // 50Hz to 600Hz only, prioritizing the human vocal range
-    let (pitch_report, _pitch_points) = analyze_pitch(&audio, sample_rate, 50.0, 600.0);
-    println!(
-        "pitch analysis done! average: {}, median: {}, lowest: {}, highest: {}",
-        pitch_report.mean, pitch_report.median, pitch_report.lowest, pitch_report.highest,
-    );
+    match analyze_pitch(&audio, sample_rate, 50.0, 600.0) {
+        Ok((pitch_report, _pitch_points)) => println!(
+            "pitch analysis done! average: {}, median: {}, lowest: {}, highest: {}",
+            pitch_report.mean, pitch_report.median, pitch_report.lowest, pitch_report.highest,
+        ),
+        // silence or no pitch in range isn't fatal, we can still encode it
+        Err(err) => println!("pitch analysis skipped: {}", err),
+    }
 
     println!("interleaving to mono...");
-    let mut audio = interleave_to_mono(audio, sample_rate as u32, channels);
+    let mut audio = interleave_to_mono(audio, sample_rate as u32, channels)
+        .expect("only mono and stereo sources can be interleaved");
     println!("interleaved!");
 
     println!("encoding to ogg...");
@@ -62,9 +66,9 @@ fn main() {
     )
     .unwrap();
 
-    // chunks of 512 satisfied the vorbis gods, so i'll use it here
+    // chunks of 512 satisfied the vorbis gods, so i'll use it here (a shorter last chunk is fine)
     for i in audio.as_f32_slice().chunks(512) {
-        encoder.encode_audio_block(&[i]).unwrap();
+        encoder.encode_audio_block([i]).unwrap();
     }
     encoder.finish().unwrap();
     println!("encoding finished!");
@@ -80,9 +84,9 @@ fn main() {
     */
 
     println!(
-        "the encoded file is {} bytes and was saved to {}.",
+        "the encoded file is {} bytes and was saved to {}.ogg.",
         ogg_out.len(),
-        format!("{}.ogg", path)
+        path
     );
 
     let mut output = std::fs::OpenOptions::new()
@@ -0,0 +1,310 @@
+use crate::contour::smooth_frequencies;
+use crate::filters::Biquad;
+use crate::{
+    detect_pitch, is_silent, pitch_report, rms_dbfs, AnalyzeOptions, AvasaraError, PitchPoint,
+    PitchReport,
+};
+
+/// Pitch analysis of audio that arrives a bit at a time (a microphone, a network stream, a file
+/// read in blocks), push samples in as they come with `push_samples` and get pitch points back
+/// as soon as every window fills up, then `finalize` for the same `PitchReport` that
+/// `analyze_pitch_with` would give for all of the audio at once. Windows, hops, the detector,
+/// the high-pass filter, the clarity floor, `quality` and outlier trimming all work exactly like
+/// they do there (the high-pass filter carries its state from one push to the next,
+/// `AnalyzeOptions::cancel` is left to you, just stop pushing), so a file pushed through in blocks of
+/// any size gives the same points as `analyze_pitch_contour` (without `AnalyzeOptions::smoothing`,
+/// see `push_samples`) and the same report.
+///
+/// Every detected pitch is kept around until `finalize` (8 bytes a window, about 340KB for an
+/// hour at 44.1kHz with the default 1024 sample windows), the samples themselves are only kept
+/// until the window they're in has been analyzed.
+#[derive(Debug, Clone)]
+pub struct PitchAnalyzer {
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: AnalyzeOptions,
+    highpass: Option<[Biquad; 2]>,
+    /// Samples pushed but not analyzed yet, from the start of the next window on (or from the
+    /// end of what was pushed, when hops are longer than windows and it's in the gap).
+    buffer: Vec<f32>,
+    /// Index (counted from the very first sample pushed) of `buffer[0]`.
+    buffer_start: usize,
+    /// Index of the first sample of the next window.
+    next_start: usize,
+    any_signal: bool,
+    /// Every `(frequency, clarity)` detected so far, in order, unfiltered, for `finalize`.
+    detected: Vec<(f32, f32)>,
+}
+
+impl PitchAnalyzer {
+    /// A fresh analyzer for mono audio at `sample_rate`, that reports pitches between
+    /// `min_frequency` and `max_frequency` and slices the audio up the way `options` says.
+    ///
+    /// # Panics
+    /// Panics if the window or hop size is zero.
+    pub fn new(
+        sample_rate: usize,
+        min_frequency: f32,
+        max_frequency: f32,
+        options: AnalyzeOptions,
+    ) -> Self {
+        assert!(
+            options.window_size > 0 && options.hop_size > 0,
+            "window and hop sizes must be non-zero"
+        );
+
+        PitchAnalyzer {
+            sample_rate,
+            min_frequency,
+            max_frequency,
+            highpass: options.highpass_hz.map(|cutoff| {
+                [Biquad::highpass(sample_rate, cutoff as f64, std::f64::consts::FRAC_1_SQRT_2); 2]
+            }),
+            options,
+            buffer: vec![],
+            buffer_start: 0,
+            next_start: 0,
+            any_signal: false,
+            detected: vec![],
+        }
+    }
+
+    /// An analyzer for live use (a tuner, a pitch meter on a mic input), where waiting for
+    /// 1024 sample windows is out of the question, its windows are at most `latency_ms` long
+    /// so a reading from `push_samples` is never older than that. The window is `latency_ms`
+    /// rounded down to a power of two (YIN panics on a lot of other lengths, rounding down keeps
+    /// the latency promise), but never below 64 samples, and it hops by half a window so a
+    /// reading comes every `latency_ms / 2` or so. Everything else is `AnalyzeOptions::default()`.
+    ///
+    /// `latency_ms` is the tradeoff knob, YIN needs two full periods in the window, so the lowest
+    /// pitch it can see is about `2000 / latency_ms` Hz (at most twice that after the rounding,
+    /// 10ms reaches down to about 350Hz, 25ms to 90Hz, 50ms to 45Hz for bass), and a shorter
+    /// window also means a noisier reading.
+    pub fn low_latency(
+        sample_rate: usize,
+        min_frequency: f32,
+        max_frequency: f32,
+        latency_ms: f32,
+    ) -> Self {
+        let samples = (latency_ms.max(0.0) / 1000.0 * sample_rate as f32) as usize;
+        // the largest power of two that fits
+        let window = (1 << samples.max(1).ilog2()).max(64);
+
+        PitchAnalyzer::new(
+            sample_rate,
+            min_frequency,
+            max_frequency,
+            AnalyzeOptions {
+                window_size: window,
+                hop_size: window / 2,
+                ..AnalyzeOptions::default()
+            },
+        )
+    }
+
+    /// Adds the next bit of mono audio and returns a pitch point for every window it completed,
+    /// in order, with `time_secs` counted from the first sample ever pushed. Windows that are
+    /// silent or whose pitch is outside the frequency range give no point, just like in
+    /// `analyze_pitch_contour`. `AnalyzeOptions::smoothing` isn't applied to these (smoothing
+    /// needs the points after a point too, which haven't arrived yet), only to the pitches that
+    /// go into `finalize`'s report, run `smooth_contour` over the last few points yourself if you
+    /// can live with the extra latency.
+    pub fn push_samples(&mut self, samples: &[f32]) -> Vec<PitchPoint> {
+        let first_new = self.buffer.len();
+        self.buffer.extend(samples);
+        if let Some(highpass) = &mut self.highpass {
+            // sample by sample through both sections in f64, like `apply_filters` does
+            for sample in &mut self.buffer[first_new..] {
+                let mut y = *sample as f64;
+                for section in highpass.iter_mut() {
+                    y = section.process(y);
+                }
+                *sample = y as f32;
+            }
+        }
+
+        let window = self.options.window_size;
+        let mut points = vec![];
+        while self.next_start + window <= self.samples_pushed() {
+            let offset = self.next_start - self.buffer_start;
+            let chunk = self.buffer[offset..offset + window].to_vec();
+            points.extend(self.analyze_window(self.next_start, &chunk));
+            self.next_start += self.options.hop_size;
+        }
+
+        // nothing before the next window is needed anymore
+        let keep_from = self.next_start.min(self.samples_pushed());
+        self.buffer.drain(..keep_from - self.buffer_start);
+        self.buffer_start = keep_from;
+
+        points
+    }
+
+    /// Analyzes the partial window left at the end of the audio (if `analyze_pitch_with` would
+    /// analyze one there too, which is when windows don't overlap, or when they do and the audio
+    /// never filled a single one) and returns its point, if it has one. Only call it once the
+    /// stream has ended, anything pushed after it is treated as the start of a new stream's
+    /// worth of windows. `finalize` calls it on its own, call it yourself first only if you want
+    /// the point.
+    pub fn flush(&mut self) -> Vec<PitchPoint> {
+        let (window, hop) = (self.options.window_size, self.options.hop_size);
+        let has_tail = self.next_start < self.samples_pushed();
+        if !has_tail || ((hop < window) && (self.next_start > 0)) {
+            return vec![];
+        }
+
+        let tail = std::mem::take(&mut self.buffer);
+        let point = self.analyze_window(self.next_start, &tail);
+        self.buffer_start += tail.len();
+        self.next_start = self.buffer_start;
+        point.into_iter().collect()
+    }
+
+    /// How many samples have been pushed so far.
+    fn samples_pushed(&self) -> usize {
+        self.buffer_start + self.buffer.len()
+    }
+
+    /// The report on everything pushed, exactly what `analyze_pitch_with` returns for the same
+    /// audio and options (including the `AvasaraError::SilentInput` and
+    /// `AvasaraError::NoPitchDetected` errors), analyzing the partial window at the end first
+    /// (see `flush`).
+    pub fn finalize(mut self) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+        self.flush();
+        if !self.any_signal {
+            return Err(AvasaraError::SilentInput);
+        }
+
+        let mut detected = std::mem::take(&mut self.detected);
+        if let Some(smoothing) = &self.options.smoothing {
+            let frequencies: Vec<f32> = detected.iter().map(|(freq, _)| *freq).collect();
+            for (point, smoothed) in detected
+                .iter_mut()
+                .zip(smooth_frequencies(&frequencies, smoothing))
+            {
+                point.0 = smoothed;
+            }
+        }
+        let (_, clarity_gate) = self.options.quality_gates();
+        detected.retain(|(_, clarity)| *clarity >= clarity_gate);
+
+        pitch_report(
+            &detected,
+            self.min_frequency,
+            self.max_frequency,
+            self.options.clarity_floor,
+            self.samples_pushed() as f64 / self.options.hop_size as f64,
+            self.options.outlier_trim_percent,
+        )
+    }
+
+    /// Runs the detector on the window starting at sample `start`, remembering what it found,
+    /// and returns its point if it's within the frequency range.
+    fn analyze_window(&mut self, start: usize, chunk: &[f32]) -> Option<PitchPoint> {
+        if is_silent(chunk) {
+            return None;
+        }
+        self.any_signal = true;
+
+        let (frequency, clarity) = detect_pitch(self.options.algorithm, chunk, self.sample_rate)?;
+        // too quiet for `quality`, it's still a point, it just stays out of the report
+        let (energy_floor_dbfs, _) = self.options.quality_gates();
+        if rms_dbfs(chunk) >= energy_floor_dbfs {
+            self.detected.push((frequency, clarity));
+        }
+        ((frequency > self.min_frequency) && (frequency < self.max_frequency)).then(|| PitchPoint {
+            time_secs: start as f64 / self.sample_rate as f64,
+            frequency,
+            clarity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_pitch_contour, analyze_pitch_with, ClarityFloor};
+
+    /// A second of a tone gliding from 200Hz up to 300Hz, on top of 30Hz rumble.
+    fn glide(sample_rate: usize) -> Vec<f32> {
+        let mut phase = 0.0f32;
+        (0..sample_rate)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                phase += std::f32::consts::TAU * (200.0 + t * 100.0) / sample_rate as f32;
+                phase.sin() * 0.4 + (std::f32::consts::TAU * 30.0 * t).sin() * 0.3
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pushing_in_blocks_matches_analyzing_at_once() {
+        let audio = glide(44100);
+        for options in [
+            AnalyzeOptions::default(),
+            AnalyzeOptions {
+                window_size: 2048,
+                hop_size: 512,
+                highpass_hz: Some(80.0),
+                clarity_floor: ClarityFloor::Adaptive,
+                ..AnalyzeOptions::default()
+            },
+            // hops longer than windows, and a partial window at the end
+            AnalyzeOptions {
+                window_size: 1024,
+                hop_size: 1500,
+                quality: 0.6,
+                ..AnalyzeOptions::default()
+            },
+        ] {
+            let mut analyzer = PitchAnalyzer::new(44100, 100.0, 500.0, options.clone());
+            let mut points = vec![];
+            // blocks of every size, some smaller than a window and some several windows long
+            let mut rest = &audio[..];
+            for size in [1, 700, 5000, 333, 1024, 9999].iter().cycle() {
+                let (block, after) = rest.split_at((*size).min(rest.len()));
+                points.extend(analyzer.push_samples(block));
+                rest = after;
+                if rest.is_empty() {
+                    break;
+                }
+            }
+            points.extend(analyzer.clone().flush());
+
+            let expected = analyze_pitch_contour(&audio, 44100, 100.0, 500.0, &options);
+            assert_eq!(points, expected, "{:?}", options);
+            let (report, kept) = analyzer.finalize().unwrap();
+            let (expected_report, expected_kept) =
+                analyze_pitch_with(&audio, 44100, 100.0, 500.0, &options).unwrap();
+            // `PitchReport` has no `PartialEq`, every field shows up in its `Debug` though
+            assert_eq!(
+                format!("{:?}", report),
+                format!("{:?}", expected_report),
+                "{:?}",
+                options
+            );
+            assert_eq!(kept, expected_kept);
+        }
+    }
+
+    #[test]
+    fn points_arrive_as_soon_as_their_window_fills() {
+        let audio = glide(44100);
+        let mut analyzer = PitchAnalyzer::new(44100, 100.0, 500.0, AnalyzeOptions::default());
+
+        assert!(analyzer.push_samples(&audio[..1023]).is_empty());
+        let first = analyzer.push_samples(&audio[1023..1024]);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].time_secs, 0.0);
+        assert!((first[0].frequency - 200.0).abs() < 3.0, "{:?}", first);
+        // the next one is a hop on
+        let second = analyzer.push_samples(&audio[1024..2048]);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].time_secs, 1024.0 / 44100.0);
+
+        let mut silent = PitchAnalyzer::new(44100, 100.0, 500.0, AnalyzeOptions::default());
+        assert!(silent.push_samples(&[0.0; 10000]).is_empty());
+        assert!(matches!(silent.finalize(), Err(AvasaraError::SilentInput)));
+    }
+}
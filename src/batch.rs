@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::{compose_to_ogg, AvasaraError};
+
+/// Fills in a naming template for one input, `{stem}` is the input's file name without its
+/// extension and `{index}` its position in the batch (starting at 0).
+fn render_name(template: &str, input: &Path, index: usize) -> String {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("{}", index));
+    template
+        .replace("{stem}", &stem)
+        .replace("{index}", &index.to_string())
+}
+
+/// First path in `dir` based on `name` that neither exists on disk nor was already handed out in
+/// this batch, `song.ogg`, then `song-1.ogg`, `song-2.ogg` and so on.
+fn unique_path(dir: &Path, name: &str, taken: &HashSet<PathBuf>) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() && !taken.contains(&candidate) {
+        return candidate;
+    }
+
+    let (base, extension) = match name.rsplit_once('.') {
+        Some((base, extension)) if !base.is_empty() => (base, Some(extension)),
+        _ => (name, None),
+    };
+    (1..)
+        .map(|n| match extension {
+            Some(extension) => dir.join(format!("{}-{}.{}", base, n, extension)),
+            None => dir.join(format!("{}-{}", base, n)),
+        })
+        .find(|path| !path.exists() && !taken.contains(path))
+        .unwrap()
+}
+
+/// Runs `compose_to_ogg` over a whole list of files and writes every result into `out_dir`, named
+/// after `template` (`{stem}` is replaced with the input's file name minus its extension,
+/// `{index}` with its position in `inputs`, so `"{stem}.ogg"` turns `music/song.flac` into
+/// `out_dir/song.ogg`). Nothing is ever overwritten, if a name is already taken (on disk, or by an
+/// earlier file in the same batch) a `-1`, `-2`... suffix is added before the extension. Each
+/// input gets its index as its stream serial.
+///
+/// Every file succeeds or fails on its own, the result for each input (in the same order) is
+/// either the path it was written to or what went wrong, a file that can't be read, decoded or
+/// written doesn't stop the rest of the batch. `out_dir` is created if it doesn't exist yet, if
+/// that fails the whole batch does, with the `AvasaraError::Io` it failed with, before any input
+/// is touched.
+pub fn compose_batch_to_dir(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    template: &str,
+    target_quality: f32,
+    remux: bool,
+) -> Result<Vec<Result<PathBuf, AvasaraError>>, AvasaraError> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut taken = HashSet::new();
+    Ok(inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let file = std::fs::File::open(input)?;
+            let prefix = input.display().to_string();
+
+            let ogg = compose_to_ogg(file, &prefix, index as i32, target_quality, remux)?;
+
+            let path = unique_path(out_dir, &render_name(template, input, index), &taken);
+            std::fs::write(&path, ogg)?;
+            taken.insert(path.clone());
+            Ok(path)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory under the system's temp dir for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("avasara-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn every_input_gets_its_own_result() {
+        let dir = scratch_dir("batch");
+        let tone: Vec<f32> = (0..22050).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let wav = dir.join("tone.wav");
+        std::fs::write(
+            &wav,
+            crate::encode_wav(&tone, 22050, 1, crate::WavFormat::Pcm16).unwrap(),
+        )
+        .unwrap();
+        let inputs = [wav.clone(), dir.join("missing.wav"), wav];
+
+        let out_dir = dir.join("out");
+        let results = compose_batch_to_dir(&inputs, &out_dir, "{stem}.ogg", 0.5, false).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &out_dir.join("tone.ogg"));
+        assert!(matches!(results[1], Err(AvasaraError::Io(_))));
+        // the same name again doesn't overwrite the first one
+        assert_eq!(results[2].as_ref().unwrap(), &out_dir.join("tone-1.ogg"));
+        assert!(std::fs::read(out_dir.join("tone-1.ogg"))
+            .unwrap()
+            .starts_with(b"OggS"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn an_uncreatable_out_dir_fails_the_whole_batch() {
+        let dir = scratch_dir("batch-dir");
+        // a directory can't be created under a plain file
+        let file = dir.join("file");
+        std::fs::write(&file, b"not a directory").unwrap();
+
+        let result = compose_batch_to_dir(
+            &[dir.join("tone.wav")],
+            &file.join("out"),
+            "{stem}.ogg",
+            0.5,
+            false,
+        );
+        assert!(matches!(result, Err(AvasaraError::Io(_))), "{:?}", result);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1706 @@
+use std::fmt;
+use std::io::{Cursor, Write};
+use std::num::{NonZeroU32, NonZeroU8};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use optivorbis::remuxer::ogg_to_ogg::Settings as RemuxSettings;
+use optivorbis::{
+    OggToOgg, Remuxer, VorbisCommentFieldsAction, VorbisOptimizerSettings, VorbisVendorStringAction,
+};
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder};
+
+use crate::{
+    downmix_to_mono, downmix_to_stereo, interleave_to_mono, measure_lufs, probe_info, resample,
+    AvasaraError, Decoder, DownmixStrategy, MediaSource, ResampleQuality,
+};
+
+/// The highest true peak (in dBTP) loudness normalization is allowed to push the audio to, the
+/// -1 dBTP EBU R128 asks for, which leaves the Vorbis encoder room for its own overshoot.
+const NORMALIZE_TRUE_PEAK_CEILING: f64 = -1.0;
+
+/// The rate `compose_to_ogg` falls back to when the encoder won't take the source's.
+const FALLBACK_SAMPLE_RATE: u32 = 48000;
+
+/// How the throwaway encoder that checks whether libvorbis takes a sample rate spends its bits,
+/// a middling quality every rate it supports at all takes, so a refusal is down to the rate and
+/// not to a bitrate that's out of range for it.
+const RATE_PROBE_BITRATE: VorbisBitrateManagementStrategy =
+    VorbisBitrateManagementStrategy::QualityVbr {
+        target_quality: 0.5,
+    };
+
+/// How much audio data (in bytes) an Ogg page has to hold before it's sent off with
+/// `ComposeBuilder::soft_start`, a quarter of libogg's usual 4 KiB.
+const SOFT_START_PAGE_SIZE: u16 = 1024;
+
+/// The lowest quality libvorbis takes, where the search for `ComposeBuilder::max_size` bottoms
+/// out.
+const MIN_QUALITY: f32 = -0.2;
+
+/// How many times `ComposeBuilder::max_size` halves the quality range after trying both ends of
+/// it, 6 gets within 0.02 of the best quality that fits.
+const SIZE_SEARCH_STEPS: usize = 6;
+
+/// Which channels `ComposeBuilder` encodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelPolicy {
+    /// Every channel as it was decoded, stereo stays stereo and 5.1 stays 5.1 (reordered into
+    /// the order Vorbis expects, so players put every channel back in the right speaker).
+    KeepOriginal,
+    /// Fold everything down to mono, stereo with `interleave_to_mono` like `compose_to_ogg` has
+    /// always done, anything with more channels with `downmix_to_mono` and
+    /// `DownmixStrategy::Itu`, which keeps the dialogue in the center channel up front.
+    #[default]
+    ForceMono,
+    /// Stereo output whatever the source, mono is copied into both sides and anything with more
+    /// channels is folded down with `downmix_to_stereo`.
+    ForceStereo,
+}
+
+/// Bytes behind an `Arc`, so a `Cursor` over them is a `MediaSource` (which has to be `'static`,
+/// ruling out a borrowed slice) without giving them up.
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// How OptiVorbis remuxes the output when `ComposeBuilder::remux` is on, set through
+/// `ComposeBuilder::remux_options`. The defaults are OptiVorbis' own, what remuxing has always
+/// used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemuxOptions {
+    /// Whether to give the stream a random serial (so `cat`ing two files together still makes a
+    /// valid chained Ogg file) instead of `stream_serial_offset` itself, `true` by default.
+    pub randomize_stream_serials: bool,
+    /// Added to the random serial, or used as the serial as is when they aren't randomized.
+    pub stream_serial_offset: u32,
+    /// Whether to treat the first sample as sample zero even when the source's granule positions
+    /// say otherwise. Leave it `false` (the default) to keep the length and timestamps exactly as
+    /// they were, including the samples the encoder marked to be trimmed off the start, set it to
+    /// repair files with broken granule positions (the usual cause of wrong durations and
+    /// seeking problems) or ones cut out of a live stream.
+    pub ignore_start_sample_offset: bool,
+    /// What to do with the vendor string, OptiVorbis appends its own tag to it by default.
+    pub vendor_string: VorbisVendorStringAction,
+    /// What to do with the comments (tags), copied as they are by default, `Delete` strips them.
+    pub comment_fields: VorbisCommentFieldsAction,
+}
+
+impl Default for RemuxOptions {
+    fn default() -> Self {
+        RemuxOptions {
+            randomize_stream_serials: true,
+            stream_serial_offset: 0,
+            ignore_start_sample_offset: false,
+            vendor_string: VorbisVendorStringAction::AppendTag,
+            comment_fields: VorbisCommentFieldsAction::Copy,
+        }
+    }
+}
+
+/// How the Vorbis encoder spends its bits, what `compose_to_ogg_with_bitrate` and
+/// `ComposeBuilder::bitrate` take. Bitrates are in bits per second for the whole stream (not per
+/// channel), libvorbis only takes a range of them for any given sample rate and channel count
+/// (roughly 32 to 500 kbit/s for 44.1kHz stereo, less for mono and lower rates), anything outside
+/// of it (zero included) makes encoding fail with `AvasaraError::Encode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OggBitrate {
+    /// Quality-based VBR, `-0.2` (smallest files) to `1.0` (best quality), what `compose_to_ogg`
+    /// uses. The bitrate follows how hard the audio is to encode, so it's the best quality for
+    /// the size but the size is hard to predict.
+    Quality(f32),
+    /// VBR aimed at an average bitrate, the encoder picks the quality level that usually lands
+    /// near it, without managing the bitrate at all, so hard audio can come out well above it.
+    Vbr(u32),
+    /// ABR, the bitrate management engine keeps the average at the given bitrate over time,
+    /// with short and small swings around it, for hitting a size budget.
+    Abr(u32),
+    /// Constrained ABR, the bitrate never goes above the given one (the closest Vorbis gets to
+    /// CBR), for streaming over a link with a hard bandwidth cap. The average ends up slightly
+    /// below it, and quality with it.
+    ConstrainedAbr(u32),
+}
+
+impl From<OggBitrate> for VorbisBitrateManagementStrategy {
+    fn from(bitrate: OggBitrate) -> Self {
+        // a zero bitrate can't be represented, 1 bit/s gets refused by libvorbis just the same
+        let nonzero = |bits: u32| NonZeroU32::new(bits.max(1)).unwrap();
+        match bitrate {
+            OggBitrate::Quality(target_quality) => {
+                VorbisBitrateManagementStrategy::QualityVbr { target_quality }
+            }
+            OggBitrate::Vbr(bits) => VorbisBitrateManagementStrategy::Vbr {
+                target_bitrate: nonzero(bits),
+            },
+            OggBitrate::Abr(bits) => VorbisBitrateManagementStrategy::Abr {
+                average_bitrate: nonzero(bits),
+            },
+            OggBitrate::ConstrainedAbr(bits) => VorbisBitrateManagementStrategy::ConstrainedAbr {
+                maximum_bitrate: nonzero(bits),
+            },
+        }
+    }
+}
+
+/// A step of the pipeline `ComposeBuilder` runs, in the order they happen, as reported through
+/// `ComposeBuilder::on_progress` (and the callbacks `compose_to_wav`, `compose_to_flac` and
+/// `compose_to_opus` take). Steps that aren't needed for a given file and set of options
+/// (resampling a file that's already at the target rate, say) are skipped entirely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComposeStage {
+    Decoding,
+    /// Folding stereo down to mono (`ChannelPolicy::ForceMono`), or any channel count for
+    /// `compose_to_wav` and `compose_to_flac`.
+    Interleaving,
+    /// Folding `channels` channels down to stereo (`ChannelPolicy::ForceStereo`), or down to mono
+    /// when there are more than 2 of them (`ChannelPolicy::ForceMono`).
+    Downmixing {
+        channels: usize,
+    },
+    /// Resampling from one rate to the other, either because a target rate was set or because
+    /// libvorbis refused the source's.
+    Resampling {
+        from: u32,
+        to: u32,
+    },
+    /// Applying loudness normalization, from the measured integrated loudness to the one the
+    /// audio ends up with (below the target if the true-peak ceiling got in the way).
+    Normalizing {
+        from_lufs: f64,
+        to_lufs: f64,
+    },
+    /// Encoding into the output format, Ogg Vorbis for `ComposeBuilder`.
+    Encoding,
+    /// Running the encoded file through `optivorbis` (or the source itself, when an Ogg Vorbis
+    /// file is passed through, see `ComposeBuilder::passthrough_vorbis`).
+    Remuxing,
+    /// Everything's done, this is always the last report of a successful run.
+    Done,
+}
+
+impl fmt::Display for ComposeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComposeStage::Decoding => write!(f, "Decoding"),
+            ComposeStage::Interleaving => write!(f, "Interleaving"),
+            ComposeStage::Downmixing { channels } => {
+                write!(f, "Downmixing ({} channels to stereo)", channels)
+            }
+            ComposeStage::Resampling { from, to } => {
+                write!(f, "Resampling ({}Hz to {}Hz)", from, to)
+            }
+            ComposeStage::Normalizing { from_lufs, to_lufs } => write!(
+                f,
+                "Normalizing ({:.1} LUFS to {:.1} LUFS)",
+                from_lufs, to_lufs
+            ),
+            ComposeStage::Encoding => write!(f, "Encoding"),
+            ComposeStage::Remuxing => write!(f, "Remuxing"),
+            ComposeStage::Done => write!(f, "Done!"),
+        }
+    }
+}
+
+/// Where a `ComposeBuilder` run is at, see `ComposeBuilder::on_progress`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub stage: ComposeStage,
+    /// How far into `stage` the run is, 0.0 to 100.0, reported as the stage starts (0.0) and
+    /// then whenever it moves on by at least a percent. `None` for stages whose length isn't
+    /// known, decoding a source that doesn't declare its length is one, resampling,
+    /// normalizing and remuxing happen in one go and are always `None`.
+    pub percent: Option<f32>,
+}
+
+/// The callback set with `ComposeBuilder::on_progress`, wrapped so the builder can still be
+/// `Debug` and `Clone`.
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// Reports a percentage that goes from 0 to 100 over `total` steps, but only once per whole
+/// percent, so a progress bar isn't redrawn for every single packet.
+struct PercentTracker<'a> {
+    builder: &'a ComposeBuilder,
+    stage: ComposeStage,
+    total: Option<u64>,
+    last: Option<u32>,
+}
+
+impl PercentTracker<'_> {
+    fn update(&mut self, done: u64) {
+        let percent = self
+            .total
+            .filter(|total| *total > 0)
+            .map(|total| (done.min(total) as f64 / total as f64 * 100.0) as f32);
+        match percent {
+            Some(percent) if self.last != Some(percent as u32) => {
+                self.last = Some(percent as u32);
+                self.builder.report(self.stage, Some(percent));
+            }
+            None if self.last.is_none() => {
+                // an unknown length only gets reported once, as the stage starts
+                self.last = Some(0);
+                self.builder.report(self.stage, None);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds up everything the decode, interleave, encode pipeline behind `compose_to_ogg` can be
+/// told, one method per option, and then runs it with `run` (or `run_from_samples` for audio
+/// that's already decoded). Every `compose_to_ogg_*` function is just a shorthand for one of
+/// these, so new options only ever show up here instead of as yet another positional argument.
+///
+/// ```no_run
+/// use avasara::ComposeBuilder;
+///
+/// let ogg = ComposeBuilder::new()
+///     .prefix("intro.flac")
+///     .quality(0.6)
+///     .sample_rate(24000)
+///     .normalize_to_lufs(-16.0)
+///     .remux(true)
+///     .run(std::fs::File::open("intro.flac").unwrap())
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ComposeBuilder {
+    prefix: String,
+    stream_serial: i32,
+    bitrate: VorbisBitrateManagementStrategy,
+    remux: bool,
+    remux_options: RemuxOptions,
+    passthrough: bool,
+    reproducible: bool,
+    target_sample_rate: Option<u32>,
+    normalize_to_lufs: Option<f32>,
+    max_size: Option<usize>,
+    comments: Vec<(String, String)>,
+    copy_tags: bool,
+    channel_policy: ChannelPolicy,
+    soft_start: bool,
+    progress: Option<ProgressCallback>,
+    print_progress: bool,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for ComposeBuilder {
+    /// An empty prefix, stream serial 0, quality 0.5 VBR, mono, the source's own sample rate, no
+    /// loudness normalization, no size budget, no comments, no remuxing and no progress reports.
+    fn default() -> Self {
+        ComposeBuilder {
+            prefix: String::new(),
+            stream_serial: 0,
+            bitrate: VorbisBitrateManagementStrategy::QualityVbr {
+                target_quality: 0.5,
+            },
+            remux: false,
+            remux_options: RemuxOptions::default(),
+            passthrough: false,
+            reproducible: false,
+            target_sample_rate: None,
+            normalize_to_lufs: None,
+            max_size: None,
+            comments: vec![],
+            copy_tags: false,
+            channel_policy: ChannelPolicy::ForceMono,
+            soft_start: false,
+            progress: None,
+            print_progress: false,
+            cancel: None,
+        }
+    }
+}
+
+impl ComposeBuilder {
+    /// Same as `ComposeBuilder::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What every line `print_progress` prints starts with (in square brackets), handy to tell
+    /// files apart when several are composed at once. Nothing else uses it, a run is silent
+    /// unless it's asked to report.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// The Ogg stream serial, really doesn't matter, pick a random 32bit integer or leave it at 0.
+    pub fn stream_serial(mut self, stream_serial: i32) -> Self {
+        self.stream_serial = stream_serial;
+        self
+    }
+
+    /// Quality-based VBR, `target_quality` between `-0.2` (smallest files) and `1.0` (best
+    /// quality), same as the `target_quality` argument of `compose_to_ogg`. Replaces whatever
+    /// `bitrate` set.
+    pub fn quality(mut self, target_quality: f32) -> Self {
+        self.bitrate = VorbisBitrateManagementStrategy::QualityVbr { target_quality };
+        self
+    }
+
+    /// Any of libvorbis' bitrate management strategies (quality VBR, bitrate VBR, ABR or
+    /// constrained ABR), for when the output has to fit a bandwidth budget rather than hit a
+    /// quality level. Takes an `OggBitrate` or vorbis_rs' own strategy. Replaces whatever
+    /// `quality` set.
+    pub fn bitrate(mut self, strategy: impl Into<VorbisBitrateManagementStrategy>) -> Self {
+        self.bitrate = strategy.into();
+        self
+    }
+
+    /// Which channels to encode, see `ChannelPolicy`, mono unless told otherwise.
+    pub fn channels(mut self, channel_policy: ChannelPolicy) -> Self {
+        self.channel_policy = channel_policy;
+        self
+    }
+
+    /// Whether to run the result through `optivorbis` for a two-pass optimization, see
+    /// `compose_to_ogg`.
+    pub fn remux(mut self, remux: bool) -> Self {
+        self.remux = remux;
+        self
+    }
+
+    /// How OptiVorbis remuxes, see `RemuxOptions`, also turns `remux` on. `reproducible`
+    /// overrides the serial, vendor string and comment settings (keeping serials fixed and
+    /// everything else as is), the rest still applies.
+    pub fn remux_options(mut self, options: RemuxOptions) -> Self {
+        self.remux = true;
+        self.remux_options = options;
+        self
+    }
+
+    /// Whether an Ogg Vorbis source should skip decoding and re-encoding entirely and only be
+    /// remuxed with OptiVorbis, see `transcode_or_remux`. That only happens when re-encoding
+    /// wouldn't change anything but the quality, so not when a target sample rate, loudness
+    /// normalization, a size budget or comments are set, nor when the channel policy would change
+    /// the channel count (a stereo file with the default `ChannelPolicy::ForceMono`), any of those
+    /// re-encode as usual. The bitrate mode doesn't matter, and the source's comments are kept whatever
+    /// `copy_tags` says, OptiVorbis copies them over.
+    pub fn passthrough_vorbis(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Whether the output should only depend on the input and the options, so the same file
+    /// always gives byte-identical Ogg files, see `compose_to_ogg_reproducible`. Only makes a
+    /// difference together with `remux`.
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Resample to `target_sample_rate` before encoding, see `compose_to_ogg_at_rate`.
+    ///
+    /// # Panics
+    /// Panics if `target_sample_rate` is zero.
+    pub fn sample_rate(mut self, target_sample_rate: u32) -> Self {
+        assert!(
+            target_sample_rate > 0,
+            "target sample rate must be non-zero"
+        );
+        self.target_sample_rate = Some(target_sample_rate);
+        self
+    }
+
+    /// Bring the audio to this integrated loudness before encoding, see
+    /// `compose_to_ogg_normalized`.
+    pub fn normalize_to_lufs(mut self, lufs: f32) -> Self {
+        self.normalize_to_lufs = Some(lufs);
+        self
+    }
+
+    /// Keep the Ogg file within `bytes` by picking the highest Vorbis quality that fits, at most
+    /// the one set with `quality` (1.0 if a bitrate mode was set, which this replaces). The audio
+    /// is encoded a few times over to find it (up to 8 times, a binary search on the quality, the
+    /// `Encoding` stage is reported for every attempt), so it takes that much longer.
+    ///
+    /// Everything that changes the audio itself happens first and isn't up for negotiation, a
+    /// `normalize_to_lufs` target is met no matter the budget, only the quality gives way. If even
+    /// the lowest quality (-0.2) doesn't fit, that's what you get, the budget is a goal rather
+    /// than a guarantee, check the length of the result if it has to be one. Remuxing happens
+    /// after the search and only ever makes the file smaller.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Adds a Vorbis comment (`("ARTIST", "...")`, `("TITLE", "...")` and so on, field names are
+    /// case-insensitive but conventionally uppercase) to the output. Comments are written in the
+    /// order they're added, and the same field can be added more than once (two artists, say).
+    pub fn comment(mut self, key: &str, value: &str) -> Self {
+        self.comments.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds every `(key, value)` pair as a Vorbis comment, see `comment`, e.g. the ones
+    /// `Decoder::vorbis_comments` gives for another file.
+    pub fn comments<K: AsRef<str>, V: AsRef<str>>(
+        mut self,
+        comments: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.comments.extend(
+            comments
+                .into_iter()
+                .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string())),
+        );
+        self
+    }
+
+    /// Whether to carry the source's own tags over to the output (as `Decoder::vorbis_comments`
+    /// maps them), so transcodes keep their artist, title, album and so on. They're written
+    /// before the ones added with `comment`, and a field set with `comment` replaces the
+    /// source's value for it instead of being written next to it. `run_from_samples` has no
+    /// source to copy from, so this does nothing there.
+    pub fn copy_tags(mut self, copy_tags: bool) -> Self {
+        self.copy_tags = copy_tags;
+        self
+    }
+
+    /// Whether a `StreamEncoder` should get audio to its sink as early as possible, for live
+    /// streaming. The header pages are flushed all the way through the sink (past any
+    /// `BufWriter` in it) as soon as the encoder is made, before the first `push`, and so is every
+    /// page after them, which are sent off once they hold 1 KiB of audio data instead of
+    /// libogg's usual 4 KiB. At 128 kbps that's a page every ~64ms instead of every ~256ms, for
+    /// about 2% more bytes (every page has its own header of 27 bytes and up). Applies to `run`
+    /// too, where it only makes the file a little bigger.
+    pub fn soft_start(mut self, soft_start: bool) -> Self {
+        self.soft_start = soft_start;
+        self
+    }
+
+    /// Calls `callback` with every step the run gets to, for progress bars in a GUI or logging
+    /// through whatever the app already uses, a run without one reports nothing at all. It's
+    /// called on the thread doing the run, so keep it quick, and a run that fails just stops
+    /// reporting, there's no final `Done` for it. Replaces `print_progress`.
+    pub fn on_progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Whether to print a `[prefix] Stage` line to stdout as each stage starts, the way
+    /// `compose_to_ogg` always used to, handy for quick scripts. Off by default, and ignored
+    /// once `on_progress` is set.
+    pub fn print_progress(mut self, print_progress: bool) -> Self {
+        self.print_progress = print_progress;
+        self
+    }
+
+    /// Makes the run check `cancel` while decoding, between the steps after it and while
+    /// encoding, and give up with `AvasaraError::Cancelled` as soon as it's set, so a transcode
+    /// can be aborted from another thread (keep a clone of the `Arc` there and `store(true)` into
+    /// it). Nothing is checked during the final remux, it's quick compared to the rest.
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// `Err(AvasaraError::Cancelled)` if the cancellation flag has been set.
+    fn check_cancelled(&self) -> Result<(), AvasaraError> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(AvasaraError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hands a step to the progress callback, or prints it if `print_progress` is on (only as
+    /// each stage starts, not every percent), or does nothing.
+    fn report(&self, stage: ComposeStage, percent: Option<f32>) {
+        match &self.progress {
+            Some(callback) => (callback.0)(Progress { stage, percent }),
+            None if self.print_progress && (percent.unwrap_or(0.0) == 0.0) => {
+                println!("[{}] {}", self.prefix, stage)
+            }
+            None => {}
+        }
+    }
+
+    /// Starts reporting progress for a stage that takes `total` steps (if known).
+    fn track(&self, stage: ComposeStage, total: Option<u64>) -> PercentTracker<'_> {
+        let mut tracker = PercentTracker {
+            builder: self,
+            stage,
+            total,
+            last: None,
+        };
+        tracker.update(0);
+        tracker
+    }
+
+    /// Sets up a `StreamEncoder` writing an Ogg Vorbis stream of `channels` channels at
+    /// `sample_rate` into `sink`, for audio that arrives bit by bit (a microphone, a live mix)
+    /// rather than as a whole file. It uses the quality (or bitrate), stream serial, comments
+    /// and `soft_start` set here, the channel policy, sample rate, loudness normalization and
+    /// remuxing all need the whole file up front, so they don't apply and the channels are
+    /// encoded as they're pushed.
+    ///
+    /// # Errors
+    /// Returns `AvasaraError::UnsupportedChannels` for zero or more than 255 channels, or
+    /// `AvasaraError::Encode` if libvorbis won't encode at `sample_rate` or writing the header
+    /// pages fails.
+    pub fn stream_encoder<W: Write>(
+        &self,
+        sample_rate: u32,
+        channels: usize,
+        sink: W,
+    ) -> Result<StreamEncoder<W>, AvasaraError> {
+        if !(1..=255).contains(&channels) {
+            return Err(AvasaraError::UnsupportedChannels(channels));
+        }
+        let sink = PageSink {
+            inner: sink,
+            flush_every_write: self.soft_start,
+        };
+        Ok(StreamEncoder {
+            encoder: self.vorbis_encoder(
+                sample_rate,
+                channels,
+                self.bitrate,
+                &self.comments,
+                sink,
+            )?,
+            channels,
+        })
+    }
+
+    /// Decodes `src` (anything `decode` takes) and encodes it with these options.
+    ///
+    /// # Errors
+    /// Returns an error if decoding, encoding or remuxing fails (see `AvasaraError`).
+    pub fn run<S: MediaSource + 'static>(&self, src: S) -> Result<Vec<u8>, AvasaraError> {
+        Ok(self.run_reporting_rate(src)?.0)
+    }
+
+    /// Same as `run`, but starts from audio that's already been decoded, see
+    /// `compose_to_ogg_from_samples`.
+    pub fn run_from_samples(
+        &self,
+        audio: Vec<f32>,
+        sample_rate: usize,
+        channels: usize,
+    ) -> Result<Vec<u8>, AvasaraError> {
+        Ok(self.encode((audio, sample_rate, channels), vec![])?.0)
+    }
+
+    /// Same as `run`, but also returns the sample rate the Ogg file ended up with, see
+    /// `compose_to_ogg_reporting_rate`.
+    pub fn run_reporting_rate<S: MediaSource + 'static>(
+        &self,
+        mut src: S,
+    ) -> Result<(Vec<u8>, u32), AvasaraError> {
+        if self.passthrough {
+            let mut bytes = vec![];
+            src.read_to_end(&mut bytes)?;
+
+            if bytes.starts_with(b"OggS") {
+                // the probe gets a handle on the bytes rather than a copy of them, and it's done
+                // with it by the time it returns, leaving them to us again
+                let shared = Arc::new(bytes);
+                let info = probe_info(Cursor::new(SharedBytes(shared.clone())));
+                bytes = Arc::try_unwrap(shared).unwrap_or_else(|shared| shared.to_vec());
+                let info = info?;
+                if (info.codec == "vorbis") && self.allows_passthrough(info.channels) {
+                    self.report(ComposeStage::Remuxing, None);
+                    let out = self.remux_ogg(bytes)?;
+                    self.report(ComposeStage::Done, None);
+
+                    return Ok((out, info.sample_rate.unwrap_or(0) as u32));
+                }
+            }
+
+            return self.transcode(Cursor::new(bytes));
+        }
+
+        self.transcode(src)
+    }
+
+    /// Everything `run_reporting_rate` does when the source isn't passed through.
+    fn transcode<S: MediaSource + 'static>(&self, src: S) -> Result<(Vec<u8>, u32), AvasaraError> {
+        // same as `decode`, but keeping the decoder around for its tags and length
+        let mut decoder = Decoder::new(src)?;
+        let mut tracker = self.track(ComposeStage::Decoding, decoder.total_frames());
+        let mut audio = vec![];
+        for block in &mut decoder {
+            self.check_cancelled()?;
+            let block = block?;
+            audio.extend(block.samples);
+            tracker.update((audio.len() / block.channels.max(1)) as u64);
+        }
+
+        let source_comments = if self.copy_tags {
+            decoder.vorbis_comments().to_vec()
+        } else {
+            vec![]
+        };
+        self.encode(
+            (audio, decoder.sample_rate(), decoder.channels()),
+            source_comments,
+        )
+    }
+
+    /// Sets up a Vorbis encoder writing into `sink` that spends its bits the way `bitrate` says
+    /// (`self.bitrate` for the real thing), fails if libvorbis can't encode at `sample_rate` (or
+    /// the quality or bitrate is out of range).
+    fn vorbis_encoder<W: Write>(
+        &self,
+        sample_rate: u32,
+        channels: usize,
+        bitrate: VorbisBitrateManagementStrategy,
+        comments: &[(String, String)],
+        sink: W,
+    ) -> Result<VorbisEncoder<W>, vorbis_rs::VorbisError> {
+        VorbisEncoder::new(
+            self.stream_serial,
+            // comments are written in exactly the order of this slice, keep it a vector (not a
+            // map) so reproducible output stays reproducible
+            comments
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+            NonZeroU32::new(sample_rate).unwrap(),
+            NonZeroU8::try_from(u8::try_from(channels)?)?,
+            bitrate,
+            self.soft_start.then_some(SOFT_START_PAGE_SIZE),
+            sink,
+        )
+    }
+
+    /// Everything `run` does after decoding, `decoded` is what `DecodedAudio::into_parts` gives
+    /// and `source_comments` the tags to copy from the source (empty unless `copy_tags` is on).
+    fn encode(
+        &self,
+        decoded: (Vec<f32>, usize, usize),
+        source_comments: Vec<(String, String)>,
+    ) -> Result<(Vec<u8>, u32), AvasaraError> {
+        let mut ogg_out = vec![];
+        let sample_rate = self.encode_into(decoded, source_comments, &mut ogg_out)?;
+
+        if self.remux {
+            self.report(ComposeStage::Remuxing, None);
+            let out = self.remux_ogg(ogg_out)?;
+            self.report(ComposeStage::Done, None);
+
+            Ok((out, sample_rate))
+        } else {
+            self.report(ComposeStage::Done, None);
+            Ok((ogg_out, sample_rate))
+        }
+    }
+
+    /// Same as `run_from_samples`, but writes the Ogg file into `sink` as it's encoded instead of
+    /// returning it, so nothing but the decoded audio has to fit in memory (unless remuxing,
+    /// OptiVorbis needs the whole file), see `encode_to_file`. Returns the sample rate the file
+    /// ended up with.
+    pub(crate) fn write_from_samples<W: Write>(
+        &self,
+        audio: Vec<f32>,
+        sample_rate: usize,
+        channels: usize,
+        mut sink: W,
+    ) -> Result<u32, AvasaraError> {
+        if self.remux {
+            let (ogg, sample_rate) = self.encode((audio, sample_rate, channels), vec![])?;
+            sink.write_all(&ogg)?;
+            return Ok(sample_rate);
+        }
+
+        let sample_rate = self.encode_into((audio, sample_rate, channels), vec![], &mut sink)?;
+        sink.flush()?;
+        self.report(ComposeStage::Done, None);
+        Ok(sample_rate)
+    }
+
+    /// Everything `encode` does before remuxing, writing the Ogg file into `sink`, returns the
+    /// sample rate it ended up with.
+    fn encode_into<W: Write>(
+        &self,
+        decoded: (Vec<f32>, usize, usize),
+        source_comments: Vec<(String, String)>,
+        mut sink: W,
+    ) -> Result<u32, AvasaraError> {
+        let (planar, sample_rate, comments) = self.prepare(decoded, source_comments)?;
+
+        match self.max_size {
+            Some(max_size) => {
+                let ogg = self.encode_to_size(&planar, sample_rate, &comments, max_size)?;
+                sink.write_all(&ogg)?;
+            }
+            None => self.encode_vorbis(&planar, sample_rate, &comments, sink)?,
+        }
+
+        Ok(sample_rate)
+    }
+
+    /// Encodes at the highest quality that keeps the Ogg file within `max_size` bytes, see
+    /// `max_size`.
+    fn encode_to_size(
+        &self,
+        planar: &[Vec<f32>],
+        sample_rate: u32,
+        comments: &[(String, String)],
+        max_size: usize,
+    ) -> Result<Vec<u8>, AvasaraError> {
+        let attempt = |target_quality: f32| {
+            let builder = ComposeBuilder {
+                bitrate: VorbisBitrateManagementStrategy::QualityVbr { target_quality },
+                ..self.clone()
+            };
+            let mut ogg = vec![];
+            builder.encode_vorbis(planar, sample_rate, comments, &mut ogg)?;
+            Ok::<_, AvasaraError>(ogg)
+        };
+
+        let mut high = match self.bitrate {
+            VorbisBitrateManagementStrategy::QualityVbr { target_quality } => target_quality,
+            _ => 1.0,
+        };
+        let best = attempt(high)?;
+        if best.len() <= max_size {
+            return Ok(best);
+        }
+        let mut low = MIN_QUALITY;
+        let mut best = attempt(low)?;
+        if best.len() > max_size {
+            return Ok(best);
+        }
+
+        // `best` always fits, `high` never does
+        for _ in 0..SIZE_SEARCH_STEPS {
+            let middle = (low + high) / 2.0;
+            let ogg = attempt(middle)?;
+            if ogg.len() <= max_size {
+                best = ogg;
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        Ok(best)
+    }
+
+    /// Turns decoded audio into what gets encoded, the channels the policy asks for (one vector
+    /// each), resampled and normalized as asked, along with its sample rate and the comments to
+    /// write.
+    #[allow(clippy::type_complexity)]
+    fn prepare(
+        &self,
+        decoded: (Vec<f32>, usize, usize),
+        source_comments: Vec<(String, String)>,
+    ) -> Result<(Vec<Vec<f32>>, u32, Vec<(String, String)>), AvasaraError> {
+        let (audio, sample_rate, channels) = decoded;
+        if (sample_rate == 0) || (channels == 0) {
+            // a zero sample rate or channel count means decoding didn't actually produce anything
+            return Err(AvasaraError::DecodeFailed);
+        }
+        if (self.channel_policy == ChannelPolicy::KeepOriginal) && (channels > 255) {
+            // the most a Vorbis stream can hold
+            return Err(AvasaraError::UnsupportedChannels(channels));
+        }
+
+        self.check_cancelled()?;
+        let mut planar: Vec<Vec<f32>> = match self.channel_policy {
+            ChannelPolicy::ForceMono if channels > 2 => {
+                self.report(ComposeStage::Downmixing { channels }, None);
+                vec![downmix_to_mono(&audio, channels, DownmixStrategy::Itu)]
+            }
+            ChannelPolicy::ForceMono => {
+                // more info at Fōn/fon's readme
+                if channels > 1 {
+                    self.report(ComposeStage::Interleaving, None);
+                }
+                let mut audio = interleave_to_mono(audio, sample_rate as u32, channels)?;
+                vec![audio.as_f32_slice().to_vec()]
+            }
+            ChannelPolicy::ForceStereo if channels == 1 => vec![audio.clone(), audio],
+            ChannelPolicy::ForceStereo => {
+                self.report(ComposeStage::Downmixing { channels }, None);
+                deinterleave(&downmix_to_stereo(&audio, channels), 2)
+            }
+            ChannelPolicy::KeepOriginal => deinterleave(&audio, channels),
+        };
+
+        let channels = planar.len();
+        let mut sample_rate = sample_rate as u32;
+
+        if let Some(target) = self
+            .target_sample_rate
+            .filter(|target| *target != sample_rate)
+        {
+            self.check_cancelled()?;
+            self.report(
+                ComposeStage::Resampling {
+                    from: sample_rate,
+                    to: target,
+                },
+                None,
+            );
+            planar = planar
+                .iter()
+                .map(|channel| {
+                    resample(
+                        channel,
+                        sample_rate as usize,
+                        target as usize,
+                        ResampleQuality::Balanced,
+                    )
+                })
+                .collect();
+            sample_rate = target;
+        }
+
+        // a throwaway encoder tells us whether libvorbis takes this rate at all, if it doesn't we
+        // resample and try again at one it definitely does. It's set up with a quality every
+        // supported rate takes rather than `self.bitrate`, a bitrate libvorbis refuses has to
+        // come back as an error from the real encoder, not as a pointless resample to 48kHz
+        if self
+            .vorbis_encoder(
+                sample_rate,
+                channels,
+                RATE_PROBE_BITRATE,
+                &[],
+                std::io::sink(),
+            )
+            .is_err()
+        {
+            self.check_cancelled()?;
+            self.report(
+                ComposeStage::Resampling {
+                    from: sample_rate,
+                    to: FALLBACK_SAMPLE_RATE,
+                },
+                None,
+            );
+            planar = planar
+                .iter()
+                .map(|channel| {
+                    resample(
+                        channel,
+                        sample_rate as usize,
+                        FALLBACK_SAMPLE_RATE as usize,
+                        ResampleQuality::Balanced,
+                    )
+                })
+                .collect();
+            sample_rate = FALLBACK_SAMPLE_RATE;
+        }
+
+        if let Some(target) = self.normalize_to_lufs {
+            self.check_cancelled()?;
+            let report = measure_lufs(&interleave(&planar), sample_rate as usize, channels);
+            if report.integrated.is_finite() {
+                // never push the true peak past the ceiling, even if that means staying under
+                // target
+                let gain_db = (target as f64 - report.integrated)
+                    .min(NORMALIZE_TRUE_PEAK_CEILING - report.true_peak);
+                self.report(
+                    ComposeStage::Normalizing {
+                        from_lufs: report.integrated,
+                        to_lufs: report.integrated + gain_db,
+                    },
+                    None,
+                );
+                let gain = 10f64.powf(gain_db / 20.0) as f32;
+                for sample in planar.iter_mut().flatten() {
+                    *sample *= gain;
+                }
+            }
+        }
+
+        // the source's tags, minus the fields that were set explicitly
+        let mut comments: Vec<(String, String)> = source_comments
+            .into_iter()
+            .filter(|(key, _)| {
+                !self
+                    .comments
+                    .iter()
+                    .any(|(explicit, _)| explicit.eq_ignore_ascii_case(key))
+            })
+            .collect();
+        comments.extend(self.comments.iter().cloned());
+
+        Ok((planar, sample_rate, comments))
+    }
+
+    /// Encodes `planar` (one vector per channel, in WAV order) with the bitrate set here and
+    /// writes the Ogg file into `sink`.
+    fn encode_vorbis<W: Write>(
+        &self,
+        planar: &[Vec<f32>],
+        sample_rate: u32,
+        comments: &[(String, String)],
+        sink: W,
+    ) -> Result<(), AvasaraError> {
+        let channels = planar.len();
+        let mut encoder =
+            self.vorbis_encoder(sample_rate, channels, self.bitrate, comments, sink)?;
+
+        // i just guessed chunking the massive f32 buffer into itty bitty 512 element chunks would
+        // work and not segfault when trying to encode, and it did, pretty cool ig. the last chunk
+        // is usually shorter than 512 and that's fine, libvorbis buffers whatever it's given
+        // internally and `finish` flushes the remainder with the exact granule position, so it
+        // must NOT be padded (that would add silence to the end).
+        let planar: Vec<&Vec<f32>> = match vorbis_channel_order(channels) {
+            Some(order) => order.iter().map(|i| &planar[*i]).collect(),
+            None => planar.iter().collect(),
+        };
+        let frames = planar[0].len();
+        let mut tracker = self.track(ComposeStage::Encoding, Some(frames as u64));
+        for start in (0..frames).step_by(512) {
+            self.check_cancelled()?;
+            let block: Vec<&[f32]> = planar
+                .iter()
+                .map(|channel| &channel[start..(start + 512).min(frames)])
+                .collect();
+            encoder.encode_audio_block(&block)?;
+            tracker.update((start + 512) as u64);
+        }
+        encoder.finish()?;
+
+        Ok(())
+    }
+
+    /// Runs an Ogg Vorbis file through OptiVorbis, with `remux_options` and whatever
+    /// `reproducible` asks for on top.
+    fn remux_ogg(&self, ogg: Vec<u8>) -> Result<Vec<u8>, AvasaraError> {
+        let mut out = vec![];
+
+        let mut options = self.remux_options;
+        if self.reproducible {
+            // turn off everything that differs from run to run (or build to build)
+            options.randomize_stream_serials = false;
+            options.stream_serial_offset = self.stream_serial as u32;
+            options.vendor_string = VorbisVendorStringAction::Copy;
+            options.comment_fields = VorbisCommentFieldsAction::Copy;
+        }
+
+        // optivorbis doesn't let us build these settings in one go, so start from the defaults
+        let mut optimizer_settings = VorbisOptimizerSettings::default();
+        optimizer_settings.vendor_string_action = options.vendor_string;
+        optimizer_settings.comment_fields_action = options.comment_fields;
+
+        let remuxer = OggToOgg::new(
+            RemuxSettings {
+                randomize_stream_serials: options.randomize_stream_serials,
+                first_stream_serial_offset: options.stream_serial_offset,
+                ignore_start_sample_offset: options.ignore_start_sample_offset,
+                ..Default::default()
+            },
+            optimizer_settings,
+        );
+        remuxer.remux(&mut Cursor::new(ogg), &mut out)?;
+
+        Ok(out)
+    }
+
+    /// Whether a source with this many channels can go through untouched, none of the options
+    /// that change the audio (or add comments) are set and the channel policy keeps its channels.
+    fn allows_passthrough(&self, channels: Option<usize>) -> bool {
+        let channels_kept = match self.channel_policy {
+            ChannelPolicy::KeepOriginal => true,
+            ChannelPolicy::ForceMono => channels == Some(1),
+            ChannelPolicy::ForceStereo => channels == Some(2),
+        };
+        channels_kept
+            && self.target_sample_rate.is_none()
+            && self.normalize_to_lufs.is_none()
+            && self.max_size.is_none()
+            && self.comments.is_empty()
+    }
+}
+
+/// Encodes audio to Ogg Vorbis as it comes in, made by `ComposeBuilder::stream_encoder`. Every
+/// `push` is encoded right away and the pages libvorbis finishes are written to the sink as they
+/// fill up, `finish` writes the rest and hands the sink back.
+pub struct StreamEncoder<W: Write> {
+    encoder: VorbisEncoder<PageSink<W>>,
+    channels: usize,
+}
+
+impl<W: Write> StreamEncoder<W> {
+    /// Encodes some interleaved samples, any number of whole frames (a trailing partial frame is
+    /// dropped). Blocks don't have to be the same size, libvorbis buffers what it's given.
+    ///
+    /// # Errors
+    /// Returns `AvasaraError::Encode` if encoding or writing a page to the sink fails.
+    pub fn push(&mut self, samples: &[f32]) -> Result<(), AvasaraError> {
+        if samples.len() < self.channels {
+            return Ok(());
+        }
+        let planar = deinterleave(samples, self.channels);
+        let block: Vec<&Vec<f32>> = match vorbis_channel_order(self.channels) {
+            Some(order) => order.iter().map(|i| &planar[*i]).collect(),
+            None => planar.iter().collect(),
+        };
+        self.encoder.encode_audio_block(&block)?;
+        Ok(())
+    }
+
+    /// Ends the stream, writing whatever libvorbis still holds (with the exact final length),
+    /// flushes the sink and returns it.
+    ///
+    /// # Errors
+    /// Returns `AvasaraError::Encode` if encoding or writing the last pages fails, or
+    /// `AvasaraError::Io` if flushing the sink does.
+    pub fn finish(self) -> Result<W, AvasaraError> {
+        let mut sink = self.encoder.finish()?;
+        sink.inner.flush()?;
+        Ok(sink.inner)
+    }
+}
+
+/// What a `StreamEncoder` hands libvorbis to write into, with `soft_start` it flushes the sink
+/// after every page so nothing waits in a buffer along the way.
+struct PageSink<W: Write> {
+    inner: W,
+    flush_every_write: bool,
+}
+
+impl<W: Write> Write for PageSink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if self.flush_every_write {
+            self.inner.flush()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Splits interleaved audio into one vector per channel, any trailing samples that don't make up
+/// a whole frame are dropped.
+fn deinterleave(audio: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    let frames = audio.len() / channels;
+    (0..channels)
+        .map(|ch| {
+            audio
+                .iter()
+                .skip(ch)
+                .step_by(channels)
+                .take(frames)
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+/// The opposite of `deinterleave`, every channel has to be the same length.
+fn interleave(planar: &[Vec<f32>]) -> Vec<f32> {
+    let frames = planar.first().map_or(0, |channel| channel.len());
+    (0..frames)
+        .flat_map(|frame| planar.iter().map(move |channel| channel[frame]))
+        .collect()
+}
+
+/// Which WAV/symphonia channel (FL, FR, FC, LFE, then the surrounds, see `downmix_to_stereo`)
+/// goes into each Vorbis channel, the Vorbis I spec fixes the order for up to 8 channels and it
+/// puts the center between the fronts and the LFE last. `None` means the order is the same (mono,
+/// stereo, quad) or, past 8 channels, undefined, so it's kept as is.
+fn vorbis_channel_order(channels: usize) -> Option<&'static [usize]> {
+    match channels {
+        // L C R
+        3 => Some(&[0, 2, 1]),
+        // FL C FR RL RR
+        5 => Some(&[0, 2, 1, 3, 4]),
+        // FL C FR RL RR LFE
+        6 => Some(&[0, 2, 1, 4, 5, 3]),
+        // FL C FR SL SR RC LFE
+        7 => Some(&[0, 2, 1, 5, 6, 4, 3]),
+        // FL C FR SL SR RL RR LFE
+        8 => Some(&[0, 2, 1, 6, 7, 4, 5, 3]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufWriter;
+    use std::sync::Mutex;
+
+    /// A sink that can still be looked at while an encoder owns it.
+    #[derive(Clone, Default)]
+    struct SharedSink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_bitrate_picks_its_management_strategy() {
+        let strategy = |bitrate: OggBitrate| ComposeBuilder::new().bitrate(bitrate).bitrate;
+
+        assert!(matches!(
+            strategy(OggBitrate::Quality(0.4)),
+            VorbisBitrateManagementStrategy::QualityVbr { target_quality } if target_quality == 0.4
+        ));
+        assert!(matches!(
+            strategy(OggBitrate::Vbr(96000)),
+            VorbisBitrateManagementStrategy::Vbr { target_bitrate } if target_bitrate.get() == 96000
+        ));
+        assert!(matches!(
+            strategy(OggBitrate::Abr(128000)),
+            VorbisBitrateManagementStrategy::Abr { average_bitrate }
+                if average_bitrate.get() == 128000
+        ));
+        assert!(matches!(
+            strategy(OggBitrate::ConstrainedAbr(64000)),
+            VorbisBitrateManagementStrategy::ConstrainedAbr { maximum_bitrate }
+                if maximum_bitrate.get() == 64000
+        ));
+        // nothing can be zero, it's left for libvorbis to refuse
+        assert!(matches!(
+            strategy(OggBitrate::Abr(0)),
+            VorbisBitrateManagementStrategy::Abr { average_bitrate } if average_bitrate.get() == 1
+        ));
+        // and the quality shorthand is replaced, not combined
+        assert!(matches!(
+            ComposeBuilder::new()
+                .quality(0.9)
+                .bitrate(OggBitrate::Abr(64000))
+                .bitrate,
+            VorbisBitrateManagementStrategy::Abr { .. }
+        ));
+    }
+
+    #[test]
+    fn only_options_that_keep_the_audio_allow_passthrough() {
+        let builder = ComposeBuilder::new().passthrough_vorbis(true);
+        // the default folds to mono, so only mono sources are left alone
+        assert!(builder.allows_passthrough(Some(1)));
+        assert!(!builder.allows_passthrough(Some(2)));
+        assert!(!builder.allows_passthrough(None));
+
+        let keep = builder.clone().channels(ChannelPolicy::KeepOriginal);
+        assert!(keep.allows_passthrough(Some(6)));
+        // the bitrate mode and the quality don't matter
+        assert!(keep
+            .clone()
+            .bitrate(OggBitrate::ConstrainedAbr(64000))
+            .allows_passthrough(Some(2)));
+        assert!(!keep.clone().sample_rate(22050).allows_passthrough(Some(2)));
+        assert!(!keep
+            .clone()
+            .normalize_to_lufs(-16.0)
+            .allows_passthrough(Some(2)));
+        assert!(!keep.clone().max_size(100_000).allows_passthrough(Some(2)));
+        assert!(!keep
+            .comment("TITLE", "something else")
+            .allows_passthrough(Some(2)));
+    }
+
+    #[test]
+    fn a_vorbis_source_is_only_remuxed() {
+        let tone: Vec<f32> = (0..44100)
+            .map(|i| (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin() * 0.5)
+            .collect();
+        let vorbis = ComposeBuilder::new()
+            .run_from_samples(tone.clone(), 44100, 1)
+            .unwrap();
+
+        let run = |builder: ComposeBuilder, source: Vec<u8>| {
+            let stages = Arc::new(Mutex::new(vec![]));
+            let log = stages.clone();
+            let ogg = builder
+                .on_progress(move |progress| log.lock().unwrap().push(progress.stage))
+                .run(Cursor::new(source))
+                .unwrap();
+            assert!(ogg.starts_with(b"OggS"));
+            let stages = stages.lock().unwrap().clone();
+            stages
+        };
+
+        assert_eq!(
+            run(
+                ComposeBuilder::new().passthrough_vorbis(true),
+                vorbis.clone()
+            ),
+            [ComposeStage::Remuxing, ComposeStage::Done]
+        );
+        // forcing a re-encode, or a source that isn't Vorbis, decodes as usual
+        for (builder, source) in [
+            (ComposeBuilder::new(), vorbis),
+            (
+                ComposeBuilder::new().passthrough_vorbis(true),
+                crate::encode_wav(&tone, 44100, 1, crate::WavFormat::Pcm16).unwrap(),
+            ),
+        ] {
+            let stages = run(builder, source);
+            assert_eq!(
+                stages.first(),
+                Some(&ComposeStage::Decoding),
+                "{:?}",
+                stages
+            );
+        }
+    }
+
+    #[test]
+    fn the_remux_defaults_are_optivorbis_own() {
+        let ours = RemuxOptions::default();
+        let settings = RemuxSettings::default();
+        let optimizer = VorbisOptimizerSettings::default();
+        assert_eq!(
+            ours.randomize_stream_serials,
+            settings.randomize_stream_serials
+        );
+        assert_eq!(
+            ours.stream_serial_offset,
+            settings.first_stream_serial_offset
+        );
+        assert_eq!(
+            ours.ignore_start_sample_offset,
+            settings.ignore_start_sample_offset
+        );
+        assert_eq!(ours.vendor_string, optimizer.vendor_string_action);
+        assert_eq!(ours.comment_fields, optimizer.comment_fields_action);
+
+        // and setting them is asking for a remux
+        assert!(!ComposeBuilder::new().remux);
+        assert!(ComposeBuilder::new().remux_options(ours).remux);
+    }
+
+    #[test]
+    fn remuxing_keeps_a_fixed_serial_and_can_strip_comments() {
+        let tone: Vec<f32> = (0..44100)
+            .map(|i| (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin() * 0.5)
+            .collect();
+        let ogg = ComposeBuilder::new()
+            .comment("TITLE", "a tone")
+            .remux_options(RemuxOptions {
+                randomize_stream_serials: false,
+                stream_serial_offset: 1234,
+                comment_fields: VorbisCommentFieldsAction::Delete,
+                ..Default::default()
+            })
+            .run_from_samples(tone, 44100, 1)
+            .unwrap();
+
+        // the serial sits in every page header, right after the granule position
+        assert_eq!(u32::from_le_bytes(ogg[14..18].try_into().unwrap()), 1234);
+        let info = probe_info(Cursor::new(ogg)).unwrap();
+        assert!(info.tags.is_empty(), "{:?}", info.tags);
+        // the length survives the remux
+        let duration = info.duration_secs.unwrap();
+        assert!((duration - 1.0).abs() < 1e-6, "{}", duration);
+    }
+
+    #[test]
+    fn soft_start_sends_the_headers_before_the_first_push() {
+        let shared = SharedSink::default();
+        // the buffer would sit on the headers until it's full without the soft start
+        let mut encoder = ComposeBuilder::new()
+            .soft_start(true)
+            .stream_encoder(44100, 2, BufWriter::new(shared.clone()))
+            .unwrap();
+        let headers = shared.0.lock().unwrap().clone();
+        assert!(headers.starts_with(b"OggS"), "{:?}", headers);
+
+        let tone: Vec<f32> = (0..44100 * 2)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect();
+        for block in tone.chunks(4410) {
+            encoder.push(block).unwrap();
+        }
+        encoder.finish().unwrap();
+        let stream = shared.0.lock().unwrap();
+        assert!(stream.starts_with(&headers) && stream.len() > headers.len());
+    }
+
+    #[test]
+    fn a_size_budget_still_meets_the_loudness_target() {
+        // ten seconds of a quiet chord over some hiss, about -30 LUFS
+        let sample_rate = 44100;
+        let mut state = 1u32;
+        let audio: Vec<f32> = (0..sample_rate * 10)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let hiss = (state >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                [220.0, 277.18, 329.63]
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin() * 0.02)
+                    .sum::<f32>()
+                    + hiss * 0.005
+            })
+            .collect();
+
+        let encode = |builder: ComposeBuilder| {
+            builder
+                .normalize_to_lufs(-16.0)
+                .on_progress(|_| {})
+                .run_from_samples(audio.clone(), sample_rate, 1)
+                .unwrap()
+        };
+        let largest = encode(ComposeBuilder::new().quality(1.0)).len();
+        let smallest = encode(ComposeBuilder::new().quality(MIN_QUALITY)).len();
+        let budget = (largest + smallest) / 2;
+
+        let ogg = encode(ComposeBuilder::new().quality(1.0).max_size(budget));
+        assert!(
+            ogg.len() <= budget && ogg.len() > smallest,
+            "{} not within {}..={}",
+            ogg.len(),
+            smallest,
+            budget
+        );
+
+        let decoded = crate::decode_audio(Cursor::new(ogg)).unwrap();
+        let lufs = measure_lufs(
+            decoded.samples(),
+            decoded.sample_rate() as usize,
+            decoded.channels() as usize,
+        )
+        .integrated;
+        assert!((lufs + 16.0).abs() < 1.0, "{}", lufs);
+    }
+
+    /// The granule position of the last page of an Ogg stream, for Vorbis the number of samples
+    /// (per channel) the stream holds.
+    fn last_granule(ogg: &[u8]) -> i64 {
+        let mut at = 0;
+        let mut granule = -1;
+        while at < ogg.len() {
+            assert_eq!(&ogg[at..at + 4], b"OggS", "lost the page sync at {}", at);
+            granule = i64::from_le_bytes(ogg[at + 6..at + 14].try_into().unwrap());
+            let segments = ogg[at + 26] as usize;
+            let body: usize = ogg[at + 27..at + 27 + segments]
+                .iter()
+                .map(|len| *len as usize)
+                .sum();
+            at += 27 + segments + body;
+        }
+        granule
+    }
+
+    #[test]
+    fn a_short_last_block_keeps_the_exact_length() {
+        // not a multiple of the 512 sample blocks the encoder is fed
+        let frames = 44100 + 123;
+        let tone: Vec<f32> = (0..frames).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let ogg = ComposeBuilder::new()
+            .on_progress(|_| {})
+            .run_from_samples(tone, 44100, 1)
+            .unwrap();
+
+        assert_eq!(last_granule(&ogg), frames as i64);
+        // without gapless trimming the decoder hands over the padding of the last block too, but
+        // never less than what went in
+        let decoded = crate::decode_audio(Cursor::new(ogg)).unwrap();
+        assert!(
+            decoded.frames() >= frames && decoded.frames() < frames + 2048,
+            "{}",
+            decoded.frames()
+        );
+    }
+
+    #[test]
+    fn cancelling_stops_the_encode_partway() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let reported = Arc::new(Mutex::new(vec![]));
+        let log = reported.clone();
+        let tone: Vec<f32> = (0..44100 * 10).map(|i| (i as f32 * 0.05).sin()).collect();
+
+        let result = ComposeBuilder::new()
+            .cancel_flag(cancel)
+            .on_progress(move |progress| {
+                if progress.stage == ComposeStage::Encoding {
+                    let percent = progress.percent.unwrap();
+                    log.lock().unwrap().push(percent);
+                    if percent >= 10.0 {
+                        flag.store(true, Ordering::Relaxed);
+                    }
+                }
+            })
+            .run_from_samples(tone, 44100, 1);
+
+        assert!(
+            matches!(result, Err(AvasaraError::Cancelled)),
+            "{:?}",
+            result
+        );
+        // the next block is where it stops, well before the end
+        let reported = reported.lock().unwrap();
+        assert!(*reported.last().unwrap() < 12.0, "{:?}", reported);
+    }
+
+    #[test]
+    fn reproducible_encodes_are_byte_identical() {
+        let tone: Vec<f32> = (0..44100 * 2)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect();
+        let encode = || {
+            ComposeBuilder::new()
+                .reproducible(true)
+                .comment("TITLE", "tone")
+                .comment("ARTIST", "avasara")
+                .on_progress(|_| {})
+                .run_from_samples(tone.clone(), 44100, 1)
+                .unwrap()
+        };
+
+        let first = encode();
+        assert!(first.starts_with(b"OggS"));
+        assert!(first == encode(), "two encodes of the same input differ");
+    }
+
+    #[test]
+    fn a_refused_bitrate_is_an_error_and_not_a_resample() {
+        let tone: Vec<f32> = (0..22050)
+            .map(|i| (std::f32::consts::TAU * 1000.0 * i as f32 / 44100.0).sin() * 0.5)
+            .collect();
+        let wav = crate::encode_wav(&tone, 44100, 1, crate::WavFormat::Float32).unwrap();
+        let stages = Arc::new(Mutex::new(vec![]));
+        let log = stages.clone();
+
+        let result = ComposeBuilder::new()
+            .bitrate(OggBitrate::Abr(1))
+            .on_progress(move |progress| log.lock().unwrap().push(progress.stage))
+            .run_reporting_rate(Cursor::new(wav));
+        assert!(
+            matches!(result, Err(AvasaraError::Encode(_))),
+            "{:?}",
+            result
+        );
+        let stages = stages.lock().unwrap();
+        assert!(
+            !stages
+                .iter()
+                .any(|stage| matches!(stage, ComposeStage::Resampling { .. })),
+            "{:?}",
+            stages
+        );
+    }
+
+    #[test]
+    fn an_unencodable_rate_is_resampled_and_reported() {
+        let encode = |sample_rate: usize| {
+            let tone: Vec<f32> = (0..sample_rate / 2)
+                .map(|i| {
+                    (std::f32::consts::TAU * 1000.0 * i as f32 / sample_rate as f32).sin() * 0.5
+                })
+                .collect();
+            let wav = crate::encode_wav(&tone, sample_rate, 1, crate::WavFormat::Float32).unwrap();
+            let stages = Arc::new(Mutex::new(vec![]));
+            let log = stages.clone();
+            let (ogg, rate) = ComposeBuilder::new()
+                .on_progress(move |progress| log.lock().unwrap().push(progress.stage))
+                .run_reporting_rate(Cursor::new(wav))
+                .unwrap();
+            assert!(ogg.starts_with(b"OggS"));
+            let stages = stages.lock().unwrap().clone();
+            (rate, stages)
+        };
+
+        // well past the 200kHz libvorbis takes
+        let (rate, stages) = encode(384000);
+        assert_eq!(rate, FALLBACK_SAMPLE_RATE);
+        assert!(
+            stages.contains(&ComposeStage::Resampling {
+                from: 384000,
+                to: FALLBACK_SAMPLE_RATE
+            }),
+            "{:?}",
+            stages
+        );
+
+        // an odd but encodable rate is left alone
+        let (rate, stages) = encode(22050);
+        assert_eq!(rate, 22050);
+        assert!(
+            !stages
+                .iter()
+                .any(|stage| matches!(stage, ComposeStage::Resampling { .. })),
+            "{:?}",
+            stages
+        );
+    }
+
+    #[test]
+    fn stream_encoder_refuses_zero_channels() {
+        assert!(matches!(
+            ComposeBuilder::new().stream_encoder(44100, 0, vec![]),
+            Err(AvasaraError::UnsupportedChannels(0))
+        ));
+    }
+
+    #[test]
+    fn normalization_meets_the_target_under_the_peak_ceiling() {
+        // a 1kHz tone at 0.05, around -29 LUFS
+        let tone: Vec<f32> = (0..48000 * 3)
+            .map(|i| (std::f32::consts::TAU * 1000.0 * i as f32 / 48000.0).sin() * 0.05)
+            .collect();
+        let normalize = |target: f32| {
+            let stages = Arc::new(Mutex::new(vec![]));
+            let log = stages.clone();
+            let (planar, sample_rate, _) = ComposeBuilder::new()
+                .normalize_to_lufs(target)
+                .on_progress(move |progress| log.lock().unwrap().push(progress.stage))
+                .prepare((tone.clone(), 48000, 1), vec![])
+                .unwrap();
+            let report = measure_lufs(&planar[0], sample_rate as usize, 1);
+            let stages = stages.lock().unwrap().clone();
+            (report, stages)
+        };
+
+        let (report, stages) = normalize(-16.0);
+        assert!((report.integrated + 16.0).abs() < 0.1, "{:?}", report);
+        assert!(
+            stages.iter().any(|stage| matches!(
+                stage,
+                ComposeStage::Normalizing { to_lufs, .. } if (to_lufs + 16.0).abs() < 0.1
+            )),
+            "{:?}",
+            stages
+        );
+
+        // a sine peaks about 3dB over its loudness, so -2 LUFS would clip, the ceiling wins
+        let (report, _) = normalize(-2.0);
+        assert!(
+            (report.true_peak - NORMALIZE_TRUE_PEAK_CEILING).abs() < 0.1,
+            "{:?}",
+            report
+        );
+        assert!(report.integrated < -3.0, "{:?}", report);
+    }
+
+    #[test]
+    fn a_run_reports_its_stages_in_order() {
+        let stereo: Vec<f32> = (0..44100)
+            .flat_map(|i| {
+                let s = (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin() * 0.5;
+                [s, s]
+            })
+            .collect();
+        let wav = crate::encode_wav(&stereo, 44100, 2, crate::WavFormat::Pcm16).unwrap();
+        let reported = Arc::new(Mutex::new(vec![]));
+        let log = reported.clone();
+
+        let ogg = ComposeBuilder::new()
+            .quality(0.3)
+            .remux(true)
+            .on_progress(move |progress| log.lock().unwrap().push(progress))
+            .run(Cursor::new(wav))
+            .unwrap();
+        assert!(ogg.starts_with(b"OggS"));
+
+        let reported = reported.lock().unwrap();
+        let mut stages: Vec<ComposeStage> = reported.iter().map(|p| p.stage).collect();
+        stages.dedup();
+        assert_eq!(
+            stages,
+            [
+                ComposeStage::Decoding,
+                ComposeStage::Interleaving,
+                ComposeStage::Encoding,
+                ComposeStage::Remuxing,
+                ComposeStage::Done
+            ]
+        );
+        // the WAV header declares its length, so decoding counts up to a full 100%
+        let decoding: Vec<f32> = reported
+            .iter()
+            .filter(|p| p.stage == ComposeStage::Decoding)
+            .map(|p| p.percent.unwrap())
+            .collect();
+        assert_eq!((decoding[0], *decoding.last().unwrap()), (0.0, 100.0));
+        assert!(decoding.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn explicit_comments_replace_the_copied_ones() {
+        let source = vec![
+            ("ARTIST".to_string(), "someone".to_string()),
+            ("TITLE".to_string(), "old title".to_string()),
+            ("ALBUM".to_string(), "an album".to_string()),
+        ];
+        let (_, _, comments) = ComposeBuilder::new()
+            .copy_tags(true)
+            .comment("title", "new title")
+            .comments([("ARTIST", "a second artist")])
+            .on_progress(|_| {})
+            .prepare((vec![0.0; 4410], 44100, 1), source)
+            .unwrap();
+
+        let pairs: Vec<(&str, &str)> = comments
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        // the source's first, minus what was set here (whatever the case), then the explicit
+        // ones in the order they were added
+        assert_eq!(
+            pairs,
+            [
+                ("ALBUM", "an album"),
+                ("title", "new title"),
+                ("ARTIST", "a second artist")
+            ]
+        );
+    }
+
+    #[test]
+    fn the_channel_policy_shapes_what_gets_encoded() {
+        let shape = |policy: ChannelPolicy, audio: Vec<f32>, channels: usize| {
+            ComposeBuilder::new()
+                .channels(policy)
+                .on_progress(|_| {})
+                .prepare((audio, 44100, channels), vec![])
+                .unwrap()
+                .0
+        };
+        // left and right told apart by their level
+        let stereo: Vec<f32> = (0..4410).flat_map(|_| [0.5, -0.25]).collect();
+
+        let kept = shape(ChannelPolicy::KeepOriginal, stereo.clone(), 2);
+        assert_eq!(kept, [vec![0.5; 4410], vec![-0.25; 4410]]);
+        assert_eq!(shape(ChannelPolicy::ForceMono, stereo, 2).len(), 1);
+
+        let mono = vec![0.5; 4410];
+        assert_eq!(
+            shape(ChannelPolicy::ForceStereo, mono.clone(), 1),
+            [mono.clone(), mono]
+        );
+        let surround = shape(ChannelPolicy::ForceStereo, vec![0.1; 6 * 4410], 6);
+        assert_eq!(surround.len(), 2);
+        assert!(surround.iter().all(|channel| channel.len() == 4410));
+        // 5.1 folds down to mono too, with the ITU gains rather than being refused
+        let frame = [0.1, 0.1, 0.2, 0.9, 0.1, 0.1];
+        let surround: Vec<f32> = (0..4410).flat_map(|_| frame).collect();
+        let mono = shape(ChannelPolicy::ForceMono, surround.clone(), 6);
+        assert_eq!(
+            mono,
+            [downmix_to_mono(&surround, 6, DownmixStrategy::Itu)],
+            "{:?}",
+            &mono[0][..4]
+        );
+        assert_eq!(mono[0].len(), 4410);
+        assert!(matches!(
+            ComposeBuilder::new()
+                .channels(ChannelPolicy::KeepOriginal)
+                .prepare((vec![0.0; 256], 44100, 256), vec![]),
+            Err(AvasaraError::UnsupportedChannels(256))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::PitchPoint;
+
+/// How far (in octaves) a point can be from exactly one octave off its local median and still
+/// count as an octave error, a sixth of an octave is two semitones.
+const OCTAVE_TOLERANCE: f32 = 1.0 / 6.0;
+
+/// How `smooth_contour` (and `AnalyzeOptions::smoothing`) cleans up a pitch contour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContourSmoothing {
+    /// How many points either side of every point make up its neighborhood, so the local median
+    /// is taken over `2 * neighbors + 1` points. Wider holds up against longer runs of bad
+    /// points, but smears fast runs of notes together.
+    pub neighbors: usize,
+    /// Whether to snap points that sit an octave above or below the local median back to it
+    /// first, see `correct_octave_errors`.
+    pub correct_octaves: bool,
+    /// Whether to run a median filter over the (corrected) contour, see `median_filter_contour`.
+    pub median_filter: bool,
+}
+
+impl Default for ContourSmoothing {
+    /// 3 neighbors either side (a 7 point median), octave correction and median filtering both on.
+    fn default() -> Self {
+        ContourSmoothing {
+            neighbors: 3,
+            correct_octaves: true,
+            median_filter: true,
+        }
+    }
+}
+
+/// Median of every point's neighborhood of frequencies, up to `neighbors` either side.
+fn local_medians(frequencies: &[f32], neighbors: usize) -> Vec<f32> {
+    (0..frequencies.len())
+        .map(|i| {
+            let mut window = frequencies
+                [i.saturating_sub(neighbors)..(i + neighbors + 1).min(frequencies.len())]
+                .to_vec();
+            window.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let mid = window.len() / 2;
+            if window.len().is_multiple_of(2) {
+                (window[mid - 1] + window[mid]) / 2.0
+            } else {
+                window[mid]
+            }
+        })
+        .collect()
+}
+
+/// `smooth_contour` on bare frequencies, in chronological order, shared with the analysis
+/// functions which don't keep the times around.
+pub(crate) fn smooth_frequencies(frequencies: &[f32], smoothing: &ContourSmoothing) -> Vec<f32> {
+    let mut frequencies = frequencies.to_vec();
+
+    if smoothing.correct_octaves {
+        let medians = local_medians(&frequencies, smoothing.neighbors);
+        for (frequency, median) in frequencies.iter_mut().zip(medians) {
+            if (*frequency <= 0.0) || (median <= 0.0) {
+                continue;
+            }
+            let octaves = (*frequency / median).log2();
+            if (octaves - 1.0).abs() < OCTAVE_TOLERANCE {
+                *frequency /= 2.0;
+            } else if (octaves + 1.0).abs() < OCTAVE_TOLERANCE {
+                *frequency *= 2.0;
+            }
+        }
+    }
+
+    if smoothing.median_filter {
+        frequencies = local_medians(&frequencies, smoothing.neighbors);
+    }
+
+    frequencies
+}
+
+/// Replaces every frequency with `f(frequencies)`, keeping the times and clarities.
+fn with_frequencies(points: &[PitchPoint], f: impl Fn(&[f32]) -> Vec<f32>) -> Vec<PitchPoint> {
+    let frequencies: Vec<f32> = points.iter().map(|p| p.frequency).collect();
+    points
+        .iter()
+        .zip(f(&frequencies))
+        .map(|(point, frequency)| PitchPoint {
+            frequency,
+            ..*point
+        })
+        .collect()
+}
+
+/// Fixes the octave jumps pitch detectors are prone to (YIN locking onto the second harmonic for
+/// a window or two, or onto a subharmonic under a weak fundamental), every point within two
+/// semitones of exactly an octave above the median of its neighborhood (up to `neighbors` points
+/// either side) is halved, and every one an octave below it doubled, everything else is left
+/// alone. Works on points in chronological order, like `analyze_pitch_contour` gives them, and
+/// neighborhoods reach across unvoiced gaps, so a real octave leap right after a pause can get
+/// "corrected" if the notes before the pause outnumber the ones after it, keep `neighbors` small
+/// next to the length of the notes.
+pub fn correct_octave_errors(points: &[PitchPoint], neighbors: usize) -> Vec<PitchPoint> {
+    smooth_contour(
+        points,
+        &ContourSmoothing {
+            neighbors,
+            correct_octaves: true,
+            median_filter: false,
+        },
+    )
+}
+
+/// Replaces every point's frequency with the median of its neighborhood (up to `neighbors` points
+/// either side), which irons out single-window glitches while keeping the steps between notes
+/// sharp, unlike a moving average. Times and clarities are kept as they are.
+pub fn median_filter_contour(points: &[PitchPoint], neighbors: usize) -> Vec<PitchPoint> {
+    smooth_contour(
+        points,
+        &ContourSmoothing {
+            neighbors,
+            correct_octaves: false,
+            median_filter: true,
+        },
+    )
+}
+
+/// Cleans up a raw pitch contour (chronological `PitchPoint`s like `analyze_pitch_contour` gives)
+/// the way `smoothing` says, octave errors are corrected first (`correct_octave_errors`) so they
+/// don't drag the median filter (`median_filter_contour`) around after. Returns the same number of
+/// points with the same times and clarities, only the frequencies change. The same thing can be
+/// done during analysis with `AnalyzeOptions::smoothing`.
+pub fn smooth_contour(points: &[PitchPoint], smoothing: &ContourSmoothing) -> Vec<PitchPoint> {
+    with_frequencies(points, |frequencies| {
+        smooth_frequencies(frequencies, smoothing)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A contour with a point every 10ms at these frequencies, the clarity counting up so it's
+    /// easy to tell points apart.
+    fn contour(frequencies: &[f32]) -> Vec<PitchPoint> {
+        frequencies
+            .iter()
+            .enumerate()
+            .map(|(i, frequency)| PitchPoint {
+                time_secs: i as f64 * 0.01,
+                frequency: *frequency,
+                clarity: i as f32 / 100.0,
+            })
+            .collect()
+    }
+
+    fn frequencies(points: &[PitchPoint]) -> Vec<f32> {
+        points.iter().map(|p| p.frequency).collect()
+    }
+
+    #[test]
+    fn octave_jumps_snap_back_and_everything_else_stays() {
+        let mut raw = vec![220.0; 20];
+        // YIN on the second harmonic, on a subharmonic, and a point a fifth up that's real
+        raw[5] = 440.0;
+        raw[6] = 445.0;
+        raw[12] = 110.0;
+        raw[16] = 330.0;
+
+        let corrected = correct_octave_errors(&contour(&raw), 3);
+        let mut expected = vec![220.0; 20];
+        expected[6] = 222.5;
+        expected[16] = 330.0;
+        assert_eq!(frequencies(&corrected), expected);
+        // only the frequencies change
+        for (before, after) in contour(&raw).iter().zip(&corrected) {
+            assert_eq!(
+                (before.time_secs, before.clarity),
+                (after.time_secs, after.clarity)
+            );
+        }
+    }
+
+    #[test]
+    fn the_median_filter_drops_glitches_but_keeps_steps() {
+        let mut raw = vec![200.0; 10];
+        raw.extend([300.0; 10]);
+        raw[4] = 263.0;
+        raw[14] = 150.0;
+
+        let filtered = median_filter_contour(&contour(&raw), 2);
+        let mut expected = vec![200.0; 10];
+        expected.extend([300.0; 10]);
+        assert_eq!(frequencies(&filtered), expected);
+    }
+
+    #[test]
+    fn smoothing_corrects_octaves_before_filtering() {
+        // a slow glide with a few octave errors and a glitch in it
+        let mut raw: Vec<f32> = (0..30).map(|i| 150.0 + i as f32).collect();
+        raw[6] *= 2.0;
+        raw[7] *= 2.0;
+        raw[20] /= 2.0;
+        raw[25] = 400.0;
+        let points = contour(&raw);
+
+        let smoothed = smooth_contour(&points, &ContourSmoothing::default());
+        assert_eq!(
+            smoothed,
+            median_filter_contour(&correct_octave_errors(&points, 3), 3)
+        );
+        // which leaves the glide itself, within a step of where it was (a glitch nudges the
+        // median of its neighbors over by one), away from the ends
+        for (i, point) in smoothed.iter().enumerate().skip(3).take(24) {
+            assert!(
+                (point.frequency - (150.0 + i as f32)).abs() <= 1.0,
+                "{} {}",
+                i,
+                point.frequency
+            );
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use pitch_detection::detector::yin::YINDetector;
+
+use crate::filters::{filter_interleaved, Biquad};
+use crate::vad::Segment;
+use crate::{dominant_frequency, spectrum, yin_pitch};
+
+/// Where a frequency ends up after sampling at `sample_rate`, anything above Nyquist folds back
+/// down into the audible band like a reflection.
+fn folded(frequency: f32, sample_rate: f32) -> f32 {
+    let wrapped = frequency % sample_rate;
+    if wrapped > sample_rate / 2.0 {
+        sample_rate - wrapped
+    } else {
+        wrapped
+    }
+}
+
+/// Estimates how likely it is that some mono audio data contains aliasing (foldover), the kind of
+/// damage left behind by resampling without a proper anti-aliasing filter or by synthesizers
+/// generating waveforms naively. It finds the strongest spectral peaks, works out the harmonic
+/// series they belong to, and then counts how many of the remaining peaks sit exactly where that
+/// series' harmonics *above* Nyquist would land after being reflected back down, clean material
+/// has nothing there, aliased material has a whole mirrored comb of them. Returns a suspicion
+/// score from 0.0 (no sign of aliasing) to 1.0 (most peaks are foldover).
+///
+/// Only meaningful for pitched material with a clear harmonic series (instruments, synths, test
+/// tones), noise or dense mixes will mostly read 0.0.
+pub fn detect_aliasing(audio: &[f32], sample_rate: usize) -> f32 {
+    let frame_len = 8192;
+    let fs = sample_rate as f32;
+    let nyquist = fs / 2.0;
+    let magnitudes = spectrum::average_magnitude_spectrum(audio, frame_len);
+    let peaks: Vec<f32> = spectrum::spectral_peaks(&magnitudes, sample_rate, frame_len, -60.0)
+        .into_iter()
+        .map(|(freq, _)| freq)
+        .filter(|freq| *freq > 20.0)
+        .take(60)
+        .collect();
+    if peaks.len() < 2 {
+        return 0.0;
+    }
+
+    let tolerance = 2.0 * fs / frame_len as f32;
+    let near = |a: f32, b: f32| (a - b).abs() <= tolerance;
+
+    // (harmonic peaks, foldover peaks) explained by a given fundamental
+    let explain = |f0: f32| -> (usize, usize) {
+        let mut harmonic = 0;
+        let mut foldover = 0;
+        for peak in &peaks {
+            let n = (peak / f0).round();
+            if n >= 1.0 && near(*peak, n * f0) {
+                harmonic += 1;
+                continue;
+            }
+            let max_harmonic = (2.0 * fs / f0) as usize;
+            let first_above = (nyquist / f0).floor() as usize + 1;
+            if (first_above..=max_harmonic).any(|k| near(*peak, folded(k as f32 * f0, fs))) {
+                foldover += 1;
+            }
+        }
+        (harmonic, foldover)
+    };
+
+    // the fundamental is usually the strongest peak, or a low multiple below it
+    let (harmonic, foldover) = peaks
+        .iter()
+        .take(3)
+        .flat_map(|peak| (1..=3).map(move |d| peak / d as f32))
+        .filter(|f0| *f0 >= 20.0)
+        .map(explain)
+        .max_by_key(|(harmonic, foldover)| harmonic + foldover)
+        .unwrap_or((0, 0));
+
+    // a lone tone can't tell us anything about where its harmonics would fold
+    if harmonic < 2 {
+        return 0.0;
+    }
+
+    foldover as f32 / (harmonic + foldover) as f32
+}
+
+/// Center frequencies (in Hz) of the octave bands used by `spectral_difference` at a given sample
+/// rate, starting at 31.25Hz and doubling until Nyquist, each band spans from half an octave below
+/// its center to half an octave above it (the first band reaches down to 0Hz and the last one all
+/// the way up to Nyquist).
+pub fn octave_band_centers(sample_rate: usize) -> Vec<f32> {
+    let nyquist = sample_rate as f32 / 2.0;
+    (0..)
+        .map(|i| 31.25 * 2f32.powi(i))
+        .take_while(|center| *center < nyquist)
+        .collect()
+}
+
+/// Compares where in frequency two versions of the same mono audio data differ (an original and
+/// its transcode, before and after some processing), returning one value per octave band (see
+/// `octave_band_centers` for the layout) holding the average level difference in dB between `a`
+/// and `b` over the whole signal, positive means `b` has less energy than `a` in that band, so a
+/// lossy encode that threw away the top end shows up as large positive values in the highest
+/// bands and ~0.0 everywhere else. The inputs should be time aligned, only their overlapping
+/// length is compared.
+pub fn spectral_difference(a: &[f32], b: &[f32], sample_rate: usize) -> Vec<f32> {
+    let len = a.len().min(b.len());
+    let frame_len = 4096;
+    let bin_hz = sample_rate as f32 / frame_len as f32;
+
+    let centers = octave_band_centers(sample_rate);
+    let bands: Vec<(usize, usize)> = centers
+        .iter()
+        .enumerate()
+        .map(|(i, center)| {
+            let low = if i == 0 {
+                0
+            } else {
+                (center / std::f32::consts::SQRT_2 / bin_hz).ceil() as usize
+            };
+            let high = if i + 1 == centers.len() {
+                frame_len / 2 + 1
+            } else {
+                (center * std::f32::consts::SQRT_2 / bin_hz).ceil() as usize
+            };
+            (low, high.min(frame_len / 2 + 1))
+        })
+        .collect();
+
+    let frames_a = spectrum::stft_magnitudes(&a[..len], frame_len, frame_len / 2);
+    let frames_b = spectrum::stft_magnitudes(&b[..len], frame_len, frame_len / 2);
+
+    let band_db = |frame: &[f32], (low, high): (usize, usize)| {
+        let energy: f32 = frame[low..high].iter().map(|m| m * m).sum();
+        10.0 * energy.max(1e-10).log10()
+    };
+
+    bands
+        .iter()
+        .map(|band| {
+            let total: f32 = frames_a
+                .iter()
+                .zip(&frames_b)
+                .map(|(fa, fb)| band_db(fa, *band) - band_db(fb, *band))
+                .sum();
+            total / frames_a.len() as f32
+        })
+        .collect()
+}
+
+/// Speed instability of an analog transfer, returned by `wow_and_flutter`, both figures are the
+/// peak frequency deviation in percent of `reference_hz` (so a tape whose pitch swings by ±0.3% a
+/// couple of times a second reads `wow_percent: 0.3`), estimated as √2 times the RMS deviation,
+/// which is exact for a sinusoidal wobble.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WowFlutterReport {
+    /// Average frequency of the reference tone in Hz.
+    pub reference_hz: f32,
+    /// Slow drift, everything below 4Hz (warped records, stretched tape, off-center spindles).
+    pub wow_percent: f32,
+    /// Fast jitter, 4Hz and up (capstan and motor irregularities, scrape flutter).
+    pub flutter_percent: f32,
+}
+
+/// Measures wow and flutter, the pitch instability that tape decks and turntables leave on a
+/// digitized recording, by tracking the frequency of a steady tone every 2.5ms with YIN and
+/// splitting its deviation from the average into the slow (< 4Hz, wow) and fast (>= 4Hz,
+/// flutter) parts. Flutter can be tracked up to about 200Hz.
+///
+/// This only means something on a *steady reference tone* (a test tone recorded onto the tape,
+/// like the standard 3150Hz one, or at least a long sustained note) somewhere between 100Hz and a
+/// few kHz, on music the melody itself reads as massive wow. Give it a few seconds of tone, the
+/// first and last half second are ignored while the filters settle. Returns `None` if there's no
+/// trackable tone (or not enough of it).
+pub fn wow_and_flutter(audio: &[f32], sample_rate: usize) -> Option<WowFlutterReport> {
+    let frame_len = sample_rate / 50;
+    let hop = (sample_rate / 400).max(1);
+    if frame_len < 4 || audio.len() < frame_len {
+        return None;
+    }
+    let contour_rate = (sample_rate as f64 / hop as f64).round() as usize;
+
+    let mut detector = YINDetector::new(frame_len, frame_len / 2);
+    let mut contour: Vec<f32> = Vec::with_capacity((audio.len() - frame_len) / hop + 1);
+    let mut last = None;
+    for start in (0..=audio.len() - frame_len).step_by(hop) {
+        let frame = &audio[start..start + frame_len];
+        // a dropout shouldn't show up as a huge deviation, hold the last good value instead
+        match yin_pitch(&mut detector, frame, sample_rate) {
+            Some((freq, clarity)) if clarity >= 0.8 => last = Some(freq),
+            _ => {}
+        }
+        if let Some(freq) = last {
+            contour.push(freq);
+        }
+    }
+
+    let settle = contour_rate / 2;
+    if contour.len() < 4 * settle {
+        return None;
+    }
+
+    let mean = contour.iter().map(|f| *f as f64).sum::<f64>() / contour.len() as f64;
+    let deviation: Vec<f32> = contour
+        .iter()
+        .map(|f| (*f as f64 / mean - 1.0) as f32)
+        .collect();
+
+    let butterworth = std::f64::consts::FRAC_1_SQRT_2;
+    let wow = filter_interleaved(
+        &deviation,
+        1,
+        &[Biquad::lowpass(contour_rate, 4.0, butterworth); 2],
+    );
+    let flutter = filter_interleaved(
+        &deviation,
+        1,
+        &[Biquad::highpass(contour_rate, 4.0, butterworth); 2],
+    );
+
+    let peak_percent = |signal: &[f32]| {
+        let settled = &signal[settle..signal.len() - settle];
+        let mean_square =
+            settled.iter().map(|d| (*d as f64).powi(2)).sum::<f64>() / settled.len() as f64;
+        (2.0 * mean_square).sqrt() as f32 * 100.0
+    };
+
+    Some(WowFlutterReport {
+        reference_hz: mean as f32,
+        wow_percent: peak_percent(&wow),
+        flutter_percent: peak_percent(&flutter),
+    })
+}
+
+/// Calibration tones `verify_sample_rate` knows about, in Hz, 440 (A4), 997 and 1000 (the usual
+/// digital/analog test tones) and 3150 (the wow and flutter standard).
+pub const REFERENCE_TONES: [f32; 4] = [440.0, 997.0, 1000.0, 3150.0];
+
+/// How far (as a fraction) a measured tone may be off a `REFERENCE_TONES` entry and still count
+/// as it, 0.1% allows for a slightly off speed on the machine that played the tape while leaving
+/// a clear gap between 997 and 1000Hz, which are only 0.3% apart.
+const REFERENCE_TOLERANCE: f32 = 0.001;
+
+/// Sample rates `verify_sample_rate` considers as corrections.
+const COMMON_RATES: [usize; 12] = [
+    8000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+
+/// Checks a file's declared sample rate against a calibration tone recorded in it, for archival
+/// work where a header occasionally lies (a 48kHz transfer tagged as 44.1kHz plays back ~9% flat,
+/// one tagged at double its rate plays an octave up). The strongest frequency in the audio is
+/// measured assuming `declared_rate`, and if it isn't one of the `REFERENCE_TONES` every common
+/// rate (8kHz to 192kHz) is tried to see which one would turn it into one.
+///
+/// Returns the sample rate the tone says the audio really has, `Some(declared_rate)` if the
+/// header checks out, or `None` if no rate lines the strongest frequency up with a reference tone
+/// (there isn't one in the audio, or it's buried under other material). This needs a *known
+/// reference tone* to mean anything, feed it the calibration tone at the head of a tape transfer
+/// rather than the whole program, on arbitrary music it'll almost always say `None`.
+pub fn verify_sample_rate(audio: &[f32], declared_rate: usize) -> Option<usize> {
+    let measured = dominant_frequency(audio, declared_rate)?;
+    let matches_reference = |freq: f32| {
+        REFERENCE_TONES
+            .iter()
+            .map(|tone| (freq / tone - 1.0).abs())
+            .filter(|error| *error < REFERENCE_TOLERANCE)
+            .reduce(f32::min)
+    };
+
+    if matches_reference(measured).is_some() {
+        return Some(declared_rate);
+    }
+
+    COMMON_RATES
+        .iter()
+        .filter_map(|rate| {
+            let actual = measured * *rate as f32 / declared_rate as f32;
+            matches_reference(actual).map(|error| (*rate, error))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(rate, _)| rate)
+}
+
+/// Estimates the reverberation time (RT60, the time in seconds it takes the sound energy of a room
+/// to die down by 60dB) from a recording of something decaying in it, ideally an impulse (a
+/// clap, a balloon pop, a starter pistol) or a loud tone that's cut off abruptly. Starting at the
+/// loudest point, the energy decay curve is computed with Schroeder backward integration (which
+/// smooths out the random fluctuations of the tail), a straight line is fitted to it between -5dB
+/// and -25dB and extrapolated to 60dB (the T20 method, since real recordings rarely have 60dB of
+/// decay above the noise floor). The average energy of the last tenth of the recording is taken
+/// as the noise floor and subtracted before integrating, so leave some room tone after the decay.
+///
+/// Returns `None` if there's no clear decay, meaning the curve never drops 25dB or the drop isn't
+/// a straight enough line (music, speech, or a decay buried in noise).
+pub fn estimate_rt60(audio: &[f32], sample_rate: usize) -> Option<f32> {
+    let (peak, _) = audio.iter().enumerate().fold((0, 0.0f32), |best, (i, s)| {
+        if s.abs() > best.1 {
+            (i, s.abs())
+        } else {
+            best
+        }
+    });
+    let tail = &audio[peak..];
+    if tail.len() < 10 {
+        return None;
+    }
+
+    let noise_floor = {
+        let last = &tail[tail.len() - tail.len() / 10..];
+        last.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / last.len().max(1) as f64
+    };
+
+    // Schroeder integral, from the end backwards
+    let mut decay: Vec<f64> = tail
+        .iter()
+        .rev()
+        .scan(0.0, |sum, s| {
+            *sum += ((*s as f64).powi(2) - noise_floor).max(0.0);
+            Some(*sum)
+        })
+        .collect();
+    decay.reverse();
+    let total = decay[0];
+    if total <= 0.0 {
+        return None;
+    }
+
+    // (time, level in dB) pairs from -5dB to -25dB
+    let points: Vec<(f64, f64)> = decay
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (i as f64 / sample_rate as f64, 10.0 * (e / total).log10()))
+        .skip_while(|(_, db)| *db > -5.0)
+        .take_while(|(_, db)| *db >= -25.0)
+        .collect();
+    let reached_bottom = decay.iter().any(|e| 10.0 * (e / total).log10() < -25.0);
+    if points.len() < 10 || !reached_bottom {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_db = points.iter().map(|(_, db)| db).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(t, db)| (t - mean_t) * (db - mean_db))
+        .sum();
+    let variance_t: f64 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+    let variance_db: f64 = points.iter().map(|(_, db)| (db - mean_db).powi(2)).sum();
+    let slope = covariance / variance_t;
+
+    // a real decay is a falling straight line in dB, anything bumpier isn't one
+    let r_squared = covariance.powi(2) / (variance_t * variance_db);
+    if slope >= 0.0 || r_squared < 0.95 {
+        return None;
+    }
+
+    Some((-60.0 / slope) as f32)
+}
+
+/// Estimates how many bits of real resolution some decoded audio carries, for catching "hi-res"
+/// files that are really 16-bit material padded out to 24 (or 8-bit material in a 16-bit file).
+/// Decoding maps an n-bit integer sample to a float that's an exact multiple of 2^-(n-1), and
+/// padding a lower resolution file just appends zero bits, so every sample is scaled back up to
+/// 24-bit integers and the lowest bit that's set in *any* of them gives the depth away: if the
+/// bottom 8 bits are zero throughout, only 16 of the 24 bits were ever used.
+///
+/// The result is between 1 and 24, or 0 for complete silence. This only works on audio straight
+/// out of the decoder, any gain change, dithering, resampling or lossy encoding fills the low bits
+/// back in (and the answer goes up to 24), and floating point sources with values that aren't on
+/// the 24-bit grid also read as 24. A single quiet stretch can't fool it, but a file that's
+/// genuinely 24-bit and happens to have a very low noise floor isn't distinguishable from one
+/// that was padded either, so treat a low result as strong evidence and 24 as "not obviously fake".
+pub fn effective_bit_depth(audio: &[f32]) -> u32 {
+    let scale = (1u32 << 23) as f64;
+    let mut used_bits = 0u32;
+
+    for sample in audio {
+        let scaled = *sample as f64 * scale;
+        if scaled.fract() != 0.0 {
+            return 24;
+        }
+        used_bits |= (scaled.abs() as u64).min(u32::MAX as u64) as u32;
+    }
+
+    if used_bits == 0 {
+        0
+    } else {
+        24u32.saturating_sub(used_bits.trailing_zeros()).max(1)
+    }
+}
+
+/// What `detect_clipping` counts as clipping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClippingOptions {
+    /// Samples at or above this absolute value (linear, 1.0 is full scale) are at the ceiling,
+    /// just under 1.0 by default so the rounding of a 16 bit or lossy decode doesn't hide them.
+    pub threshold: f32,
+    /// How many consecutive samples of a channel have to sit at the ceiling before it's clipping,
+    /// a single sample touching full scale is just a loud peak, three or more in a row means the
+    /// waveform got flattened.
+    pub min_run: usize,
+}
+
+impl Default for ClippingOptions {
+    fn default() -> Self {
+        ClippingOptions {
+            threshold: 0.999,
+            min_run: 3,
+        }
+    }
+}
+
+/// What `detect_clipping` found.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClippingReport {
+    /// Total samples (over every channel) that are part of a clipped run.
+    pub clipped_sample_count: usize,
+    /// Where the clipping is, in seconds, runs in different channels that overlap (or touch) are
+    /// merged into one region.
+    pub clipped_regions: Vec<Segment>,
+    /// The longest clipped run in any channel, in samples, a handful is a hot master, hundreds
+    /// mean the recording was badly overdriven.
+    pub max_consecutive: usize,
+}
+
+impl ClippingReport {
+    /// Whether any clipping was found at all.
+    pub fn is_clipped(&self) -> bool {
+        self.clipped_sample_count > 0
+    }
+}
+
+/// Looks for clipping in some interleaved audio data, runs of at least `options.min_run`
+/// consecutive samples in a channel stuck at (or past) `options.threshold`, which is what a
+/// waveform that hit the ceiling of a converter or an integer format looks like, for flagging
+/// damaged uploads in QA. Audio that was normalized down after clipping won't reach the
+/// threshold any more, lower it to the level of the flattened peaks if you suspect that. Trailing
+/// samples that don't make up a whole frame are ignored.
+///
+/// # Panics
+/// Panics if `channels` or `sample_rate` is zero.
+pub fn detect_clipping(
+    audio: &[f32],
+    channels: usize,
+    sample_rate: usize,
+    options: &ClippingOptions,
+) -> ClippingReport {
+    assert!(
+        channels > 0,
+        "can't look for clipping in audio with no channels"
+    );
+    assert!(sample_rate > 0, "sample rate can't be zero");
+    let frames = audio.len() / channels;
+    let min_run = options.min_run.max(1);
+
+    // clipped runs of every channel as frame ranges
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for channel in 0..channels {
+        let mut start = None;
+        for frame in 0..=frames {
+            let at_ceiling =
+                frame < frames && audio[frame * channels + channel].abs() >= options.threshold;
+            match (at_ceiling, start) {
+                (true, None) => start = Some(frame),
+                (false, Some(s)) => {
+                    if frame - s >= min_run {
+                        runs.push((s, frame));
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let clipped_sample_count = runs.iter().map(|(start, end)| end - start).sum();
+    let max_consecutive = runs
+        .iter()
+        .map(|(start, end)| end - start)
+        .max()
+        .unwrap_or(0);
+
+    runs.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (start, end) in runs {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let secs = |frame: usize| frame as f64 / sample_rate as f64;
+    ClippingReport {
+        clipped_sample_count,
+        clipped_regions: merged
+            .into_iter()
+            .map(|(start, end)| Segment {
+                start: secs(start),
+                end: secs(end),
+            })
+            .collect(),
+        max_consecutive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A second of a sawtooth-like tone, harmonics 1 to `harmonics` of `f0` at 1/k amplitude,
+    /// each summed at the frequency it actually lands on after sampling.
+    fn harmonic_tone(f0: f32, harmonics: usize, sample_rate: usize) -> Vec<f32> {
+        (0..sample_rate)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                (1..=harmonics)
+                    .map(|k| (2.0 * std::f32::consts::PI * k as f32 * f0 * t).sin() / k as f32)
+                    .sum::<f32>()
+                    * 0.3
+            })
+            .collect()
+    }
+
+    /// Deterministic white noise in -1.0..1.0, a plain LCG so the tests don't need `rand`.
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_lowpass_shows_up_in_the_high_bands() {
+        let original: Vec<f32> = noise(88200, 5).iter().map(|s| s * 0.5).collect();
+        let mut lowpassed = original.clone();
+        let chain = [Biquad::lowpass(44100, 2000.0, std::f64::consts::FRAC_1_SQRT_2); 4];
+        crate::apply_filters(&mut lowpassed, 1, &chain);
+
+        let difference = spectral_difference(&original, &lowpassed, 44100);
+        let centers = octave_band_centers(44100);
+        assert_eq!(difference.len(), centers.len());
+        for (center, db) in centers.iter().zip(&difference) {
+            if *center <= 500.0 {
+                assert!(db.abs() < 1.0, "{}dB at {}Hz", db, center);
+            } else if *center >= 8000.0 {
+                assert!(*db > 20.0, "{}dB at {}Hz", db, center);
+            }
+        }
+    }
+
+    #[test]
+    fn aliased_harmonics_raise_suspicion() {
+        // 17 harmonics of 1234Hz stay under Nyquist, 40 of them fold back down naively
+        let clean = detect_aliasing(&harmonic_tone(1234.0, 17, 44100), 44100);
+        let aliased = detect_aliasing(&harmonic_tone(1234.0, 40, 44100), 44100);
+        assert!(clean < 0.05, "{}", clean);
+        assert!(aliased > 0.2, "{}", aliased);
+    }
+
+    /// A `reference` Hz tone whose frequency swings by ±`depth` (a fraction) `rate` times a
+    /// second, what a warped record does to a test tone.
+    fn wobbly_tone(
+        reference: f64,
+        depth: f64,
+        rate: f64,
+        sample_rate: usize,
+        secs: f64,
+    ) -> Vec<f32> {
+        use std::f64::consts::TAU;
+        (0..(sample_rate as f64 * secs) as usize)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                // the integral of reference * (1 + depth * sin(TAU * rate * t))
+                let phase = TAU * reference * (t - depth / (TAU * rate) * (TAU * rate * t).cos());
+                (phase.sin() * 0.5) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slow_pitch_modulation_reads_as_wow() {
+        let report = wow_and_flutter(&wobbly_tone(1000.0, 0.003, 1.0, 44100, 4.0), 44100).unwrap();
+        assert!((report.reference_hz - 1000.0).abs() < 1.0, "{:?}", report);
+        assert!((report.wow_percent - 0.3).abs() < 0.05, "{:?}", report);
+        assert!(report.flutter_percent < 0.05, "{:?}", report);
+
+        let steady = wow_and_flutter(&wobbly_tone(1000.0, 0.0, 1.0, 44100, 4.0), 44100).unwrap();
+        assert!(steady.wow_percent < 0.02, "{:?}", steady);
+    }
+
+    #[test]
+    fn a_mislabeled_rate_is_found_from_the_calibration_tone() {
+        let tone = |frequency: f32, sample_rate: usize| -> Vec<f32> {
+            (0..sample_rate)
+                .map(|i| {
+                    (std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32).sin() * 0.5
+                })
+                .collect()
+        };
+
+        // a 48kHz transfer of the 997Hz tone, tagged as 44.1kHz
+        assert_eq!(verify_sample_rate(&tone(997.0, 48000), 44100), Some(48000));
+        // honest headers check out, and 997Hz isn't taken for 1000Hz or the other way around
+        assert_eq!(verify_sample_rate(&tone(997.0, 44100), 44100), Some(44100));
+        assert_eq!(verify_sample_rate(&tone(1000.0, 48000), 48000), Some(48000));
+        // halfway between the two is neither
+        assert_eq!(verify_sample_rate(&tone(998.5, 44100), 44100), None);
+    }
+
+    #[test]
+    fn rt60_of_an_exponential_decay() {
+        // noise dying down 60dB in 0.6 seconds, over a faint room tone
+        let sample_rate = 44100;
+        let rt60 = 0.6;
+        let decay: Vec<f32> = noise(sample_rate * 2, 9)
+            .iter()
+            .zip(noise(sample_rate * 2, 10))
+            .enumerate()
+            .map(|(i, (tail, room))| {
+                let t = i as f32 / sample_rate as f32;
+                tail * 10f32.powf(-3.0 * t / rt60) + room * 1e-5
+            })
+            .collect();
+
+        let estimate = estimate_rt60(&decay, sample_rate).unwrap();
+        assert!((estimate - rt60).abs() < 0.05, "{}", estimate);
+
+        // steady noise never decays
+        assert_eq!(
+            estimate_rt60(&noise(sample_rate * 2, 11), sample_rate),
+            None
+        );
+    }
+
+    #[test]
+    fn padded_16_bit_content_reads_as_16_bits() {
+        // the same tone on the 24 and the 16 bit grid, the way a decoder hands both over
+        let tone: Vec<f32> = harmonic_tone(440.0, 3, 44100);
+        let on_grid = |bits: i32| -> Vec<f32> {
+            let scale = 2f32.powi(bits - 1);
+            tone.iter()
+                .map(|s| (s * 0.5 * scale).round() / scale)
+                .collect()
+        };
+
+        assert_eq!(effective_bit_depth(&on_grid(24)), 24);
+        assert_eq!(effective_bit_depth(&on_grid(16)), 16);
+        assert_eq!(effective_bit_depth(&vec![0.0; 1000]), 0);
+    }
+
+    #[test]
+    fn clipped_runs_are_counted_and_merged_across_channels() {
+        // stereo at 1kHz, a clean loud peak on the left at frame 100, the left flattened over
+        // frames 200..210 and the right over 205..220, and a lone run of 50 on the right at 500
+        let mut audio = vec![0.3; 2 * 1000];
+        audio[200] = 1.0;
+        for frame in 200..210 {
+            audio[frame * 2] = 1.0;
+        }
+        for frame in 205..220 {
+            audio[frame * 2 + 1] = -1.0;
+        }
+        for frame in 500..550 {
+            audio[frame * 2 + 1] = 1.0;
+        }
+
+        let report = detect_clipping(&audio, 2, 1000, &ClippingOptions::default());
+        assert!(report.is_clipped());
+        assert_eq!(report.clipped_sample_count, 10 + 15 + 50);
+        assert_eq!(report.max_consecutive, 50);
+        assert_eq!(
+            report.clipped_regions,
+            [
+                Segment {
+                    start: 0.2,
+                    end: 0.22
+                },
+                Segment {
+                    start: 0.5,
+                    end: 0.55
+                }
+            ]
+        );
+
+        // a longer minimum run leaves only the overdriven stretch, and a ceiling above the
+        // samples finds nothing
+        let strict = ClippingOptions {
+            min_run: 20,
+            ..ClippingOptions::default()
+        };
+        assert_eq!(
+            detect_clipping(&audio, 2, 1000, &strict).max_consecutive,
+            50
+        );
+        assert_eq!(
+            detect_clipping(&audio, 2, 1000, &strict).clipped_sample_count,
+            50
+        );
+        let above = ClippingOptions {
+            threshold: 1.5,
+            ..ClippingOptions::default()
+        };
+        assert!(!detect_clipping(&audio, 2, 1000, &above).is_clipped());
+    }
+}
@@ -0,0 +1,226 @@
+/// How `downmix_to_mono` folds multichannel audio down to a single channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownmixStrategy {
+    /// Every channel contributes equally, the plain average of all of them.
+    #[default]
+    Average,
+    /// Only the center channel is kept, which in film and TV mixes is where the dialogue lives,
+    /// so this gets you the voices without most of the music and effects. Layouts without a
+    /// discrete center (mono, stereo, quad) fall back to the average of the front left and right
+    /// channels, the "phantom" center.
+    CenterOnly,
+    /// The standard ITU-R BS.775 downmix, the center at full level, left and right at -3dB,
+    /// surrounds at -6dB and the LFE dropped (same as `downmix_to_stereo` with both sides summed
+    /// at -3dB). Keeps dialogue intelligible where `Average` would bury it under the surrounds.
+    /// Unlike `Average` this adds up rather than averages, so loud multichannel material can peak
+    /// above full scale.
+    Itu,
+}
+
+/// -3dB, the ITU downmix gain for anything that's split between (or folded from) two channels.
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Index of the center channel in an interleaved frame, assuming the usual WAV/symphonia channel
+/// order (FL, FR, FC, LFE, ...), `None` for layouts that don't have one.
+fn center_channel(channels: usize) -> Option<usize> {
+    match channels {
+        // 3.0, 5.0, 5.1, 6.1 and 7.1, quad (4 channels) is FL, FR, RL, RR so it has no center
+        3 | 5..=8 => Some(2),
+        _ => None,
+    }
+}
+
+/// How much each channel of an interleaved frame feeds into the left and right output of an ITU
+/// downmix, again assuming the usual WAV/symphonia channel order. Channels past the 8th (and
+/// every LFE) are dropped.
+fn itu_stereo_gains(channels: usize) -> Vec<(f32, f32)> {
+    let left = (1.0, 0.0);
+    let right = (0.0, 1.0);
+    let center = (MINUS_3DB, MINUS_3DB);
+    let lfe = (0.0, 0.0);
+    let left_surround = (MINUS_3DB, 0.0);
+    let right_surround = (0.0, MINUS_3DB);
+    let back_center = (0.5, 0.5);
+
+    let mut gains = match channels {
+        1 => vec![center],
+        2 => vec![left, right],
+        3 => vec![left, right, center],
+        4 => vec![left, right, left_surround, right_surround],
+        5 => vec![left, right, center, left_surround, right_surround],
+        6 => vec![left, right, center, lfe, left_surround, right_surround],
+        7 => vec![
+            left,
+            right,
+            center,
+            lfe,
+            back_center,
+            left_surround,
+            right_surround,
+        ],
+        _ => vec![
+            left,
+            right,
+            center,
+            lfe,
+            left_surround,
+            right_surround,
+            left_surround,
+            right_surround,
+        ],
+    };
+    gains.resize(channels, (0.0, 0.0));
+    gains
+}
+
+/// Folds interleaved audio data with any number of channels down to interleaved stereo using the
+/// standard ITU-R BS.775 coefficients, center and surrounds go into both sides at -3dB (the
+/// surrounds each into their own side, a 6.1 back center into both at -6dB) and the LFE is
+/// dropped, it's meant to be redundant with the main channels. Mono is copied into both sides at
+/// -3dB and stereo passes through untouched. Channels are expected in the usual WAV/symphonia
+/// order (FL, FR, FC, LFE, then the surrounds), anything past the 8th is dropped, as are any
+/// trailing samples that don't make up a whole frame.
+///
+/// The coefficients aren't normalized, that's what keeps a centered voice at the same level it
+/// had in the original mix, but it also means a loud, busy surround mix can peak above full
+/// scale, check (and scale down) before converting to integer samples.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn downmix_to_stereo(audio: &[f32], channels: usize) -> Vec<f32> {
+    assert!(channels > 0, "can't downmix audio with no channels");
+    if channels == 2 {
+        return audio[..audio.len() / 2 * 2].to_vec();
+    }
+
+    let gains = itu_stereo_gains(channels);
+    audio
+        .chunks_exact(channels)
+        .flat_map(|frame| {
+            frame
+                .iter()
+                .zip(&gains)
+                .fold([0.0, 0.0], |[l, r], (sample, (to_left, to_right))| {
+                    [l + sample * to_left, r + sample * to_right]
+                })
+        })
+        .collect()
+}
+
+/// Folds interleaved audio data with any number of channels down to mono using the given
+/// `DownmixStrategy`, unlike `interleave_to_mono` this doesn't give up on anything above stereo.
+/// Channels are expected in the usual WAV/symphonia order (FL, FR, FC, LFE, then the surrounds),
+/// which is what `decode` hands back, any trailing samples that don't make up a whole frame are
+/// dropped.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn downmix_to_mono(audio: &[f32], channels: usize, strategy: DownmixStrategy) -> Vec<f32> {
+    assert!(channels > 0, "can't downmix audio with no channels");
+
+    match (strategy, center_channel(channels)) {
+        (DownmixStrategy::CenterOnly, Some(center)) => audio
+            .chunks_exact(channels)
+            .map(|frame| frame[center])
+            .collect(),
+        // no discrete center, the phantom one sits between the front pair
+        (DownmixStrategy::CenterOnly, None) if channels >= 2 => audio
+            .chunks_exact(channels)
+            .map(|frame| (frame[0] + frame[1]) / 2.0)
+            .collect(),
+        (DownmixStrategy::Itu, _) if channels > 1 => {
+            let gains: Vec<f32> = itu_stereo_gains(channels)
+                .iter()
+                .map(|(to_left, to_right)| (to_left + to_right) * MINUS_3DB)
+                .collect();
+            audio
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().zip(&gains).map(|(s, g)| s * g).sum())
+                .collect()
+        }
+        _ => audio
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn center_only_keeps_just_the_center_of_a_5_1_mix() {
+        // every channel gets its own tone, the center a voice-ish 300Hz
+        let frames = 4410;
+        let mut audio = Vec::with_capacity(frames * 6);
+        for i in 0..frames {
+            let t = i as f32 / 44100.0;
+            for channel in 0..6 {
+                let frequency = if channel == 2 {
+                    300.0
+                } else {
+                    1000.0 * (channel + 1) as f32
+                };
+                audio.push((2.0 * std::f32::consts::PI * frequency * t).sin() * 0.5);
+            }
+        }
+
+        let center: Vec<f32> = audio.chunks_exact(6).map(|frame| frame[2]).collect();
+        assert_eq!(
+            downmix_to_mono(&audio, 6, DownmixStrategy::CenterOnly),
+            center
+        );
+    }
+
+    #[test]
+    fn center_only_falls_back_to_the_phantom_center() {
+        let stereo = [1.0, 0.0, 0.5, 0.5, -1.0, 0.0];
+        assert_eq!(
+            downmix_to_mono(&stereo, 2, DownmixStrategy::CenterOnly),
+            vec![0.5, 0.5, -0.5]
+        );
+    }
+
+    #[test]
+    fn itu_downmix_of_a_5_1_frame() {
+        // FL, FR, FC, LFE, SL, SR, each with its own value so every gain is visible
+        let frame = [0.1, 0.2, 0.4, 0.9, 0.3, 0.5];
+
+        let stereo = downmix_to_stereo(&frame, 6);
+        let expected_left = 0.1 + 0.4 * MINUS_3DB + 0.3 * MINUS_3DB;
+        let expected_right = 0.2 + 0.4 * MINUS_3DB + 0.5 * MINUS_3DB;
+        assert!((stereo[0] - expected_left).abs() < 1e-6, "{:?}", stereo);
+        assert!((stereo[1] - expected_right).abs() < 1e-6, "{:?}", stereo);
+
+        // the LFE never makes it in, whichever way it's folded
+        let lfe_only = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        assert_eq!(downmix_to_stereo(&lfe_only, 6), vec![0.0, 0.0]);
+        assert_eq!(
+            downmix_to_mono(&lfe_only, 6, DownmixStrategy::Itu),
+            vec![0.0]
+        );
+    }
+
+    #[test]
+    fn every_layout_up_to_7_1_downmixes() {
+        for channels in 1..=8 {
+            let audio = vec![0.25; channels * 10];
+            assert_eq!(
+                downmix_to_stereo(&audio, channels).len(),
+                20,
+                "{}",
+                channels
+            );
+            for strategy in [
+                DownmixStrategy::Average,
+                DownmixStrategy::CenterOnly,
+                DownmixStrategy::Itu,
+            ] {
+                let mono = downmix_to_mono(&audio, channels, strategy);
+                assert_eq!(mono.len(), 10, "{} {:?}", channels, strategy);
+                assert!(mono.iter().all(|s| s.is_finite() && *s > 0.0));
+            }
+        }
+    }
+}
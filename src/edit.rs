@@ -0,0 +1,197 @@
+use crate::vad::Segment;
+
+/// Cuts a `length_secs` long clip out of the middle of some interleaved audio data, the middle of
+/// a song or a recording is usually its most representative part (past the intro, before the
+/// fade out), which makes this a decent default for previews and for sampling fixed-size inputs
+/// for ML. If the audio is shorter than that, all of it is returned centered in silence instead,
+/// so the result is always exactly `length_secs` long (rounded to whole frames) and never splits
+/// a frame between channels.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn center_excerpt(
+    audio: &[f32],
+    channels: usize,
+    sample_rate: usize,
+    length_secs: f64,
+) -> Vec<f32> {
+    assert!(
+        channels > 0,
+        "can't cut an excerpt out of audio with no channels"
+    );
+
+    let frames = audio.len() / channels;
+    let wanted = (length_secs * sample_rate as f64).round().max(0.0) as usize;
+
+    if frames >= wanted {
+        let start = (frames - wanted) / 2;
+        audio[start * channels..(start + wanted) * channels].to_vec()
+    } else {
+        let before = (wanted - frames) / 2;
+        let mut excerpt = vec![0.0; wanted * channels];
+        excerpt[before * channels..(before + frames) * channels]
+            .copy_from_slice(&audio[..frames * channels]);
+        excerpt
+    }
+}
+
+/// Length of the frames `detect_silence` measures the level of, in seconds, and so the
+/// resolution of the silent stretches it finds.
+const SILENCE_FRAME_SECS: f64 = 0.01;
+
+/// Finds every stretch of some mono audio data that stays below `threshold_db` (dBFS RMS,
+/// measured over 10ms frames, -60.0 is a good start for clean recordings, -40.0 for noisy ones)
+/// for at least `min_duration` seconds, returned in order as `Segment`s in seconds. Shorter dips
+/// (the gaps between words, the attack of a note) aren't silence. The edges are rounded to the
+/// 10ms frames, a stretch that runs into the end of the audio ends exactly there.
+pub fn detect_silence(
+    audio: &[f32],
+    sample_rate: usize,
+    threshold_db: f32,
+    min_duration: f64,
+) -> Vec<Segment> {
+    let frame_len = ((SILENCE_FRAME_SECS * sample_rate as f64).round() as usize).max(1);
+    let threshold = 10f32.powf(threshold_db / 10.0);
+    let total = audio.len() as f64 / sample_rate as f64;
+    let time = |frame: usize| ((frame * frame_len) as f64 / sample_rate as f64).min(total);
+
+    let quiet: Vec<bool> = audio
+        .chunks(frame_len)
+        .map(|frame| frame.iter().map(|s| s * s).sum::<f32>() / (frame.len() as f32) < threshold)
+        .collect();
+
+    let mut silences = vec![];
+    let mut start = None;
+    for (i, is_quiet) in quiet.iter().chain(std::iter::once(&false)).enumerate() {
+        match (is_quiet, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                let segment = Segment {
+                    start: time(s),
+                    end: time(i),
+                };
+                if segment.duration() >= min_duration {
+                    silences.push(segment);
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    silences
+}
+
+/// Cuts the leading and trailing silence off some mono audio data, what `detect_silence` (with
+/// the same `threshold_db` and `min_duration`) finds at the very start and end of it, so a dead
+/// second before the first word goes but a short breath doesn't make a difference either way.
+/// Internal silence is kept, see `strip_silence` for that. Audio that's silent all the way
+/// through comes back empty.
+pub fn trim_silence(
+    audio: &[f32],
+    sample_rate: usize,
+    threshold_db: f32,
+    min_duration: f64,
+) -> Vec<f32> {
+    let to_sample = |secs: f64| ((secs * sample_rate as f64).round() as usize).min(audio.len());
+    let silences = detect_silence(audio, sample_rate, threshold_db, min_duration);
+
+    let start = match silences.first() {
+        Some(first) if to_sample(first.start) == 0 => to_sample(first.end),
+        _ => 0,
+    };
+    let end = match silences.last() {
+        Some(last) if to_sample(last.end) == audio.len() => to_sample(last.start),
+        _ => audio.len(),
+    };
+
+    audio[start..end.max(start)].to_vec()
+}
+
+/// Same as `trim_silence`, but removes every silent stretch `detect_silence` finds, the internal
+/// ones too, for squeezing the dead air out of recordings (dataset preparation, long pauses in a
+/// lecture). What's left is spliced back to back, with nothing to smooth over the cuts, so a
+/// threshold that's high enough to cut into sound can leave audible clicks.
+pub fn strip_silence(
+    audio: &[f32],
+    sample_rate: usize,
+    threshold_db: f32,
+    min_duration: f64,
+) -> Vec<f32> {
+    let to_sample = |secs: f64| ((secs * sample_rate as f64).round() as usize).min(audio.len());
+
+    let mut kept = Vec::with_capacity(audio.len());
+    let mut position = 0;
+    for silence in detect_silence(audio, sample_rate, threshold_db, min_duration) {
+        let start = to_sample(silence.start);
+        kept.extend_from_slice(&audio[position..start.max(position)]);
+        position = to_sample(silence.end);
+    }
+    kept.extend_from_slice(&audio[position.min(audio.len())..]);
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_excerpt_comes_from_the_middle() {
+        // ten seconds of stereo where every frame holds its own index, both channels
+        let audio: Vec<f32> = (0..1000).flat_map(|i| [i as f32, i as f32]).collect();
+
+        let excerpt = center_excerpt(&audio, 2, 100, 2.0);
+        assert_eq!(excerpt.len(), 200 * 2);
+        // 400 frames before the excerpt and 400 after it
+        assert_eq!(excerpt[0], 400.0);
+        assert_eq!(excerpt[excerpt.len() - 1], 599.0);
+        assert!(excerpt.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn a_short_source_is_padded_on_both_sides() {
+        let audio = vec![1.0; 100];
+
+        let excerpt = center_excerpt(&audio, 1, 100, 3.0);
+        assert_eq!(excerpt.len(), 300);
+        assert!(excerpt[..100].iter().all(|s| *s == 0.0));
+        assert!(excerpt[100..200].iter().all(|s| *s == 1.0));
+        assert!(excerpt[200..].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn silence_is_found_trimmed_and_stripped() {
+        // 0.5s of silence, 1s of tone, a 0.3s pause, 1s of tone, a 50ms dip, 0.5s of tone, 0.7s
+        // of silence, all at 10kHz so the 10ms frames line up with the edges
+        let tone = |secs: f64| vec![0.5; (secs * 10000.0) as usize];
+        let quiet = |secs: f64| vec![0.0; (secs * 10000.0) as usize];
+        let audio: Vec<f32> = [
+            quiet(0.5),
+            tone(1.0),
+            quiet(0.3),
+            tone(1.0),
+            quiet(0.05),
+            tone(0.5),
+            quiet(0.7),
+        ]
+        .concat();
+
+        let silences = detect_silence(&audio, 10000, -60.0, 0.1);
+        let edges: Vec<(f64, f64)> = silences.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(edges.len(), 3, "{:?}", edges);
+        for ((start, end), expected) in edges.iter().zip([(0.0, 0.5), (1.5, 1.8), (3.35, 4.05)]) {
+            assert!((start - expected.0).abs() < 1e-9, "{:?}", edges);
+            assert!((end - expected.1).abs() < 1e-9, "{:?}", edges);
+        }
+
+        // only the ends go, the pause and the dip stay
+        let trimmed = trim_silence(&audio, 10000, -60.0, 0.1);
+        assert_eq!(trimmed.len(), 28500);
+        assert_eq!((trimmed[0], trimmed[trimmed.len() - 1]), (0.5, 0.5));
+        // the pause goes too, the dip is too short to count
+        let stripped = strip_silence(&audio, 10000, -60.0, 0.1);
+        assert_eq!(stripped.len(), 25500);
+        assert!(trim_silence(&quiet(1.0), 10000, -60.0, 0.1).is_empty());
+    }
+}
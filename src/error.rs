@@ -0,0 +1,115 @@
+use std::fmt;
+
+use optivorbis::remuxer::ogg_to_ogg::RemuxError;
+use symphonia::core::errors::Error as SymphoniaError;
+use vorbis_rs::VorbisError;
+
+/// Everything that can go wrong in Avasara, returned instead of panicking so you can tell the
+/// difference between "this file is broken" and "this file is just quiet".
+#[derive(Debug)]
+pub enum AvasaraError {
+    /// The input is entirely silent (or empty), so there's nothing to analyze, this isn't a
+    /// decoding problem, the audio just has no signal in it.
+    SilentInput,
+    /// The input has signal in it, but not a single chunk produced a pitch within the requested
+    /// frequency range.
+    NoPitchDetected,
+    /// The input couldn't be decoded at all (an unsupported format or corrupt data), there's no
+    /// audio to work with.
+    DecodeFailed,
+    /// The operation was cancelled through its cancellation flag before it finished.
+    Cancelled,
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// Symphonia gave up on the input, the format or codec isn't supported, or the stream is
+    /// broken badly enough that decoding can't continue (a single bad packet is skipped instead,
+    /// see `DecodeWarning`).
+    Decode(SymphoniaError),
+    /// libvorbis refused to encode the audio (usually a sample rate or quality it doesn't take).
+    Encode(VorbisError),
+    /// OptiVorbis couldn't remux the encoded Ogg file.
+    Remux(RemuxError),
+    /// libopus refused to encode the audio (usually a bitrate or setting it doesn't take).
+    #[cfg(feature = "opus")]
+    Opus(opus::Error),
+    /// The audio has a channel count the function can't deal with, it holds the count.
+    UnsupportedChannels(usize),
+    /// The audio has a sample rate the output format can't store, it holds the rate.
+    UnsupportedSampleRate(usize),
+    /// There's more audio than the output format can hold in one file.
+    TooLong,
+    /// The file has audio, but no track matching the `TrackSelector` that was asked for.
+    TrackNotFound,
+}
+
+impl fmt::Display for AvasaraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AvasaraError::SilentInput => write!(f, "the input audio is silent"),
+            AvasaraError::NoPitchDetected => {
+                write!(f, "no valid pitch points within the frequency range")
+            }
+            AvasaraError::DecodeFailed => write!(f, "the input audio could not be decoded"),
+            AvasaraError::Cancelled => write!(f, "the operation was cancelled"),
+            AvasaraError::Io(e) => write!(f, "i/o error: {}", e),
+            AvasaraError::Decode(e) => write!(f, "decoding failed: {}", e),
+            AvasaraError::Encode(e) => write!(f, "encoding failed: {}", e),
+            AvasaraError::Remux(e) => write!(f, "remuxing failed: {}", e),
+            #[cfg(feature = "opus")]
+            AvasaraError::Opus(e) => write!(f, "opus encoding failed: {}", e),
+            AvasaraError::UnsupportedChannels(channels) => {
+                write!(f, "unsupported channel count: {}", channels)
+            }
+            AvasaraError::UnsupportedSampleRate(sample_rate) => {
+                write!(f, "unsupported sample rate: {}Hz", sample_rate)
+            }
+            AvasaraError::TooLong => write!(f, "too much audio for the output format"),
+            AvasaraError::TrackNotFound => write!(f, "no audio track matches the selection"),
+        }
+    }
+}
+
+impl std::error::Error for AvasaraError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AvasaraError::Io(e) => Some(e),
+            AvasaraError::Decode(e) => Some(e),
+            AvasaraError::Encode(e) => Some(e),
+            AvasaraError::Remux(e) => Some(e),
+            #[cfg(feature = "opus")]
+            AvasaraError::Opus(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AvasaraError {
+    fn from(e: std::io::Error) -> Self {
+        AvasaraError::Io(e)
+    }
+}
+
+impl From<SymphoniaError> for AvasaraError {
+    fn from(e: SymphoniaError) -> Self {
+        AvasaraError::Decode(e)
+    }
+}
+
+impl From<VorbisError> for AvasaraError {
+    fn from(e: VorbisError) -> Self {
+        AvasaraError::Encode(e)
+    }
+}
+
+impl From<RemuxError> for AvasaraError {
+    fn from(e: RemuxError) -> Self {
+        AvasaraError::Remux(e)
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<opus::Error> for AvasaraError {
+    fn from(e: opus::Error) -> Self {
+        AvasaraError::Opus(e)
+    }
+}
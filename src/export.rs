@@ -0,0 +1,420 @@
+use std::fmt::{Display, Write};
+
+use crate::{hz_to_midi, NoteName, PitchPoint};
+
+/// A number the way JSON wants it, `null` for NaN and the infinities, which JSON has no way to
+/// write.
+fn json_number<T: Into<f64> + Display + Copy>(value: T) -> String {
+    if value.into().is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Writes a pitch contour (like `analyze_pitch_contour` gives) as CSV, a
+/// `time_secs,frequency,clarity` header and then one line per point, ready for `pandas.read_csv`,
+/// R or a spreadsheet. Numbers are written in full precision with a `.` as the decimal separator,
+/// lines end with `\n`.
+pub fn contour_to_csv(points: &[PitchPoint]) -> String {
+    let mut csv = String::from("time_secs,frequency,clarity\n");
+    for point in points {
+        // writing into a String can't fail
+        let _ = writeln!(
+            csv,
+            "{},{},{}",
+            point.time_secs, point.frequency, point.clarity
+        );
+    }
+    csv
+}
+
+/// Writes a pitch contour as a JSON array of
+/// `{"time_secs": ..., "frequency": ..., "clarity": ...}` objects, for `json.loads` and anything
+/// else that reads JSON, without pulling in a JSON crate. Non-finite numbers (which detectors
+/// don't produce, but a contour can be built by hand) are written as `null`. Turn on the `serde`
+/// feature to serialize the other result types too.
+pub fn contour_to_json(points: &[PitchPoint]) -> String {
+    let objects: Vec<String> = points
+        .iter()
+        .map(|point| {
+            format!(
+                "{{\"time_secs\":{},\"frequency\":{},\"clarity\":{}}}",
+                json_number(point.time_secs),
+                json_number(point.frequency),
+                json_number(point.clarity)
+            )
+        })
+        .collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// Writes a pitch contour as a Praat PitchTier in Praat's short text format, which Praat opens
+/// directly (Open → Read from file...) for viewing, editing and manipulating pitch next to a
+/// TextGrid. `duration_secs` is the length of the audio the contour came from, Praat wants the
+/// tier to span the whole sound so the two line up, it's stretched to the last point if that's
+/// further. Points are written in time order, ones with a non-finite or non-positive frequency
+/// are left out (a PitchTier only holds voiced points, gaps are unvoiced). Times are written as
+/// they are, and `analyze_pitch_contour` stamps every point with the start of its window, add
+/// half a window to line them up with Praat's own pitch analysis, which uses window centers.
+pub fn contour_to_pitch_tier(points: &[PitchPoint], duration_secs: f64) -> String {
+    let mut voiced: Vec<&PitchPoint> = points
+        .iter()
+        .filter(|p| p.frequency.is_finite() && p.frequency > 0.0 && p.time_secs.is_finite())
+        .collect();
+    voiced.sort_by(|a, b| {
+        a.time_secs
+            .partial_cmp(&b.time_secs)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let end = voiced
+        .last()
+        .map_or(duration_secs, |last| last.time_secs.max(duration_secs));
+
+    let mut tier = String::from("File type = \"ooTextFile\"\nObject class = \"PitchTier\"\n\n");
+    // time domain and point count, then every point as its time and frequency on their own lines
+    let _ = writeln!(tier, "0\n{}\n{}", end.max(0.0), voiced.len());
+    for point in voiced {
+        let _ = writeln!(tier, "{}\n{}", point.time_secs, point.frequency);
+    }
+    tier
+}
+
+/// How `plot_pitch_contour_svg` draws a contour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchPlotOptions {
+    /// Size of the whole SVG (axes and labels included) in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Lowest and highest frequency on the axis in Hz, `None` fits the axis to the contour, a
+    /// semitone past its lowest and highest points. Fix both to compare plots side by side.
+    pub min_hz: Option<f32>,
+    pub max_hz: Option<f32>,
+    /// Tuning reference for the note gridlines, 440.0 unless you know better.
+    pub a4_hz: f32,
+    /// Points the detector was less sure about than this (0.0 to 1.0) are left out, like
+    /// unvoiced ones.
+    pub min_clarity: f32,
+    /// A gap between consecutive drawn points longer than this (in seconds) breaks the line,
+    /// rather than drawing a straight line across a pause.
+    pub max_gap_secs: f64,
+    /// Color of the contour line, anything SVG takes (`"#d0402b"`, `"steelblue"`, ...).
+    pub line_color: String,
+}
+
+impl Default for PitchPlotOptions {
+    /// 800 by 300 pixels, axis fit to the contour, A4 = 440Hz, every point drawn, lines broken at
+    /// gaps over 100ms, a red line.
+    fn default() -> Self {
+        PitchPlotOptions {
+            width: 800,
+            height: 300,
+            min_hz: None,
+            max_hz: None,
+            a4_hz: 440.0,
+            min_clarity: 0.0,
+            max_gap_secs: 0.1,
+            line_color: "#d0402b".to_string(),
+        }
+    }
+}
+
+/// Plots a pitch contour (chronological `PitchPoint`s like `analyze_pitch_contour` gives) as an
+/// SVG line chart, for dropping straight into an HTML report (inline or as an `<img>`) without a
+/// plotting library. Frequency goes on a log axis so every semitone is the same height, with a
+/// faint gridline on every semitone and a labelled one on every C (on every natural note when
+/// the axis spans two octaves or less), time goes along the bottom with a tick every second (or
+/// every 5, 10, ... seconds for longer contours). Unvoiced points, ones below
+/// `options.min_clarity` and ones outside the axis are left out, and the line breaks across gaps
+/// longer than `options.max_gap_secs`. An empty contour gives empty axes.
+pub fn plot_pitch_contour_svg(contour: &[PitchPoint], options: &PitchPlotOptions) -> String {
+    const LEFT: f64 = 44.0;
+    const RIGHT: f64 = 12.0;
+    const TOP: f64 = 12.0;
+    const BOTTOM: f64 = 28.0;
+
+    let width = (options.width as f64).max(LEFT + RIGHT + 1.0);
+    let height = (options.height as f64).max(TOP + BOTTOM + 1.0);
+    let (plot_w, plot_h) = (width - LEFT - RIGHT, height - TOP - BOTTOM);
+
+    let voiced: Vec<&PitchPoint> = contour
+        .iter()
+        .filter(|p| {
+            p.frequency.is_finite()
+                && p.frequency > 0.0
+                && p.time_secs.is_finite()
+                && p.clarity >= options.min_clarity
+        })
+        .collect();
+
+    // the axis in fractional MIDI notes, which is what makes it logarithmic
+    let lowest = voiced
+        .iter()
+        .map(|p| p.frequency)
+        .fold(f32::INFINITY, f32::min);
+    let highest = voiced.iter().map(|p| p.frequency).fold(0.0, f32::max);
+    let (fit_low, fit_high) = if voiced.is_empty() {
+        // A2 to A5, where most voices and instruments sit
+        (45.0, 81.0)
+    } else {
+        (
+            hz_to_midi(lowest, options.a4_hz).floor() - 1.0,
+            hz_to_midi(highest, options.a4_hz).ceil() + 1.0,
+        )
+    };
+    let low = options
+        .min_hz
+        .map_or(fit_low, |hz| hz_to_midi(hz, options.a4_hz));
+    let high = options
+        .max_hz
+        .map_or(fit_high, |hz| hz_to_midi(hz, options.a4_hz));
+    let (low, high) = if high > low {
+        (low, high)
+    } else {
+        (low, low + 1.0)
+    };
+
+    let start = voiced.first().map_or(0.0, |p| p.time_secs);
+    let end = voiced.last().map_or(1.0, |p| p.time_secs).max(start + 1e-3);
+
+    let x = |t: f64| LEFT + (t - start) / (end - start) * plot_w;
+    let y = |midi: f32| TOP + (1.0 - ((midi - low) / (high - low)) as f64) * plot_h;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"10\">",
+        width, height
+    );
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>",
+        width, height
+    );
+
+    // note gridlines, labelled on every C, or every natural note when there's room
+    let label_naturals = high - low <= 24.0;
+    for midi in (low.ceil() as i32)..=(high.floor() as i32) {
+        let name = NoteName::from_midi(midi);
+        let natural = !name.to_string().ends_with('#');
+        let labelled = name == NoteName::C || (label_naturals && natural);
+        let line_y = y(midi as f32);
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{:.2}\" x2=\"{}\" y2=\"{:.2}\" stroke=\"{}\"/>",
+            LEFT,
+            line_y,
+            LEFT + plot_w,
+            line_y,
+            if name == NoteName::C {
+                "#b0b0b0"
+            } else {
+                "#ececec"
+            }
+        );
+        if labelled {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{:.2}\" text-anchor=\"end\" dominant-baseline=\"middle\">\
+                 {}{}</text>",
+                LEFT - 4.0,
+                line_y,
+                name,
+                midi.div_euclid(12) - 1
+            );
+        }
+    }
+
+    // time ticks, at most about 10 of them
+    let step = [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+        .into_iter()
+        .find(|step| (end - start) / step <= 10.0)
+        .unwrap_or(1800.0);
+    let mut tick = (start / step).ceil() * step;
+    while tick <= end {
+        let tick_x = x(tick);
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{0:.2}\" y1=\"{1}\" x2=\"{0:.2}\" y2=\"{2}\" stroke=\"#808080\"/>\n\
+             <text x=\"{0:.2}\" y=\"{3}\" text-anchor=\"middle\">{4}s</text>",
+            tick_x,
+            TOP + plot_h,
+            TOP + plot_h + 4.0,
+            TOP + plot_h + 16.0,
+            tick
+        );
+        tick += step;
+    }
+    let _ = writeln!(
+        svg,
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#808080\"/>",
+        LEFT, TOP, plot_w, plot_h
+    );
+
+    // the contour, one polyline per stretch without gaps
+    let mut runs: Vec<Vec<&PitchPoint>> = vec![];
+    for point in voiced {
+        let midi = hz_to_midi(point.frequency, options.a4_hz);
+        if midi < low || midi > high {
+            runs.push(vec![]);
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run)
+                if run.last().is_some_and(|last| {
+                    point.time_secs - last.time_secs <= options.max_gap_secs
+                }) =>
+            {
+                run.push(point)
+            }
+            _ => runs.push(vec![point]),
+        }
+    }
+    for run in runs.iter().filter(|run| !run.is_empty()) {
+        let coordinates: Vec<String> = run
+            .iter()
+            .map(|p| {
+                format!(
+                    "{:.2},{:.2}",
+                    x(p.time_secs),
+                    y(hz_to_midi(p.frequency, options.a4_hz))
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" \
+             stroke-linejoin=\"round\"/>",
+            coordinates.join(" "),
+            options.line_color
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<PitchPoint> {
+        vec![
+            PitchPoint {
+                time_secs: 0.0,
+                frequency: 220.5,
+                clarity: 0.75,
+            },
+            PitchPoint {
+                time_secs: 0.0125,
+                frequency: 221.0,
+                clarity: 1.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_and_json_write_every_point() {
+        assert_eq!(
+            contour_to_csv(&points()),
+            "time_secs,frequency,clarity\n0,220.5,0.75\n0.0125,221,1\n"
+        );
+        assert_eq!(contour_to_csv(&[]), "time_secs,frequency,clarity\n");
+
+        let mut points = points();
+        points[1].frequency = f32::NAN;
+        assert_eq!(
+            contour_to_json(&points),
+            "[{\"time_secs\":0,\"frequency\":220.5,\"clarity\":0.75},\
+             {\"time_secs\":0.0125,\"frequency\":null,\"clarity\":1}]"
+        );
+        assert_eq!(contour_to_json(&[]), "[]");
+    }
+
+    #[test]
+    fn pitch_tiers_hold_the_voiced_points_in_order() {
+        let mut points = points();
+        points.reverse();
+        points.push(PitchPoint {
+            time_secs: 0.5,
+            frequency: 0.0,
+            clarity: 0.1,
+        });
+
+        assert_eq!(
+            contour_to_pitch_tier(&points, 2.5),
+            "File type = \"ooTextFile\"\nObject class = \"PitchTier\"\n\n\
+             0\n2.5\n2\n0\n220.5\n0.0125\n221\n"
+        );
+        // the tier reaches the last point, even past the audio's end
+        assert!(contour_to_pitch_tier(&points, 0.01).contains("\n0\n0.0125\n2\n"));
+        assert!(contour_to_pitch_tier(&[], 1.0).ends_with("\n0\n1\n0\n"));
+    }
+
+    #[test]
+    fn the_plot_puts_notes_on_a_log_axis() {
+        // A3 for a second, a pause, then half a second of A4 with one point off the axis
+        let mut contour: Vec<PitchPoint> = (0..100)
+            .map(|i| PitchPoint {
+                time_secs: i as f64 * 0.01,
+                frequency: 220.0,
+                clarity: 0.9,
+            })
+            .collect();
+        contour.extend((150..=200).map(|i| PitchPoint {
+            time_secs: i as f64 * 0.01,
+            frequency: if i == 175 { 2000.0 } else { 440.0 },
+            clarity: 0.9,
+        }));
+        let options = PitchPlotOptions {
+            min_hz: Some(110.0),
+            max_hz: Some(880.0),
+            ..PitchPlotOptions::default()
+        };
+
+        let svg = plot_pitch_contour_svg(&contour, &options);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\""));
+        assert!(svg.ends_with("</svg>\n"));
+        // a gridline on every semitone from A2 to A5, labels only on the Cs over three octaves
+        assert_eq!(svg.matches("<line x1=\"44\"").count(), 37);
+        for label in [">C3<", ">C4<", ">C5<", ">0s<", ">1s<", ">2s<"] {
+            assert!(svg.contains(label), "{}", label);
+        }
+        assert!(!svg.contains(">A4<"));
+
+        // the pause and the point off the axis both break the line, and an octave is a third
+        // of the 260 pixel high plot
+        let lines: Vec<&str> = svg
+            .lines()
+            .filter(|line| line.starts_with("<polyline"))
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("points=\"44.00,185.33 "), "{}", lines[0]);
+        assert!(lines[1].contains(",98.67 "), "{}", lines[1]);
+        assert!(lines[2].contains("788.00,98.67\""), "{}", lines[2]);
+        assert!(lines[2].contains("stroke=\"#d0402b\""));
+
+        // nothing to draw still gives the axes, A2 to A5
+        let empty = plot_pitch_contour_svg(&[], &PitchPlotOptions::default());
+        assert!(!empty.contains("<polyline") && empty.contains(">C4<"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn the_reports_serialize_and_deserialize() {
+        fn both<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        both::<PitchPoint>();
+        both::<crate::PitchReport>();
+        both::<crate::NearestNote>();
+        both::<crate::NoteEvent>();
+        both::<crate::PitchBand>();
+        both::<crate::KeyReport>();
+        both::<crate::LoudnessReport>();
+        both::<crate::diagnostics::ClippingReport>();
+        both::<crate::diagnostics::WowFlutterReport>();
+        both::<crate::rhythm::TempoReport>();
+        both::<crate::Segment>();
+        both::<crate::PeakPair>();
+    }
+}
@@ -0,0 +1,379 @@
+use std::f32::consts::PI;
+
+use crate::spectrum::{self, WindowFunction};
+use crate::{
+    analyze_pitch, beat_strength, decode_audio, downmix_to_mono, tonal_balance, AvasaraError,
+    DownmixStrategy, MediaSource,
+};
+
+/// How many values `feature_vector` returns, the layout is documented there.
+pub const FEATURE_VECTOR_LEN: usize = 15;
+
+/// Loudness values below this (including the -inf of a band with nothing in it) are clamped to
+/// it, so every value in the vector stays finite.
+const LOUDNESS_FLOOR: f64 = -70.0;
+
+/// Mean spectral centroid, 85% rolloff (both in Hz) and flatness (0.0 for a pure tone, 1.0 for
+/// white noise) over all non-silent frames.
+fn spectral_shape(audio: &[f32], sample_rate: usize) -> (f32, f32, f32) {
+    let frame_len = 2048;
+    let bin_hz = sample_rate as f32 / frame_len as f32;
+
+    let mut sums = (0.0, 0.0, 0.0);
+    let mut frames = 0;
+    for frame in spectrum::stft_magnitudes(audio, frame_len, frame_len / 2) {
+        let total: f32 = frame.iter().sum();
+        let energy: f32 = frame.iter().map(|m| m * m).sum();
+        if total <= 1e-6 {
+            continue;
+        }
+
+        let centroid = frame
+            .iter()
+            .enumerate()
+            .map(|(i, m)| i as f32 * bin_hz * m)
+            .sum::<f32>()
+            / total;
+
+        let mut running = 0.0;
+        let rolloff_bin = frame
+            .iter()
+            .position(|m| {
+                running += m * m;
+                running >= 0.85 * energy
+            })
+            .unwrap_or(frame.len() - 1);
+
+        let powers: Vec<f32> = frame.iter().map(|m| m * m + 1e-12).collect();
+        let log_mean = powers.iter().map(|p| p.ln()).sum::<f32>() / powers.len() as f32;
+        let flatness = log_mean.exp() / (powers.iter().sum::<f32>() / powers.len() as f32);
+
+        sums.0 += centroid;
+        sums.1 += rolloff_bin as f32 * bin_hz;
+        sums.2 += flatness;
+        frames += 1;
+    }
+
+    if frames == 0 {
+        return (0.0, 0.0, 0.0);
+    }
+    let frames = frames as f32;
+    (sums.0 / frames, sums.1 / frames, sums.2 / frames)
+}
+
+/// Decodes an audio file and boils the whole thing down to a single fixed-length vector of
+/// summary features, ready to be fed into clustering or a classifier without any per-frame
+/// bookkeeping. Multichannel audio is averaged down to mono first. The vector is always
+/// `FEATURE_VECTOR_LEN` (15) values long and every value is finite, laid out as:
+///
+/// | index | feature |
+/// |-------|---------|
+/// | 0 | pitch mean in Hz (`analyze_pitch` with 50-1000Hz, 0.0 if no pitch was found) |
+/// | 1 | pitch median in Hz |
+/// | 2 | lowest pitch in Hz |
+/// | 3 | highest pitch in Hz |
+/// | 4 | percentage of chunks that produced a pitch (`PitchReport::chunks_used`) |
+/// | 5 | integrated loudness in LUFS (`tonal_balance`, clamped to -70) |
+/// | 6 | low band (< 250Hz) loudness relative to the whole, in LU (clamped to -70) |
+/// | 7 | mid band (250Hz - 4kHz) loudness relative to the whole, in LU |
+/// | 8 | high band (> 4kHz) loudness relative to the whole, in LU |
+/// | 9 | mean spectral centroid in Hz |
+/// | 10 | mean spectral rolloff (85% of the energy) in Hz |
+/// | 11 | mean spectral flatness, 0.0 (tonal) to 1.0 (noise) |
+/// | 12 | zero crossing rate, crossings per sample |
+/// | 13 | `beat_strength`, 0.0 to 1.0 |
+/// | 14 | crest factor of the whole file in dB |
+///
+/// The features have wildly different scales (Hz next to ratios), so standardize each index over
+/// your dataset before computing distances. Returns whatever error `decode` does if the file
+/// can't be decoded, `AvasaraError::DecodeFailed` if decoding produced no usable stream and
+/// `AvasaraError::SilentInput` if it decodes to nothing but silence.
+pub fn feature_vector<S: MediaSource + 'static>(src: S) -> Result<Vec<f32>, AvasaraError> {
+    let (audio, sample_rate, channels) = decode_audio(src)?.into_parts();
+    if sample_rate == 0 || channels == 0 {
+        return Err(AvasaraError::DecodeFailed);
+    }
+    let audio = downmix_to_mono(&audio, channels, DownmixStrategy::Average);
+
+    let peak = audio.iter().fold(0.0f32, |peak, s| s.abs().max(peak));
+    if peak == 0.0 {
+        return Err(AvasaraError::SilentInput);
+    }
+
+    let pitch = match analyze_pitch(&audio, sample_rate, 50.0, 1000.0) {
+        Ok((report, _)) => [
+            report.mean,
+            report.median,
+            report.lowest,
+            report.highest,
+            report.chunks_used as f32,
+        ],
+        Err(AvasaraError::NoPitchDetected) | Err(AvasaraError::SilentInput) => [0.0; 5],
+        Err(e) => return Err(e),
+    };
+
+    let balance = tonal_balance(&audio, sample_rate, 1);
+    let loudness = [balance.overall, balance.low, balance.mid, balance.high]
+        .map(|value| value.max(LOUDNESS_FLOOR) as f32);
+
+    let (centroid, rolloff, flatness) = spectral_shape(&audio, sample_rate);
+
+    let crossings = audio
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    let zcr = crossings as f32 / audio.len() as f32;
+
+    let rms = (audio.iter().map(|s| s * s).sum::<f32>() / audio.len() as f32).sqrt();
+    let crest = 20.0 * (peak / rms).log10();
+
+    let mut features = Vec::with_capacity(FEATURE_VECTOR_LEN);
+    features.extend(pitch);
+    features.extend(loudness);
+    features.extend([centroid, rolloff, flatness, zcr]);
+    features.push(beat_strength(&audio, sample_rate));
+    features.push(crest);
+
+    // NaN from a degenerate input shouldn't poison someone's whole dataset
+    Ok(features
+        .into_iter()
+        .map(|value| if value.is_finite() { value } else { 0.0 })
+        .collect())
+}
+
+/// How `mfcc` frames the audio and builds its mel filterbank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MfccOptions {
+    /// Length of every analysis frame in samples, 25ms is the speech standard (400 samples at
+    /// 16kHz, 1102 at 44.1kHz).
+    pub frame_len: usize,
+    /// Distance between consecutive frames in samples, 10ms is the speech standard.
+    pub hop: usize,
+    /// Number of triangular filters in the mel filterbank, the number of coefficients asked for
+    /// can't be more than this.
+    pub mel_bands: usize,
+    /// Lowest frequency the filterbank covers, in Hz.
+    pub min_frequency: f32,
+    /// Highest frequency the filterbank covers, in Hz, `None` goes up to the Nyquist frequency.
+    pub max_frequency: Option<f32>,
+    /// Taper applied to every frame, Hamming is the usual one for speech.
+    pub window: WindowFunction,
+}
+
+impl Default for MfccOptions {
+    /// 25ms frames every 10ms at 16kHz (400 and 160 samples), 26 mel bands from 0Hz to Nyquist
+    /// and a Hamming window, the classic speech recognition setup.
+    fn default() -> Self {
+        MfccOptions {
+            frame_len: 400,
+            hop: 160,
+            mel_bands: 26,
+            min_frequency: 0.0,
+            max_frequency: None,
+            window: WindowFunction::Hamming,
+        }
+    }
+}
+
+impl MfccOptions {
+    /// The classic speech setup (see `Default`) with the 25ms frames and 10ms hop worked out for
+    /// `sample_rate`.
+    pub fn for_sample_rate(sample_rate: usize) -> Self {
+        MfccOptions {
+            frame_len: sample_rate / 40,
+            hop: sample_rate / 100,
+            ..Default::default()
+        }
+    }
+}
+
+/// Hz to mel, the HTK formula.
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+/// Mel to Hz, the inverse of `hz_to_mel`.
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+/// Triangular filters evenly spaced on the mel scale between `min` and `max` Hz, each one a
+/// weight per FFT bin of a `frame_len` sample frame.
+fn mel_filterbank(
+    bands: usize,
+    frame_len: usize,
+    sample_rate: usize,
+    min: f32,
+    max: f32,
+) -> Vec<Vec<f32>> {
+    let bins = frame_len / 2 + 1;
+    let bin_hz = sample_rate as f32 / frame_len as f32;
+    let (low, high) = (hz_to_mel(min), hz_to_mel(max));
+    // band i rises from edge i to edge i + 1 and falls back to zero at edge i + 2
+    let edges: Vec<f32> = (0..bands + 2)
+        .map(|i| mel_to_hz(low + (high - low) * i as f32 / (bands + 1) as f32))
+        .collect();
+
+    edges
+        .windows(3)
+        .map(|edge| {
+            (0..bins)
+                .map(|bin| {
+                    let hz = bin as f32 * bin_hz;
+                    if hz <= edge[0] || hz >= edge[2] {
+                        0.0
+                    } else if hz <= edge[1] {
+                        (hz - edge[0]) / (edge[1] - edge[0])
+                    } else {
+                        (edge[2] - hz) / (edge[2] - edge[1])
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes `N` mel-frequency cepstral coefficients for every frame of some mono audio data, the
+/// standard compact description of a sound's timbre for speech and audio classification. Every
+/// frame's power spectrum (from `stft` with the given window) is summed into a bank of triangular
+/// filters spaced evenly on the mel scale, the log of every band's energy is taken and an
+/// orthonormal DCT-II of those turns them into cepstral coefficients, of which the first `N` are
+/// kept (13 is the usual number, coefficient 0 is the frame's overall log energy).
+///
+/// Frames are centered every `options.hop` samples like `stft`'s, so frame `i` belongs to the
+/// time `i * hop / sample_rate` and there are `audio.len() / hop + 1` of them. Use
+/// `MfccOptions::for_sample_rate` for the usual 25ms/10ms framing at any rate. Silent frames get
+/// the log of a tiny floor instead of -inf, so every value is finite.
+///
+/// # Panics
+/// Panics if the frame length, hop or number of mel bands is zero, if `N` is more than the
+/// number of mel bands, or if the frequency range is empty.
+pub fn mfcc<const N: usize>(
+    audio: &[f32],
+    sample_rate: usize,
+    options: &MfccOptions,
+) -> Vec<[f32; N]> {
+    assert!(
+        options.mel_bands > 0,
+        "the filterbank needs at least one band"
+    );
+    assert!(
+        N <= options.mel_bands,
+        "can't have more coefficients than mel bands"
+    );
+    let max_frequency = options
+        .max_frequency
+        .unwrap_or(sample_rate as f32 / 2.0)
+        .min(sample_rate as f32 / 2.0);
+    assert!(
+        options.min_frequency < max_frequency,
+        "the filterbank's frequency range is empty"
+    );
+
+    let spectrogram = spectrum::stft(
+        audio,
+        sample_rate,
+        options.frame_len,
+        options.hop,
+        options.window,
+    );
+    let filterbank = mel_filterbank(
+        options.mel_bands,
+        options.frame_len,
+        sample_rate,
+        options.min_frequency,
+        max_frequency,
+    );
+
+    let bands = options.mel_bands as f32;
+    spectrogram
+        .magnitudes
+        .iter()
+        .map(|frame| {
+            let log_energies: Vec<f32> = filterbank
+                .iter()
+                .map(|filter| {
+                    let energy: f32 = filter.iter().zip(frame).map(|(w, m)| w * m * m).sum();
+                    energy.max(1e-10).ln()
+                })
+                .collect();
+
+            std::array::from_fn(|k| {
+                let scale = if k == 0 {
+                    (1.0 / bands).sqrt()
+                } else {
+                    (2.0 / bands).sqrt()
+                };
+                scale
+                    * log_energies
+                        .iter()
+                        .enumerate()
+                        .map(|(n, e)| e * (PI * k as f32 * (n as f32 + 0.5) / bands).cos())
+                        .sum::<f32>()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_wav, WavFormat};
+    use std::io::Cursor;
+
+    #[test]
+    fn a_tone_gives_a_full_finite_vector() {
+        // a stereo 440Hz tone with a little noise on top, so no feature is trivially zero
+        let mut state = 7u32;
+        let audio: Vec<f32> = (0..44100 * 3)
+            .flat_map(|i| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let hiss = (state >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                let tone = (2.0 * PI * 440.0 * i as f32 / 44100.0).sin() * 0.5 + hiss * 0.01;
+                [tone, tone]
+            })
+            .collect();
+        let wav = encode_wav(&audio, 44100, 2, WavFormat::Float32).unwrap();
+
+        let features = feature_vector(Cursor::new(wav)).unwrap();
+        assert_eq!(features.len(), FEATURE_VECTOR_LEN);
+        assert!(features.iter().all(|f| f.is_finite()), "{:?}", features);
+        assert!((features[0] - 440.0).abs() < 2.0, "{:?}", features);
+    }
+
+    #[test]
+    fn louder_audio_only_moves_the_first_coefficient() {
+        let options = MfccOptions::for_sample_rate(16000);
+        let mut state = 3u32;
+        let voice: Vec<f32> = (0..16000)
+            .map(|i| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let hiss = (state >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                let t = i as f32 / 16000.0;
+                ((2.0 * PI * 150.0 * t).sin() + (2.0 * PI * 450.0 * t).sin() * 0.5) * 0.2
+                    + hiss * 0.01
+            })
+            .collect();
+        let louder: Vec<f32> = voice.iter().map(|s| s * 2.0).collect();
+
+        let quiet = mfcc::<13>(&voice, 16000, &options);
+        let loud = mfcc::<13>(&louder, 16000, &options);
+        assert_eq!(quiet.len(), voice.len() / options.hop + 1);
+        // twice the amplitude is four times the energy in every band, which only the overall log
+        // energy (scaled by the orthonormal DCT) sees
+        let shift = (options.mel_bands as f32).sqrt() * 4f32.ln();
+        for (q, l) in quiet.iter().zip(&loud).skip(2).take(90) {
+            assert!((l[0] - q[0] - shift).abs() < 1e-2, "{} {}", q[0], l[0]);
+            for k in 1..13 {
+                assert!((l[k] - q[k]).abs() < 1e-2, "{} {:?} {:?}", k, q, l);
+            }
+        }
+
+        // silence bottoms out at the floor instead of going to -inf
+        let silent = mfcc::<13>(&[0.0; 1600], 16000, &options);
+        for frame in &silent {
+            assert!(frame.iter().all(|c| c.is_finite()), "{:?}", frame);
+            assert!(frame[1..].iter().all(|c| c.abs() < 1e-3), "{:?}", frame);
+        }
+    }
+}
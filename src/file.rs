@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    analyze_pitch_with, decode_file, downmix_to_mono, AnalyzeOptions, AvasaraError, DecodedAudio,
+    Decoder, DownmixStrategy, PitchAnalyzer, PitchReport,
+};
+
+/// Files bigger than this (in bytes) are streamed by `AudioFile` unless told otherwise, 64MiB is
+/// about six minutes of 16 bit 44.1kHz stereo WAV, or a few hours of MP3, and decodes to roughly
+/// four times that as f32 samples.
+pub const DEFAULT_STREAMING_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// How an `AudioFile` gets at its audio, picked from the file's size when it's opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Decoded in full the first time anything needs it and kept around, so every query after
+    /// the first is just analysis.
+    InMemory,
+    /// Decoded again for every query, one `Decoder` block at a time, straight into the analysis,
+    /// the whole track is never held in memory.
+    Streaming,
+}
+
+/// An audio file on disk that picks how to decode itself by its size, small files are decoded
+/// once and cached for cheap repeated queries, big ones (above `DEFAULT_STREAMING_THRESHOLD`, or
+/// whatever `streaming_threshold` sets) are streamed through `Decoder` and `PitchAnalyzer` every
+/// time, so hour-long recordings don't need gigabytes of memory. Both give the same results, the
+/// threshold only trades memory for decoding the file more than once.
+#[derive(Debug, Clone)]
+pub struct AudioFile {
+    path: PathBuf,
+    size: u64,
+    threshold: u64,
+    cached: Option<DecodedAudio>,
+}
+
+impl AudioFile {
+    /// Opens the file at `path`, nothing is decoded yet, only its size is looked up. Fails with
+    /// `AvasaraError::Io` if it doesn't exist or can't be read.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, AvasaraError> {
+        let path = path.as_ref().to_path_buf();
+        let size = std::fs::metadata(&path)?.len();
+
+        Ok(AudioFile {
+            path,
+            size,
+            threshold: DEFAULT_STREAMING_THRESHOLD,
+            cached: None,
+        })
+    }
+
+    /// Files bigger than `bytes` are streamed, anything else is decoded into memory, see
+    /// `AccessMode`. Changing it drops whatever was cached.
+    pub fn streaming_threshold(mut self, bytes: u64) -> Self {
+        self.threshold = bytes;
+        self.cached = None;
+        self
+    }
+
+    /// Size of the file in bytes, as it was when it was opened.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether queries decode the file into memory once or stream it every time.
+    pub fn mode(&self) -> AccessMode {
+        if self.size > self.threshold {
+            AccessMode::Streaming
+        } else {
+            AccessMode::InMemory
+        }
+    }
+
+    /// The decoded audio, decoding it first if that hasn't happened yet, for running anything
+    /// else in the crate on it without decoding the file again. `None` for streamed files, which
+    /// are never decoded into memory.
+    pub fn audio(&mut self) -> Result<Option<&DecodedAudio>, AvasaraError> {
+        if self.mode() == AccessMode::Streaming {
+            return Ok(None);
+        }
+        if self.cached.is_none() {
+            self.cached = Some(decode_file(&self.path)?);
+        }
+        Ok(self.cached.as_ref())
+    }
+
+    /// The decoded audio if it's been decoded (and kept) already, doesn't decode anything.
+    pub fn cached(&self) -> Option<&DecodedAudio> {
+        self.cached.as_ref()
+    }
+
+    /// `analyze_pitch_with` on the file, folded down to mono with `DownmixStrategy::Average`
+    /// first, either on the cached audio or streamed block by block through a `PitchAnalyzer`
+    /// depending on `mode`, the report is the same either way.
+    ///
+    /// # Panics
+    /// Panics if the window or hop size is zero.
+    pub fn analyze_pitch(
+        &mut self,
+        min_frequency: f32,
+        max_frequency: f32,
+        options: &AnalyzeOptions,
+    ) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+        if let Some(audio) = self.audio()? {
+            return analyze_pitch_with(
+                &audio.to_mono(),
+                audio.sample_rate() as usize,
+                min_frequency,
+                max_frequency,
+                options,
+            );
+        }
+
+        let mut analyzer = None;
+        for block in Decoder::open(&self.path)? {
+            let block = block?;
+            let mono = downmix_to_mono(
+                &block.samples,
+                block.channels.max(1),
+                DownmixStrategy::Average,
+            );
+            analyzer
+                .get_or_insert_with(|| {
+                    PitchAnalyzer::new(
+                        block.sample_rate,
+                        min_frequency,
+                        max_frequency,
+                        options.clone(),
+                    )
+                })
+                .push_samples(&mono);
+        }
+
+        match analyzer {
+            Some(analyzer) => analyzer.finalize(),
+            // nothing decoded at all, same as analyzing an empty buffer
+            None => Err(AvasaraError::SilentInput),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_wav, WavFormat};
+
+    #[test]
+    fn big_files_stream_and_small_ones_cache() {
+        let path =
+            std::env::temp_dir().join(format!("avasara-audio-file-{}.wav", std::process::id()));
+        let tone: Vec<f32> = (0..44100 * 3)
+            .flat_map(|i| {
+                let s = (std::f32::consts::TAU * 220.0 * i as f32 / 44100.0).sin() * 0.5;
+                [s, s]
+            })
+            .collect();
+        std::fs::write(
+            &path,
+            encode_wav(&tone, 44100, 2, WavFormat::Pcm16).unwrap(),
+        )
+        .unwrap();
+
+        // a threshold below the file's size streams it and never keeps the samples around
+        let mut streamed = AudioFile::open(&path).unwrap().streaming_threshold(1024);
+        assert_eq!(streamed.mode(), AccessMode::Streaming);
+        let (streamed_report, streamed_points) = streamed
+            .analyze_pitch(50.0, 1000.0, &AnalyzeOptions::default())
+            .unwrap();
+        assert!(streamed.audio().unwrap().is_none());
+        assert!(streamed.cached().is_none());
+
+        let mut small = AudioFile::open(&path).unwrap();
+        assert_eq!(small.mode(), AccessMode::InMemory);
+        let (report, points) = small
+            .analyze_pitch(50.0, 1000.0, &AnalyzeOptions::default())
+            .unwrap();
+        assert_eq!(small.cached().unwrap().frames(), 44100 * 3);
+        assert_eq!(points, streamed_points);
+        assert_eq!(report.chunks_used, streamed_report.chunks_used);
+
+        // the cached copy answers the next query even with the file gone
+        std::fs::remove_file(&path).unwrap();
+        let (again, _) = small
+            .analyze_pitch(50.0, 1000.0, &AnalyzeOptions::default())
+            .unwrap();
+        assert!((again.mean - 220.0).abs() < 2.0, "{}", again.mean);
+    }
+}
@@ -0,0 +1,304 @@
+use std::f64::consts::PI;
+
+/// A single second order IIR section (transposed direct form II), coefficients are normalized so
+/// that a0 is always 1, designs follow the RBJ audio EQ cookbook unless noted otherwise.
+///
+/// A `Biquad` carries its filter state along, so feeding one long signal through it in pieces
+/// gives the same result as all at once, but every channel needs its own copy (see
+/// `apply_filters`). Frequencies are in Hz and must be below Nyquist, `q` sets how sharp the
+/// corner or peak is, `FRAC_1_SQRT_2` (about 0.707) is the flat Butterworth response, and two
+/// identical low or high-pass sections in a row make a steeper 24dB/octave slope.
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    /// A section straight from its coefficients, for designs the other constructors don't cover.
+    pub fn new(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// The cosine of the angular frequency and the bandwidth term (alpha) every RBJ design
+    /// starts from.
+    fn prepare(sample_rate: usize, frequency: f64, q: f64) -> (f64, f64) {
+        let w0 = 2.0 * PI * frequency / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        (cos, sin / (2.0 * q))
+    }
+
+    /// Lets everything below `cutoff` through, 12dB/octave above it.
+    pub fn lowpass(sample_rate: usize, cutoff: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::prepare(sample_rate, cutoff, q);
+
+        Self::new(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// Lets everything above `cutoff` through, 12dB/octave below it, the one for rumble, hum
+    /// and handling noise under a voice.
+    pub fn highpass(sample_rate: usize, cutoff: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::prepare(sample_rate, cutoff, q);
+
+        Self::new(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// Lets a band around `center` through (at 0dB at the center), higher `q` is a narrower
+    /// band.
+    pub fn bandpass(sample_rate: usize, center: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::prepare(sample_rate, center, q);
+        Self::new(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    /// Cuts a narrow band around `center` out completely, for a mains hum at 50 or 60Hz or a
+    /// single whistling tone, higher `q` is a narrower notch (10 or more leaves the music
+    /// around it alone).
+    pub fn notch(sample_rate: usize, center: f64, q: f64) -> Self {
+        let (cos, alpha) = Self::prepare(sample_rate, center, q);
+        Self::new(1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    /// Boosts (positive `gain_db`) or cuts (negative) a bell shaped band around `center`, the
+    /// building block of a parametric EQ.
+    pub fn peaking(sample_rate: usize, center: f64, q: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let (cos, alpha) = Self::prepare(sample_rate, center, q);
+        Self::new(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    /// Boosts or cuts everything below `cutoff` by `gain_db`, with the steepest slope that
+    /// doesn't overshoot (the cookbook's S = 1).
+    pub fn low_shelf(sample_rate: usize, cutoff: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let (cos, alpha) = Self::prepare(sample_rate, cutoff, std::f64::consts::FRAC_1_SQRT_2);
+        let root = 2.0 * a.sqrt() * alpha;
+        Self::new(
+            a * ((a + 1.0) - (a - 1.0) * cos + root),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - root),
+            (a + 1.0) + (a - 1.0) * cos + root,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - root,
+        )
+    }
+
+    /// Boosts or cuts everything above `cutoff` by `gain_db`, like `low_shelf` but for the top
+    /// end.
+    pub fn high_shelf(sample_rate: usize, cutoff: f64, gain_db: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let (cos, alpha) = Self::prepare(sample_rate, cutoff, std::f64::consts::FRAC_1_SQRT_2);
+        let root = 2.0 * a.sqrt() * alpha;
+        Self::new(
+            a * ((a + 1.0) + (a - 1.0) * cos + root),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - root),
+            (a + 1.0) - (a - 1.0) * cos + root,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - root,
+        )
+    }
+
+    /// Filters a single sample.
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    /// Filters a whole mono buffer in place, carrying the state on from whatever was processed
+    /// before.
+    pub fn process_buffer(&mut self, audio: &mut [f32]) {
+        for sample in audio {
+            *sample = self.process(*sample as f64) as f32;
+        }
+    }
+
+    /// Forgets everything processed so far, as if the section had just been made.
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+/// Runs every channel of some interleaved audio data through its own fresh copy of `chain` (the
+/// sections are applied in order) in place, e.g. `&[Biquad::highpass(sr, 80.0, FRAC_1_SQRT_2);
+/// 2]` for a steep 80Hz high-pass in front of pitch analysis. `chain` itself isn't touched, so
+/// it can be reused on the next buffer. Trailing samples that don't make up a whole frame are
+/// filtered like the channels they'd belong to.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn apply_filters(audio: &mut [f32], channels: usize, chain: &[Biquad]) {
+    assert!(channels > 0, "can't filter audio with no channels");
+    let filtered = filter_interleaved(audio, channels, chain);
+    audio.copy_from_slice(&filtered);
+}
+
+/// Runs every channel of an interleaved buffer through its own copy of `chain` (the sections are
+/// applied in order), returns a new interleaved buffer of the same length.
+pub(crate) fn filter_interleaved(audio: &[f32], channels: usize, chain: &[Biquad]) -> Vec<f32> {
+    let mut states: Vec<Vec<Biquad>> = vec![chain.to_vec(); channels];
+    let mut out = Vec::with_capacity(audio.len());
+
+    for frame in audio.chunks(channels) {
+        for (sample, sections) in frame.iter().zip(states.iter_mut()) {
+            let mut y = *sample as f64;
+            for section in sections.iter_mut() {
+                y = section.process(y);
+            }
+            out.push(y as f32);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    /// Steady-state gain (in dB) of `filter` for a sine at `frequency`, measured over the second
+    /// half of a second of it so the filter has settled.
+    fn gain_db(filter: Biquad, frequency: f64, sample_rate: usize) -> f64 {
+        let tone: Vec<f32> = (0..sample_rate)
+            .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin() as f32)
+            .collect();
+        let mut filtered = tone.clone();
+        apply_filters(&mut filtered, 1, &[filter]);
+
+        let power = |audio: &[f32]| audio.iter().map(|s| (*s as f64).powi(2)).sum::<f64>();
+        let half = sample_rate / 2;
+        10.0 * (power(&filtered[half..]) / power(&tone[half..])).log10()
+    }
+
+    #[test]
+    fn every_design_has_the_textbook_response() {
+        let sr = 48000;
+        let close = |gain: f64, expected: f64| (gain - expected).abs() < 0.3;
+
+        let lowpass = Biquad::lowpass(sr, 1000.0, FRAC_1_SQRT_2);
+        assert!(close(gain_db(lowpass, 100.0, sr), 0.0));
+        assert!(close(gain_db(lowpass, 1000.0, sr), -3.0));
+        assert!(gain_db(lowpass, 10000.0, sr) < -35.0);
+
+        let highpass = Biquad::highpass(sr, 1000.0, FRAC_1_SQRT_2);
+        assert!(close(gain_db(highpass, 10000.0, sr), 0.0));
+        assert!(close(gain_db(highpass, 1000.0, sr), -3.0));
+        assert!(gain_db(highpass, 100.0, sr) < -35.0);
+
+        let bandpass = Biquad::bandpass(sr, 2000.0, 2.0);
+        assert!(close(gain_db(bandpass, 2000.0, sr), 0.0));
+        assert!(gain_db(bandpass, 200.0, sr) < -20.0);
+
+        let notch = Biquad::notch(sr, 60.0, 10.0);
+        assert!(gain_db(notch, 60.0, sr) < -40.0);
+        assert!(close(gain_db(notch, 240.0, sr), 0.0));
+
+        let peaking = Biquad::peaking(sr, 3000.0, 1.0, 6.0);
+        assert!(close(gain_db(peaking, 3000.0, sr), 6.0));
+        assert!(close(gain_db(peaking, 100.0, sr), 0.0));
+
+        let low_shelf = Biquad::low_shelf(sr, 200.0, 6.0);
+        assert!(close(gain_db(low_shelf, 20.0, sr), 6.0));
+        assert!(close(gain_db(low_shelf, 5000.0, sr), 0.0));
+
+        let high_shelf = Biquad::high_shelf(sr, 5000.0, -6.0);
+        assert!(close(gain_db(high_shelf, 20000.0, sr), -6.0));
+        assert!(close(gain_db(high_shelf, 100.0, sr), 0.0));
+    }
+
+    #[test]
+    fn filtering_in_pieces_matches_filtering_at_once() {
+        let audio: Vec<f32> = (0..1000)
+            .map(|i| ((i * 37) % 101) as f32 / 50.0 - 1.0)
+            .collect();
+        let mut section = Biquad::lowpass(44100, 2000.0, FRAC_1_SQRT_2);
+
+        let mut whole = audio.clone();
+        section.clone().process_buffer(&mut whole);
+        let mut pieces = audio.clone();
+        for piece in pieces.chunks_mut(77) {
+            section.process_buffer(piece);
+        }
+        assert_eq!(whole, pieces);
+
+        // and a reset starts over
+        section.reset();
+        let mut again = audio;
+        section.process_buffer(&mut again);
+        assert_eq!(whole, again);
+    }
+
+    #[test]
+    fn a_highpass_in_front_of_the_analysis_clears_the_hum() {
+        // a quiet 220Hz voice over loud 50Hz mains hum
+        let audio: Vec<f32> = (0..44100 * 2)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                (2.0 * std::f32::consts::PI * 220.0 * t).sin() * 0.1
+                    + (2.0 * std::f32::consts::PI * 50.0 * t).sin() * 0.8
+            })
+            .collect();
+        let options = crate::AnalyzeOptions {
+            window_size: 2048,
+            hop_size: 2048,
+            highpass_hz: Some(120.0),
+            ..crate::AnalyzeOptions::default()
+        };
+
+        let (report, _) = crate::analyze_pitch_with(&audio, 44100, 40.0, 1000.0, &options).unwrap();
+        assert!((report.median - 220.0).abs() < 3.0, "{}", report.median);
+        let (unfiltered, _) = crate::analyze_pitch_with(
+            &audio,
+            44100,
+            40.0,
+            1000.0,
+            &crate::AnalyzeOptions {
+                highpass_hz: None,
+                ..options
+            },
+        )
+        .unwrap();
+        assert!(
+            (unfiltered.median - 50.0).abs() < 3.0,
+            "{}",
+            unfiltered.median
+        );
+    }
+}
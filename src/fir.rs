@@ -0,0 +1,180 @@
+use std::f64::consts::PI;
+
+use crate::match_loudness;
+
+/// Convolves some mono audio data with an arbitrary FIR kernel, in place, for whenever the
+/// built-in filters aren't what you need. The output is shifted back by the kernel's group delay
+/// (`(coefficients.len() - 1) / 2` samples), so with the symmetric, linear phase kernels the
+/// `*_kernel` functions design nothing moves in time, `[1.0]` (or `[0.0, 1.0, 0.0]`) leaves the
+/// audio untouched. Samples before the start and past the end are treated as silence, so the
+/// first and last half-kernel of the output fade in/out a little, pad the input yourself if that
+/// matters. An empty kernel does nothing.
+pub fn apply_fir(audio: &mut [f32], coefficients: &[f32]) {
+    if coefficients.is_empty() {
+        return;
+    }
+
+    let input = audio.to_vec();
+    let delay = (coefficients.len() - 1) / 2;
+
+    for (n, out) in audio.iter_mut().enumerate() {
+        // y[n] = sum of h[k] * x[n + delay - k], skipping whatever falls outside the input
+        *out = coefficients
+            .iter()
+            .enumerate()
+            .filter_map(|(k, h)| {
+                let i = (n + delay).checked_sub(k)?;
+                input.get(i).map(|x| h * x)
+            })
+            .sum();
+    }
+}
+
+/// Same as `apply_fir`, but measures the integrated loudness before filtering and applies makeup
+/// gain afterwards so the output is just as loud as the input (see `match_loudness`), cutting the
+/// lows out of something otherwise makes it sound quieter, which is rarely what you meant. Returns
+/// the makeup gain that was applied in dB.
+pub fn apply_fir_with_makeup_gain(
+    audio: &mut [f32],
+    sample_rate: usize,
+    coefficients: &[f32],
+) -> f64 {
+    let original = audio.to_vec();
+    apply_fir(audio, coefficients);
+    match_loudness(audio, &original, sample_rate, 1)
+}
+
+/// Blackman window of `len` samples (symmetric, for filter design).
+fn blackman(len: usize) -> Vec<f64> {
+    let m = (len - 1).max(1) as f64;
+    (0..len)
+        .map(|i| {
+            let x = i as f64 / m;
+            0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+        })
+        .collect()
+}
+
+/// Blackman windowed sinc low-pass, normalized to unity gain at DC.
+fn windowed_sinc(sample_rate: usize, cutoff: f64, taps: usize) -> Vec<f64> {
+    let fc = cutoff / sample_rate as f64;
+    let center = (taps - 1) as f64 / 2.0;
+
+    let kernel: Vec<f64> = blackman(taps)
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            let x = i as f64 - center;
+            let sinc = if x == 0.0 {
+                2.0 * fc
+            } else {
+                (2.0 * PI * fc * x).sin() / (PI * x)
+            };
+            sinc * w
+        })
+        .collect();
+
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|h| h / sum).collect()
+}
+
+/// Rounds a tap count up to the next odd number (at least 3), so the kernel has a center tap and
+/// a whole number of samples of group delay.
+fn odd_taps(taps: usize) -> usize {
+    (taps | 1).max(3)
+}
+
+/// Designs a linear phase low-pass FIR kernel (Blackman windowed sinc) for `apply_fir`, passing
+/// everything below `cutoff` Hz. More `taps` means a steeper transition band (roughly
+/// `5.5 * sample_rate / taps` Hz wide) at the cost of more work per sample, even counts are
+/// rounded up to the next odd one.
+pub fn lowpass_kernel(sample_rate: usize, cutoff: f32, taps: usize) -> Vec<f32> {
+    windowed_sinc(sample_rate, cutoff as f64, odd_taps(taps))
+        .into_iter()
+        .map(|h| h as f32)
+        .collect()
+}
+
+/// Designs a linear phase high-pass FIR kernel for `apply_fir`, passing everything above `cutoff`
+/// Hz, made by spectrally inverting the matching `lowpass_kernel`.
+pub fn highpass_kernel(sample_rate: usize, cutoff: f32, taps: usize) -> Vec<f32> {
+    let taps = odd_taps(taps);
+    let mut kernel: Vec<f32> = windowed_sinc(sample_rate, cutoff as f64, taps)
+        .into_iter()
+        .map(|h| -h as f32)
+        .collect();
+    kernel[taps / 2] += 1.0;
+    kernel
+}
+
+/// Designs a linear phase band-pass FIR kernel for `apply_fir`, passing everything between `low`
+/// and `high` Hz, the difference of two `lowpass_kernel`s.
+pub fn bandpass_kernel(sample_rate: usize, low: f32, high: f32, taps: usize) -> Vec<f32> {
+    let taps = odd_taps(taps);
+    let upper = windowed_sinc(sample_rate, high as f64, taps);
+    let lower = windowed_sinc(sample_rate, low as f64, taps);
+    upper
+        .iter()
+        .zip(&lower)
+        .map(|(u, l)| (u - l) as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// RMS of everything but the first and last `edge` samples, where the kernel runs off the
+    /// ends of the input.
+    fn inner_rms(audio: &[f32], edge: usize) -> f32 {
+        let inner = &audio[edge..audio.len() - edge];
+        (inner.iter().map(|s| s * s).sum::<f32>() / inner.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn an_identity_kernel_leaves_the_audio_alone() {
+        let tone = sine(440.0, 44100, 0.1);
+        for kernel in [&[1.0][..], &[0.0, 1.0, 0.0]] {
+            let mut filtered = tone.clone();
+            apply_fir(&mut filtered, kernel);
+            assert_eq!(filtered, tone);
+        }
+    }
+
+    #[test]
+    fn a_lowpass_kernel_keeps_the_lows_and_cuts_the_highs() {
+        let kernel = lowpass_kernel(44100, 1000.0, 255);
+        let gain = |frequency: f32| {
+            let tone = sine(frequency, 44100, 0.5);
+            let mut filtered = tone.clone();
+            apply_fir(&mut filtered, &kernel);
+            inner_rms(&filtered, kernel.len()) / inner_rms(&tone, kernel.len())
+        };
+
+        assert!((gain(200.0) - 1.0).abs() < 0.01, "{}", gain(200.0));
+        assert!(gain(5000.0) < 0.001, "{}", gain(5000.0));
+    }
+
+    #[test]
+    fn makeup_gain_keeps_the_loudness_after_a_lowpass() {
+        // most of the loudness is up where the low-pass cuts
+        let original: Vec<f32> = sine(200.0, 48000, 3.0)
+            .iter()
+            .zip(sine(3000.0, 48000, 3.0))
+            .map(|(low, high)| low * 0.1 + high * 0.3)
+            .collect();
+        let mut filtered = original.clone();
+
+        let gain_db =
+            apply_fir_with_makeup_gain(&mut filtered, 48000, &lowpass_kernel(48000, 1000.0, 255));
+        assert!(gain_db > 6.0, "{}", gain_db);
+        let difference = crate::loudness_match_report(&original, &filtered, 48000, 1);
+        assert!(difference.abs() < 0.1, "{}", difference);
+    }
+}
@@ -0,0 +1,680 @@
+use crate::{
+    decode_audio, downmix_to_mono, effective_bit_depth, AvasaraError, ComposeStage,
+    DownmixStrategy, MediaSource, Progress,
+};
+
+/// Writes bits MSB first, the way every field in a FLAC stream is laid out.
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    pending: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: vec![],
+            accumulator: 0,
+            pending: 0,
+        }
+    }
+
+    /// Appends the low `bits` bits of `value` (at most 32 at a time).
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.pending += bits;
+        while self.pending >= 8 {
+            self.pending -= 8;
+            self.bytes.push((self.accumulator >> self.pending) as u8);
+        }
+    }
+
+    /// Appends a signed value as a `bits` wide two's complement number.
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// Appends `zeros` zero bits followed by a one, the unary part of a Rice code.
+    fn write_unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    /// Pads with zero bits up to the next byte boundary.
+    fn align(&mut self) {
+        if self.pending > 0 {
+            self.write(0, 8 - self.pending);
+        }
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+/// CRC-8 of a frame header (polynomial 0x07).
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// CRC-16 of a whole frame (polynomial 0x8005).
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// MD5 of the raw samples, what STREAMINFO carries so `flac -t` (and any decoder that checks)
+/// can verify the whole file decodes back to exactly what went in.
+fn md5(message: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    let constants: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend(((message.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in padded.chunks_exact(64) {
+        let words: Vec<u32> = chunk
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+/// Residual of the fixed polynomial predictor of the given order (0 to 4) for every sample past
+/// the first `order` ones.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+/// Maps a signed residual onto the unsigned numbers Rice codes are made of (0, -1, 1, -2, ...).
+fn fold(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+/// The Rice parameter (and the bits it costs) for a partition of `count` residuals whose folded
+/// values add up to `sum`, estimated from the sum alone (`count * (k + 1) + sum >> k`), which is
+/// what makes searching every partition order cheap.
+fn rice_parameter(sum: u64, count: usize, max_parameter: u32) -> (u32, u64) {
+    (0..=max_parameter)
+        .map(|k| (k, count as u64 * (k as u64 + 1) + (sum >> k)))
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap_or((0, u64::MAX))
+}
+
+/// How the residual of a subframe gets partitioned, the partition order and every partition's
+/// Rice parameter.
+struct RicePlan {
+    order: u32,
+    parameters: Vec<u32>,
+    bits: u64,
+}
+
+/// Finds the cheapest partitioning of a residual for a block of `block_len` samples predicted
+/// with `predictor_order` warm-up samples, trying every partition order up to `max_order` that
+/// divides the block evenly.
+fn plan_residual(
+    residual: &[i64],
+    block_len: usize,
+    predictor_order: usize,
+    max_order: u32,
+    max_parameter: u32,
+) -> RicePlan {
+    let folded: Vec<u64> = residual.iter().map(|r| fold(*r)).collect();
+    let mut best: Option<RicePlan> = None;
+
+    for order in 0..=max_order {
+        let partitions = 1usize << order;
+        if !block_len.is_multiple_of(partitions) || block_len / partitions <= predictor_order {
+            break;
+        }
+        let per_partition = block_len / partitions;
+
+        let mut parameters = Vec::with_capacity(partitions);
+        let mut bits = 0;
+        let mut start = 0;
+        for p in 0..partitions {
+            let count = per_partition - if p == 0 { predictor_order } else { 0 };
+            let sum: u64 = folded[start..start + count].iter().sum();
+            let (parameter, cost) = rice_parameter(sum, count, max_parameter);
+            parameters.push(parameter);
+            bits += cost + if max_parameter > 14 { 5 } else { 4 };
+            start += count;
+        }
+
+        if best.as_ref().is_none_or(|best| bits < best.bits) {
+            best = Some(RicePlan {
+                order,
+                parameters,
+                bits,
+            });
+        }
+    }
+
+    best.unwrap_or(RicePlan {
+        order: 0,
+        parameters: vec![0],
+        bits: u64::MAX,
+    })
+}
+
+/// How a single channel of a block gets stored.
+enum Subframe {
+    Constant(i64),
+    Verbatim,
+    Fixed {
+        order: usize,
+        residual: Vec<i64>,
+        plan: RicePlan,
+    },
+}
+
+/// Settings a compression level boils down to.
+#[derive(Debug, Clone, Copy)]
+struct LevelSettings {
+    block_len: usize,
+    max_fixed_order: usize,
+    max_partition_order: u32,
+    /// 0 is independent channels only, 1 adds mid/side, 2 tries every stereo decorrelation.
+    stereo_search: u8,
+}
+
+impl LevelSettings {
+    fn for_level(level: u8) -> Self {
+        let level = level.min(8);
+        LevelSettings {
+            block_len: if level <= 2 { 1152 } else { 4096 },
+            max_fixed_order: if level == 0 { 2 } else { 4 },
+            max_partition_order: match level {
+                0..=2 => 3,
+                3 => 4,
+                4 | 5 => 5,
+                _ => 6,
+            },
+            stereo_search: level.min(2),
+        }
+    }
+}
+
+/// Picks the smallest way to store one channel of a block, returns it with its size in bits.
+fn plan_subframe(
+    samples: &[i64],
+    bits_per_sample: u32,
+    settings: &LevelSettings,
+) -> (Subframe, u64) {
+    if samples.iter().all(|s| *s == samples[0]) {
+        return (Subframe::Constant(samples[0]), 8 + bits_per_sample as u64);
+    }
+
+    let verbatim_bits = 8 + samples.len() as u64 * bits_per_sample as u64;
+    let mut best = (Subframe::Verbatim, verbatim_bits);
+    // wide residuals (24 bit audio, the extra bit of a side channel) need 5 bit Rice parameters
+    let max_parameter = if bits_per_sample > 16 { 30 } else { 14 };
+
+    for order in 0..=settings
+        .max_fixed_order
+        .min(samples.len().saturating_sub(1))
+    {
+        let residual = fixed_residual(samples, order);
+        let plan = plan_residual(
+            &residual,
+            samples.len(),
+            order,
+            settings.max_partition_order,
+            max_parameter,
+        );
+        let bits = 8 + order as u64 * bits_per_sample as u64 + 6 + plan.bits;
+        if bits < best.1 {
+            best = (
+                Subframe::Fixed {
+                    order,
+                    residual,
+                    plan,
+                },
+                bits,
+            );
+        }
+    }
+
+    best
+}
+
+/// Writes a planned subframe.
+fn write_subframe(
+    writer: &mut BitWriter,
+    samples: &[i64],
+    subframe: &Subframe,
+    bits_per_sample: u32,
+) {
+    match subframe {
+        Subframe::Constant(value) => {
+            writer.write(0b0000_0000, 8);
+            writer.write_signed(*value, bits_per_sample);
+        }
+        Subframe::Verbatim => {
+            writer.write(0b0000_0010, 8);
+            for sample in samples {
+                writer.write_signed(*sample, bits_per_sample);
+            }
+        }
+        Subframe::Fixed {
+            order,
+            residual,
+            plan,
+        } => {
+            // zero padding bit, type 001xxx with the order, no wasted bits
+            writer.write(((0b001000 | *order as u64) << 1) & 0x7e, 8);
+            for sample in &samples[..*order] {
+                writer.write_signed(*sample, bits_per_sample);
+            }
+
+            let wide = plan.parameters.iter().any(|k| *k > 14);
+            writer.write(if wide { 0b01 } else { 0b00 }, 2);
+            writer.write(plan.order as u64, 4);
+            let per_partition = samples.len() >> plan.order;
+            let mut start = 0;
+            for (p, parameter) in plan.parameters.iter().enumerate() {
+                let count = per_partition - if p == 0 { *order } else { 0 };
+                writer.write(*parameter as u64, if wide { 5 } else { 4 });
+                for r in &residual[start..start + count] {
+                    let folded = fold(*r);
+                    writer.write_unary(folded >> parameter);
+                    writer.write(folded, *parameter);
+                }
+                start += count;
+            }
+        }
+    }
+}
+
+/// Appends a frame number in FLAC's extended UTF-8 coding.
+fn write_utf8(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write(value, 8);
+        return;
+    }
+    let mut continuation = 1;
+    while value >= 1u64 << (6 * continuation + (6 - continuation)) {
+        continuation += 1;
+    }
+    let marker = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+    writer.write(marker | (value >> (6 * continuation)), 8);
+    for i in (0..continuation).rev() {
+        writer.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+/// Encodes a single frame of `channels` planar channels, `number` is its position in the stream.
+fn encode_frame(
+    channels: &[Vec<i64>],
+    number: u64,
+    bits_per_sample: u32,
+    settings: &LevelSettings,
+) -> Vec<u8> {
+    let block_len = channels[0].len();
+
+    // (channel assignment, the channels as stored, their bit depths)
+    let mut assignment = (
+        channels.len() as u64 - 1,
+        channels.to_vec(),
+        vec![bits_per_sample; channels.len()],
+    );
+    let mut planned: Vec<(Subframe, u64)> = assignment
+        .1
+        .iter()
+        .map(|samples| plan_subframe(samples, bits_per_sample, settings))
+        .collect();
+
+    if channels.len() == 2 && settings.stereo_search > 0 {
+        let (left, right) = (&channels[0], &channels[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let side_plan = plan_subframe(&side, bits_per_sample + 1, settings);
+        let mid_plan = plan_subframe(&mid, bits_per_sample, settings);
+
+        let independent = planned[0].1 + planned[1].1;
+        let mut best = (independent, 0);
+        if mid_plan.1 + side_plan.1 < best.0 {
+            best = (mid_plan.1 + side_plan.1, 0b1010);
+        }
+        if settings.stereo_search > 1 {
+            if planned[0].1 + side_plan.1 < best.0 {
+                best = (planned[0].1 + side_plan.1, 0b1000);
+            }
+            if side_plan.1 + planned[1].1 < best.0 {
+                best = (side_plan.1 + planned[1].1, 0b1001);
+            }
+        }
+
+        let wide = bits_per_sample + 1;
+        match best.1 {
+            0b1010 => {
+                assignment = (0b1010, vec![mid, side], vec![bits_per_sample, wide]);
+                planned = vec![mid_plan, side_plan];
+            }
+            0b1000 => {
+                assignment = (
+                    0b1000,
+                    vec![left.clone(), side],
+                    vec![bits_per_sample, wide],
+                );
+                planned = vec![planned.remove(0), side_plan];
+            }
+            0b1001 => {
+                assignment = (
+                    0b1001,
+                    vec![side, right.clone()],
+                    vec![wide, bits_per_sample],
+                );
+                planned = vec![side_plan, planned.remove(1)];
+            }
+            _ => {}
+        }
+    }
+
+    let mut writer = BitWriter::new();
+    // sync code, fixed blocking, block size as 16 bits at the end of the header, sample rate
+    // from STREAMINFO
+    writer.write(0b1111_1111_1111_1000, 16);
+    writer.write(0b0111, 4);
+    writer.write(0b0000, 4);
+    writer.write(assignment.0, 4);
+    writer.write(if bits_per_sample == 16 { 0b100 } else { 0b110 }, 3);
+    writer.write(0, 1);
+    write_utf8(&mut writer, number);
+    writer.write(block_len as u64 - 1, 16);
+    let header_crc = crc8(&writer.bytes);
+    writer.write(header_crc as u64, 8);
+
+    for ((samples, (subframe, _)), bits) in assignment.1.iter().zip(&planned).zip(&assignment.2) {
+        write_subframe(&mut writer, samples, subframe, *bits);
+    }
+
+    let mut frame = writer.into_bytes();
+    let crc = crc16(&frame);
+    frame.extend(crc.to_be_bytes());
+    frame
+}
+
+/// Encodes some audio data (interleaved already if it has more than one channel) into a FLAC
+/// file in memory, for archival copies that need to stay bit-exact. `bits_per_sample` is 16 or
+/// 24, samples are scaled to that and clipped to full scale, so audio decoded from a 16 bit
+/// source survives a round trip through a 16 bit FLAC file unchanged (use `effective_bit_depth`
+/// to find out what a file actually needs, or just let `compose_to_flac` pick).
+///
+/// `compression_level` goes from 0 (fastest) to 8 (smallest) like the reference encoder's, higher
+/// levels use longer blocks, search more predictor and partition orders and try every stereo
+/// decorrelation. Only the fixed polynomial predictors are used (no LPC), so files come out
+/// somewhat larger than the reference encoder's at the same level, but every FLAC decoder reads
+/// them, and the STREAMINFO block carries the MD5 of the audio so `flac -t` can verify them.
+/// Trailing samples that don't make up a whole frame are dropped.
+///
+/// # Panics
+/// Panics if `channels` isn't between 1 and 8, `bits_per_sample` isn't 16 or 24, or if
+/// `sample_rate` is zero or above 655350Hz (FLAC's limit).
+pub fn encode_flac(
+    audio: &[f32],
+    sample_rate: usize,
+    channels: usize,
+    bits_per_sample: u32,
+    compression_level: u8,
+) -> Vec<u8> {
+    assert!(
+        (1..=8).contains(&channels),
+        "FLAC takes between 1 and 8 channels"
+    );
+    assert!(
+        bits_per_sample == 16 || bits_per_sample == 24,
+        "only 16 and 24 bit FLAC files can be written"
+    );
+    assert!(
+        sample_rate > 0 && sample_rate <= 655350,
+        "FLAC sample rates go from 1 to 655350Hz"
+    );
+    let settings = LevelSettings::for_level(compression_level);
+
+    let frames = audio.len() / channels;
+    let scale = (1i64 << (bits_per_sample - 1)) as f64;
+    let (min, max) = (-scale as i64, scale as i64 - 1);
+    let quantized: Vec<i64> = audio[..frames * channels]
+        .iter()
+        .map(|sample| ((*sample as f64 * scale).round() as i64).clamp(min, max))
+        .collect();
+
+    let bytes_per_sample = bits_per_sample as usize / 8;
+    let raw: Vec<u8> = quantized
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes().into_iter().take(bytes_per_sample))
+        .collect();
+
+    let mut encoded_frames = vec![];
+    for (number, block) in quantized.chunks(settings.block_len * channels).enumerate() {
+        let planar: Vec<Vec<i64>> = (0..channels)
+            .map(|c| block.iter().skip(c).step_by(channels).copied().collect())
+            .collect();
+        encoded_frames.push(encode_frame(
+            &planar,
+            number as u64,
+            bits_per_sample,
+            &settings,
+        ));
+    }
+
+    let min_frame = encoded_frames.iter().map(Vec::len).min().unwrap_or(0);
+    let max_frame = encoded_frames.iter().map(Vec::len).max().unwrap_or(0);
+    let min_block = if frames <= settings.block_len {
+        frames
+    } else {
+        settings.block_len
+    };
+
+    let mut info = BitWriter::new();
+    info.write(min_block.max(16).min(settings.block_len) as u64, 16);
+    info.write(settings.block_len as u64, 16);
+    info.write(min_frame as u64, 24);
+    info.write(max_frame as u64, 24);
+    info.write(sample_rate as u64, 20);
+    info.write(channels as u64 - 1, 3);
+    info.write(bits_per_sample as u64 - 1, 5);
+    info.write((frames as u64) >> 32, 4);
+    info.write(frames as u64, 32);
+    let mut info = info.into_bytes();
+    info.extend(md5(&raw));
+
+    let mut flac = b"fLaC".to_vec();
+    // the last (and only) metadata block, STREAMINFO, 34 bytes
+    flac.extend([0x80, 0, 0, 34]);
+    flac.extend(info);
+    for frame in encoded_frames {
+        flac.extend(frame);
+    }
+    flac
+}
+
+/// The FLAC counterpart to `compose_to_ogg`, for archival copies, decodes `src`, folds it down to
+/// mono (the plain average of every channel, like `compose_to_wav`) and encodes it with
+/// `encode_flac` at the given `compression_level` (0 to 8), in 16 bits if the decoded audio
+/// doesn't use any more than that (anything from a CD, MP3 or other 16 bit source) and 24 bits
+/// otherwise. `on_progress` gets every stage as it starts, the same way
+/// `ComposeBuilder::on_progress` does (without percentages, and `Interleaving` only for sources
+/// with more than one channel), pass `|_| {}` to ignore them. Fails if decoding does, or with
+/// `AvasaraError::DecodeFailed` if the sample rate is above what FLAC can store.
+pub fn compose_to_flac<S: MediaSource + 'static>(
+    src: S,
+    compression_level: u8,
+    on_progress: impl Fn(Progress),
+) -> Result<Vec<u8>, AvasaraError> {
+    let report = |stage| {
+        on_progress(Progress {
+            stage,
+            percent: None,
+        })
+    };
+
+    report(ComposeStage::Decoding);
+    let (audio, sample_rate, channels) = decode_audio(src)?.into_parts();
+    if (sample_rate == 0) || (channels == 0) || (sample_rate > 655350) {
+        return Err(AvasaraError::DecodeFailed);
+    }
+
+    if channels > 1 {
+        report(ComposeStage::Interleaving);
+    }
+    let audio = downmix_to_mono(&audio, channels, DownmixStrategy::Average);
+
+    let bits = if effective_bit_depth(&audio) <= 16 {
+        16
+    } else {
+        24
+    };
+    report(ComposeStage::Encoding);
+    let out = encode_flac(&audio, sample_rate, 1, bits, compression_level);
+    report(ComposeStage::Done);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A stereo tone with a little noise on top, every sample on the grid of a `bits` deep file so
+    /// it can come back unchanged.
+    fn on_grid(bits: u32, frames: usize) -> Vec<f32> {
+        let scale = (1i32 << (bits - 1)) as f32;
+        let mut state = 0x1234_5678u32;
+        (0..frames)
+            .flat_map(|i| {
+                let tone = (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin() * 0.5;
+                [tone, -tone * 0.7].map(|sample| {
+                    state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                    let noise = (state >> 8) as f32 / (1 << 24) as f32 * 0.02 - 0.01;
+                    ((sample + noise) * scale).round() / scale
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_level_decodes_back_to_the_exact_samples() {
+        for bits in [16, 24] {
+            let audio = on_grid(bits, 44100);
+            let mut sizes = vec![];
+            for level in [0, 3, 5, 8] {
+                let flac = encode_flac(&audio, 44100, 2, bits, level);
+                sizes.push(flac.len());
+                let (decoded, sample_rate, channels) =
+                    decode_audio(Cursor::new(flac)).unwrap().into_parts();
+
+                assert_eq!((sample_rate, channels), (44100, 2));
+                assert_eq!(decoded.len(), audio.len(), "{} bits, level {}", bits, level);
+                assert!(
+                    decoded == audio,
+                    "{} bits, level {} didn't round trip",
+                    bits,
+                    level
+                );
+            }
+            assert!(sizes[3] <= sizes[0], "{:?}", sizes);
+        }
+    }
+
+    #[test]
+    fn composing_reports_through_the_callback() {
+        let stereo = on_grid(16, 4410);
+        let source = crate::encode_wav(&stereo, 44100, 2, crate::WavFormat::Pcm16).unwrap();
+
+        let stages = std::cell::RefCell::new(vec![]);
+        let flac = compose_to_flac(Cursor::new(source), 5, |progress| {
+            stages.borrow_mut().push(progress.stage)
+        })
+        .unwrap();
+        assert_eq!(
+            stages.into_inner(),
+            [
+                ComposeStage::Decoding,
+                ComposeStage::Interleaving,
+                ComposeStage::Encoding,
+                ComposeStage::Done
+            ]
+        );
+        let (mono, _, channels) = decode_audio(Cursor::new(flac)).unwrap().into_parts();
+        assert_eq!((mono.len(), channels), (4410, 1));
+    }
+}
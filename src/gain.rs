@@ -0,0 +1,118 @@
+/// Multiplies some audio data (interleaved or not, every sample gets the same treatment) by a
+/// gain in dB, +6.0 roughly doubles the amplitude and -6.0 halves it. Nothing is clipped, float
+/// audio can go past full scale, so check with `peak_dbfs` (or use `normalize_peak`) before
+/// converting to integer samples.
+pub fn apply_gain_db(audio: &mut [f32], gain_db: f32) {
+    let gain = 10f32.powf(gain_db / 20.0);
+    for sample in audio {
+        *sample *= gain;
+    }
+}
+
+/// Scales some audio data so its highest sample peak lands exactly on `target_dbfs` (-1.0 leaves
+/// a bit of headroom for lossy encoders, whose output can overshoot the input's peaks), up or
+/// down, and returns the gain that took in dB. Across every channel if the audio is interleaved,
+/// so the balance between them stays the same. Digital silence is left alone and gets 0.0 back.
+pub fn normalize_peak(audio: &mut [f32], target_dbfs: f32) -> f32 {
+    let peak = audio.iter().fold(0.0f32, |peak, s| s.abs().max(peak));
+    if peak == 0.0 {
+        return 0.0;
+    }
+
+    let gain_db = target_dbfs - 20.0 * peak.log10();
+    apply_gain_db(audio, gain_db);
+    gain_db
+}
+
+/// Gain of a fade `position` of the way through it (0.0 to 1.0), a raised cosine so it starts
+/// and ends without a kink, which a plain linear ramp has and which can be audible on short fades.
+fn fade_curve(position: f32) -> f32 {
+    0.5 - 0.5 * (std::f32::consts::PI * position).cos()
+}
+
+/// Fades the first `duration_secs` of some interleaved audio data in from silence, for getting
+/// rid of the click a cut in the middle of a waveform makes at the start of a file, a few
+/// milliseconds is enough for that, a second or more is a musical fade. A fade longer than the
+/// audio covers all of it, the gain only reaches 1.0 at its very end. Trailing samples that don't
+/// make up a whole frame are left alone.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn fade_in(audio: &mut [f32], channels: usize, sample_rate: usize, duration_secs: f64) {
+    assert!(channels > 0, "can't fade audio with no channels");
+    let length = (duration_secs * sample_rate as f64).round().max(0.0) as usize;
+
+    for (i, frame) in audio.chunks_exact_mut(channels).take(length).enumerate() {
+        let gain = fade_curve(i as f32 / length as f32);
+        for sample in frame {
+            *sample *= gain;
+        }
+    }
+}
+
+/// Same as `fade_in`, but fades the last `duration_secs` of the audio out to silence, for the
+/// click at the end of a file. The very last frame ends up at exactly zero.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn fade_out(audio: &mut [f32], channels: usize, sample_rate: usize, duration_secs: f64) {
+    assert!(channels > 0, "can't fade audio with no channels");
+    let length = (duration_secs * sample_rate as f64).round().max(0.0) as usize;
+
+    for (i, frame) in audio
+        .chunks_exact_mut(channels)
+        .rev()
+        .take(length)
+        .enumerate()
+    {
+        let gain = fade_curve(i as f32 / length as f32);
+        for sample in frame {
+            *sample *= gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_and_peak_normalization() {
+        let mut audio = vec![0.25, -0.5, 0.1];
+        apply_gain_db(&mut audio, 20.0 * 2f32.log10());
+        for (sample, expected) in audio.iter().zip([0.5, -1.0, 0.2]) {
+            assert!((sample - expected).abs() < 1e-6, "{:?}", audio);
+        }
+
+        let gain = normalize_peak(&mut audio, -6.0);
+        assert!((gain + 6.0).abs() < 1e-4, "{}", gain);
+        assert!(
+            (audio[1] + 10f32.powf(-6.0 / 20.0)).abs() < 1e-6,
+            "{:?}",
+            audio
+        );
+        // the balance between samples is kept
+        assert!((audio[0] / audio[1] + 0.5).abs() < 1e-6, "{:?}", audio);
+
+        let mut silence = vec![0.0; 10];
+        assert_eq!(normalize_peak(&mut silence, -1.0), 0.0);
+        assert!(silence.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn fades_start_and_end_at_silence_and_leave_the_middle_alone() {
+        // a second of stereo at 1kHz, 100ms fades
+        let mut audio = vec![1.0; 2000];
+        fade_in(&mut audio, 2, 1000, 0.1);
+        fade_out(&mut audio, 2, 1000, 0.1);
+
+        assert_eq!(audio[..2], [0.0, 0.0]);
+        assert_eq!(audio[1998..], [0.0, 0.0]);
+        // halfway through the fade in, both channels alike
+        assert!((audio[100] - 0.5).abs() < 1e-6 && audio[100] == audio[101]);
+        // rising all the way in, untouched in between
+        assert!(audio[..200].windows(4).all(|w| w[0] <= w[2]));
+        assert!(audio[200..1800].iter().all(|s| *s == 1.0));
+        assert!(audio[1800..].windows(4).all(|w| w[0] >= w[2]));
+    }
+}
@@ -0,0 +1,362 @@
+use std::path::Path;
+
+use crate::spectrum::{stft, WindowFunction};
+use crate::AvasaraError;
+
+/// An 8 bit RGB image, what `render_spectrogram` draws into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbImage {
+    pub width: u32,
+    pub height: u32,
+    /// Rows top to bottom, every pixel as 3 bytes (red, green, blue) left to right.
+    pub pixels: Vec<u8>,
+}
+
+impl RgbImage {
+    /// The `[r, g, b]` of the pixel at `x`, `y` (counted from the top left).
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` is outside the image.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        assert!(x < self.width && y < self.height, "pixel outside the image");
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// The image as a PNG file. The pixel data is stored without compression (the deflate
+    /// stream only has stored blocks), so files are about `width * height * 3` bytes, big but
+    /// readable by anything that opens PNGs, and no compression library is needed.
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = vec![];
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+        png_chunk(&mut png, b"IHDR", &header);
+
+        // every scanline starts with its filter type, always 0 (none) here
+        let row_len = self.width as usize * 3;
+        let mut raw = Vec::with_capacity((row_len + 1) * self.height as usize);
+        for row in self
+            .pixels
+            .chunks(row_len.max(1))
+            .take(self.height as usize)
+        {
+            raw.push(0);
+            raw.extend(row);
+        }
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Writes the image to `path` as a PNG file, see `encode_png`.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), AvasaraError> {
+        std::fs::write(path, self.encode_png())?;
+        Ok(())
+    }
+}
+
+/// CRC-32 (the ISO-HDLC one PNG and zip use) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Adler-32 of `data`, the checksum that ends a zlib stream.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// `data` wrapped in a zlib stream made of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, no preset dictionary, header check bits making it a multiple of 31
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(u16::MAX as usize).collect()
+    };
+    let last = blocks.len() - 1;
+    for (i, block) in blocks.into_iter().enumerate() {
+        // BFINAL on the last block, BTYPE 00 (stored), then the length and its complement
+        zlib.push((i == last) as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(data).to_be_bytes());
+    zlib
+}
+
+/// Appends a PNG chunk (length, type, data and the CRC of the type and data) to `png`.
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// The colors `render_spectrogram` maps levels to, from the floor up to the loudest bin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectrogramColors {
+    /// Black through purple, red and yellow to white, the usual heat map, easiest to read quiet
+    /// detail off.
+    #[default]
+    Heat,
+    /// Black to white.
+    Grayscale,
+}
+
+impl SpectrogramColors {
+    /// The color of `level`, 0 being the floor and 1 the loudest bin.
+    fn color(&self, level: f32) -> [u8; 3] {
+        let level = level.clamp(0.0, 1.0);
+        match self {
+            SpectrogramColors::Grayscale => {
+                let v = (level * 255.0).round() as u8;
+                [v, v, v]
+            }
+            SpectrogramColors::Heat => {
+                const STOPS: [[f32; 3]; 5] = [
+                    [0.0, 0.0, 0.0],
+                    [90.0, 20.0, 130.0],
+                    [220.0, 50.0, 50.0],
+                    [250.0, 200.0, 40.0],
+                    [255.0, 255.0, 255.0],
+                ];
+                let position = level * (STOPS.len() - 1) as f32;
+                let i = (position as usize).min(STOPS.len() - 2);
+                let t = position - i as f32;
+                let mix = |c: usize| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * t).round();
+                [mix(0) as u8, mix(1) as u8, mix(2) as u8]
+            }
+        }
+    }
+}
+
+/// What `render_spectrogram` draws and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectrogramOptions {
+    /// STFT frame length in samples, every bin is one pixel tall, so this sets the height too
+    /// (`frame_len / 2 + 1` pixels before `max_frequency` cuts it down).
+    pub frame_len: usize,
+    /// STFT hop in samples, every frame is one pixel wide.
+    pub hop: usize,
+    pub window: WindowFunction,
+    /// The level (in dB below the loudest bin) that's drawn as the bottom color, anything quieter
+    /// is drawn the same.
+    pub floor_db: f32,
+    /// Bins above this frequency (in Hz) are left out, `None` draws all the way to Nyquist.
+    pub max_frequency: Option<f32>,
+    pub colors: SpectrogramColors,
+}
+
+impl Default for SpectrogramOptions {
+    /// 2048 sample Hann frames every 512 samples, 90dB of range, up to Nyquist, heat colors.
+    fn default() -> Self {
+        SpectrogramOptions {
+            frame_len: 2048,
+            hop: 512,
+            window: WindowFunction::Hann,
+            floor_db: -90.0,
+            max_frequency: None,
+            colors: SpectrogramColors::Heat,
+        }
+    }
+}
+
+/// Draws a spectrogram of some mono audio data for eyeballing it, time runs left to right (one
+/// pixel per STFT frame) and frequency bottom to top (one pixel per bin, linear), every pixel
+/// colored by its level in dB relative to the loudest bin in the whole thing, from
+/// `options.floor_db` up to 0dB. Built on `stft`, so a frame of 2048 samples at 44.1kHz gives
+/// 21.5Hz per pixel, and an hour of audio at a hop of 512 is over 300000 pixels wide, raise the
+/// hop (or cut an excerpt) for long files. Save it with `RgbImage::save_png`. Silent audio gives
+/// an image that's the floor color all over, audio shorter than a frame is zero padded into a
+/// single frame, one pixel wide.
+///
+/// # Panics
+/// Panics if `options.frame_len` or `options.hop` is zero.
+pub fn render_spectrogram(
+    audio: &[f32],
+    sample_rate: usize,
+    options: &SpectrogramOptions,
+) -> RgbImage {
+    let spectrogram = stft(
+        audio,
+        sample_rate,
+        options.frame_len,
+        options.hop,
+        options.window,
+    );
+
+    let bins = match options.max_frequency {
+        Some(max) => (0..spectrogram.bins())
+            .take_while(|bin| spectrogram.bin_frequency(*bin) <= max)
+            .count()
+            .max(1),
+        None => spectrogram.bins(),
+    };
+    let width = spectrogram.frames();
+
+    let loudest = spectrogram
+        .magnitudes
+        .iter()
+        .flat_map(|frame| frame[..bins].iter())
+        .fold(0.0f32, |loudest, magnitude| loudest.max(*magnitude));
+    let floor_db = options.floor_db.min(-f32::EPSILON);
+
+    let mut pixels = vec![0; width * bins * 3];
+    for (x, frame) in spectrogram.magnitudes.iter().enumerate() {
+        for (bin, magnitude) in frame[..bins].iter().enumerate() {
+            let db = if loudest > 0.0 {
+                20.0 * (magnitude / loudest).max(f32::MIN_POSITIVE).log10()
+            } else {
+                floor_db
+            };
+            // the lowest bin goes on the bottom row
+            let y = bins - 1 - bin;
+            let i = (y * width + x) * 3;
+            pixels[i..i + 3].copy_from_slice(&options.colors.color(1.0 - db / floor_db));
+        }
+    }
+
+    RgbImage {
+        width: width as u32,
+        height: bins as u32,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_their_check_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(b""), 1);
+        // long enough for the modulo to kick in more than once
+        let long = vec![0xff; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for byte in &long {
+            a = (a + *byte as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&long), ((b << 16) | a) as u32);
+    }
+
+    /// Splits a PNG into its chunks' types and data, checking the signature and every CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        let mut chunks = vec![];
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc32(body), crc, "bad CRC at byte {}", at);
+            chunks.push((body[..4].try_into().unwrap(), body[4..].to_vec()));
+            at += 12 + len;
+        }
+        chunks
+    }
+
+    /// Unpacks a zlib stream of stored blocks, checking its header and Adler-32.
+    fn unstore(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let mut data = vec![];
+        let mut at = 2;
+        loop {
+            let last = zlib[at] & 1 == 1;
+            assert_eq!(zlib[at] >> 1, 0, "not a stored block");
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+            let check = u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]);
+            assert_eq!(len, !check);
+            data.extend(&zlib[at + 5..at + 5 + len as usize]);
+            at += 5 + len as usize;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(zlib[at..], adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn the_png_holds_every_pixel() {
+        // big enough for the pixels to take more than one stored block
+        let (width, height) = (300u32, 100u32);
+        let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let image = RgbImage {
+            width,
+            height,
+            pixels: pixels.clone(),
+        };
+
+        let chunks = chunks(&image.encode_png());
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        let header = &chunks[0].1;
+        assert_eq!(header[..8], [0, 0, 1, 44, 0, 0, 0, 100]);
+        assert_eq!(header[8..], [8, 2, 0, 0, 0]);
+
+        let raw = unstore(&chunks[1].1);
+        assert!(raw.len() > u16::MAX as usize);
+        let row_len = width as usize * 3;
+        assert_eq!(raw.len(), (row_len + 1) * height as usize);
+        for (y, row) in raw.chunks(row_len + 1).enumerate() {
+            assert_eq!(row[0], 0);
+            assert_eq!(row[1..], pixels[y * row_len..(y + 1) * row_len]);
+        }
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn a_tone_is_the_brightest_row() {
+        let audio: Vec<f32> = (0..22050)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 22050.0).sin())
+            .collect();
+        let options = SpectrogramOptions {
+            colors: SpectrogramColors::Grayscale,
+            ..SpectrogramOptions::default()
+        };
+        let image = render_spectrogram(&audio, 22050, &options);
+
+        let x = image.width / 2;
+        let brightest = (0..image.height)
+            .max_by_key(|y| image.pixel(x, *y)[0])
+            .unwrap();
+        // the lowest bin is on the bottom row
+        let bin = (image.height - 1 - brightest) as f32;
+        let hz_per_bin = 22050.0 / options.frame_len as f32;
+        assert!((bin * hz_per_bin - 1000.0).abs() <= hz_per_bin, "{}", bin);
+        assert_eq!(image.pixel(x, brightest), [255, 255, 255]);
+    }
+}
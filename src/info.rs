@@ -0,0 +1,182 @@
+use symphonia::core::codecs::{CodecParameters, CODEC_TYPE_NULL};
+use symphonia::core::meta::StandardTagKey;
+
+use crate::stream::{codec_name, initial_tags, probe, tag_pair, tag_value};
+use crate::{AvasaraError, MediaSource};
+
+/// What `probe_info` found out about a file without decoding it. Everything but `codec` and
+/// `tags` is optional because not every container stores it up front (a raw MP3 stream doesn't
+/// know its own length, lossy codecs have no bit depth and so on).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    /// Short name of the codec of the first audio track, like `"flac"`, `"mp3"` or `"pcm_s16le"`.
+    pub codec: String,
+    pub sample_rate: Option<usize>,
+    pub channels: Option<usize>,
+    /// Which channels there are, like `"FRONT_LEFT | FRONT_RIGHT"`, in the order they're
+    /// interleaved.
+    pub channel_layout: Option<String>,
+    /// Length of the track in seconds, as declared by the container (so a file with a lying or
+    /// truncated header reports the wrong length here, `decode` will tell the truth).
+    pub duration_secs: Option<f64>,
+    /// Bits per sample of the stored audio, only known for PCM and lossless codecs, see
+    /// `effective_bit_depth` for whether they're actually used.
+    pub bit_depth: Option<u32>,
+    /// Artist and title, picked out of `tags` when the tag format has a recognized key for them.
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    /// Every tag as (key, value) pairs, keys exactly as the file has them (so `"TPE1"` in ID3,
+    /// `"ARTIST"` in Vorbis comments), tags in front of the container (ID3v2) come first.
+    pub tags: Vec<(String, String)>,
+}
+
+/// Length of a track in seconds as its container declares it, `None` if it doesn't.
+fn duration_secs(params: &CodecParameters) -> Option<f64> {
+    match (params.n_frames, params.sample_rate, params.time_base) {
+        (Some(frames), Some(rate), _) => Some(frames as f64 / rate as f64),
+        (Some(frames), None, Some(time_base)) => {
+            let time = time_base.calc_time(frames);
+            Some(time.seconds as f64 + time.frac)
+        }
+        _ => None,
+    }
+}
+
+/// Reads a file's header and metadata without decoding any audio, for deciding what to do with
+/// it (is it long enough, is it already mono, who's it by) before paying for a full `decode`.
+/// Takes the same sources as `decode` and looks at the same track (the first audio one). Only
+/// the tags present at the start are returned, tags that change partway through a stream show
+/// up through `Decoder::tags` instead. Fails the same way `Decoder::new` does.
+pub fn probe_info<S: MediaSource + 'static>(src: S) -> Result<MediaInfo, AvasaraError> {
+    let mut probed = probe(src)?;
+    let tags = initial_tags(&mut probed);
+
+    // artist and title, from whichever metadata has them first
+    let standard = |key: StandardTagKey| {
+        tags.iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(tag_value)
+    };
+    let artist = standard(StandardTagKey::Artist);
+    let title = standard(StandardTagKey::TrackTitle);
+
+    let params = &probed
+        .format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(AvasaraError::DecodeFailed)?
+        .codec_params;
+
+    Ok(MediaInfo {
+        codec: codec_name(params),
+        sample_rate: params.sample_rate.map(|rate| rate as usize),
+        channels: params.channels.map(|channels| channels.count()),
+        channel_layout: params.channels.map(|channels| format!("{:?}", channels)),
+        duration_secs: duration_secs(params),
+        bit_depth: params.bits_per_sample,
+        artist,
+        title,
+        tags: tags.iter().map(tag_pair).collect(),
+    })
+}
+
+/// One audio track of a container, as listed by `list_tracks`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+    /// Position among the file's audio tracks, what `TrackSelector::Index` takes.
+    pub index: usize,
+    /// The container's own id for the track (MKV track numbers, MP4 track ids).
+    pub id: u32,
+    /// Short name of the codec, like `"flac"`, `"aac"` or `"opus"`, what `TrackSelector::Codec`
+    /// takes.
+    pub codec: String,
+    /// Language tag, if the container has one (MKV uses ISO 639-2 codes like `"eng"`).
+    pub language: Option<String>,
+    pub sample_rate: Option<usize>,
+    pub channels: Option<usize>,
+    /// Length in seconds as declared by the container, like `MediaInfo::duration_secs`.
+    pub duration_secs: Option<f64>,
+}
+
+/// Lists every audio track in a file without decoding any of them, for picking one to hand to
+/// `decode_track` (or just showing the user what's there). Most files have exactly one, MKV and
+/// MP4 files can have several (dubs, commentaries, a lossless and a lossy version). Subtitle and
+/// video tracks aren't listed. Fails like `probe_info` does, an empty list means the file has
+/// no audio at all.
+pub fn list_tracks<S: MediaSource + 'static>(src: S) -> Result<Vec<TrackInfo>, AvasaraError> {
+    let probed = probe(src)?;
+
+    Ok(probed
+        .format
+        .tracks()
+        .iter()
+        .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .enumerate()
+        .map(|(index, track)| {
+            let params = &track.codec_params;
+            TrackInfo {
+                index,
+                id: track.id,
+                codec: codec_name(params),
+                language: track.language.clone(),
+                sample_rate: params.sample_rate.map(|rate| rate as usize),
+                channels: params.channels.map(|channels| channels.count()),
+                duration_secs: duration_secs(params),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A 16 bit stereo WAV file of `frames` silent frames with a RIFF INFO chunk (artist and
+    /// title) in front of the audio, where the tags of a WAV file usually live.
+    fn tagged_wav(frames: usize) -> Vec<u8> {
+        let wav =
+            crate::encode_wav(&vec![0.0; frames * 2], 44100, 2, crate::WavFormat::Pcm16).unwrap();
+
+        let mut info = b"INFO".to_vec();
+        for (key, value) in [(b"IART", &b"Someone\0"[..]), (b"INAM", &b"A Song\0\0"[..])] {
+            info.extend(key);
+            info.extend((value.len() as u32).to_le_bytes());
+            info.extend(value);
+        }
+        let mut list = b"LIST".to_vec();
+        list.extend((info.len() as u32).to_le_bytes());
+        list.extend(info);
+
+        // RIFF header and the 16 byte fmt chunk, then the list, then the data chunk
+        let mut tagged = wav[..36].to_vec();
+        tagged.extend(&list);
+        tagged.extend(&wav[36..]);
+        let riff_len = (tagged.len() - 8) as u32;
+        tagged[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        tagged
+    }
+
+    #[test]
+    fn probing_reads_the_header_and_tags() {
+        let info = probe_info(Cursor::new(tagged_wav(88200))).unwrap();
+
+        assert_eq!(info.codec, "pcm_s16le");
+        assert_eq!(info.sample_rate, Some(44100));
+        assert_eq!(info.channels, Some(2));
+        assert_eq!(info.bit_depth, Some(16));
+        assert!(
+            (info.duration_secs.unwrap() - 2.0).abs() < 1e-6,
+            "{:?}",
+            info
+        );
+        assert_eq!(info.artist.as_deref(), Some("Someone"));
+        assert_eq!(info.title.as_deref(), Some("A Song"));
+        assert_eq!(info.tags.len(), 2, "{:?}", info.tags);
+
+        // and decoding hands the same tags over along with the samples
+        let decoded = crate::decode_audio(Cursor::new(tagged_wav(100))).unwrap();
+        assert_eq!(decoded.tags, info.tags);
+    }
+}
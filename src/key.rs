@@ -0,0 +1,242 @@
+use std::fmt;
+
+use crate::notes::NoteName;
+use crate::spectrum;
+
+/// Krumhansl-Kessler major key profile, how well each pitch class (starting at the tonic) fits a
+/// major key, from listeners' ratings.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+
+/// Krumhansl-Kessler minor key profile, same as `MAJOR_PROFILE` but for a minor key.
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Frequency range the chromagram takes its energy from, below 55Hz the bins are too coarse to
+/// tell semitones apart and above 5kHz it's mostly overtones and noise.
+const CHROMA_MIN_HZ: f32 = 55.0;
+const CHROMA_MAX_HZ: f32 = 5000.0;
+
+/// Major or minor.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Major => write!(f, "major"),
+            Mode::Minor => write!(f, "minor"),
+        }
+    }
+}
+
+/// The key `detect_key` settled on.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyReport {
+    pub tonic: NoteName,
+    pub mode: Mode,
+    /// How well the audio's pitch class distribution matches the key's profile, the Pearson
+    /// correlation between the two clamped to 0.0 to 1.0. Tonal pop and classical usually land
+    /// above 0.7, below about 0.5 the key is a guess (atonal material, drums, speech).
+    pub confidence: f32,
+}
+
+impl KeyReport {
+    /// The key on the Camelot wheel DJs use for harmonic mixing, `"8B"` for C major, `"8A"` for
+    /// A minor, keys whose numbers are one apart (or the same number with the other letter) mix
+    /// without clashing.
+    pub fn camelot(&self) -> String {
+        let pitch_class = NoteName::ALL
+            .iter()
+            .position(|note| *note == self.tonic)
+            .unwrap_or(0);
+        // a minor key sits on the same number as its relative major, three semitones up
+        let (major, letter) = match self.mode {
+            Mode::Major => (pitch_class, 'B'),
+            Mode::Minor => ((pitch_class + 3) % 12, 'A'),
+        };
+        // the wheel goes around the circle of fifths, with C major at 8
+        format!("{}{}", (major * 7 + 7) % 12 + 1, letter)
+    }
+}
+
+impl fmt::Display for KeyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.tonic, self.mode)
+    }
+}
+
+/// Pearson correlation of two equally long sequences, 0.0 if either one is flat.
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / a.len() as f32;
+    let mean_b = b.iter().sum::<f32>() / b.len() as f32;
+    let covariance: f32 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (x - mean_a) * (y - mean_b))
+        .sum();
+    let spread_a: f32 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let spread_b: f32 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    let denominator = (spread_a * spread_b).sqrt();
+    if denominator > 0.0 {
+        covariance / denominator
+    } else {
+        0.0
+    }
+}
+
+/// How much of some mono audio data's tonal energy falls into each of the twelve pitch classes
+/// (index 0 is C, 1 is C# and so on up to B), summed over the whole input with every frame
+/// scaled so its strongest pitch class is 1.0, so the loud parts don't drown out the rest.
+/// Silent input gives all zeros. This is what `detect_key` matches against its key profiles.
+pub fn chroma(audio: &[f32], sample_rate: usize) -> [f32; 12] {
+    // long frames so neighbouring semitones in the bass land in different bins
+    let frame_len = 8192;
+    let bin_hz = sample_rate as f32 / frame_len as f32;
+
+    let mut total = [0.0; 12];
+    for frame in spectrum::stft_magnitudes(audio, frame_len, frame_len / 2) {
+        let mut classes = [0.0f32; 12];
+        for (bin, magnitude) in frame.iter().enumerate() {
+            let hz = bin as f32 * bin_hz;
+            if !(CHROMA_MIN_HZ..=CHROMA_MAX_HZ).contains(&hz) {
+                continue;
+            }
+            let midi = (69.0 + 12.0 * (hz / 440.0).log2()).round() as i32;
+            classes[midi.rem_euclid(12) as usize] += magnitude * magnitude;
+        }
+
+        let strongest = classes.iter().fold(0.0f32, |max, c| max.max(*c));
+        if strongest > 1e-9 {
+            for (sum, class) in total.iter_mut().zip(classes) {
+                *sum += class / strongest;
+            }
+        }
+    }
+
+    total
+}
+
+/// Detects the musical key of some mono audio data, for tagging a library for harmonic mixing.
+/// The pitch class distribution from `chroma` is correlated with the Krumhansl-Kessler profile
+/// of every major and minor key (all 24 of them) and the best match wins, see
+/// `KeyReport::confidence` for how far to trust it. Works best on a whole song or a long stretch
+/// of one, a few seconds of a single chord can easily read as a neighbouring key, and assumes the
+/// key doesn't change along the way (a modulating piece gets whichever key it spends the most
+/// time in, or a muddle of both). Relative keys (C major and A minor) share every note and are
+/// the most common mixup. Returns `None` for silent input.
+pub fn detect_key(audio: &[f32], sample_rate: usize) -> Option<KeyReport> {
+    let chroma = chroma(audio, sample_rate);
+    if chroma.iter().all(|c| *c == 0.0) {
+        return None;
+    }
+
+    let mut best: Option<KeyReport> = None;
+    for tonic in 0..12 {
+        // rotate the chroma so the candidate tonic is at index 0, like the profiles
+        let rotated: Vec<f32> = (0..12).map(|i| chroma[(tonic + i) % 12]).collect();
+        for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+            let score = correlation(&rotated, profile);
+            if best.is_none_or(|best| score > best.confidence) {
+                best = Some(KeyReport {
+                    tonic: NoteName::from_midi(tonic as i32),
+                    mode,
+                    confidence: score,
+                });
+            }
+        }
+    }
+
+    best.map(|key| KeyReport {
+        confidence: key.confidence.clamp(0.0, 1.0),
+        ..key
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second of each chord in turn, every chord a list of MIDI notes played as sines.
+    fn progression(chords: &[&[i32]], sample_rate: usize) -> Vec<f32> {
+        chords
+            .iter()
+            .flat_map(|chord| {
+                (0..sample_rate).map(move |i| {
+                    let t = i as f32 / sample_rate as f32;
+                    chord
+                        .iter()
+                        .map(|note| {
+                            let hz = 440.0 * 2f32.powf((*note - 69) as f32 / 12.0);
+                            (2.0 * std::f32::consts::PI * hz * t).sin() * 0.2
+                        })
+                        .sum::<f32>()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_cadence_gives_its_key() {
+        // I IV V I in D major, then i iv V i in E minor
+        let d_major = progression(
+            &[
+                &[50, 62, 66, 69],
+                &[55, 62, 67, 71],
+                &[57, 61, 64, 69],
+                &[50, 62, 66, 69],
+            ],
+            22050,
+        );
+        let key = detect_key(&d_major, 22050).unwrap();
+        assert_eq!(
+            (key.tonic, key.mode),
+            (NoteName::D, Mode::Major),
+            "{:?}",
+            key
+        );
+        assert!(key.confidence > 0.7, "{:?}", key);
+
+        let e_minor = progression(
+            &[
+                &[52, 64, 67, 71],
+                &[57, 64, 69, 72],
+                &[59, 63, 66, 71],
+                &[52, 64, 67, 71],
+            ],
+            22050,
+        );
+        let key = detect_key(&e_minor, 22050).unwrap();
+        assert_eq!(
+            (key.tonic, key.mode),
+            (NoteName::E, Mode::Minor),
+            "{:?}",
+            key
+        );
+
+        assert_eq!(detect_key(&[0.0; 22050], 22050), None);
+    }
+
+    #[test]
+    fn relative_keys_share_a_camelot_number() {
+        let key = |tonic, mode| KeyReport {
+            tonic,
+            mode,
+            confidence: 1.0,
+        };
+        assert_eq!(key(NoteName::C, Mode::Major).camelot(), "8B");
+        assert_eq!(key(NoteName::A, Mode::Minor).camelot(), "8A");
+        // a fifth up is one step around the wheel
+        assert_eq!(key(NoteName::G, Mode::Major).camelot(), "9B");
+        assert_eq!(key(NoteName::E, Mode::Minor).camelot(), "9A");
+        assert_eq!(key(NoteName::F, Mode::Major).camelot(), "7B");
+        assert_eq!(key(NoteName::D, Mode::Major).to_string(), "D major");
+    }
+}
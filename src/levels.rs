@@ -0,0 +1,289 @@
+use crate::filters::{apply_filters, Biquad};
+
+fn peak(audio: &[f32]) -> f32 {
+    audio.iter().fold(0.0, |peak, s| s.abs().max(peak))
+}
+
+fn rms(audio: &[f32]) -> f32 {
+    if audio.is_empty() {
+        return 0.0;
+    }
+    (audio.iter().map(|s| s * s).sum::<f32>() / audio.len() as f32).sqrt()
+}
+
+/// Crest factor (peak to RMS ratio, in dB) of every `window_secs` long window of some mono audio
+/// data, returned as `(start time in seconds, crest factor)` pairs. Untouched acoustic recordings
+/// usually sit somewhere around 12-20dB, heavy compression and limiting squash the peaks towards
+/// the average and pull it down to single digits (a pure sine is about 3dB), so a sudden drop
+/// shows you where a recording was over-processed. Silent windows report 0.0, the last window may
+/// be shorter than the rest.
+pub fn crest_timeline(audio: &[f32], sample_rate: usize, window_secs: f64) -> Vec<(f64, f32)> {
+    let window = ((window_secs * sample_rate as f64).round() as usize).max(1);
+
+    audio
+        .chunks(window)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let time = (i * window) as f64 / sample_rate as f64;
+            (time, crest_factor(chunk))
+        })
+        .collect()
+}
+
+/// Converts a linear amplitude (1.0 being full scale) to dBFS, -inf for zero.
+fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        20.0 * amplitude.log10()
+    } else {
+        f32::NEG_INFINITY
+    }
+}
+
+/// The sample peak of some audio data in dBFS, 0.0 for a sample at full scale, positive if
+/// something already clips (float audio can go past 1.0), -inf for digital silence. This is the
+/// peak of the samples as they are, the waveform between them can go higher, see
+/// `LoudnessReport::true_peak` for that. Interleaved audio works as is, the peak is across every
+/// channel.
+pub fn peak_dbfs(audio: &[f32]) -> f32 {
+    to_dbfs(peak(audio))
+}
+
+/// The RMS (average power) level of some audio data in dBFS, a full scale sine sits at about
+/// -3.0, a full scale square wave at 0.0, -inf for digital silence (or no audio at all). Across
+/// every channel if the audio is interleaved. Unweighted, so bass counts as much as anything
+/// else, use `measure_lufs` for how loud it *sounds*.
+pub fn rms_dbfs(audio: &[f32]) -> f32 {
+    to_dbfs(rms(audio))
+}
+
+/// Crest factor of some audio data, the peak to RMS ratio in dB (`peak_dbfs - rms_dbfs`), 3dB
+/// for a pure sine, 0dB for a square wave, 12-20dB for untouched acoustic recordings and single
+/// digits for heavily limited masters. 0.0 for digital silence.
+pub fn crest_factor(audio: &[f32]) -> f32 {
+    let rms = rms(audio);
+    if rms > 0.0 {
+        20.0 * (peak(audio) / rms).log10()
+    } else {
+        0.0
+    }
+}
+
+/// The RMS level (in dBFS) of `window_secs` long windows of some mono audio data taken every
+/// `hop_secs`, returned as `(start time in seconds, level)` pairs, the loudness curve you'd draw
+/// under a waveform or use to spot fades and dropouts. Windows that overlap the end of the audio
+/// are cut short, silent ones report -inf.
+///
+/// # Panics
+/// Panics if `window_secs` or `hop_secs` round to zero samples.
+pub fn rms_envelope(
+    audio: &[f32],
+    sample_rate: usize,
+    window_secs: f64,
+    hop_secs: f64,
+) -> Vec<(f64, f32)> {
+    let window = (window_secs * sample_rate as f64).round() as usize;
+    let hop = (hop_secs * sample_rate as f64).round() as usize;
+    assert!(
+        window > 0 && hop > 0,
+        "window and hop must be at least a sample long"
+    );
+
+    (0..audio.len())
+        .step_by(hop)
+        .map(|start| {
+            let time = start as f64 / sample_rate as f64;
+            (
+                time,
+                rms_dbfs(&audio[start..(start + window).min(audio.len())]),
+            )
+        })
+        .collect()
+}
+
+/// The DC offset of every channel of some interleaved audio data, the average of its samples,
+/// which should be (very close to) zero for anything that went through a microphone. Cheap USB
+/// mics and badly calibrated converters leave a constant bias that shows up here, a few
+/// thousandths is harmless, anything around 0.01 (-40dBFS) or more skews RMS levels and pitch
+/// clarity and eats headroom. Trailing samples that don't make up a whole frame are ignored,
+/// empty audio reports zeros.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn measure_dc_offset(audio: &[f32], channels: usize) -> Vec<f32> {
+    assert!(channels > 0, "can't measure audio with no channels");
+    let frames = audio.len() / channels;
+
+    let mut sums = vec![0.0f64; channels];
+    for frame in audio.chunks_exact(channels) {
+        for (sum, sample) in sums.iter_mut().zip(frame) {
+            *sum += *sample as f64;
+        }
+    }
+    sums.into_iter()
+        .map(|sum| (sum / frames.max(1) as f64) as f32)
+        .collect()
+}
+
+/// Removes a constant DC offset from every channel of some interleaved audio data by
+/// subtracting its average (see `measure_dc_offset`), which leaves everything else exactly as it
+/// was. Run it before analysis and encoding. A bias that drifts over the recording isn't constant,
+/// so the average only gets part of it, see `remove_dc_drift` for that.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn remove_dc_offset(audio: &mut [f32], channels: usize) {
+    let offsets = measure_dc_offset(audio, channels);
+    for frame in audio.chunks_exact_mut(channels) {
+        for (sample, offset) in frame.iter_mut().zip(&offsets) {
+            *sample -= offset;
+        }
+    }
+}
+
+/// Cutoff of the high-pass `remove_dc_drift` uses, well below anything audible (or any voice's
+/// pitch) but high enough to follow a bias that wanders over a few seconds.
+const DC_HIGHPASS_HZ: f64 = 10.0;
+
+/// Same as `remove_dc_offset`, but takes out a DC offset that drifts over the recording too (a
+/// mic warming up, a bias that wanders with the battery) by running every channel through a 10Hz
+/// high-pass filter instead of subtracting a single average. The filter needs a moment to settle,
+/// so the first ~50ms still carry part of the offset, and it shifts the phase of the lowest bass
+/// a little, use `remove_dc_offset` when the bias is constant.
+///
+/// # Panics
+/// Panics if `channels` or `sample_rate` is zero.
+pub fn remove_dc_drift(audio: &mut [f32], channels: usize, sample_rate: usize) {
+    assert!(channels > 0, "can't filter audio with no channels");
+    assert!(sample_rate > 0, "sample rate can't be zero");
+
+    let highpass = Biquad::highpass(sample_rate, DC_HIGHPASS_HZ, std::f64::consts::FRAC_1_SQRT_2);
+    apply_filters(audio, channels, &[highpass]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crest_factor_drops_where_the_audio_is_squashed() {
+        // drum-like bursts of noise, four a second, left alone for two seconds and then slammed
+        // into a limiter for two more
+        let sample_rate = 44100;
+        let mut state = 1u32;
+        let drums: Vec<f32> = (0..sample_rate * 4)
+            .map(|i| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let hiss = (state >> 8) as f32 / (1 << 23) as f32 - 1.0;
+                let decay = (-((i % (sample_rate / 4)) as f32) / 1000.0).exp();
+                hiss * decay * 0.9
+            })
+            .collect();
+        let audio: Vec<f32> = drums
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                if i < sample_rate * 2 {
+                    *s
+                } else {
+                    (s * 20.0).clamp(-0.5, 0.5)
+                }
+            })
+            .collect();
+
+        let timeline = crest_timeline(&audio, sample_rate, 0.5);
+        assert_eq!(timeline.len(), 8);
+        for (time, crest) in timeline {
+            if time < 2.0 {
+                assert!(crest > 10.0, "{} at {}", crest, time);
+            } else {
+                assert!(crest < 6.0, "{} at {}", crest, time);
+            }
+        }
+    }
+
+    #[test]
+    fn levels_of_textbook_signals() {
+        let sine: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        assert!(peak_dbfs(&sine).abs() < 1e-3, "{}", peak_dbfs(&sine));
+        assert!((rms_dbfs(&sine) + 3.01).abs() < 0.01, "{}", rms_dbfs(&sine));
+        assert!((crest_factor(&sine) - 3.01).abs() < 0.01);
+
+        let square: Vec<f32> = (0..48000)
+            .map(|i| if (i / 24) % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        assert!((peak_dbfs(&square) + 6.02).abs() < 0.01);
+        assert!((rms_dbfs(&square) + 6.02).abs() < 0.01);
+        assert!(crest_factor(&square).abs() < 1e-4);
+
+        assert_eq!(peak_dbfs(&[0.0; 100]), f32::NEG_INFINITY);
+        assert_eq!(rms_dbfs(&[]), f32::NEG_INFINITY);
+        assert_eq!(crest_factor(&[0.0; 100]), 0.0);
+        // float audio can go past full scale
+        assert!((peak_dbfs(&[0.0, -2.0]) - 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn the_envelope_follows_a_step_down() {
+        // a second at full scale, then a second 20dB down, at 1kHz
+        let audio: Vec<f32> = (0..2000)
+            .map(|i| if i < 1000 { 1.0 } else { 0.1 })
+            .collect();
+
+        let envelope = rms_envelope(&audio, 1000, 0.1, 0.05);
+        assert_eq!(envelope.len(), 40);
+        for (time, level) in &envelope {
+            if *time < 0.9 {
+                assert!(level.abs() < 1e-4, "{} at {}", level, time);
+            } else if *time >= 1.0 {
+                assert!((level + 20.0).abs() < 1e-4, "{} at {}", level, time);
+            }
+        }
+        // straddling the step, a bit of both
+        let (_, straddling) = envelope[19];
+        assert!(straddling < 0.0 && straddling > -20.0, "{}", straddling);
+    }
+
+    #[test]
+    fn dc_offsets_are_measured_and_removed_per_channel() {
+        // a stereo tone, the left channel biased up by 0.05 and the right down by 0.02
+        let tone: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 48000.0).sin() * 0.5)
+            .collect();
+        let mut audio: Vec<f32> = tone.iter().flat_map(|s| [s + 0.05, s - 0.02]).collect();
+
+        let offsets = measure_dc_offset(&audio, 2);
+        assert!((offsets[0] - 0.05).abs() < 1e-4, "{:?}", offsets);
+        assert!((offsets[1] + 0.02).abs() < 1e-4, "{:?}", offsets);
+
+        remove_dc_offset(&mut audio, 2);
+        assert!(measure_dc_offset(&audio, 2).iter().all(|o| o.abs() < 1e-6));
+        // only the bias goes
+        for (frame, s) in audio.chunks(2).zip(&tone) {
+            assert!((frame[0] - s).abs() < 1e-4 && (frame[1] - s).abs() < 1e-4);
+        }
+        assert_eq!(measure_dc_offset(&[], 2), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn a_drifting_offset_is_filtered_out() {
+        // a 200Hz tone on a bias that creeps from 0.0 to 0.1 over two seconds
+        let mut audio: Vec<f32> = (0..96000)
+            .map(|i| {
+                let t = i as f32 / 48000.0;
+                (2.0 * std::f32::consts::PI * 200.0 * t).sin() * 0.5 + 0.05 * t
+            })
+            .collect();
+
+        remove_dc_drift(&mut audio, 1, 48000);
+        // a tenth of a second at a time, once the filter has settled
+        for window in audio[4800..].chunks(4800) {
+            let offset = measure_dc_offset(window, 1)[0];
+            assert!(offset.abs() < 0.002, "{}", offset);
+        }
+        // and the tone itself comes through at its level, a half scale sine's -9dBFS
+        let level = rms_dbfs(&audio[48000..]);
+        assert!((level + 9.03).abs() < 0.1, "{}", level);
+    }
+}
@@ -1,131 +1,504 @@
 pub use fon::chan::Ch32;
 pub use fon::Audio;
-pub use optivorbis::{OggToOgg, Remuxer};
+pub use optivorbis::{OggToOgg, Remuxer, VorbisCommentFieldsAction, VorbisVendorStringAction};
+pub use symphonia::core::io::{MediaSource, ReadOnlySource};
 pub use vorbis_rs;
 
-use std::io::Cursor;
-use std::num::{NonZeroU32, NonZeroU8};
+pub mod analyzer;
+pub mod batch;
+pub mod compose;
+pub mod contour;
+pub mod diagnostics;
+pub mod downmix;
+pub mod dynamics;
+pub mod edit;
+mod error;
+pub mod export;
+pub mod features;
+pub mod file;
+pub mod filters;
+pub mod fir;
+#[cfg(feature = "flac")]
+pub mod flac;
+pub mod gain;
+#[cfg(feature = "image")]
+pub mod image;
+pub mod info;
+pub mod key;
+pub mod levels;
+pub mod loudness;
+pub mod midi;
+pub mod notes;
+#[cfg(feature = "opus")]
+pub mod ogg_opus;
+pub mod onsets;
+pub mod pcm;
+pub mod polarity;
+pub mod resample;
+pub mod rhythm;
+pub mod spectrum;
+pub mod stream;
+pub mod timeline;
+pub mod vad;
+pub mod wav;
+pub mod waveform;
 
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
-use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+pub use analyzer::PitchAnalyzer;
+pub use batch::compose_batch_to_dir;
+pub use compose::{
+    ChannelPolicy, ComposeBuilder, ComposeStage, OggBitrate, Progress, RemuxOptions, StreamEncoder,
+};
+pub use contour::{correct_octave_errors, median_filter_contour, smooth_contour, ContourSmoothing};
+pub use diagnostics::{
+    detect_aliasing, detect_clipping, effective_bit_depth, estimate_rt60, octave_band_centers,
+    spectral_difference, verify_sample_rate, wow_and_flutter, ClippingOptions, ClippingReport,
+    WowFlutterReport, REFERENCE_TONES,
+};
+pub use downmix::{downmix_to_mono, downmix_to_stereo, DownmixStrategy};
+pub use dynamics::de_ess;
+pub use edit::{center_excerpt, detect_silence, strip_silence, trim_silence};
+pub use error::AvasaraError;
+pub use export::{
+    contour_to_csv, contour_to_json, contour_to_pitch_tier, plot_pitch_contour_svg,
+    PitchPlotOptions,
+};
+pub use features::{feature_vector, mfcc, MfccOptions, FEATURE_VECTOR_LEN};
+pub use file::{AccessMode, AudioFile, DEFAULT_STREAMING_THRESHOLD};
+pub use filters::{apply_filters, Biquad};
+pub use fir::{
+    apply_fir, apply_fir_with_makeup_gain, bandpass_kernel, highpass_kernel, lowpass_kernel,
+};
+#[cfg(feature = "flac")]
+pub use flac::{compose_to_flac, encode_flac};
+pub use gain::{apply_gain_db, fade_in, fade_out, normalize_peak};
+#[cfg(feature = "image")]
+pub use image::{render_spectrogram, RgbImage, SpectrogramColors, SpectrogramOptions};
+pub use info::{list_tracks, probe_info, MediaInfo, TrackInfo};
+pub use key::{chroma, detect_key, KeyReport, Mode};
+pub use levels::{
+    crest_factor, crest_timeline, measure_dc_offset, peak_dbfs, remove_dc_drift, remove_dc_offset,
+    rms_dbfs, rms_envelope,
+};
+pub use loudness::{
+    loudness_match_report, match_loudness, measure_lufs, tonal_balance, LoudnessReport,
+    TonalBalance,
+};
+#[cfg(feature = "midi")]
+pub use midi::export_midi;
+pub use midi::{notes_to_midi_file, MidiNote};
+pub use notes::{
+    cents_offset, confidence_band, contour_to_cents, dominant_note, hz_to_cents, hz_to_midi,
+    hz_to_note_name, midi_to_hz, nearest_note, segment_notes, NearestNote, NoteEvent, NoteName,
+    NoteSegmentOptions, PitchBand,
+};
+#[cfg(feature = "opus")]
+pub use ogg_opus::{compose_to_opus, encode_opus, OpusApplication, OpusSettings};
+pub use onsets::{detect_onsets, onset_envelope, onset_envelope_times};
+pub use pcm::{to_raw_bytes, PcmFormat};
+pub use polarity::{detect_polarity, invert_polarity, Polarity};
+pub use resample::{resample, ResampleQuality};
+pub use rhythm::{
+    beat_positions, beat_strength, estimate_bpm, estimate_tempo, find_loop_points, TempoReport,
+};
+pub use spectrum::{stft, Spectrogram, WindowFunction};
+pub use stream::{DecodedBlock, Decoder, ProbeHint, TrackSelector};
+pub use timeline::align_to_frame_rate;
+pub use vad::{detect_voice_activity, voice_activity, Segment, VadOptions};
+pub use wav::{compose_to_wav, encode_wav, encode_wav_with, WavFormat, WavOptions};
+pub use waveform::{compute_peaks, encode_waveform_dat, PeakBits, PeakPair};
 
-use pitch_detection::detector::{yin::YINDetector, PitchDetector};
-use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use pitch_detection::detector::{
+    autocorrelation::AutocorrelationDetector, mcleod::McLeodDetector, yin::YINDetector,
+    PitchDetector,
+};
+
+/// Decoded audio along with what it takes to interpret it, what `decode_audio` returns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedAudio {
+    /// Interleaved f32 samples, `channels` of them per frame.
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// How long the audio is, worked out from the samples actually decoded (not what the
+    /// container declares, unlike `MediaInfo::duration_secs`).
+    pub duration: Duration,
+    /// Every tag in the file as (key, value) pairs, see `Decoder::tags`.
+    pub tags: Vec<(String, String)>,
+}
+
+impl DecodedAudio {
+    /// The interleaved samples.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// How many frames (samples per channel) there are.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / (self.channels.max(1) as usize)
+    }
+
+    /// The audio folded down to mono with `downmix_to_mono` (`DownmixStrategy::Average`), what
+    /// every analysis function takes, mono audio is just copied.
+    pub fn to_mono(&self) -> Vec<f32> {
+        downmix_to_mono(
+            &self.samples,
+            self.channels.max(1) as usize,
+            DownmixStrategy::Average,
+        )
+    }
+
+    /// The samples, sample rate and channel count in the `usize`s the rest of the crate takes,
+    /// in the order the deprecated `decode` returns them.
+    pub fn into_parts(self) -> (Vec<f32>, usize, usize) {
+        (
+            self.samples,
+            self.sample_rate as usize,
+            self.channels as usize,
+        )
+    }
+}
 
 /// Decodes from many formats (namely: aac, alac, flac, isomp4, mkv, mp3, ogg, pcm, vorbis and of
-/// course, wav) using a cursor so that it can support both in-memory and on-disk audio, outputs
-/// a vector of f32 sound samples, sample rate, and channel count respectively.
-pub fn decode(src: Cursor<Vec<u8>>) -> (Vec<f32>, usize, usize) {
-    // more info at getting_started.md of Symphonia
-    let mss = MediaSourceStream::new(Box::new(src), Default::default());
-    let hint = Hint::new();
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = Default::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .expect("unsupported format");
-
-    let mut format = probed.format;
-
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .expect("no supported audio tracks");
+/// course, wav) from any symphonia `MediaSource`, a `File` straight from disk, a
+/// `Cursor<Vec<u8>>` for audio that's already in memory, or anything that's only `Read` (a
+/// network stream, a pipe) wrapped in a `ReadOnlySource`, so nothing has to be read into memory
+/// up front. Outputs a `DecodedAudio` holding the interleaved f32 samples, sample rate, channel
+/// count, length and tags.
+///
+/// Anything that went slightly wrong along the way (skipped packets etc.) is silently ignored,
+/// use `decode_with_warnings` if you want to know about it. Returns `AvasaraError::Decode` if the
+/// format or codec isn't supported or the stream breaks in a way decoding can't recover from,
+/// and `AvasaraError::DecodeFailed` if the file has no audio track at all.
+pub fn decode_audio<S: MediaSource + 'static>(src: S) -> Result<DecodedAudio, AvasaraError> {
+    decode_all(Decoder::new(src)?)
+}
+
+/// Same as `decode_audio`, with a hint about what the source is (its file extension, MIME type
+/// or both, see `ProbeHint`) so symphonia's prober tries that format first, which makes
+/// headerless formats like raw AAC in ADTS frames probe faster and more reliably. A hint that
+/// turns out wrong isn't fatal, probing falls back to looking at the content.
+pub fn decode_audio_with_hint<S: MediaSource + 'static>(
+    src: S,
+    hint: &ProbeHint,
+) -> Result<DecodedAudio, AvasaraError> {
+    decode_all(Decoder::with_hint(src, TrackSelector::default(), hint)?)
+}
+
+/// Same as `decode_audio`, but opens the file at `path` itself (see `Decoder::open`), so there's
+/// no need to read it into a `Vec` and wrap it in a `Cursor` first, the extension is handed to
+/// symphonia as a hint for the format and the file is read as it's decoded. Fails with
+/// `AvasaraError::Io` if the file can't be opened, otherwise like `decode_audio`.
+pub fn decode_file<P: AsRef<Path>>(path: P) -> Result<DecodedAudio, AvasaraError> {
+    decode_all(Decoder::open(path)?)
+}
+
+/// Decodes everything `decoder` has left, shared by `decode_audio` and `decode_file`.
+fn decode_all(mut decoder: Decoder) -> Result<DecodedAudio, AvasaraError> {
+    let mut samples = vec![];
+    for block in &mut decoder {
+        samples.extend(block?.samples);
+    }
 
-    let dec_opts: DecoderOptions = Default::default();
+    let sample_rate = decoder.sample_rate();
+    let channels = decoder.channels();
+    let frames = samples.len() / channels.max(1);
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: sample_rate as u32,
+        channels: channels as u16,
+        duration: Duration::from_secs_f64(frames as f64 / sample_rate.max(1) as f64),
+        tags: decoder.tags().to_vec(),
+    })
+}
 
-    // will later be set in the decode loop using spec
-    let mut sample_rate = 0;
-    let mut channels = 0;
+/// Same as `decode_audio`, but returns a bare tuple of the samples, sample rate and channel count
+/// respectively, which is all too easy to unpack in the wrong order.
+#[deprecated(note = "use `decode_audio`, `DecodedAudio::into_parts` gives this same tuple")]
+pub fn decode<S: MediaSource + 'static>(src: S) -> Result<(Vec<f32>, usize, usize), AvasaraError> {
+    Ok(decode_audio(src)?.into_parts())
+}
 
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+/// Something that went wrong while decoding but wasn't bad enough to stop it, collected by
+/// `decode_with_warnings` so you can inspect the damage after the fact.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeWarning {
+    /// A packet couldn't be decoded (corrupt data, or a read error in the middle of it) and was
+    /// skipped, the resulting audio has a gap where it should have been, `timestamp` is the
+    /// packet's timestamp in the track's timebase.
+    SkippedPacket { timestamp: u64, reason: String },
+    /// The container reported a new metadata revision partway through the stream (chained ogg
+    /// files and internet radio dumps do this), `decode` discards the new tags, `Decoder::tags`
+    /// keeps them.
+    MetadataChanged { after_samples: usize },
+    /// The sample rate or channel count changed partway through the stream, `decode` still
+    /// returns everything as one buffer, so samples after `after_samples` are interpreted with the
+    /// wrong rate/layout, the returned rate and channel count are the last ones seen. Use
+    /// `decode_segments` to get each part with its own rate and channel count instead.
+    SpecChanged {
+        after_samples: usize,
+        sample_rate: usize,
+        channels: usize,
+    },
+    /// The container started a new logical stream partway through (chained ogg files, internet
+    /// radio dumps that move on to the next song), the codec was set up again from the new
+    /// stream's parameters and decoding carried on from `after_samples`. Usually followed by
+    /// `MetadataChanged`, and by `SpecChanged` if the new stream isn't encoded like the old one.
+    StreamReset { after_samples: usize },
+}
 
-    let track_id = track.id;
+/// A stretch of a decoded stream with a single sample rate and channel count, see
+/// `decode_segments`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedSegment {
+    /// Interleaved f32 samples, `channels` of them per frame.
+    pub samples: Vec<f32>,
+    pub sample_rate: usize,
+    pub channels: usize,
+    /// Where the segment starts in the whole decoded stream, counting interleaved samples like
+    /// `DecodeWarning`'s `after_samples`.
+    pub offset: usize,
+}
+
+/// Same as `decode`, but additionally returns every `DecodeWarning` encountered along the way in
+/// the order they happened, an empty vector means the file decoded cleanly.
+#[allow(clippy::type_complexity)]
+pub fn decode_with_warnings<S: MediaSource + 'static>(
+    src: S,
+) -> Result<(Vec<f32>, usize, usize, Vec<DecodeWarning>), AvasaraError> {
+    let mut decoder = Decoder::new(src)?;
 
     let mut audio = vec![];
+    for block in &mut decoder {
+        audio.extend(block?.samples);
+    }
 
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(Error::ResetRequired) => {
-                unimplemented!(); // NOTE: refer to getting_started.md of Symphonia
-            }
-            Err(err) => {
-                if (&err).to_string() == "end of stream" {
-                    // this is called whenever audiofile finishes reading
-                    break;
-                } else {
-                    // an unrecoverable error occured, halt decoding.
-                    // FIXME: return error using Result
-                    panic!("{}", err);
-                }
-            }
-        };
+    let sample_rate = decoder.sample_rate();
+    let channels = decoder.channels();
+    Ok((audio, sample_rate, channels, decoder.take_warnings()))
+}
 
-        while !format.metadata().is_latest() {
-            format.metadata().pop();
+/// Same as `decode`, but decodes the audio track picked by `selector` (by position, language or
+/// codec preference) instead of always the first one, for MKV and MP4 files with more than one,
+/// see `list_tracks` for what a file has. Fails with `AvasaraError::TrackNotFound` if no track
+/// matches.
+pub fn decode_track<S: MediaSource + 'static>(
+    src: S,
+    selector: TrackSelector,
+) -> Result<(Vec<f32>, usize, usize), AvasaraError> {
+    let mut decoder = Decoder::with_track(src, selector)?;
 
-            /*
-            if let Some(rev) = format.metadata().current() {
-                dbg!(&rev);
-            }
-            */
+    let mut audio = vec![];
+    for block in &mut decoder {
+        audio.extend(block?.samples);
+    }
+
+    Ok((audio, decoder.sample_rate(), decoder.channels()))
+}
 
-            // consume the new metadata at the head of the metadata queue...? dont think i need it
+/// Same as `decode`, but splits the audio wherever the sample rate or channel count changes
+/// instead of returning it as one buffer with the wrong rate/layout for part of it, which chained
+/// ogg files and internet radio dumps can do when consecutive songs weren't encoded alike. A file
+/// that keeps the same parameters throughout (nearly all of them) gives exactly one segment
+/// holding what `decode` returns, resample and mix the segments yourself (see `resample` and
+/// `downmix_to_stereo`) if you need them as one.
+pub fn decode_segments<S: MediaSource + 'static>(
+    src: S,
+) -> Result<Vec<DecodedSegment>, AvasaraError> {
+    let mut decoder = Decoder::new(src)?;
+
+    let mut segments: Vec<DecodedSegment> = vec![];
+    for block in &mut decoder {
+        let block = block?;
+        match segments.last_mut() {
+            Some(segment)
+                if segment.sample_rate == block.sample_rate
+                    && segment.channels == block.channels =>
+            {
+                segment.samples.extend(block.samples)
+            }
+            _ => segments.push(DecodedSegment {
+                samples: block.samples,
+                sample_rate: block.sample_rate,
+                channels: block.channels,
+                offset: block.offset,
+            }),
         }
+    }
 
-        if packet.track_id() != track_id {
-            continue;
+    Ok(segments)
+}
+
+/// Same as `decode`, but only decodes the part of the track between `start` and `end` (measured
+/// from the start of the track), for previews, thumbnails and anything else that only needs a
+/// slice of a long file. It seeks straight to `start` with `Decoder::seek` instead of decoding
+/// everything before it, and stops as soon as `end` is reached, so a 30 second preview of a three
+/// hour recording costs about as much as 30 seconds of decoding. An `end` past the end of the
+/// track just gives everything from `start` on. Fails like `Decoder::seek` does if `start` is past
+/// the end or the source can't seek.
+///
+/// # Panics
+/// Panics if `end` is before `start`.
+pub fn decode_range<S: MediaSource + 'static>(
+    src: S,
+    start: Duration,
+    end: Duration,
+) -> Result<(Vec<f32>, usize, usize), AvasaraError> {
+    assert!(end >= start, "the range must not end before it starts");
+    let mut decoder = Decoder::new(src)?;
+    if !start.is_zero() {
+        decoder.seek(start)?;
+    }
+
+    let mut audio = vec![];
+    for block in &mut decoder {
+        let block = block?;
+        audio.extend(block.samples);
+
+        let wanted_frames =
+            ((end - start).as_secs_f64() * block.sample_rate as f64).round() as usize;
+        let wanted = wanted_frames * block.channels;
+        if audio.len() >= wanted {
+            audio.truncate(wanted);
+            break;
         }
+    }
 
-        match decoder.decode(&packet) {
-            Ok(decoded) => {
-                let spec = decoded.spec().clone().to_owned();
-                channels = spec.channels.count();
-                sample_rate = spec.rate;
+    Ok((audio, decoder.sample_rate(), decoder.channels()))
+}
 
-                let mut sample_buf =
-                    SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
-                sample_buf.copy_interleaved_ref(decoded);
-                let samples = sample_buf.samples();
-                for i in samples {
-                    audio.push(*i);
-                }
-            }
-            Err(Error::IoError(_)) => {
-                continue;
-            }
-            Err(Error::DecodeError(_)) => {
-                continue;
-            }
-            Err(err) => {
-                // an unrecoverable error occured, halt decoding.
-                // FIXME: return err using Result
-                panic!("{}", err);
-            }
+/// Same as `decode`, but checks `cancel` before every packet and bails out with
+/// `AvasaraError::Cancelled` as soon as it's set, so a worker thread stuck on a three hour file
+/// can be freed up from elsewhere (wrap the flag in an `Arc` to share it), see
+/// `ComposeBuilder::cancel_flag` for doing the same to a whole transcode.
+pub fn decode_cancellable<S: MediaSource + 'static>(
+    src: S,
+    cancel: &AtomicBool,
+) -> Result<(Vec<f32>, usize, usize), AvasaraError> {
+    let mut decoder = Decoder::new(src)?;
+
+    let mut audio = vec![];
+    for block in &mut decoder {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(AvasaraError::Cancelled);
         }
+        audio.extend(block?.samples);
     }
 
-    (audio, sample_rate as usize, channels)
+    Ok((audio, decoder.sample_rate(), decoder.channels()))
+}
+
+/// Whether a chunk's RMS level is below -90dBFS, quiet enough that any pitch YIN finds in it is
+/// just noise.
+fn is_silent(chunk: &[f32]) -> bool {
+    let mean_square = chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len().max(1) as f32;
+    mean_square < 1e-9
+}
+
+/// Runs YIN on a chunk and returns `(frequency, clarity)`, with the clarity being 1 - d' (YIN's
+/// normalized difference at the detected period) so it actually lands between 0.0 and 1.0.
+/// pitch-detection 0.3 divides by `clarity_threshold` when it reports clarity, so passing 0.0
+/// there gives back infinity for every chunk, instead we pass a tiny threshold (which only rejects
+/// dips that barely go below 1.0, i.e. no periodicity at all) and undo its formula.
+pub(crate) fn yin_pitch(
+    detector: &mut YINDetector<f32>,
+    chunk: &[f32],
+    sample_rate: usize,
+) -> Option<(f32, f32)> {
+    const PROBE: f32 = 1e-3;
+    let pitch = detector.get_pitch(chunk, sample_rate, 0.0, PROBE)?;
+    // reported = PROBE + peak / (-PROBE * (1 - PROBE)) where peak = (1 - PROBE) - d'
+    let peak = (pitch.clarity - PROBE) * -PROBE * (1.0 - PROBE);
+    Some((pitch.frequency, (peak + PROBE).clamp(0.0, 1.0)))
+}
+
+/// Which pitch detector the analysis functions run on every window, all three come from the
+/// pitch-detection crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PitchAlgorithm {
+    /// YIN, the default, accurate and robust for most monophonic material, but a weak
+    /// fundamental under strong harmonics can throw it off by an octave.
+    #[default]
+    Yin,
+    /// McLeod's pitch method (normalized square difference), tends to hold on to the fundamental
+    /// better than YIN on bass-heavy or harmonically rich material, clarity is the height of the
+    /// chosen peak of the normalized function (0.0 to 1.0).
+    McLeod,
+    /// Plain autocorrelation, the cheapest and the least accurate (no interpolation between
+    /// lags, so high pitches are coarse), mostly useful as a second opinion.
+    Autocorrelation,
+}
+
+/// Runs the chosen detector on a chunk and returns `(frequency, clarity)`, with clarity between
+/// 0.0 and 1.0 whatever the algorithm. YIN goes through `yin_pitch`, McLeod and autocorrelation
+/// pick the first peak of their (normalized) function that's above 0.7 of the zero-lag value,
+/// pitch-detection's suggested threshold, a lower one makes them latch on to the small peaks
+/// right after lag zero and report wildly high pitches.
+pub(crate) fn detect_pitch(
+    algorithm: PitchAlgorithm,
+    chunk: &[f32],
+    sample_rate: usize,
+) -> Option<(f32, f32)> {
+    const PEAK_THRESHOLD: f32 = 0.7;
+    let (size, padding) = (chunk.len(), chunk.len() / 2);
+    let pitch = match algorithm {
+        PitchAlgorithm::Yin => {
+            return yin_pitch(&mut YINDetector::new(size, padding), chunk, sample_rate)
+        }
+        PitchAlgorithm::McLeod => {
+            McLeodDetector::new(size, padding).get_pitch(chunk, sample_rate, 0.0, PEAK_THRESHOLD)?
+        }
+        PitchAlgorithm::Autocorrelation => AutocorrelationDetector::new(size, padding).get_pitch(
+            chunk,
+            sample_rate,
+            0.0,
+            PEAK_THRESHOLD,
+        )?,
+    };
+    Some((pitch.frequency, pitch.clarity.clamp(0.0, 1.0)))
 }
 
 fn mean(list: &[f32]) -> f32 {
     list.iter().sum::<f32>() / list.len() as f32
 }
 
+/// The `percent`th percentile (0 to 100) of a sorted, non-empty list, linearly interpolated
+/// between the two closest values.
+fn percentile(sorted: &[f32], percent: f32) -> f32 {
+    let position = (percent.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f32;
+    let below = position.floor() as usize;
+    let above = (below + 1).min(sorted.len() - 1);
+    let fraction = position - below as f32;
+    sorted[below] + (sorted[above] - sorted[below]) * fraction
+}
+
 fn median(list: &[f32]) -> f32 {
     let len = list.len();
     let mid = len / 2;
-    if len % 2 == 0 {
+    if len.is_multiple_of(2) {
         mean(&list[(mid - 1)..(mid + 1)])
     } else {
         list[mid]
@@ -134,9 +507,11 @@ fn median(list: &[f32]) -> f32 {
 
 /// Basic report of the pitch values observed, returned by `analyze_pitch`, refer to it's
 /// documentation for more in-depth explanation.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Copy)]
 pub struct PitchReport {
-    /// This is the percentage of chunks (1024 sound samples) used out of the whole audio data,
+    /// This is the percentage of chunks (1024 sound samples, or whatever windows were set with
+    /// `AnalyzeOptions`) used out of the whole audio data,
     /// this is out of 100, so 17.7[...] would signify 17.7[...]% percent of the chunks were used
     /// as valid pitch points, and the rest were discarded because they were either way too high or
     /// way too low, or just couldn't be measured with YIN at all, rule of thumb for instrumentals
@@ -149,6 +524,117 @@ pub struct PitchReport {
     pub median: f32,
     pub lowest: f32,
     pub highest: f32,
+    /// The 5th, 25th, 75th and 95th percentiles of the pitch points (interpolated between the
+    /// nearest two), all in Hz like the rest. Like every other statistic here they're taken after
+    /// outlier trimming, so with the default 10% trim `p5` sits between `lowest` and `p25`
+    /// rather than at the 5th percentile of everything detected, set
+    /// `AnalyzeOptions::outlier_trim_percent` to 0 for that.
+    pub p5: f32,
+    pub p25: f32,
+    pub p75: f32,
+    pub p95: f32,
+    /// Population standard deviation of the pitch points in Hz, how much the pitch moves around
+    /// the mean (intonation range, vibrato depth).
+    pub std_dev: f32,
+    /// Mean clarity (the detector's confidence, 0.0 to 1.0) of the pitch points that made it
+    /// into the report.
+    pub mean_clarity: f32,
+    /// The equal tempered notes (A4 = 440Hz) nearest to `mean` and `median`, and how many cents
+    /// sharp or flat of them they are, see `nearest_note`.
+    pub mean_note: NearestNote,
+    pub median_note: NearestNote,
+}
+
+/// A single pitch measurement at a point in time, `time_secs` is where the analysis window
+/// started, `frequency` is in Hz and `clarity` is the detector's (YIN's, unless another
+/// `PitchAlgorithm` was picked) confidence in it (0.0 to 1.0).
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchPoint {
+    pub time_secs: f64,
+    pub frequency: f32,
+    pub clarity: f32,
+}
+
+/// Which statistic `aggregate_pitch` (and with it `analyze_pitch_segments` and `segment_notes`)
+/// uses to boil a group of pitch points down to one value,
+/// `Median` shrugs off the odd octave error, `Mean` follows slow drifts (vibrato, sliding into a
+/// note) more faithfully, and `ClarityWeightedMean` is a mean where confident points count more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PitchStatistic {
+    Mean,
+    #[default]
+    Median,
+    ClarityWeightedMean,
+}
+
+/// Reduces a group of pitch points (a note, a segment, a whole phrase) to a single representative
+/// frequency in Hz using the chosen `PitchStatistic`, points with a non-positive frequency are
+/// ignored, returns `None` if nothing is left (or, for `ClarityWeightedMean`, if every clarity is
+/// zero).
+pub fn aggregate_pitch(points: &[PitchPoint], statistic: PitchStatistic) -> Option<f32> {
+    let points: Vec<&PitchPoint> = points.iter().filter(|p| p.frequency > 0.0).collect();
+    if points.is_empty() {
+        return None;
+    }
+
+    match statistic {
+        PitchStatistic::Mean => {
+            let freqs: Vec<f32> = points.iter().map(|p| p.frequency).collect();
+            Some(mean(&freqs))
+        }
+        PitchStatistic::Median => {
+            let mut freqs: Vec<f32> = points.iter().map(|p| p.frequency).collect();
+            freqs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            Some(median(&freqs))
+        }
+        PitchStatistic::ClarityWeightedMean => {
+            let weights: f32 = points.iter().map(|p| p.clarity.max(0.0)).sum();
+            if weights <= 0.0 {
+                return None;
+            }
+            let sum: f32 = points
+                .iter()
+                .map(|p| p.frequency * p.clarity.max(0.0))
+                .sum();
+            Some(sum / weights)
+        }
+    }
+}
+
+/// Which pitch points the analysis trusts, based on YIN's clarity (its confidence, 0.0 to 1.0).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClarityFloor {
+    /// Every pitch point is accepted no matter how unsure YIN was about it.
+    #[default]
+    None,
+    /// Pitch points with a clarity below this value are rejected.
+    Fixed(f32),
+    /// The floor is worked out per file from the clarity of all its (non-silent) chunks, see
+    /// `adaptive_clarity_floor`, so a clean studio recording and a noisy phone recording are each
+    /// judged against their own baseline instead of one number that's too strict for one and too
+    /// lenient for the other.
+    Adaptive,
+}
+
+/// The acceptance floor `ClarityFloor::Adaptive` uses for a set of clarity values: their mean
+/// plus half a standard deviation, so only the points YIN was more sure about than usual *for
+/// this file* get through. On a clean recording the clarities bunch up near 1.0 and the floor
+/// sits right under them, on a noisy one they're lower and spread out, which pulls the floor down
+/// with them. The floor is capped at the best point so there's always something left. Returns
+/// 0.0 (accept everything) for an empty set.
+pub fn adaptive_clarity_floor(clarities: &[f32]) -> f32 {
+    if clarities.is_empty() {
+        return 0.0;
+    }
+    let mean = mean(clarities);
+    let variance =
+        clarities.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / clarities.len() as f32;
+    // never above the best point, with a few far outliers half a deviation can land past all
+    // of the others, and rounding alone could reject every point of a file where they all have
+    // the same clarity
+    let best = clarities.iter().fold(f32::MIN, |best, c| c.max(best));
+    (mean + 0.5 * variance.sqrt()).min(best)
 }
 
 /// Analyze the pitch of some audio data, it splits up the audio data into chunks of 1024 sound
@@ -161,148 +647,1772 @@ pub struct PitchReport {
 /// the clamped pitch points, this usually means that we'll usually be getting very accurate and
 /// very clean pitch points; this function will return those as a Vec<f32> (each value is in Hz)
 /// along with a basic `PitchReport`, containing mean, median, lowest, and highest pitch points
-/// observed for convenience. (Along with a `chunks_used` which is more complex, refer to
-/// `PitchReport`'s documentation)
+/// observed for convenience, plus percentiles, standard deviation and mean clarity. (Along with a
+/// `chunks_used` which is more complex, refer to `PitchReport`'s documentation) The 10% can be
+/// changed through `AnalyzeOptions::outlier_trim_percent` and `analyze_pitch_with`.
 ///
-/// Note: This function will panic if there aren't any valid pitch points (within your min/max
-/// frequencies) in your audio data.
+/// Chunks that are effectively silent (below -90dBFS RMS) are skipped before running YIN on them,
+/// if every chunk is silent this returns `AvasaraError::SilentInput`, and if there's signal but
+/// no valid pitch points (within your min/max frequencies) it returns
+/// `AvasaraError::NoPitchDetected`.
 pub fn analyze_pitch(
     audio_data: &[f32],
     sample_rate: usize,
     min_frequency: f32,
     max_frequency: f32,
-) -> (PitchReport, Vec<f32>) {
-    let mut pitch_points: Vec<(f32, f32)> = vec![];
-    for chunk in audio_data.chunks(1024) {
-        let mut detector = YINDetector::new(chunk.len(), chunk.len() / 2);
-        // power threshold and clarity threshold are zero, we'll clamp the array ourselves instead.
-        let pitch_opt = detector.get_pitch(chunk, sample_rate, 0.0, 0.0);
-        if let Some(v) = pitch_opt {
-            pitch_points.push((v.frequency, v.clarity))
+) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+    analyze_pitch_with(
+        audio_data,
+        sample_rate,
+        min_frequency,
+        max_frequency,
+        &AnalyzeOptions::default(),
+    )
+}
+
+/// How `analyze_pitch_with` and `analyze_pitch_contour` slice the audio up and which pitch
+/// detector they run on every slice.
+#[derive(Debug, Clone)]
+pub struct AnalyzeOptions {
+    /// Length of every analysis window in samples, longer windows can see lower pitches (YIN
+    /// needs at least two periods, so 1024 samples at 48kHz bottoms out around 94Hz) and are less
+    /// noisy, shorter ones follow fast changes better. Stick to powers of two, YIN's FFT can
+    /// panic on window lengths with large prime factors.
+    pub window_size: usize,
+    /// How far apart consecutive windows start, in samples, anything below `window_size` makes
+    /// them overlap (a quarter of the window is 75% overlap) for a denser, smoother contour at
+    /// the cost of proportionally more work.
+    pub hop_size: usize,
+    /// The detector to use, see `PitchAlgorithm`.
+    pub algorithm: PitchAlgorithm,
+    /// Cutoff (in Hz) of a steep (24dB/octave) high-pass filter run over the audio before it's
+    /// sliced up, `None` to analyze it as is. Rumble, wind and mains hum under a field recording
+    /// can pull a detector down to their own frequency, 60-80Hz clears them while leaving even a
+    /// low male voice alone. Keep it well below `min_frequency`, the filter attenuates the
+    /// fundamental around its cutoff.
+    pub highpass_hz: Option<f32>,
+    /// How much of the pitch points to throw away as outliers at each end (in percent, clamped to
+    /// 0 to 49) once they're sorted, before anything is reported. The default 10 drops the lowest
+    /// and highest 10% (octave errors, breath noise that still looked pitched), 0 keeps everything
+    /// for when the extremes are the point (the full range of a singer, say).
+    pub outlier_trim_percent: f32,
+    /// Octave-error correction and median filtering run over the detected pitches (in
+    /// chronological order, before the frequency range is applied, so an octave error that
+    /// landed outside of it can still be brought back), `None` to use them as detected, see
+    /// `smooth_contour`.
+    pub smoothing: Option<ContourSmoothing>,
+    /// Pitch points YIN wasn't confident enough about are thrown away before the frequency range
+    /// and outlier trimming are applied, see `ClarityFloor`. With a floor that's too strict for
+    /// the material the analysis can end up returning `AvasaraError::NoPitchDetected`.
+    /// `analyze_pitch_contour` leaves it to you, filter on `clarity` there.
+    pub clarity_floor: ClarityFloor,
+    /// A single knob (0.0 to 1.0) for "fewer but better" pitch points without tuning anything,
+    /// it raises two gates together, a window must be louder than `-90 + 50 * quality` dBFS RMS
+    /// (so -90dBFS at 0.0, -40dBFS at 1.0) for its pitch point to count, and the point must have
+    /// a clarity of at least `0.95 * quality` (on top of `clarity_floor`). At the default 0.0
+    /// neither gate is there, and since both only ever get stricter, a higher `quality` never
+    /// lets more windows through them. `chunks_used` counts what's left after outlier trimming
+    /// though, which trims a rounded 10% off each end, so crossing a rounding boundary can make
+    /// it tick back up by a chunk at each end (a 5 point pitch set gets trimmed to 3, a 4 point
+    /// one isn't trimmed at all), it follows `quality` down otherwise. Values outside 0.0..=1.0
+    /// are clamped. Like `clarity_floor` it only applies to the report, not to
+    /// `analyze_pitch_contour`.
+    pub quality: f32,
+    /// Checked before every window by `analyze_pitch_with`, which bails out with
+    /// `AvasaraError::Cancelled` as soon as it's set, so a UI can abort the analysis of a very
+    /// long file from another thread (keep a clone of the `Arc` to set it) without waiting for
+    /// the end. `None` always runs to the end.
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for AnalyzeOptions {
+    /// 1024 sample windows, back to back, analyzed with YIN without any filtering or smoothing,
+    /// every pitch point accepted no matter its clarity, 10% of them trimmed at either end and no
+    /// way to cancel, what `analyze_pitch` uses.
+    fn default() -> Self {
+        AnalyzeOptions {
+            window_size: 1024,
+            hop_size: 1024,
+            algorithm: PitchAlgorithm::Yin,
+            highpass_hz: None,
+            outlier_trim_percent: 10.0,
+            smoothing: None,
+            clarity_floor: ClarityFloor::None,
+            quality: 0.0,
+            cancel: None,
+        }
+    }
+}
+
+impl AnalyzeOptions {
+    /// The audio `options` says to analyze, high-passed if `highpass_hz` is set, untouched (and
+    /// uncopied) otherwise.
+    fn prefiltered<'a>(&self, audio: &'a [f32], sample_rate: usize) -> Cow<'a, [f32]> {
+        match self.highpass_hz {
+            Some(cutoff) => {
+                let mut filtered = audio.to_vec();
+                apply_filters(
+                    &mut filtered,
+                    1,
+                    &[Biquad::highpass(sample_rate, cutoff as f64, std::f64::consts::FRAC_1_SQRT_2);
+                        2],
+                );
+                Cow::Owned(filtered)
+            }
+            None => Cow::Borrowed(audio),
+        }
+    }
+
+    /// Windows of `window_size` samples overlapping by `overlap_percent` (0 to 100, clamped to
+    /// at most 99 so consecutive windows never start at the same sample), e.g. `(2048, 75.0)`
+    /// for 2048 sample windows every 512 samples.
+    pub fn with_overlap(window_size: usize, overlap_percent: f32) -> Self {
+        let overlap = overlap_percent.clamp(0.0, 99.0) / 100.0;
+        AnalyzeOptions {
+            window_size,
+            hop_size: ((window_size as f32 * (1.0 - overlap)).round() as usize).max(1),
+            ..AnalyzeOptions::default()
+        }
+    }
+
+    /// The energy floor (in dBFS RMS) and clarity floor `quality` works out to, `(-inf, -inf)`
+    /// at 0.0.
+    fn quality_gates(&self) -> (f32, f32) {
+        let quality = self.quality.clamp(0.0, 1.0);
+        if quality > 0.0 {
+            (-90.0 + 50.0 * quality, 0.95 * quality)
+        } else {
+            (f32::NEG_INFINITY, f32::NEG_INFINITY)
+        }
+    }
+}
+
+/// Same as `analyze_pitch`, but with the analysis windows configured through `AnalyzeOptions`
+/// instead of the fixed back to back 1024 sample chunks, with `AnalyzeOptions::default()` it's
+/// exactly `analyze_pitch`. With overlapping windows every window is a chunk of its own, so
+/// `PitchReport::chunks_used` is relative to the number of hops that fit in the audio.
+///
+/// # Panics
+/// Panics if the window or hop size is zero.
+pub fn analyze_pitch_with(
+    audio_data: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: &AnalyzeOptions,
+) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+    analyze(
+        audio_data,
+        sample_rate,
+        min_frequency,
+        max_frequency,
+        options,
+        None,
+    )
+}
+
+/// Same as `analyze_pitch`, but only looks at the parts of the audio inside `segments` (times in
+/// seconds, from `detect_voice_activity` or anywhere else), so the silence, breathing and
+/// background noise between the sentences of an interview never make it into the analysis. Every
+/// segment is cut into its own 1024 sample chunks, only whole chunks are analyzed (a segment
+/// shorter than 1024 samples is skipped), and `PitchReport::chunks_used` is relative to the
+/// total length of the segments rather than the whole audio. Returns `AvasaraError::SilentInput`
+/// if there's nothing (or only silence) to analyze within the segments.
+pub fn analyze_pitch_in_segments(
+    audio_data: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    segments: &[Segment],
+) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+    analyze(
+        audio_data,
+        sample_rate,
+        min_frequency,
+        max_frequency,
+        &AnalyzeOptions::default(),
+        Some(segments),
+    )
+}
+
+/// The pitch of every analysis window of some mono audio data in chronological order, for
+/// plotting pitch over time or lining it up with lyrics, where `analyze_pitch`'s sorted and
+/// trimmed frequencies are no use. Every point's `time_secs` is where its window starts. Windows
+/// that are silent or whose pitch falls outside `min_frequency..max_frequency` get no point at
+/// all, so unvoiced stretches show up as gaps (which `align_to_frame_rate` keeps as gaps). No
+/// outlier trimming or clarity floor is applied, filter on `clarity` yourself if you need to,
+/// `AnalyzeOptions::smoothing` is though.
+///
+/// # Panics
+/// Panics if the window or hop size is zero.
+pub fn analyze_pitch_contour(
+    audio_data: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: &AnalyzeOptions,
+) -> Vec<PitchPoint> {
+    assert!(
+        options.window_size > 0 && options.hop_size > 0,
+        "window and hop sizes must be non-zero"
+    );
+
+    let audio_data = options.prefiltered(audio_data, sample_rate);
+    let windows: Vec<(usize, &[f32])> =
+        analysis_windows(&audio_data, options.window_size, options.hop_size).collect();
+    let points = map_windows(&windows, |start, chunk| {
+        if is_silent(chunk) {
+            return Ok(None);
         }
+        Ok(
+            detect_pitch(options.algorithm, chunk, sample_rate).map(|(frequency, clarity)| {
+                PitchPoint {
+                    time_secs: start as f64 / sample_rate as f64,
+                    frequency,
+                    clarity,
+                }
+            }),
+        )
+    });
+    // nothing in here can fail, there's no cancellation flag to check
+    let mut points: Vec<PitchPoint> = points.unwrap_or_default().into_iter().flatten().collect();
+    if let Some(smoothing) = &options.smoothing {
+        points = smooth_contour(&points, smoothing);
+    }
+
+    points
+        .into_iter()
+        .filter(|p| (p.frequency > min_frequency) && (p.frequency < max_frequency))
+        .collect()
+}
+
+/// The representative pitch of every one of `segments` (times in seconds, from
+/// `detect_voice_activity`, `segment_notes` or anywhere else) in Hz, one entry per segment in the
+/// same order, boiled down from the points of `analyze_pitch_contour` whose window starts inside
+/// the segment with the chosen `PitchStatistic`. `Median` shrugs off the odd octave error,
+/// `Mean` follows a phrase that drifts. A segment without any pitch points (silent, unvoiced, or
+/// shorter than a hop) gets `None`.
+///
+/// # Panics
+/// Panics if the window or hop size is zero.
+pub fn analyze_pitch_segments(
+    audio_data: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    segments: &[Segment],
+    options: &AnalyzeOptions,
+    statistic: PitchStatistic,
+) -> Vec<Option<f32>> {
+    let contour = analyze_pitch_contour(
+        audio_data,
+        sample_rate,
+        min_frequency,
+        max_frequency,
+        options,
+    );
+
+    segments
+        .iter()
+        .map(|segment| {
+            let points: Vec<PitchPoint> = contour
+                .iter()
+                .filter(|p| p.time_secs >= segment.start && p.time_secs < segment.end)
+                .copied()
+                .collect();
+            aggregate_pitch(&points, statistic)
+        })
+        .collect()
+}
+
+/// The analysis windows `analyze` walks over, `window` samples long and `hop` apart, each with
+/// the index of its first sample. Without overlap it stops at the first window that reaches the
+/// end of the audio (which may be shorter than the rest), so with `hop == window` these are
+/// exactly `audio.chunks(window)`. With overlap only full windows are kept (unless the audio is
+/// shorter than a single window), the tail they'd cover is already in the windows before it, and
+/// YIN panics on a lot of the odd lengths a partial window would end up with.
+fn analysis_windows(
+    audio: &[f32],
+    window: usize,
+    hop: usize,
+) -> impl Iterator<Item = (usize, &[f32])> {
+    (0..audio.len())
+        .step_by(hop)
+        .take_while(move |start| {
+            if hop < window {
+                *start == 0 || start + window <= audio.len()
+            } else {
+                *start == 0 || start - hop + window < audio.len()
+            }
+        })
+        .map(move |start| (start, &audio[start..(start + window).min(audio.len())]))
+}
+
+/// Fewest windows worth handing to a thread of their own with the `parallel` feature, below
+/// that sending them off costs more than it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_WINDOWS: usize = 64;
+
+/// Runs `analyze_window` on every analysis window (given its first sample and its samples) and
+/// returns the results in the same order as the windows, or the first error one of them
+/// returned. With the `parallel` feature the windows are analyzed on rayon's thread pool (in runs
+/// of at least `PARALLEL_MIN_WINDOWS`), which on a long file cuts the time roughly by the number
+/// of cores, without it they're done one after another on the calling thread. Every window is
+/// analyzed on its own either way and rayon collects them back in order, so the results are
+/// exactly the same.
+fn map_windows<T, F>(windows: &[(usize, &[f32])], analyze_window: F) -> Result<Vec<T>, AvasaraError>
+where
+    T: Send,
+    F: Fn(usize, &[f32]) -> Result<T, AvasaraError> + Sync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+
+        windows
+            .par_iter()
+            .with_min_len(PARALLEL_MIN_WINDOWS)
+            .map(|(start, chunk)| analyze_window(*start, chunk))
+            .collect()
     }
 
+    #[cfg(not(feature = "parallel"))]
+    {
+        windows
+            .iter()
+            .map(|(start, chunk)| analyze_window(*start, chunk))
+            .collect()
+    }
+}
+
+/// The analysis windows of every segment (clamped to the audio, in order), each segment windowed
+/// on its own from its first sample, along with how many samples the segments cover. Only full
+/// windows are kept, a segment shorter than one window contributes nothing, a partial window at
+/// the end of every segment would otherwise be the odd lengths YIN panics on.
+fn segment_windows<'a>(
+    audio: &'a [f32],
+    sample_rate: usize,
+    segments: &[Segment],
+    options: &AnalyzeOptions,
+) -> (Vec<(usize, &'a [f32])>, usize) {
+    let to_sample =
+        |secs: f64| ((secs.max(0.0) * sample_rate as f64).round() as usize).min(audio.len());
+
+    let mut windows = vec![];
+    let mut covered = 0;
+    for segment in segments {
+        let (start, end) = (to_sample(segment.start), to_sample(segment.end));
+        if end <= start {
+            continue;
+        }
+        covered += end - start;
+        windows.extend(
+            analysis_windows(&audio[start..end], options.window_size, options.hop_size)
+                .filter(|(_, chunk)| chunk.len() == options.window_size)
+                .map(|(offset, chunk)| (start + offset, chunk)),
+        );
+    }
+
+    (windows, covered)
+}
+
+fn analyze(
+    audio_data: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: &AnalyzeOptions,
+    segments: Option<&[Segment]>,
+) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+    assert!(
+        options.window_size > 0 && options.hop_size > 0,
+        "window and hop sizes must be non-zero"
+    );
+
+    let (energy_floor_dbfs, clarity_gate) = options.quality_gates();
+    let audio_data = options.prefiltered(audio_data, sample_rate);
+    let (windows, analyzed_len) = match segments {
+        None => (
+            analysis_windows(&audio_data, options.window_size, options.hop_size).collect(),
+            audio_data.len(),
+        ),
+        Some(segments) => segment_windows(&audio_data, sample_rate, segments, options),
+    };
+    // `None` for a silent window, `Some(None)` for one that's too quiet or has no pitch
+    let results = map_windows(&windows, |_, chunk| {
+        if let Some(cancel) = &options.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(AvasaraError::Cancelled);
+            }
+        }
+        if is_silent(chunk) {
+            return Ok(None);
+        }
+        if rms_dbfs(chunk) < energy_floor_dbfs {
+            return Ok(Some(None));
+        }
+
+        // no power or clarity threshold, we'll clamp the array ourselves instead.
+        Ok(Some(detect_pitch(options.algorithm, chunk, sample_rate)))
+    })?;
+
+    if results.iter().all(Option::is_none) {
+        return Err(AvasaraError::SilentInput);
+    }
+    let mut pitch_points: Vec<(f32, f32)> = results.into_iter().flatten().flatten().collect();
+    if let Some(smoothing) = &options.smoothing {
+        let frequencies: Vec<f32> = pitch_points.iter().map(|(freq, _)| *freq).collect();
+        for (point, smoothed) in pitch_points
+            .iter_mut()
+            .zip(contour::smooth_frequencies(&frequencies, smoothing))
+        {
+            point.0 = smoothed;
+        }
+    }
+    pitch_points.retain(|(_, clarity)| *clarity >= clarity_gate);
+
+    pitch_report(
+        &pitch_points,
+        min_frequency,
+        max_frequency,
+        options.clarity_floor,
+        analyzed_len as f64 / options.hop_size as f64,
+        options.outlier_trim_percent,
+    )
+}
+
+/// The report half of `analyze`, shared with `PitchAnalyzer::finalize`, takes every detected
+/// `(frequency, clarity)` (smoothed already, if it's going to be) in chronological order and
+/// applies the clarity floor, the frequency range and outlier trimming before boiling what's left
+/// down to a `PitchReport`. `windows` is how many hops fit in the analyzed audio, which
+/// `chunks_used` is relative to.
+fn pitch_report(
+    pitch_points: &[(f32, f32)],
+    min_frequency: f32,
+    max_frequency: f32,
+    clarity_floor: ClarityFloor,
+    windows: f64,
+    outlier_trim_percent: f32,
+) -> Result<(PitchReport, Vec<f32>), AvasaraError> {
+    let floor = match clarity_floor {
+        ClarityFloor::None => f32::NEG_INFINITY,
+        ClarityFloor::Fixed(floor) => floor,
+        ClarityFloor::Adaptive => {
+            let clarities: Vec<f32> = pitch_points.iter().map(|(_, clarity)| *clarity).collect();
+            adaptive_clarity_floor(&clarities)
+        }
+    };
+
     // TODO: convert into an iterator, it'll be faster
-    let mut freqvec = vec![];
-    for (freq, _) in &pitch_points {
-        if (*freq > min_frequency as f32) && (*freq < max_frequency as f32) {
-            freqvec.push(*freq);
+    let mut kept = vec![];
+    for (freq, clarity) in pitch_points {
+        if (*freq > min_frequency) && (*freq < max_frequency) && (*clarity >= floor) {
+            kept.push((*freq, *clarity));
         }
     }
 
     // sorts the pitch points smallest to highest, then takes the bottom 10% (low) and high
-    // 10% (high), then removes both of them leaving 80% of the values, practically removing
-    // the extreme highs and extreme lows.
-    freqvec.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-    let low = (freqvec.len() as f64 * 0.10).round() as usize;
-    let high = freqvec.len() - low;
-    let freqvec: Vec<f32> = freqvec[low..high].to_vec();
+    // 10% (high) (or whatever `outlier_trim_percent` says), then removes both of them leaving
+    // 80% of the values, practically removing the extreme highs and extreme lows.
+    if kept.is_empty() {
+        return Err(AvasaraError::NoPitchDetected);
+    }
+    kept.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let trim = outlier_trim_percent.clamp(0.0, 49.0) as f64 / 100.0;
+    let low = (kept.len() as f64 * trim).round() as usize;
+    let high = kept.len() - low;
+    let kept = &kept[low..high];
+    let freqvec: Vec<f32> = kept.iter().map(|(freq, _)| *freq).collect();
+    let clarities: Vec<f32> = kept.iter().map(|(_, clarity)| *clarity).collect();
+
+    let average = mean(&freqvec);
+    let median_frequency = median(&freqvec);
+    let variance =
+        freqvec.iter().map(|f| (f - average).powi(2)).sum::<f32>() / freqvec.len() as f32;
 
     // what the actual fuck is happening
     let pitch_report = PitchReport {
-        chunks_used: (freqvec.len() as f64 / (audio_data.len() as f64 / 1024.0)) * 100.0,
-        mean: mean(&freqvec),
-        median: median(&freqvec),
+        chunks_used: (freqvec.len() as f64 / windows) * 100.0,
+        mean: average,
+        median: median_frequency,
         lowest: *freqvec.first().unwrap(),
         highest: *freqvec.last().unwrap(),
+        p5: percentile(&freqvec, 5.0),
+        p25: percentile(&freqvec, 25.0),
+        p75: percentile(&freqvec, 75.0),
+        p95: percentile(&freqvec, 95.0),
+        std_dev: variance.sqrt(),
+        mean_clarity: mean(&clarities),
+        mean_note: nearest_note(average, 440.0),
+        median_note: nearest_note(median_frequency, 440.0),
     };
 
-    (pitch_report, freqvec)
+    Ok((pitch_report, freqvec))
+}
+
+/// Frequency (in Hz) of the strongest peak in the magnitude spectrum of some mono audio data,
+/// the crudest pitch estimate there is, it's right for pure tones and wrong (usually by an octave
+/// or more) whenever a harmonic is louder than the fundamental, which is exactly what makes it a
+/// useful second opinion next to YIN. Returns `None` for silent or peakless input.
+pub fn dominant_frequency(audio: &[f32], sample_rate: usize) -> Option<f32> {
+    let frame_len = audio.len().next_power_of_two().clamp(256, 8192);
+    let magnitudes = spectrum::average_magnitude_spectrum(audio, frame_len);
+    spectrum::spectral_peaks(&magnitudes, sample_rate, frame_len, -60.0)
+        .first()
+        .map(|(freq, _)| *freq)
+}
+
+/// YIN's and the FFT peak's pitch for a single analysis window, as returned by
+/// `cross_validate_pitch`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchComparison {
+    /// Where the window starts, in seconds.
+    pub time_secs: f64,
+    /// YIN's estimate in Hz, `None` if it found nothing within the frequency range.
+    pub yin: Option<f32>,
+    /// `dominant_frequency`'s estimate in Hz, `None` if it found nothing within the frequency
+    /// range.
+    pub fft: Option<f32>,
+    /// Whether both found a pitch and they're within a semitone of each other.
+    pub agree: bool,
+}
+
+/// Runs both YIN and `dominant_frequency` on every analysis window of some mono audio data (the
+/// same windows `analyze_pitch_with` uses for `options`, high-passed if it says so, but only full
+/// ones, a partial window at the end is skipped) and reports both estimates side by side, along
+/// with whether they agree. On clean, simple material they almost always do, windows where they
+/// don't are the likely octave errors (a strong second harmonic pulls the FFT peak up an octave,
+/// a weak fundamental throws YIN off) or just noise, so this is meant as the input to an octave
+/// correction pass or a quick sanity check of how trustworthy a contour is. Silent windows are
+/// reported with neither estimate. Only the windowing and the high-pass filter of `options` are
+/// used, the detector is always YIN.
+///
+/// # Panics
+/// Panics if the window or hop size is zero.
+pub fn cross_validate_pitch(
+    audio: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: &AnalyzeOptions,
+) -> Vec<PitchComparison> {
+    assert!(
+        options.window_size > 0 && options.hop_size > 0,
+        "window and hop sizes must be non-zero"
+    );
+    let in_range = |freq: f32| (freq > min_frequency && freq < max_frequency).then_some(freq);
+
+    let audio = options.prefiltered(audio, sample_rate);
+    let mut detector = YINDetector::new(options.window_size, options.window_size / 2);
+    analysis_windows(&audio, options.window_size, options.hop_size)
+        .filter(|(_, chunk)| chunk.len() == options.window_size)
+        .map(|(start, chunk)| {
+            let time_secs = start as f64 / sample_rate as f64;
+            if is_silent(chunk) {
+                return PitchComparison {
+                    time_secs,
+                    yin: None,
+                    fft: None,
+                    agree: false,
+                };
+            }
+
+            let yin = yin_pitch(&mut detector, chunk, sample_rate).and_then(|(f, _)| in_range(f));
+            let fft = dominant_frequency(chunk, sample_rate).and_then(in_range);
+            let agree = match (yin, fft) {
+                (Some(yin), Some(fft)) => hz_to_cents(fft, yin).abs() < 100.0,
+                _ => false,
+            };
+
+            PitchComparison {
+                time_secs,
+                yin,
+                fft,
+                agree,
+            }
+        })
+        .collect()
+}
+
+/// Pitch and loudness of a single analysis window, as returned by `pitch_with_energy`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchEnergyFrame {
+    /// Where the window starts, in seconds.
+    pub time_secs: f64,
+    /// The detector's estimate in Hz, `None` for silent windows and ones with no pitch in the
+    /// frequency range.
+    pub frequency: Option<f32>,
+    /// The detector's confidence in `frequency` (0.0 to 1.0), 0.0 when there's no pitch.
+    pub clarity: f32,
+    /// RMS level of the window in dBFS (-120.0 for digital silence).
+    pub rms_dbfs: f32,
+}
+
+/// Pitch and RMS energy of every analysis window of some mono audio data (the same windows
+/// `analyze_pitch_with` uses for `options`, high-passed if it says so, but only full ones, a
+/// partial window at the end is skipped), computed together in a single pass so the two series
+/// can't drift apart, every frame carries both, one frame per window, in order. Meant for plots
+/// that draw the pitch contour with its brightness or thickness following the level, so unvoiced
+/// and quiet stretches fade out instead of needing a separately computed envelope lined up by
+/// hand. Only the windowing, the high-pass filter and the detector of `options` are used.
+///
+/// # Panics
+/// Panics if the window or hop size is zero.
+pub fn pitch_with_energy(
+    audio: &[f32],
+    sample_rate: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+    options: &AnalyzeOptions,
+) -> Vec<PitchEnergyFrame> {
+    assert!(
+        options.window_size > 0 && options.hop_size > 0,
+        "window and hop sizes must be non-zero"
+    );
+
+    let audio = options.prefiltered(audio, sample_rate);
+    analysis_windows(&audio, options.window_size, options.hop_size)
+        .filter(|(_, chunk)| chunk.len() == options.window_size)
+        .map(|(start, chunk)| {
+            let pitch = if is_silent(chunk) {
+                None
+            } else {
+                detect_pitch(options.algorithm, chunk, sample_rate)
+                    .filter(|(freq, _)| *freq > min_frequency && *freq < max_frequency)
+            };
+
+            PitchEnergyFrame {
+                time_secs: start as f64 / sample_rate as f64,
+                frequency: pitch.map(|(freq, _)| freq),
+                clarity: pitch.map_or(0.0, |(_, clarity)| clarity),
+                rms_dbfs: rms_dbfs(chunk).max(-120.0),
+            }
+        })
+        .collect()
+}
+
+/// Estimates the pitch a listener would *perceive* from some mono audio data, as opposed to the
+/// periodicity YIN measures, this matters for sounds whose fundamental is missing (telephone
+/// audio, small speakers) or whose partials aren't neatly harmonic (bells, gongs, some pianos).
+/// It averages the magnitude spectrum over the whole input, picks out the strongest spectral
+/// peaks, then scores every candidate fundamental between 40Hz and 2kHz by how well those peaks
+/// line up with its harmonics (subharmonic summation, lower harmonics count more), the winner is
+/// refined using the peaks it matched. Returns `None` for silent or peakless input.
+///
+/// Note: This is approximate and experimental, the candidate that "wins" for a strongly
+/// inharmonic sound is a best guess that may not match what every listener hears, and it runs on
+/// the whole buffer at once, so feed it a single sustained sound rather than a melody.
+pub fn perceived_pitch(audio: &[f32], sample_rate: usize) -> Option<f32> {
+    let frame_len = 8192;
+    let magnitudes = spectrum::average_magnitude_spectrum(audio, frame_len);
+    let peaks: Vec<(f32, f32)> =
+        spectrum::spectral_peaks(&magnitudes, sample_rate, frame_len, -40.0)
+            .into_iter()
+            .filter(|(freq, _)| *freq >= 20.0)
+            .take(15)
+            .collect();
+    if peaks.is_empty() {
+        return None;
+    }
+
+    let min_f0: f32 = 40.0;
+    let max_f0: f32 = 2000.0;
+    // how far (as a fraction of a harmonic number) a peak may stray and still count, inharmonic
+    // partials are often a few percent sharp or flat.
+    let tolerance = 0.04;
+    let compression: f32 = 0.84;
+
+    // the weight a peak contributes towards `f0`, along with the harmonic number it matched
+    let matches = |f0: f32, freq: f32| -> Option<(f32, f32)> {
+        let ratio = freq / f0;
+        let harmonic = ratio.round();
+        if !(1.0..=15.0).contains(&harmonic) {
+            return None;
+        }
+        let deviation = (ratio - harmonic).abs() / harmonic;
+        if deviation > tolerance {
+            return None;
+        }
+        Some((
+            compression.powi(harmonic as i32 - 1) * (1.0 - deviation / tolerance),
+            harmonic,
+        ))
+    };
+
+    let score = |f0: f32| -> f32 {
+        peaks
+            .iter()
+            .filter_map(|(freq, mag)| matches(f0, *freq).map(|(w, _)| w * mag))
+            .sum()
+    };
+
+    // candidates on a 1/96th octave grid
+    let steps = (96.0 * (max_f0 / min_f0).log2()) as i32;
+    let (best, best_score) = (0..=steps)
+        .map(|i| min_f0 * 2f32.powf(i as f32 / 96.0))
+        .map(|f0| (f0, score(f0)))
+        .fold((0.0, 0.0), |acc, c| if c.1 > acc.1 { c } else { acc });
+    if best_score <= 0.0 {
+        return None;
+    }
+
+    // refine using the peaks the winner matched, each one implies a fundamental of freq / n
+    let (sum, weights) = peaks
+        .iter()
+        .filter_map(|(freq, mag)| matches(best, *freq).map(|(w, n)| (freq / n, w * mag)))
+        .fold((0.0, 0.0), |(sum, weights), (f0, w)| {
+            (sum + f0 * w, weights + w)
+        });
+
+    Some(sum / weights)
 }
 
 /// Interleaves a vector of f32 sound samples to make mono if stereo, doesn't support more
-/// channels, returns an `Audio<Ch32, 1>` (mono pcm data)
+/// channels (see `downmix_to_mono` for those), returns an `Audio<Ch32, 1>` (mono pcm data), or
+/// `AvasaraError::UnsupportedChannels` for anything other than 1 or 2 channels.
 pub fn interleave_to_mono(
     audio: Vec<f32>,
     sample_rate: u32,
     src_channels: usize,
-) -> Audio<Ch32, 1> {
+) -> Result<Audio<Ch32, 1>, AvasaraError> {
     if src_channels == 1 {
-        let audio = Audio::<Ch32, 1>::with_f32_buffer(sample_rate, audio);
-
-        audio
+        Ok(Audio::<Ch32, 1>::with_f32_buffer(sample_rate, audio))
     } else if src_channels == 2 {
         let audio = Audio::<Ch32, 2>::with_f32_buffer(sample_rate, audio);
-        let audio = Audio::<Ch32, 1>::with_audio(sample_rate, &audio);
-
-        audio
-    } else if src_channels > 2 {
-        panic!("more than 2 channels provided");
+        Ok(Audio::<Ch32, 1>::with_audio(sample_rate, &audio))
     } else {
-        panic!("No channels? (megamind stare)");
+        // more than 2 channels, or no channels? (megamind stare)
+        Err(AvasaraError::UnsupportedChannels(src_channels))
+    }
+}
+
+/// Measures the time offset between the left and right channels of some interleaved stereo audio
+/// data by cross-correlating them and finding the lag (within `max_ms` in either direction) where
+/// they line up best, refined to a fraction of a sample. The result is in milliseconds, positive
+/// means the right channel lags behind the left, negative means the left lags behind the right.
+/// Worth checking before `interleave_to_mono`, since summing two channels that are offset by even
+/// a fraction of a millisecond comb-filters the result. `channels` is the channel count of
+/// `audio`, and there's only a delay to measure between exactly two channels, so this returns
+/// `None` (rather than a made up number) for anything but stereo, for empty audio, and for a
+/// buffer that isn't a whole number of L/R pairs.
+pub fn interchannel_delay(
+    audio: &[f32],
+    sample_rate: usize,
+    channels: usize,
+    max_ms: f32,
+) -> Option<f32> {
+    if (channels != 2) || audio.is_empty() || !audio.len().is_multiple_of(2) {
+        return None;
     }
+
+    let left: Vec<f32> = audio.iter().step_by(2).copied().collect();
+    let right: Vec<f32> = audio.iter().skip(1).step_by(2).copied().collect();
+    let max_lag =
+        ((max_ms / 1000.0 * sample_rate as f32) as usize).min(left.len().saturating_sub(1));
+
+    let correlation = spectrum::cross_correlation(&left, &right, max_lag);
+    let (peak, _) = correlation
+        .iter()
+        .enumerate()
+        .fold(
+            (max_lag, f32::MIN),
+            |acc, (i, r)| if *r > acc.1 { (i, *r) } else { acc },
+        );
+
+    // parabolic interpolation around the peak for sub-sample accuracy
+    let offset = match (
+        correlation.get(peak.wrapping_sub(1)),
+        correlation.get(peak + 1),
+    ) {
+        (Some(l), Some(r)) => {
+            let c = correlation[peak];
+            let denom = l - 2.0 * c + r;
+            if denom.abs() > f32::EPSILON {
+                0.5 * (l - r) / denom
+            } else {
+                0.0
+            }
+        }
+        _ => 0.0,
+    };
+
+    let lag = peak as f32 - max_lag as f32 + offset;
+    Some(lag / sample_rate as f32 * 1000.0)
 }
 
 /// Convenience function which chains the decoding step, the interleaving step and the encoding
-/// step together, for `src` you can load a file as bytes and convert it into a Cursor<Vec<u8>>, or
-/// use in-memory audio data wrapped in a cursor and use it, for a list of the formats it can
-/// decode refer to `decode`'s documentation, then it interleaves it to mono and then encodes it
+/// step together, for `src` you can pass a `File` directly, or use in-memory audio data wrapped
+/// in a cursor (anything `decode` takes works), for a list of the formats it can decode refer to
+/// `decode`'s documentation, then it interleaves it to mono and then encodes it
 /// into Ogg Vorbis, using the `stream_serial`, `target_quality` and `remux` arguments for that,
 /// `stream_serial` really doesn't matter, just pick a random 32bit integer or use 0 or something,
 /// `target_quality` is a float between `-0.2` and `2.0`, the lowest one meaning more compression
 /// and less quality and the bigger one vice versa, `remux` is just for whether you want to use
 /// `optivorbis` to do a two-pass optimization on the result, may or may not be useful but it
 /// exists ig. Feel free to look at the source for a reference of how you can make a function like
-/// this using Avasara yourself!
+/// this using Avasara yourself! All the `compose_to_ogg_*` variants are shorthands for
+/// `ComposeBuilder`, use it directly if you need more than one of their options at once. They
+/// all run silently, use `ComposeBuilder::on_progress` to follow along (or
+/// `ComposeBuilder::print_progress`, which is where `prefix` shows up).
+///
+/// # Errors
+/// Returns an error if decoding, encoding or remuxing fails (see `AvasaraError`). i highly
+/// recommend you write your own function by looking at the source of this one, this is not at all
+/// intended for production use, only as a convenience function for prototyping.
+pub fn compose_to_ogg<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    remux: bool,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .run(src)
+}
+
+/// Same as `compose_to_ogg`, but takes any `OggBitrate` instead of just a quality level, so the
+/// output can be made to fit a bitrate budget for streaming delivery, `OggBitrate::Abr` for an
+/// average one, `OggBitrate::ConstrainedAbr` for a hard cap. `OggBitrate::Quality(q)` gives
+/// exactly what `compose_to_ogg` does with `target_quality` set to `q`. Remuxing doesn't change
+/// the bitrate mode, it only makes the file a bit smaller.
+///
+/// # Errors
+/// Same as `compose_to_ogg`, plus `AvasaraError::Encode` if libvorbis doesn't take the bitrate
+/// for the file's sample rate and channel count.
+pub fn compose_to_ogg_with_bitrate<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    bitrate: OggBitrate,
+    remux: bool,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .bitrate(bitrate)
+        .remux(remux)
+        .run(src)
+}
+
+/// Same as `compose_to_ogg` with remuxing on, except that a source that's already Ogg Vorbis
+/// isn't decoded and re-encoded at all, it goes straight to OptiVorbis, which is much faster and
+/// doesn't stack a second generation of lossy artifacts on top of the first. `target_quality`
+/// only applies to sources that do get encoded, set `force_reencode` to encode Vorbis sources
+/// too (to bring a 320kbit/s file down to something smaller, say). Unlike `compose_to_ogg`,
+/// every channel is kept, so the output has the same shape whichever way it was made (use
+/// `ComposeBuilder` with `passthrough_vorbis` for other channel policies). The whole source is
+/// read into memory first to check what it is.
+///
+/// # Errors
+/// Same as `compose_to_ogg`, reading the source can also fail with `AvasaraError::Io`.
+pub fn transcode_or_remux<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    force_reencode: bool,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .channels(ChannelPolicy::KeepOriginal)
+        .remux(true)
+        .passthrough_vorbis(!force_reencode)
+        .run(src)
+}
+
+/// Encodes `audio` into an Ogg Vorbis file at `path` (created, or truncated if it already
+/// exists) with everything set on `options`, writing it out as it's encoded rather than building
+/// the whole file in memory first (except with remuxing on, OptiVorbis needs all of it). The
+/// counterpart to `decode_file`, the two together make a transcode without any buffers to juggle.
+/// `ComposeBuilder::copy_tags` has nothing to copy from here, pass `audio.tags` to
+/// `ComposeBuilder::comments` to keep them (before handing `audio` over, it's taken by value so
+/// its samples go to the encoder without a copy). Returns the sample rate the file ended up
+/// with, see `compose_to_ogg_reporting_rate`.
+///
+/// # Errors
+/// `AvasaraError::Io` if the file can't be created or written, otherwise the same as
+/// `ComposeBuilder::run_from_samples`. A failed encode can leave a partial file behind.
+pub fn encode_to_file<P: AsRef<Path>>(
+    path: P,
+    audio: DecodedAudio,
+    options: &ComposeBuilder,
+) -> Result<u32, AvasaraError> {
+    let file = BufWriter::new(File::create(path)?);
+    let (samples, sample_rate, channels) = audio.into_parts();
+    options.write_from_samples(samples, sample_rate, channels, file)
+}
+
+/// Same as `compose_to_ogg`, but also returns the sample rate the Ogg file ended up with. That's
+/// the source's own rate whenever the encoder accepts it, libvorbis refuses rates above 200kHz
+/// though (some measurement and DXD-ish recordings go way past that), in which case the audio is
+/// resampled to 48kHz with `resample` and encoded at that rate instead of failing, so check the
+/// returned rate if anything downstream depends on it.
+pub fn compose_to_ogg_reporting_rate<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    remux: bool,
+) -> Result<(Vec<u8>, u32), AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .run_reporting_rate(src)
+}
+
+/// Same as `compose_to_ogg`, but resamples the audio to `target_sample_rate` (with `resample` at
+/// `ResampleQuality::Balanced`) before encoding, for normalizing a pile of sources with mixed
+/// rates to a single one, e.g. 24kHz for a TTS dataset. A source that's already at that rate is
+/// left alone, and if libvorbis won't take the target rate the output falls back to 48kHz like
+/// `compose_to_ogg_reporting_rate` describes.
 ///
 /// # Panics
-/// Panics if there was a problem while decoding, i highly recommend you write your own function by
-/// looking at the source of this one, this is not at all intended for production use, only as a
-/// convenience function for prototyping.
-pub fn compose_to_ogg(
-    src: Cursor<Vec<u8>>,
+/// Panics if `target_sample_rate` is zero.
+pub fn compose_to_ogg_at_rate<S: MediaSource + 'static>(
+    src: S,
     prefix: &str,
     stream_serial: i32,
     target_quality: f32,
     remux: bool,
-) -> Vec<u8> {
-    println!("[{}] Decoding", prefix);
-    let (audio, sample_rate, channels) = decode(src);
-    if (sample_rate == 0) || (channels == 0) {
-        // FIXME: why tf did i use panic!?
-        panic!("Sample rate or channel count was zero, indicating that there was a problem with decoding, exiting.");
-    }
-    println!("[{}] Decoded", prefix);
-
-    // more info at Fōn/fon's readme
-    println!("[{}] Interleaving", prefix);
-    let mut audio = interleave_to_mono(audio, sample_rate as u32, channels);
-    println!("[{}] Interleaved", prefix);
-
-    println!("[{}] Encoding (to Ogg Vorbis)", prefix);
-    let mut ogg_out = vec![];
-
-    let mut encoder = VorbisEncoder::new(
-        stream_serial,
-        [("", ""); 0],
-        NonZeroU32::new(sample_rate as u32).unwrap(),
-        NonZeroU8::new(1).unwrap(), // because mono
-        VorbisBitrateManagementStrategy::QualityVbr {
-            target_quality, // 2.0 to -0.2
-        },
-        None,
-        &mut ogg_out,
-    )
-    .unwrap();
+    target_sample_rate: u32,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .sample_rate(target_sample_rate)
+        .run(src)
+}
 
-    // i just guessed chunking the massive f32 buffer into itty bitty 512 element chunks would work
-    // and not segfault when trying to encode, and it did, pretty cool ig. FIXME: too many unwraps
-    for i in audio.as_f32_slice().chunks(512) {
-        encoder.encode_audio_block(&[i]).unwrap();
+/// Same as `compose_to_ogg`, but brings the audio to an integrated loudness of
+/// `normalize_to_lufs` (see `measure_lufs`, -16 is a common target for web and podcast clips,
+/// -23 is EBU R128 broadcast) right before encoding, so clips uploaded at wildly different
+/// volumes all come out equally loud. The gain is a plain linear one and is capped so the true
+/// peak never goes above -1 dBTP, which means a quiet clip with loud transients can end up below
+/// the target rather than clipping. Silent input is encoded as is.
+pub fn compose_to_ogg_normalized<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    remux: bool,
+    normalize_to_lufs: f32,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .normalize_to_lufs(normalize_to_lufs)
+        .run(src)
+}
+
+/// Same as `compose_to_ogg`, except the output only depends on the input and the arguments, so
+/// encoding the same file twice gives byte-identical Ogg files (useful for content-addressed
+/// storage and reproducible builds of transcoded assets). Without remuxing that's already the
+/// case, the encoder writes a fixed vendor string and no comments are added, with remuxing
+/// OptiVorbis would otherwise pick a random stream serial and append its own version tag (with
+/// its build date) to the vendor string, here the serial is kept as `stream_serial` and the
+/// vendor string and comments are copied over untouched, in their original order.
+pub fn compose_to_ogg_reproducible<S: MediaSource + 'static>(
+    src: S,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    remux: bool,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .reproducible(true)
+        .run(src)
+}
+
+/// Same as `compose_to_ogg`, but starts from audio that's already been decoded (the vector of
+/// interleaved samples, sample rate and channel count `DecodedAudio::into_parts` gives, in that
+/// order) instead of the file's bytes, for pipelines that decode once, analyze, and then encode,
+/// so the file doesn't get decoded a second time. The output is byte-for-byte what
+/// `compose_to_ogg` gives for the original file, as long as the samples weren't changed in
+/// between (change them if you want, that's kind of the point, it just won't match anymore).
+///
+/// # Errors
+/// Same as `compose_to_ogg`, a sample rate or channel count of zero is `AvasaraError::DecodeFailed`.
+pub fn compose_to_ogg_from_samples(
+    audio: Vec<f32>,
+    sample_rate: usize,
+    channels: usize,
+    prefix: &str,
+    stream_serial: i32,
+    target_quality: f32,
+    remux: bool,
+) -> Result<Vec<u8>, AvasaraError> {
+    ComposeBuilder::new()
+        .prefix(prefix)
+        .stream_serial(stream_serial)
+        .quality(target_quality)
+        .remux(remux)
+        .run_from_samples(audio, sample_rate, channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// Deterministic white noise in -1.0..1.0, a plain LCG so the tests don't need `rand`.
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
     }
-    encoder.finish().unwrap();
 
-    if remux {
-        let mut out = vec![];
+    #[test]
+    fn yin_clarity_is_between_zero_and_one() {
+        let tone = sine(220.0, 44100, 0.1);
+        let mut detector = YINDetector::new(1024, 512);
+        let (frequency, clarity) = yin_pitch(&mut detector, &tone[..1024], 44100).unwrap();
+        assert!((frequency - 220.0).abs() < 2.0, "{}", frequency);
+        assert!(clarity > 0.9 && clarity <= 1.0, "{}", clarity);
 
-        OggToOgg::new_with_defaults()
-            .remux(&mut Cursor::new(ogg_out), &mut out)
+        let hiss = noise(1024, 1);
+        if let Some((_, noisy)) = yin_pitch(&mut detector, &hiss, 44100) {
+            assert!(noisy.is_finite() && noisy < clarity, "{}", noisy);
+        }
+    }
+
+    #[test]
+    fn analyze_pitch_finds_a_clean_tone() {
+        let (report, _) = analyze_pitch(&sine(220.0, 44100, 1.0), 44100, 50.0, 600.0).unwrap();
+        assert!((report.median - 220.0).abs() < 2.0, "{}", report.median);
+    }
+
+    #[test]
+    fn interchannel_delay_finds_a_delayed_right_channel() {
+        // the right channel is the left one 22 samples (about half a millisecond) later
+        let left = noise(44100, 3);
+        let mut stereo = vec![];
+        for i in 0..left.len() {
+            stereo.push(left[i]);
+            stereo.push(if i >= 22 { left[i - 22] } else { 0.0 });
+        }
+
+        let delay = interchannel_delay(&stereo, 44100, 2, 5.0).unwrap();
+        assert!((delay - 22.0 / 44.1).abs() < 0.01, "{}", delay);
+        assert_eq!(interchannel_delay(&stereo[1..], 44100, 2, 5.0), None);
+        assert_eq!(interchannel_delay(&[], 44100, 2, 5.0), None);
+    }
+
+    #[test]
+    fn interchannel_delay_rejects_anything_but_stereo() {
+        // the same samples read as quad or 5.1 (both whole frames) aren't L/R pairs
+        let audio = noise(44100 * 6, 4);
+        for channels in [1, 4, 6] {
+            assert_eq!(interchannel_delay(&audio, 44100, channels, 5.0), None);
+        }
+        assert!(interchannel_delay(&audio, 44100, 2, 5.0).is_some());
+    }
+
+    #[test]
+    fn cancelling_stops_the_analysis_partway() {
+        // three minutes of tone, thousands of chunks, and the flag goes up a few chunks in
+        let tone = sine(220.0, 44100, 180.0);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let started = std::time::Instant::now();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            flag.store(true, Ordering::Relaxed);
+        });
+
+        let options = AnalyzeOptions {
+            cancel: Some(cancel),
+            ..AnalyzeOptions::default()
+        };
+        let result = analyze_pitch_with(&tone, 44100, 50.0, 600.0, &options);
+        canceller.join().unwrap();
+        assert!(
+            matches!(result, Err(AvasaraError::Cancelled)),
+            "{:?}",
+            result
+        );
+        assert!(
+            started.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            started.elapsed()
+        );
+    }
+
+    #[test]
+    fn silence_is_reported_as_silence() {
+        for audio in [vec![0.0; 44100], vec![]] {
+            assert!(matches!(
+                analyze_pitch(&audio, 44100, 50.0, 600.0),
+                Err(AvasaraError::SilentInput)
+            ));
+        }
+        // a tone above the range isn't silence, there's just no pitch in it
+        assert!(matches!(
+            analyze_pitch(&sine(2000.0, 44100, 1.0), 44100, 50.0, 600.0),
+            Err(AvasaraError::NoPitchDetected)
+        ));
+    }
+
+    #[test]
+    fn segment_pitch_follows_the_chosen_statistic() {
+        // 0.8 seconds of 200Hz that jump to 300Hz for the last 0.2, then another second of 200Hz
+        let mut audio = sine(200.0, 44100, 0.8);
+        audio.extend(sine(300.0, 44100, 0.2));
+        audio.extend(sine(200.0, 44100, 1.0));
+        let segments = [
+            Segment {
+                start: 0.0,
+                end: 1.0,
+            },
+            Segment {
+                start: 1.0,
+                end: 2.0,
+            },
+            Segment {
+                start: 5.0,
+                end: 6.0,
+            },
+        ];
+
+        let pitch = |statistic| {
+            analyze_pitch_segments(
+                &audio,
+                44100,
+                50.0,
+                600.0,
+                &segments,
+                &AnalyzeOptions::default(),
+                statistic,
+            )
+        };
+        let median = pitch(PitchStatistic::Median);
+        let mean = pitch(PitchStatistic::Mean);
+
+        assert!((median[0].unwrap() - 200.0).abs() < 2.0, "{:?}", median);
+        // the jump drags the mean up by about a fifth of the 100Hz it's worth
+        assert!((mean[0].unwrap() - 220.0).abs() < 5.0, "{:?}", mean);
+        assert!((mean[1].unwrap() - 200.0).abs() < 2.0, "{:?}", mean);
+        assert_eq!((median[2], mean[2]), (None, None));
+    }
+
+    /// FLAC's header and frame checksums, a CRC-8 (polynomial 0x07) or CRC-16 (0x8005) with
+    /// `width` 8 or 16, neither reflected.
+    fn flac_crc(data: &[u8], width: u32, polynomial: u32) -> u32 {
+        let top = 1 << (width - 1);
+        let mask = (top << 1) - 1;
+        let mut crc = 0u32;
+        for byte in data {
+            crc ^= (*byte as u32) << (width - 8);
+            for _ in 0..8 {
+                crc = if crc & top != 0 {
+                    (crc << 1) ^ polynomial
+                } else {
+                    crc << 1
+                };
+            }
+            crc &= mask;
+        }
+        crc
+    }
+
+    /// A bare-bones mono, 16 bit, 44.1kHz FLAC file of 1152 sample frames with every subframe
+    /// stored verbatim (so `audio` should be a multiple of 1152 samples long). The subframe of
+    /// frame number `corrupt` gets a reserved type, its checksums are still right though, so the
+    /// container hands it over like any other and only the codec notices.
+    fn verbatim_flac(audio: &[i16], corrupt: usize) -> Vec<u8> {
+        let mut file = b"fLaC".to_vec();
+        // STREAMINFO, the last (and only) metadata block, 34 bytes: block sizes, unknown frame
+        // sizes, then rate, channels - 1, bits - 1 and length packed into 64 bits, and no MD5
+        file.extend([0x80, 0, 0, 34]);
+        file.extend(1152u16.to_be_bytes());
+        file.extend(1152u16.to_be_bytes());
+        file.extend([0; 6]);
+        file.extend(((44100u64 << 44) | (15 << 36) | audio.len() as u64).to_be_bytes());
+        file.extend([0; 16]);
+
+        for (number, block) in audio.chunks(1152).enumerate() {
+            let start = file.len();
+            // sync code, 1152 samples at 44.1kHz, mono, 16 bit, then the frame number (UTF-8
+            // coded, a single byte below 128) and the header's CRC-8
+            file.extend([0xff, 0xf8, 0x39, 0x08, number as u8]);
+            file.push(flac_crc(&file[start..], 8, 0x07) as u8);
+            file.push(if number == corrupt {
+                0b0000_0100
+            } else {
+                0b0000_0010
+            });
+            for sample in block {
+                file.extend(sample.to_be_bytes());
+            }
+            let crc = flac_crc(&file[start..], 16, 0x8005) as u16;
+            file.extend(crc.to_be_bytes());
+        }
+
+        file
+    }
+
+    #[test]
+    fn corrupt_packets_are_skipped_with_a_warning() {
+        let tone: Vec<i16> = sine(220.0, 44100, 1.1)[..40 * 1152]
+            .iter()
+            .map(|s| (s * 16000.0) as i16)
+            .collect();
+
+        let (audio, sample_rate, channels, warnings) =
+            decode_with_warnings(std::io::Cursor::new(verbatim_flac(&tone, 20))).unwrap();
+        assert_eq!((sample_rate, channels), (44100, 1));
+        // everything but the broken frame
+        assert_eq!(audio.len(), 39 * 1152);
+        assert!(
+            warnings.iter().any(|warning| matches!(
+                warning,
+                DecodeWarning::SkippedPacket { reason, .. } if reason.contains("reserved")
+            )),
+            "{:?}",
+            warnings
+        );
+    }
+
+    #[test]
+    fn perceived_pitch_hears_a_missing_fundamental() {
+        // harmonics 2 to 6 of 200Hz, nothing at 200Hz itself
+        let sample_rate = 44100;
+        let audio: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                (2..=6)
+                    .map(|h| (2.0 * std::f32::consts::PI * 200.0 * h as f32 * t).sin() * 0.15)
+                    .sum()
+            })
+            .collect();
+
+        let pitch = perceived_pitch(&audio, sample_rate).unwrap();
+        assert!((pitch - 200.0).abs() < 5.0, "{}", pitch);
+    }
+
+    #[test]
+    fn the_adaptive_floor_follows_the_recording() {
+        let clean: Vec<f32> = sine(220.0, 44100, 2.0).iter().map(|s| s * 0.5).collect();
+        let noisy: Vec<f32> = clean
+            .iter()
+            .zip(noise(clean.len(), 3))
+            .map(|(s, n)| s + n * 0.2)
+            .collect();
+        let floor = |audio: &[f32]| {
+            let contour =
+                analyze_pitch_contour(audio, 44100, 50.0, 2000.0, &AnalyzeOptions::default());
+            let clarities: Vec<f32> = contour.iter().map(|point| point.clarity).collect();
+            adaptive_clarity_floor(&clarities)
+        };
+
+        let (clean_floor, noisy_floor) = (floor(&clean), floor(&noisy));
+        assert!(clean_floor > 0.9, "{}", clean_floor);
+        assert!(
+            noisy_floor < clean_floor - 0.05,
+            "{} vs {}",
+            noisy_floor,
+            clean_floor
+        );
+
+        // the noisy recording is judged against its own baseline and still finds the tone
+        let options = AnalyzeOptions {
+            clarity_floor: ClarityFloor::Adaptive,
+            ..AnalyzeOptions::default()
+        };
+        let (report, _) = analyze_pitch_with(&noisy, 44100, 50.0, 2000.0, &options).unwrap();
+        assert!((report.mean - 220.0).abs() < 3.0, "{}", report.mean);
+    }
+
+    #[test]
+    fn yin_and_the_fft_peak_disagree_on_a_missing_fundamental() {
+        let agreement = |audio: &[f32]| {
+            let comparisons =
+                cross_validate_pitch(audio, 44100, 50.0, 2000.0, &AnalyzeOptions::default());
+            comparisons.iter().filter(|c| c.agree).count() as f32 / comparisons.len() as f32
+        };
+
+        let clean: Vec<f32> = sine(440.0, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+        assert!(agreement(&clean) > 0.9, "{}", agreement(&clean));
+
+        // the 2nd and 3rd harmonics of 220Hz without the fundamental, YIN hears the 220Hz they
+        // repeat at and the FFT peak sits on one of the harmonics
+        let ambiguous: Vec<f32> = sine(440.0, 44100, 1.0)
+            .iter()
+            .zip(sine(660.0, 44100, 1.0))
+            .map(|(second, third)| second * 0.4 + third * 0.3)
+            .collect();
+        assert!(agreement(&ambiguous) < 0.1, "{}", agreement(&ambiguous));
+    }
+
+    #[test]
+    fn cross_validation_only_looks_at_full_windows() {
+        // an odd length, the partial window YIN would panic on is left out
+        let tone: Vec<f32> = sine(440.0, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+        let tone = &tone[..44099];
+        let options = AnalyzeOptions::with_overlap(2048, 50.0);
+        let comparisons = cross_validate_pitch(tone, 44100, 50.0, 2000.0, &options);
+
+        assert_eq!(comparisons.len(), (tone.len() - 2048) / 1024 + 1);
+        for (i, comparison) in comparisons.iter().enumerate() {
+            assert_eq!(comparison.time_secs, (i * 1024) as f64 / 44100.0);
+            assert!(comparison.agree, "{:?}", comparison);
+        }
+    }
+
+    #[test]
+    fn repeated_runs_give_identical_results() {
+        // long enough for the `parallel` feature to actually split the windows up
+        let audio: Vec<f32> = sine(196.0, 44100, 4.0)
+            .iter()
+            .zip(noise(44100 * 4, 11))
+            .map(|(s, n)| s * 0.4 + n * 0.1)
+            .collect();
+        let run = || {
+            let (report, points) = analyze_pitch(&audio, 44100, 50.0, 1000.0).unwrap();
+            let contour =
+                analyze_pitch_contour(&audio, 44100, 50.0, 1000.0, &AnalyzeOptions::default());
+            // the Debug output of a float round-trips, so equal strings mean equal bits
+            (
+                format!("{:?}", report),
+                points.iter().map(|p| p.to_bits()).collect::<Vec<_>>(),
+                format!("{:?}", contour),
+            )
+        };
+
+        let first = run();
+        for _ in 0..5 {
+            assert!(run() == first, "a run came out different");
+        }
+    }
+
+    #[test]
+    fn higher_quality_keeps_fewer_chunks() {
+        // a tone fading in from -100dBFS to -20dBFS, so every step up the energy gate drops
+        // another stretch of it
+        let secs = 8.0;
+        let audio: Vec<f32> = sine(220.0, 44100, secs)
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let db = -100.0 + 80.0 * i as f32 / (44100.0 * secs);
+                s * 10f32.powf(db / 20.0) * std::f32::consts::SQRT_2
+            })
+            .collect();
+        let windows = audio.len() as f64 / 1024.0;
+
+        let used: Vec<f64> = [0.0, 0.25, 0.5, 0.75, 1.0]
+            .iter()
+            .map(|quality| {
+                let options = AnalyzeOptions {
+                    quality: *quality,
+                    ..AnalyzeOptions::default()
+                };
+                analyze_pitch_with(&audio, 44100, 50.0, 1000.0, &options)
+                    .unwrap()
+                    .0
+                    .chunks_used
+            })
+            .collect();
+        for pair in used.windows(2) {
+            // at most the chunk at each end that rounding the trim can hand back
+            assert!(pair[1] <= pair[0] + 2.0 * 100.0 / windows, "{:?}", used);
+        }
+        assert!(used[4] < used[0] / 2.0, "{:?}", used);
+    }
+
+    #[test]
+    fn energy_frames_follow_the_options_on_any_length() {
+        // an odd length with overlapping windows, the partial window YIN would panic on is left
+        // out
+        let tone: Vec<f32> = sine(220.0, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+        let tone = &tone[..30001];
+        let options = AnalyzeOptions::with_overlap(2048, 75.0);
+        let frames = pitch_with_energy(tone, 44100, 50.0, 1000.0, &options);
+
+        assert_eq!(frames.len(), (tone.len() - 2048) / 512 + 1);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.time_secs, (i * 512) as f64 / 44100.0);
+            assert!(
+                (frame.frequency.unwrap() - 220.0).abs() < 2.0,
+                "{:?}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn pitch_and_energy_share_their_frames() {
+        // a second of tone, then half a second of nothing
+        let mut audio: Vec<f32> = sine(330.0, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+        audio.resize(audio.len() + 22050, 0.0);
+
+        let frames = pitch_with_energy(&audio, 44100, 50.0, 1000.0, &AnalyzeOptions::default());
+        // 66150 samples, the partial window at the end is left out
+        assert_eq!(frames.len(), audio.len() / 1024);
+        // the same windows, at the same times, as the default contour
+        let contour =
+            analyze_pitch_contour(&audio, 44100, 50.0, 1000.0, &AnalyzeOptions::default());
+        assert!(contour.len() >= 40, "{}", contour.len());
+        for point in &contour {
+            let frame = frames
+                .iter()
+                .find(|frame| frame.time_secs == point.time_secs)
+                .unwrap();
+            assert_eq!(frame.frequency, Some(point.frequency));
+        }
+
+        for frame in &frames[..40] {
+            assert!(
+                (frame.frequency.unwrap() - 330.0).abs() < 2.0,
+                "{:?}",
+                frame
+            );
+            // a half scale sine is 3dB under its peak of -6dBFS
+            assert!((frame.rms_dbfs + 9.03).abs() < 0.1, "{:?}", frame);
+        }
+        for frame in &frames[44..] {
+            assert_eq!((frame.frequency, frame.rms_dbfs), (None, -120.0));
+        }
+    }
+
+    #[test]
+    fn a_low_latency_analyzer_reads_a_buffer_too_short_for_a_default_window() {
+        // 15ms of A4, not even two thirds of a 1024 sample window
+        let tone: Vec<f32> = sine(440.0, 44100, 0.015).iter().map(|s| s * 0.5).collect();
+
+        let mut analyzer = PitchAnalyzer::new(44100, 50.0, 1000.0, AnalyzeOptions::default());
+        assert!(analyzer.push_samples(&tone).is_empty());
+
+        // 10ms is 441 samples, rounded down to a 256 sample window every 128 samples
+        let mut live = PitchAnalyzer::low_latency(44100, 50.0, 1000.0, 10.0);
+        let points = live.push_samples(&tone);
+        assert_eq!(points.len(), (tone.len() - 256) / 128 + 1);
+        let point = points.last().unwrap();
+        assert!((point.frequency - 440.0).abs() < 5.0, "{:?}", point);
+        // the latest reading is of the last full window
+        assert_eq!(point.time_secs, 384.0 / 44100.0);
+
+        // a tiny latency still gets a window YIN can run on
+        let mut tiny = PitchAnalyzer::low_latency(44100, 50.0, 1000.0, 0.01);
+        assert!(tiny.push_samples(&tone).is_empty());
+    }
+
+    #[test]
+    fn composing_from_samples_matches_composing_from_bytes() {
+        let stereo: Vec<f32> = sine(330.0, 44100, 2.0)
+            .iter()
+            .zip(sine(440.0, 44100, 2.0))
+            .flat_map(|(left, right)| [left * 0.5, right * 0.5])
+            .collect();
+        let wav = encode_wav(&stereo, 44100, 2, WavFormat::Pcm16).unwrap();
+
+        let from_bytes =
+            compose_to_ogg(std::io::Cursor::new(wav.clone()), "test", 7, 0.5, false).unwrap();
+        let (audio, sample_rate, channels) = decode_audio(std::io::Cursor::new(wav))
+            .unwrap()
+            .into_parts();
+        let from_samples =
+            compose_to_ogg_from_samples(audio, sample_rate, channels, "test", 7, 0.5, false)
+                .unwrap();
+        assert!(from_bytes == from_samples, "the two Ogg files differ");
+    }
+
+    #[test]
+    fn garbage_input_is_an_error_not_a_panic() {
+        let garbage: Vec<u8> = (0..4096).map(|i| (i * 37 % 251) as u8).collect();
+
+        let decoded = decode_audio(std::io::Cursor::new(garbage.clone()));
+        assert!(
+            matches!(decoded, Err(AvasaraError::Decode(_))),
+            "{:?}",
+            decoded
+        );
+        let composed = compose_to_ogg(std::io::Cursor::new(garbage), "", 0, 0.5, false);
+        assert!(
+            matches!(composed, Err(AvasaraError::Decode(_))),
+            "{:?}",
+            composed
+        );
+        assert!(matches!(
+            interleave_to_mono(vec![0.0; 30], 44100, 3),
+            Err(AvasaraError::UnsupportedChannels(3))
+        ));
+    }
+
+    #[test]
+    fn overlapping_windows_give_a_denser_contour() {
+        let tone = sine(220.0, 48000, 2.0);
+        let options = AnalyzeOptions {
+            window_size: 2048,
+            hop_size: 512,
+            ..AnalyzeOptions::default()
+        };
+
+        let dense = analyze_pitch_contour(&tone, 48000, 50.0, 1000.0, &options);
+        // every window that fits in full, a quarter window apart
+        assert_eq!(dense.len(), (tone.len() - 2048) / 512 + 1);
+        for pair in dense.windows(2) {
+            let step = pair[1].time_secs - pair[0].time_secs;
+            assert!((step - 512.0 / 48000.0).abs() < 1e-9, "{}", step);
+        }
+        let sparse = analyze_pitch_contour(&tone, 48000, 50.0, 1000.0, &AnalyzeOptions::default());
+        assert_eq!(sparse.len(), tone.len().div_ceil(1024));
+
+        let (report, _) = analyze_pitch_with(&tone, 48000, 50.0, 1000.0, &options).unwrap();
+        assert!((report.mean - 220.0).abs() < 1.0, "{}", report.mean);
+    }
+
+    #[test]
+    fn the_contour_follows_a_pitch_change_in_time_order() {
+        // half a second of 220Hz, then half a second of 330Hz
+        let mut audio = sine(220.0, 44100, 0.5);
+        audio.extend(sine(330.0, 44100, 0.5));
+
+        let contour =
+            analyze_pitch_contour(&audio, 44100, 50.0, 1000.0, &AnalyzeOptions::default());
+        assert!(contour.len() >= 40, "{}", contour.len());
+        assert!(contour
+            .windows(2)
+            .all(|pair| pair[0].time_secs < pair[1].time_secs));
+        for point in &contour {
+            // the window straddling the change can read either
+            if (point.time_secs - 0.5).abs() < 1024.0 / 44100.0 {
+                continue;
+            }
+            let expected = if point.time_secs < 0.5 { 220.0 } else { 330.0 };
+            assert!((point.frequency - expected).abs() < 2.0, "{:?}", point);
+            assert!(point.clarity > 0.9, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn every_algorithm_finds_a_clean_tone() {
+        let tone: Vec<f32> = sine(220.0, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+
+        for algorithm in [
+            PitchAlgorithm::Yin,
+            PitchAlgorithm::McLeod,
+            PitchAlgorithm::Autocorrelation,
+        ] {
+            let options = AnalyzeOptions {
+                algorithm,
+                ..AnalyzeOptions::default()
+            };
+            let (report, _) = analyze_pitch_with(&tone, 44100, 50.0, 1000.0, &options).unwrap();
+            // autocorrelation only has whole lags, 200 samples is 220.5Hz
+            assert!(
+                (report.median - 220.0).abs() < 3.0,
+                "{:?}: {}",
+                algorithm,
+                report.median
+            );
+
+            let contour = analyze_pitch_contour(&tone, 44100, 50.0, 1000.0, &options);
+            assert!(
+                contour
+                    .iter()
+                    .all(|point| (0.0..=1.0).contains(&point.clarity)),
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn decoding_stops_once_cancelled() {
+        let wav = encode_wav(&sine(440.0, 44100, 1.0), 44100, 1, WavFormat::Pcm16).unwrap();
+
+        let untouched = AtomicBool::new(false);
+        let (audio, sample_rate, channels) =
+            decode_cancellable(std::io::Cursor::new(wav.clone()), &untouched).unwrap();
+        assert_eq!((audio.len(), sample_rate, channels), (44100, 44100, 1));
+
+        let cancelled = AtomicBool::new(true);
+        let result = decode_cancellable(std::io::Cursor::new(wav), &cancelled);
+        assert!(
+            matches!(result, Err(AvasaraError::Cancelled)),
+            "{:?}",
+            result.map(|(audio, _, _)| audio.len())
+        );
+    }
+
+    #[test]
+    fn a_range_starts_and_ends_on_the_exact_frame() {
+        // every frame holds its own index (scaled), so where the range lands can be read back
+        let frames = 44100 * 3;
+        let ramp: Vec<f32> = (0..frames)
+            .flat_map(|i| {
+                let s = i as f32 / frames as f32;
+                [s, -s]
+            })
+            .collect();
+        let wav = encode_wav(&ramp, 44100, 2, WavFormat::Float32).unwrap();
+
+        let (audio, sample_rate, channels) = decode_range(
+            std::io::Cursor::new(wav.clone()),
+            Duration::from_secs(1),
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        assert_eq!((sample_rate, channels), (44100, 2));
+        assert_eq!(audio.len(), 22050 * 2);
+        assert_eq!(audio[..2], ramp[44100 * 2..44100 * 2 + 2]);
+        assert_eq!(audio[audio.len() - 2..], ramp[66150 * 2 - 2..66150 * 2]);
+
+        // an end past the end of the track gives the rest of it
+        let (tail, _, _) = decode_range(
+            std::io::Cursor::new(wav),
+            Duration::from_millis(2500),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_eq!(tail[..], ramp[110250 * 2..]);
+    }
+
+    #[test]
+    fn the_contour_matches_a_window_by_window_run() {
+        // long enough for the `parallel` feature to split the windows across threads
+        let audio: Vec<f32> = sine(330.0, 44100, 6.0)
+            .iter()
+            .zip(noise(44100 * 6, 5))
+            .map(|(s, n)| s * 0.5 + n * 0.05)
+            .collect();
+        let options = AnalyzeOptions {
+            hop_size: 256,
+            ..AnalyzeOptions::default()
+        };
+
+        let contour = analyze_pitch_contour(&audio, 44100, 50.0, 1000.0, &options);
+        let expected: Vec<PitchPoint> = analysis_windows(&audio, 1024, 256)
+            .filter_map(|(start, chunk)| {
+                let (frequency, clarity) = detect_pitch(PitchAlgorithm::Yin, chunk, 44100)?;
+                Some(PitchPoint {
+                    time_secs: start as f64 / 44100.0,
+                    frequency,
+                    clarity,
+                })
+            })
+            .filter(|p| (p.frequency > 50.0) && (p.frequency < 1000.0))
+            .collect();
+        assert!(expected.len() > 1000, "{}", expected.len());
+        assert_eq!(contour, expected);
+        assert!(contour
+            .windows(2)
+            .all(|pair| pair[0].time_secs < pair[1].time_secs));
+    }
+
+    #[test]
+    fn decoded_audio_keeps_rate_and_channels_apart() {
+        // 22.05kHz stereo, left at half scale and right silent, a rate and channel count that
+        // can't be mixed up
+        let stereo: Vec<f32> = (0..11025).flat_map(|_| [0.5, 0.0]).collect();
+        let wav = encode_wav(&stereo, 22050, 2, WavFormat::Float32).unwrap();
+
+        let audio = decode_audio(std::io::Cursor::new(wav.clone())).unwrap();
+        assert_eq!((audio.sample_rate(), audio.channels()), (22050, 2));
+        assert_eq!(audio.frames(), 11025);
+        assert_eq!(audio.duration(), Duration::from_millis(500));
+        assert_eq!(audio.samples(), &stereo[..]);
+        assert!(audio.tags().is_empty(), "{:?}", audio.tags());
+        assert_eq!(audio.to_mono(), vec![0.25; 11025]);
+
+        #[allow(deprecated)]
+        let tuple = decode(std::io::Cursor::new(wav)).unwrap();
+        assert_eq!(tuple, audio.into_parts());
+        assert_eq!((tuple.1, tuple.2), (22050, 2));
+    }
+
+    #[test]
+    fn files_decode_and_encode_by_path() {
+        let dir = std::env::temp_dir();
+        let wav_path = dir.join(format!("avasara-decode-file-{}.wav", std::process::id()));
+        let ogg_path = dir.join(format!("avasara-encode-file-{}.ogg", std::process::id()));
+        let wav = encode_wav(&sine(440.0, 44100, 0.5), 44100, 1, WavFormat::Pcm16).unwrap();
+        std::fs::write(&wav_path, &wav).unwrap();
+
+        let audio = decode_file(&wav_path).unwrap();
+        assert_eq!(audio, decode_audio(std::io::Cursor::new(wav)).unwrap());
+        let streamed: usize = Decoder::open(&wav_path)
+            .unwrap()
+            .map(|block| block.unwrap().samples.len())
+            .sum();
+        assert_eq!(streamed, 22050);
+
+        let options = ComposeBuilder::new().stream_serial(7);
+        let expected = options
+            .run_from_samples(audio.samples.clone(), 44100, 1)
             .unwrap();
-        println!("[{}] Encoded and remuxed!", prefix);
+        let rate = encode_to_file(&ogg_path, audio, &options).unwrap();
+        assert_eq!(rate, 44100);
+        assert_eq!(std::fs::read(&ogg_path).unwrap(), expected);
 
-        out
-    } else {
-        println!("[{}] Encoded!", prefix);
-        ogg_out
+        std::fs::remove_file(&wav_path).unwrap();
+        std::fs::remove_file(&ogg_path).unwrap();
+        assert!(matches!(decode_file(&wav_path), Err(AvasaraError::Io(_))));
+    }
+
+    #[test]
+    fn report_statistics_of_an_even_spread() {
+        // 101Hz to 200Hz in 1Hz steps, the clarity alternating between 0.6 and 1.0
+        let points: Vec<(f32, f32)> = (101..=200)
+            .map(|f| (f as f32, if f % 2 == 0 { 1.0 } else { 0.6 }))
+            .collect();
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        let (report, kept) =
+            pitch_report(&points, 50.0, 1000.0, ClarityFloor::None, 200.0, 0.0).unwrap();
+        assert_eq!(kept.len(), 100);
+        assert!(close(report.chunks_used as f32, 50.0));
+        assert!(close(report.mean, 150.5) && close(report.median, 150.5));
+        assert_eq!((report.lowest, report.highest), (101.0, 200.0));
+        // interpolated between the two nearest points
+        assert!(close(report.p5, 105.95), "{}", report.p5);
+        assert!(close(report.p25, 125.75), "{}", report.p25);
+        assert!(close(report.p75, 175.25), "{}", report.p75);
+        assert!(close(report.p95, 195.05), "{}", report.p95);
+        // (n² - 1) / 12 for n evenly spaced steps
+        assert!(
+            close(report.std_dev, (9999.0f32 / 12.0).sqrt()),
+            "{}",
+            report.std_dev
+        );
+        assert!(close(report.mean_clarity, 0.8));
+
+        // the default trim drops ten points off either end
+        let (trimmed, kept) =
+            pitch_report(&points, 50.0, 1000.0, ClarityFloor::None, 200.0, 10.0).unwrap();
+        assert_eq!(kept.len(), 80);
+        assert!(close(trimmed.chunks_used as f32, 40.0));
+        assert_eq!((trimmed.lowest, trimmed.highest), (111.0, 190.0));
+        assert!(close(trimmed.mean, 150.5));
+        assert!(trimmed.std_dev < report.std_dev);
+        // and the clarity floor goes before anything else
+        let (confident, _) =
+            pitch_report(&points, 50.0, 1000.0, ClarityFloor::Fixed(0.9), 200.0, 0.0).unwrap();
+        assert_eq!((confident.lowest, confident.mean_clarity), (102.0, 1.0));
     }
 }
@@ -0,0 +1,457 @@
+use std::f64::consts::PI;
+
+use crate::filters::{filter_interleaved, Biquad};
+use crate::resample::{resample, ResampleQuality};
+
+/// The two K-weighting stages from ITU-R BS.1770 (a high shelf modelling the head followed by
+/// the "RLB" high-pass), designed for any sample rate using the same derivation as libebur128.
+fn k_weighting(sample_rate: usize) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        vh + vb * k / q + k * k,
+        2.0 * (k * k - vh),
+        vh - vb * k / q + k * k,
+        1.0 + k / q + k * k,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let highpass = Biquad::new(
+        1.0,
+        -2.0,
+        1.0,
+        1.0 + k / q + k * k,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    );
+
+    [shelf, highpass]
+}
+
+/// Channel weights from BS.1770, assuming the usual wav/symphonia channel order (L, R, C, LFE,
+/// Ls, Rs, ...), the LFE channel is ignored and the surrounds get a +1.5dB boost.
+fn channel_weight(index: usize, channels: usize) -> f64 {
+    if channels < 6 {
+        return 1.0;
+    }
+    match index {
+        3 => 0.0,
+        4 | 5 => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn to_lufs(power: f64) -> f64 {
+    if power <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        -0.691 + 10.0 * power.log10()
+    }
+}
+
+/// Channel-weighted mean square of each 400ms gating block (75% overlap) of an interleaved,
+/// already K-weighted buffer, audio shorter than a single block is measured as one block.
+fn block_powers(weighted: &[f32], sample_rate: usize, channels: usize) -> Vec<f64> {
+    window_powers(weighted, sample_rate, channels, 0.4)
+}
+
+/// Same as `block_powers` but with windows of `window_secs` instead of 400ms (still every
+/// 100ms), 3 seconds gives the short-term loudness of EBU R128.
+fn window_powers(
+    weighted: &[f32],
+    sample_rate: usize,
+    channels: usize,
+    window_secs: f64,
+) -> Vec<f64> {
+    let frames = weighted.len() / channels;
+    let block = (sample_rate as f64 * window_secs).round() as usize;
+    let step = (sample_rate as f64 * 0.1).round() as usize;
+
+    let mut starts = vec![];
+    if frames < block || block == 0 {
+        if frames > 0 {
+            starts.push(0);
+        }
+    } else {
+        let mut start = 0;
+        while start + block <= frames {
+            starts.push(start);
+            start += step;
+        }
+    }
+
+    starts
+        .into_iter()
+        .map(|start| {
+            let end = (start + block).min(frames);
+            let mut power = 0.0;
+            for ch in 0..channels {
+                let weight = channel_weight(ch, channels);
+                if weight == 0.0 {
+                    continue;
+                }
+                let mut sum = 0.0;
+                for frame in start..end {
+                    let s = weighted[frame * channels + ch] as f64;
+                    sum += s * s;
+                }
+                power += weight * sum / (end - start) as f64;
+            }
+            power
+        })
+        .collect()
+}
+
+/// Applies the absolute (-70 LUFS) and relative (-10 LU) gates of BS.1770 and returns which
+/// blocks survived them.
+fn gate(powers: &[f64]) -> Vec<bool> {
+    let above_absolute: Vec<bool> = powers.iter().map(|p| to_lufs(*p) > -70.0).collect();
+
+    let (sum, count) = powers
+        .iter()
+        .zip(&above_absolute)
+        .filter(|(_, keep)| **keep)
+        .fold((0.0, 0), |(sum, count), (p, _)| (sum + p, count + 1));
+    if count == 0 {
+        return vec![false; powers.len()];
+    }
+    let relative = to_lufs(sum / count as f64) - 10.0;
+
+    powers
+        .iter()
+        .zip(above_absolute)
+        .map(|(p, keep)| keep && to_lufs(*p) > relative)
+        .collect()
+}
+
+fn gated_loudness(powers: &[f64], keep: &[bool]) -> f64 {
+    let (sum, count) = powers
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| **keep)
+        .fold((0.0, 0), |(sum, count), (p, _)| (sum + p, count + 1));
+    if count == 0 {
+        f64::NEG_INFINITY
+    } else {
+        to_lufs(sum / count as f64)
+    }
+}
+
+/// Loudness of each frequency band relative to the loudness of the whole signal, returned by
+/// `tonal_balance`, all band values are in LU (so 0.0 means "as loud as everything together" and
+/// -12.0 means the band sits 12 LU below the full mix), `overall` is in LUFS.
+#[derive(Debug, Clone, Copy)]
+pub struct TonalBalance {
+    pub overall: f64,
+    /// Everything below 250Hz.
+    pub low: f64,
+    /// 250Hz to 4kHz.
+    pub mid: f64,
+    /// Everything above 4kHz.
+    pub high: f64,
+}
+
+/// Measures the tonal balance of some interleaved audio data in loudness terms rather than raw
+/// energy, the signal gets K-weighted (same as a regular LUFS measurement) and split into low
+/// (< 250Hz), mid (250Hz - 4kHz) and high (> 4kHz) bands with 4th order crossovers, then every
+/// band is measured over the same gating blocks that survive the BS.1770 gates on the full signal,
+/// so a band that only plays during silence-gated parts doesn't get to skew the result. Bands
+/// with no energy at all (and completely silent input) report negative infinity.
+///
+/// # Panics
+/// Panics if `channels` is zero.
+pub fn tonal_balance(audio: &[f32], sample_rate: usize, channels: usize) -> TonalBalance {
+    assert!(channels > 0, "can't measure audio with no channels");
+    let weighted = filter_interleaved(audio, channels, &k_weighting(sample_rate));
+
+    let low_crossover = 250.0;
+    let high_crossover = 4000.0;
+    let butterworth = std::f64::consts::FRAC_1_SQRT_2;
+
+    let low = filter_interleaved(
+        &weighted,
+        channels,
+        &[
+            Biquad::lowpass(sample_rate, low_crossover, butterworth),
+            Biquad::lowpass(sample_rate, low_crossover, butterworth),
+        ],
+    );
+    let mid = filter_interleaved(
+        &weighted,
+        channels,
+        &[
+            Biquad::highpass(sample_rate, low_crossover, butterworth),
+            Biquad::highpass(sample_rate, low_crossover, butterworth),
+            Biquad::lowpass(sample_rate, high_crossover, butterworth),
+            Biquad::lowpass(sample_rate, high_crossover, butterworth),
+        ],
+    );
+    let high = filter_interleaved(
+        &weighted,
+        channels,
+        &[
+            Biquad::highpass(sample_rate, high_crossover, butterworth),
+            Biquad::highpass(sample_rate, high_crossover, butterworth),
+        ],
+    );
+
+    let powers = block_powers(&weighted, sample_rate, channels);
+    let keep = gate(&powers);
+    let overall = gated_loudness(&powers, &keep);
+
+    let relative = |band: &[f32]| {
+        if overall.is_finite() {
+            gated_loudness(&block_powers(band, sample_rate, channels), &keep) - overall
+        } else {
+            f64::NEG_INFINITY
+        }
+    };
+
+    TonalBalance {
+        overall,
+        low: relative(&low),
+        mid: relative(&mid),
+        high: relative(&high),
+    }
+}
+
+/// Integrated (gated) loudness of some interleaved audio data in LUFS, negative infinity if
+/// nothing survives the gates.
+fn integrated_loudness(audio: &[f32], sample_rate: usize, channels: usize) -> f64 {
+    let weighted = filter_interleaved(audio, channels, &k_weighting(sample_rate));
+    let powers = block_powers(&weighted, sample_rate, channels);
+    gated_loudness(&powers, &gate(&powers))
+}
+
+/// How much louder `b` is than `a` in LU (integrated loudness of `b` minus that of `a`, both
+/// interleaved with the same layout), so a positive value means `b` needs to come down by that
+/// many dB to match. Meant for fair A/B comparisons of two masters or two encodes, louder almost
+/// always *sounds* better, so match them before judging anything else. If either input is silent
+/// (nothing survives the BS.1770 gates) the result is infinite or NaN.
+pub fn loudness_match_report(a: &[f32], b: &[f32], sample_rate: usize, channels: usize) -> f64 {
+    integrated_loudness(b, sample_rate, channels) - integrated_loudness(a, sample_rate, channels)
+}
+
+/// Applies makeup gain to `audio` so its integrated loudness matches that of `reference` (same
+/// interleaved layout, usually the same audio before some processing), and returns the gain that
+/// was applied in dB. Any EQ or filtering changes how loud things are, which makes "is this
+/// better?" comparisons and long processing chains a guessing game, run this after each step to
+/// keep the level where it was. Nothing is changed (and 0.0 is returned) if either side is silent,
+/// the gain is plain linear scaling, so boosting can push peaks past full scale.
+pub fn match_loudness(
+    audio: &mut [f32],
+    reference: &[f32],
+    sample_rate: usize,
+    channels: usize,
+) -> f64 {
+    let gain_db = loudness_match_report(audio, reference, sample_rate, channels);
+    if !gain_db.is_finite() {
+        return 0.0;
+    }
+
+    let gain = 10f64.powf(gain_db / 20.0) as f32;
+    for sample in audio.iter_mut() {
+        *sample *= gain;
+    }
+    gain_db
+}
+
+/// Everything EBU R128 asks for of a piece of audio, returned by `measure_lufs`. Loudness values
+/// are in LUFS and negative infinity when there's nothing to measure (silence, or nothing
+/// surviving the gates), `lra` is in LU and `true_peak` in dBTP.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReport {
+    /// Gated loudness of the whole programme, the number broadcast specs put a target on (-23
+    /// LUFS for EBU R128, -24 for ATSC A/85, around -14 to -16 for streaming platforms).
+    pub integrated: f64,
+    /// Loudest 3 second window (measured every 100ms).
+    pub short_term_max: f64,
+    /// Loudest 400ms window (measured every 100ms).
+    pub momentary_max: f64,
+    /// Loudness range (EBU Tech 3342), the spread between the 10th and 95th percentile of the
+    /// gated short-term loudness, so how much the level moves around over the programme, 0.0 if
+    /// nothing survives the gates.
+    pub lra: f64,
+    /// Highest sample peak of the signal oversampled 4x (2x from 96kHz, as is from 192kHz), which
+    /// catches the peaks between samples that a DAC or a lossy encoder will produce.
+    pub true_peak: f64,
+}
+
+/// Measures some interleaved audio data the way EBU R128 (and ITU-R BS.1770 underneath it)
+/// prescribes, for checking broadcast or platform compliance. The signal is K-weighted, the
+/// integrated loudness is gated at -70 LUFS absolute and -10 LU relative, the loudness range uses
+/// 3 second windows gated at -70 LUFS and -20 LU, and the true peak comes from 4x oversampling.
+/// Channels are weighted as in `tonal_balance` (LFE ignored, surrounds boosted in 5.1 and up).
+/// Audio shorter than a window is measured as a single shorter window.
+///
+/// # Panics
+/// Panics if `sample_rate` or `channels` is zero.
+pub fn measure_lufs(audio: &[f32], sample_rate: usize, channels: usize) -> LoudnessReport {
+    assert!(
+        sample_rate > 0 && channels > 0,
+        "sample rate and channel count must be non-zero"
+    );
+    let weighted = filter_interleaved(audio, channels, &k_weighting(sample_rate));
+
+    let momentary = block_powers(&weighted, sample_rate, channels);
+    let integrated = gated_loudness(&momentary, &gate(&momentary));
+
+    let short_term = window_powers(&weighted, sample_rate, channels, 3.0);
+    let loudest = |powers: &[f64]| {
+        powers
+            .iter()
+            .map(|p| to_lufs(*p))
+            .fold(f64::NEG_INFINITY, f64::max)
+    };
+
+    LoudnessReport {
+        integrated,
+        short_term_max: loudest(&short_term),
+        momentary_max: loudest(&momentary),
+        lra: loudness_range(&short_term),
+        true_peak: true_peak(audio, sample_rate, channels),
+    }
+}
+
+/// Loudness range per EBU Tech 3342 from the short-term (3 second) window powers.
+fn loudness_range(short_term: &[f64]) -> f64 {
+    let above_absolute: Vec<f64> = short_term
+        .iter()
+        .copied()
+        .filter(|p| to_lufs(*p) > -70.0)
+        .collect();
+    if above_absolute.is_empty() {
+        return 0.0;
+    }
+    let relative = to_lufs(above_absolute.iter().sum::<f64>() / above_absolute.len() as f64) - 20.0;
+
+    let mut gated: Vec<f64> = above_absolute
+        .iter()
+        .map(|p| to_lufs(*p))
+        .filter(|lufs| *lufs > relative)
+        .collect();
+    if gated.is_empty() {
+        return 0.0;
+    }
+    gated.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let percentile = |p: f64| gated[((gated.len() - 1) as f64 * p).round() as usize];
+    percentile(0.95) - percentile(0.10)
+}
+
+/// True peak in dBTP of some interleaved audio data, every channel is oversampled with
+/// `resample` and the largest absolute sample of any of them is taken, negative infinity for
+/// silence.
+pub(crate) fn true_peak(audio: &[f32], sample_rate: usize, channels: usize) -> f64 {
+    let factor = match sample_rate {
+        0..=95_999 => 4,
+        96_000..=191_999 => 2,
+        _ => 1,
+    };
+
+    let mut peak = 0.0f32;
+    for ch in 0..channels {
+        let channel: Vec<f32> = audio.iter().skip(ch).step_by(channels).copied().collect();
+        let oversampled = resample(
+            &channel,
+            sample_rate,
+            sample_rate * factor,
+            ResampleQuality::Balanced,
+        );
+        peak = oversampled
+            .iter()
+            .chain(&channel)
+            .fold(peak, |peak, s| peak.max(s.abs()));
+    }
+
+    if peak > 0.0 {
+        20.0 * (peak as f64).log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bass_heavy_signal_reads_louder_in_the_low_band() {
+        let bass = sine(80.0, 0.5, 48000, 3.0);
+        let treble = sine(8000.0, 0.05, 48000, 3.0);
+        let mix: Vec<f32> = bass.iter().zip(&treble).map(|(b, t)| b + t).collect();
+
+        let balance = tonal_balance(&mix, 48000, 1);
+        assert!(balance.low > balance.high + 6.0, "{:?}", balance);
+    }
+
+    #[test]
+    fn a_3db_louder_copy_reads_3_lu_louder() {
+        let a: Vec<f32> = sine(1000.0, 0.2, 48000, 3.0)
+            .iter()
+            .zip(sine(300.0, 0.1, 48000, 3.0))
+            .flat_map(|(x, y)| [x + y, x - y])
+            .collect();
+        let gain = 10f32.powf(3.0 / 20.0);
+        let b: Vec<f32> = a.iter().map(|s| s * gain).collect();
+
+        let difference = loudness_match_report(&a, &b, 48000, 2);
+        assert!((difference - 3.0).abs() < 0.01, "{}", difference);
+        assert!((loudness_match_report(&b, &a, 48000, 2) + 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn the_tech_3341_reference_tone_reads_minus_23_lufs() {
+        // EBU Tech 3341 case 1, a 1kHz sine at -23dBFS on both channels of a stereo file
+        let tone = sine(1000.0, 10f32.powf(-23.0 / 20.0), 48000, 10.0);
+        let stereo: Vec<f32> = tone.iter().flat_map(|s| [*s, *s]).collect();
+
+        let report = measure_lufs(&stereo, 48000, 2);
+        assert!((report.integrated + 23.0).abs() < 0.1, "{:?}", report);
+        assert!((report.short_term_max + 23.0).abs() < 0.1, "{:?}", report);
+        assert!((report.momentary_max + 23.0).abs() < 0.1, "{:?}", report);
+        assert!((report.true_peak + 23.0).abs() < 0.1, "{:?}", report);
+        // a steady level doesn't range at all
+        assert!(report.lra < 0.1, "{:?}", report);
+
+        let silence = measure_lufs(&vec![0.0; 48000 * 2], 48000, 2);
+        assert_eq!(silence.integrated, f64::NEG_INFINITY);
+        assert_eq!(silence.lra, 0.0);
+    }
+
+    #[test]
+    fn a_10_db_step_reads_as_10_lu_of_range() {
+        // EBU Tech 3342 case 1, -20dBFS then -30dBFS, a little shorter
+        let mut audio = sine(1000.0, 10f32.powf(-20.0 / 20.0), 48000, 10.0);
+        audio.extend(sine(1000.0, 10f32.powf(-30.0 / 20.0), 48000, 10.0));
+        let stereo: Vec<f32> = audio.iter().flat_map(|s| [*s, *s]).collect();
+
+        let report = measure_lufs(&stereo, 48000, 2);
+        assert!((report.lra - 10.0).abs() < 1.0, "{:?}", report);
+        // -30 is within 10 LU of the ungated average, so both halves count
+        assert!(
+            report.integrated < -20.0 && report.integrated > -30.0,
+            "{:?}",
+            report
+        );
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(feature = "midi")]
+use crate::NoteEvent;
+
+/// A single note to be written out by `notes_to_midi_file`, times are in seconds from the start
+/// and `note` is a MIDI note number (60 is middle C, 69 is A4).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiNote {
+    pub start_secs: f64,
+    pub duration_secs: f64,
+    pub note: u8,
+    /// How hard the note is hit, 1 to 127 (0 would be read as a note off by most software).
+    pub velocity: u8,
+}
+
+/// Ticks per quarter note used in the files `notes_to_midi_file` writes, fine enough that a 16th
+/// note triplet at 300 BPM still lands on a whole tick.
+const TICKS_PER_QUARTER: u16 = 480;
+
+/// Appends a MIDI variable-length quantity (7 bits per byte, most significant first, every byte
+/// but the last has its top bit set).
+fn push_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Writes notes out as a Standard MIDI File (format 0, a single track, every note on channel 1)
+/// that any DAW or notation program can import, for getting a transcribed melody out of Avasara
+/// and into something you can edit and play. `tempo_bpm` only decides where the bar lines fall
+/// once it's imported, the notes are converted from seconds to ticks at that tempo so they sound
+/// at exactly the times given either way, pass the song's real tempo (`estimate_bpm` can help) if
+/// you want them to line up with the grid. Notes don't need to be sorted, overlapping notes of the
+/// same pitch are written as they are, and a non-positive `tempo_bpm` falls back to 120.
+pub fn notes_to_midi_file(notes: &[MidiNote], tempo_bpm: f32) -> Vec<u8> {
+    let tempo_bpm = if tempo_bpm > 0.0 { tempo_bpm } else { 120.0 };
+    let micros_per_quarter = (60_000_000.0 / tempo_bpm as f64).round() as u32;
+    let ticks_per_sec = TICKS_PER_QUARTER as f64 * tempo_bpm as f64 / 60.0;
+    let to_ticks = |secs: f64| (secs.max(0.0) * ticks_per_sec).round() as u32;
+
+    // (tick, is_note_on, note, velocity), note offs sort before note ons at the same tick so a
+    // repeated note gets released before it's struck again
+    let mut events: Vec<(u32, bool, u8, u8)> = notes
+        .iter()
+        .flat_map(|n| {
+            let note = n.note.min(127);
+            let start = to_ticks(n.start_secs);
+            let end = to_ticks(n.start_secs + n.duration_secs.max(0.0)).max(start + 1);
+            [
+                (start, true, note, n.velocity.clamp(1, 127)),
+                (end, false, note, 0),
+            ]
+        })
+        .collect();
+    events.sort_by_key(|(tick, is_on, _, _)| (*tick, *is_on));
+
+    let mut track = vec![];
+    // tempo meta event at tick 0
+    push_vlq(&mut track, 0);
+    track.extend([0xff, 0x51, 0x03]);
+    track.extend(&micros_per_quarter.to_be_bytes()[1..]);
+
+    let mut last_tick = 0;
+    for (tick, is_on, note, velocity) in events {
+        push_vlq(&mut track, tick - last_tick);
+        last_tick = tick;
+        if is_on {
+            track.extend([0x90, note, velocity]);
+        } else {
+            track.extend([0x80, note, 0x40]);
+        }
+    }
+    // end of track
+    push_vlq(&mut track, 0);
+    track.extend([0xff, 0x2f, 0x00]);
+
+    let mut file = Vec::with_capacity(22 + track.len());
+    file.extend(b"MThd");
+    file.extend(6u32.to_be_bytes());
+    file.extend(0u16.to_be_bytes()); // format 0
+    file.extend(1u16.to_be_bytes()); // one track
+    file.extend(TICKS_PER_QUARTER.to_be_bytes());
+    file.extend(b"MTrk");
+    file.extend((track.len() as u32).to_be_bytes());
+    file.extend(track);
+    file
+}
+
+/// Velocity every note gets in `export_midi`, a firm mezzo forte, there's no loudness in a
+/// `NoteEvent` to go by.
+#[cfg(feature = "midi")]
+const EXPORT_VELOCITY: u8 = 96;
+
+/// Writes notes found by `segment_notes` out as a Standard MIDI File, so a hummed or sung melody
+/// goes straight into a DAW, the last step of `analyze_pitch_contour` → `smooth_contour` →
+/// `segment_notes`. Every note gets the same velocity, and `tempo_bpm` works the same way it does
+/// in `notes_to_midi_file` (only the grid changes, not when the notes sound).
+#[cfg(feature = "midi")]
+pub fn export_midi(notes: &[NoteEvent], tempo_bpm: f32) -> Vec<u8> {
+    let notes: Vec<MidiNote> = notes
+        .iter()
+        .map(|note| note.to_midi_note(EXPORT_VELOCITY))
+        .collect();
+    notes_to_midi_file(&notes, tempo_bpm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a MIDI variable-length quantity at `at`, moving `at` past it.
+    fn read_vlq(bytes: &[u8], at: &mut usize) -> u32 {
+        let mut value = 0;
+        loop {
+            let byte = bytes[*at];
+            *at += 1;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    /// Parses a format 0 file back into its notes (start and end in seconds, note, velocity),
+    /// in the order they start, only knows the events `notes_to_midi_file` writes.
+    fn parse_notes(file: &[u8]) -> Vec<(f64, f64, u8, u8)> {
+        assert_eq!(&file[..4], b"MThd");
+        assert_eq!(&file[8..10], &[0, 0], "not format 0");
+        assert_eq!(&file[10..12], &[0, 1], "not a single track");
+        let ticks_per_quarter = u16::from_be_bytes([file[12], file[13]]) as f64;
+        assert_eq!(&file[14..18], b"MTrk");
+        let track_len = u32::from_be_bytes(file[18..22].try_into().unwrap()) as usize;
+        let track = &file[22..];
+        assert_eq!(track.len(), track_len);
+
+        let mut secs_per_tick = 0.5 / ticks_per_quarter;
+        let (mut at, mut tick) = (0, 0);
+        let mut sounding: Vec<(u32, u8, u8)> = vec![];
+        let mut notes = vec![];
+        loop {
+            tick += read_vlq(track, &mut at);
+            match track[at] {
+                0xff => {
+                    let (kind, len) = (track[at + 1], track[at + 2] as usize);
+                    let data = &track[at + 3..at + 3 + len];
+                    at += 3 + len;
+                    match kind {
+                        0x51 => {
+                            let micros = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                            secs_per_tick = micros as f64 / 1e6 / ticks_per_quarter;
+                        }
+                        0x2f => break,
+                        other => panic!("unexpected meta event {:x}", other),
+                    }
+                }
+                0x90 => {
+                    sounding.push((tick, track[at + 1], track[at + 2]));
+                    at += 3;
+                }
+                0x80 => {
+                    let note = track[at + 1];
+                    let on = sounding.iter().position(|(_, n, _)| *n == note).unwrap();
+                    let (start, _, velocity) = sounding.remove(on);
+                    notes.push((
+                        start as f64 * secs_per_tick,
+                        tick as f64 * secs_per_tick,
+                        note,
+                        velocity,
+                    ));
+                    at += 3;
+                }
+                other => panic!("unexpected status byte {:x}", other),
+            }
+        }
+        assert_eq!(at, track.len(), "bytes after the end of track");
+        assert!(sounding.is_empty(), "notes left hanging");
+
+        notes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        notes
+    }
+
+    #[test]
+    fn the_written_notes_parse_back() {
+        // out of order, with A4 struck again the moment it's released, and one long enough to
+        // need a multi-byte delta
+        let notes = [
+            MidiNote {
+                start_secs: 0.5,
+                duration_secs: 0.5,
+                note: 69,
+                velocity: 80,
+            },
+            MidiNote {
+                start_secs: 0.0,
+                duration_secs: 0.25,
+                note: 60,
+                velocity: 100,
+            },
+            MidiNote {
+                start_secs: 1.0,
+                duration_secs: 4.0,
+                note: 69,
+                velocity: 127,
+            },
+        ];
+
+        let parsed = parse_notes(&notes_to_midi_file(&notes, 93.0));
+        let tick = 60.0 / 93.0 / TICKS_PER_QUARTER as f64;
+        assert_eq!(parsed.len(), 3);
+        for ((start, end, note, velocity), expected) in
+            parsed.iter().zip([notes[1], notes[0], notes[2]])
+        {
+            assert!((start - expected.start_secs).abs() <= tick, "{:?}", parsed);
+            assert!(
+                (end - expected.start_secs - expected.duration_secs).abs() <= tick,
+                "{:?}",
+                parsed
+            );
+            assert_eq!((*note, *velocity), (expected.note, expected.velocity));
+        }
+    }
+
+    #[cfg(feature = "midi")]
+    #[test]
+    fn segmented_notes_export_at_one_velocity() {
+        let notes = [
+            NoteEvent {
+                start_secs: 0.1,
+                duration_secs: 0.4,
+                midi_note: 62,
+                frequency: 293.0,
+            },
+            NoteEvent {
+                start_secs: 0.6,
+                duration_secs: 0.3,
+                midi_note: 64,
+                frequency: 330.5,
+            },
+        ];
+
+        let parsed = parse_notes(&export_midi(&notes, 120.0));
+        let tick = 60.0 / 120.0 / TICKS_PER_QUARTER as f64;
+        assert_eq!(parsed.len(), 2);
+        for ((start, end, note, velocity), expected) in parsed.iter().zip(&notes) {
+            assert!((start - expected.start_secs).abs() <= tick, "{:?}", parsed);
+            assert!(
+                (end - expected.start_secs - expected.duration_secs).abs() <= tick,
+                "{:?}",
+                parsed
+            );
+            assert_eq!((*note, *velocity), (expected.midi_note, EXPORT_VELOCITY));
+        }
+    }
+}
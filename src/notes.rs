@@ -0,0 +1,659 @@
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(feature = "midi")]
+use crate::MidiNote;
+use crate::{aggregate_pitch, PitchPoint, PitchStatistic};
+
+/// The twelve pitch classes of western equal temperament, sharps only.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteName {
+    C,
+    CSharp,
+    D,
+    DSharp,
+    E,
+    F,
+    FSharp,
+    G,
+    GSharp,
+    A,
+    ASharp,
+    B,
+}
+
+impl NoteName {
+    pub(crate) const ALL: [NoteName; 12] = [
+        NoteName::C,
+        NoteName::CSharp,
+        NoteName::D,
+        NoteName::DSharp,
+        NoteName::E,
+        NoteName::F,
+        NoteName::FSharp,
+        NoteName::G,
+        NoteName::GSharp,
+        NoteName::A,
+        NoteName::ASharp,
+        NoteName::B,
+    ];
+
+    /// The pitch class of a MIDI note number (60 is middle C).
+    pub fn from_midi(midi: i32) -> NoteName {
+        NoteName::ALL[midi.rem_euclid(12) as usize]
+    }
+}
+
+impl fmt::Display for NoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NoteName::C => "C",
+            NoteName::CSharp => "C#",
+            NoteName::D => "D",
+            NoteName::DSharp => "D#",
+            NoteName::E => "E",
+            NoteName::F => "F",
+            NoteName::FSharp => "F#",
+            NoteName::G => "G",
+            NoteName::GSharp => "G#",
+            NoteName::A => "A",
+            NoteName::ASharp => "A#",
+            NoteName::B => "B",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Distance of `frequency` from `reference_hz` in cents (hundredths of an equal tempered
+/// semitone, so an octave up is +1200.0 and an octave down is -1200.0).
+pub fn hz_to_cents(frequency: f32, reference_hz: f32) -> f32 {
+    1200.0 * (frequency / reference_hz).log2()
+}
+
+/// The (fractional) MIDI note number of a frequency, tuned so that `a4_hz` (440.0 unless you know
+/// better) is MIDI note 69, so 261.63Hz is about 60.0 (middle C) and a quarter tone above it
+/// 60.5. Round it for the nearest note.
+pub fn hz_to_midi(frequency: f32, a4_hz: f32) -> f32 {
+    69.0 + hz_to_cents(frequency, a4_hz) / 100.0
+}
+
+/// The opposite of `hz_to_midi`, the frequency of a (possibly fractional) MIDI note number.
+pub fn midi_to_hz(midi: f32, a4_hz: f32) -> f32 {
+    a4_hz * 2f32.powf((midi - 69.0) / 12.0)
+}
+
+/// Nearest MIDI note number of a frequency, tuned so that `a4_hz` is MIDI note 69.
+fn nearest_midi(frequency: f32, a4_hz: f32) -> i32 {
+    hz_to_midi(frequency, a4_hz).round() as i32
+}
+
+/// How far `frequency` is from the nearest equal tempered note in cents, -50.0 to 50.0, positive
+/// when it's sharp and negative when it's flat, the number a tuner's needle shows.
+pub fn cents_offset(frequency: f32, a4_hz: f32) -> f32 {
+    let midi = hz_to_midi(frequency, a4_hz);
+    (midi - midi.round()) * 100.0
+}
+
+/// The nearest equal tempered note to a frequency and how far off it the frequency is, see
+/// `nearest_note`. Displays like a tuner would, `A4 +12 cents`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearestNote {
+    pub name: NoteName,
+    /// Octave in scientific pitch notation, middle C is C4.
+    pub octave: i32,
+    /// Distance from the note in cents, see `cents_offset`.
+    pub cents: f32,
+}
+
+impl fmt::Display for NearestNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{} {:+.0} cents", self.name, self.octave, self.cents)
+    }
+}
+
+/// The equal tempered note closest to `frequency` (tuned so that `a4_hz` is A4), along with
+/// the cents it's off by.
+pub fn nearest_note(frequency: f32, a4_hz: f32) -> NearestNote {
+    let midi = nearest_midi(frequency, a4_hz);
+    NearestNote {
+        name: NoteName::from_midi(midi),
+        octave: midi.div_euclid(12) - 1,
+        cents: cents_offset(frequency, a4_hz),
+    }
+}
+
+/// Name of the equal tempered note closest to `frequency` in scientific pitch notation, like
+/// `"A4"` or `"C#3"` (sharps only, like `NoteName`), for labels, see `nearest_note` for how far
+/// off it is too.
+pub fn hz_to_note_name(frequency: f32, a4_hz: f32) -> String {
+    let note = nearest_note(frequency, a4_hz);
+    format!("{}{}", note.name, note.octave)
+}
+
+/// Converts a pitch contour to the log-frequency domain, returning `(time_secs, cents)` pairs
+/// where the cents are relative to `reference_hz` (use the passage's median, a tonic, or just
+/// 440.0), this makes every interval the same height no matter the register, so plotted melodies
+/// look the way they sound. Points with a non-positive frequency are dropped.
+pub fn contour_to_cents(points: &[PitchPoint], reference_hz: f32) -> Vec<(f64, f32)> {
+    points
+        .iter()
+        .filter(|p| p.frequency > 0.0)
+        .map(|p| (p.time_secs, hz_to_cents(p.frequency, reference_hz)))
+        .collect()
+}
+
+/// Finds the note that a monophonic passage spends most of its time on, every pitch point counts
+/// for as long as it lasts (until the next point, but never longer than the analysis hop, the
+/// smallest spacing between two points, so the note right before an unvoiced stretch isn't
+/// credited with the silence after it) multiplied by its clarity, so confident and sustained
+/// notes win over short or shaky ones. Returns the note name, its octave (scientific pitch
+/// notation, so A4 is `(A, 4)`) and the fraction of the passage's weighted duration it occupies
+/// (0.0 to 1.0), or `None` if there are no usable points or no single note holds more than half
+/// of the passage.
+///
+/// `a4_hz` is the tuning reference, 440.0 unless you know better.
+pub fn dominant_note(points: &[PitchPoint], a4_hz: f32) -> Option<(NoteName, i32, f32)> {
+    let mut weights: HashMap<i32, f64> = HashMap::new();
+    let mut total = 0.0;
+
+    // there's a point for every analysis window that had a pitch, so the closest two are a hop
+    // apart and any wider gap is windows that had none, a lone point gets a unit duration so it
+    // still counts
+    let hop = points
+        .windows(2)
+        .map(|pair| pair[1].time_secs - pair[0].time_secs)
+        .filter(|gap| *gap > 0.0)
+        .fold(f64::INFINITY, f64::min);
+    let hop = if hop.is_finite() { hop } else { 1.0 };
+
+    for (i, point) in points.iter().enumerate() {
+        if !(point.frequency.is_finite() && point.frequency > 0.0) {
+            continue;
+        }
+
+        let duration = points
+            .get(i + 1)
+            .map_or(hop, |next| next.time_secs - point.time_secs)
+            .clamp(0.0, hop);
+        let weight = duration * point.clarity.max(0.0) as f64;
+
+        *weights
+            .entry(nearest_midi(point.frequency, a4_hz))
+            .or_insert(0.0) += weight;
+        total += weight;
+    }
+
+    if total <= 0.0 {
+        return None;
+    }
+
+    let (midi, weight) = weights
+        .into_iter()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+    let fraction = (weight / total) as f32;
+
+    if fraction <= 0.5 {
+        None
+    } else {
+        Some((NoteName::from_midi(midi), midi.div_euclid(12) - 1, fraction))
+    }
+}
+
+/// A pitch point widened into a confidence band, returned by `confidence_band`, `low` and `high`
+/// are the edges of the band in Hz (`frequency` moved down/up by `half_width_cents`), ready to be
+/// shaded around the contour in a plot.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchBand {
+    pub time_secs: f64,
+    pub frequency: f32,
+    pub low: f32,
+    pub high: f32,
+    pub half_width_cents: f32,
+}
+
+/// Cents added to a band's half width per unit of missing clarity, a point YIN had no confidence
+/// in at all gets a full tone of uncertainty either side from this alone.
+const CLARITY_UNCERTAINTY_CENTS: f32 = 200.0;
+
+/// Turns a pitch contour into a band plot, every voiced point gets an uncertainty in cents made of
+/// two parts, how unsure YIN was about it (`(1 - clarity) * 200` cents) and how much it disagrees
+/// with its surroundings (the standard deviation, in cents, of the voiced points up to
+/// `neighbors` positions before and after it, itself included). A steady, confident note gets a
+/// hairline band, a shaky or noisy stretch a wide one, which is what a UI wants to shade so
+/// nobody reads too much into the uncertain parts. Points with a non-positive frequency are
+/// dropped, same as `contour_to_cents`.
+pub fn confidence_band(points: &[PitchPoint], neighbors: usize) -> Vec<PitchBand> {
+    let voiced: Vec<&PitchPoint> = points
+        .iter()
+        .filter(|p| p.frequency.is_finite() && p.frequency > 0.0)
+        .collect();
+
+    (0..voiced.len())
+        .map(|i| {
+            let point = voiced[i];
+            let window =
+                &voiced[i.saturating_sub(neighbors)..(i + neighbors + 1).min(voiced.len())];
+
+            // spread around the window's own mean, in cents so it doesn't depend on the register
+            let cents: Vec<f32> = window
+                .iter()
+                .map(|p| hz_to_cents(p.frequency, point.frequency))
+                .collect();
+            let mean = cents.iter().sum::<f32>() / cents.len() as f32;
+            let spread =
+                (cents.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / cents.len() as f32).sqrt();
+
+            let half_width_cents =
+                (1.0 - point.clarity).clamp(0.0, 1.0) * CLARITY_UNCERTAINTY_CENTS + spread;
+            let ratio = 2f32.powf(half_width_cents / 1200.0);
+
+            PitchBand {
+                time_secs: point.time_secs,
+                frequency: point.frequency,
+                low: point.frequency / ratio,
+                high: point.frequency * ratio,
+                half_width_cents,
+            }
+        })
+        .collect()
+}
+
+/// A single note found in a pitch contour by `segment_notes`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteEvent {
+    pub start_secs: f64,
+    pub duration_secs: f64,
+    /// Nearest MIDI note number of the note's median pitch (60 is middle C, 69 is A4).
+    pub midi_note: u8,
+    /// Frequency of the note's pitch points boiled down to one value in Hz with
+    /// `NoteSegmentOptions::statistic` (their mean by default), for how in tune it was sung (see
+    /// `cents_offset`).
+    pub frequency: f32,
+}
+
+#[cfg(feature = "midi")]
+impl NoteEvent {
+    /// The note as a `MidiNote` for `notes_to_midi_file`, struck with `velocity` (1 to 127).
+    pub fn to_midi_note(&self, velocity: u8) -> MidiNote {
+        MidiNote {
+            start_secs: self.start_secs,
+            duration_secs: self.duration_secs,
+            note: self.midi_note,
+            velocity,
+        }
+    }
+}
+
+/// How `segment_notes` splits a contour into notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteSegmentOptions {
+    /// Tuning reference, 440.0 unless you know better.
+    pub a4_hz: f32,
+    /// Points the detector was less sure about than this (0.0 to 1.0) count as unvoiced, breaths
+    /// and consonants tend to come out as low clarity noise.
+    pub min_clarity: f32,
+    /// How far (in semitones) the pitch can stray from the median of the note so far before a
+    /// new note starts, wide enough for vibrato and sloppy intonation, narrow enough to catch a
+    /// step to the next semitone.
+    pub pitch_tolerance_semitones: f32,
+    /// Notes shorter than this (in seconds) are folded into the note they're glued to (a slide
+    /// into a note, a wobble mid-note) or dropped if they stand alone (a blip of noise).
+    pub min_note_secs: f64,
+    /// A gap between consecutive points longer than this (in seconds) ends the note, this is
+    /// what separates notes with a breath or a consonant between them.
+    pub max_gap_secs: f64,
+    /// Onset times in seconds (what `detect_onsets` gives for the same audio), a note is split at
+    /// every one of them that lands inside it, which is the only way to tell two sung notes of
+    /// the same pitch apart when there's no gap between them. Empty to go by pitch alone.
+    pub onsets: Vec<f32>,
+    /// How the pitch points of a note are boiled down to its `NoteEvent::frequency`, see
+    /// `PitchStatistic`.
+    pub statistic: PitchStatistic,
+}
+
+impl Default for NoteSegmentOptions {
+    /// A4 = 440Hz, clarity of at least 0.5, notes stay within 0.7 semitones, last at least 80ms
+    /// and end at gaps of over 100ms, no onsets, and the mean as the note's frequency.
+    fn default() -> Self {
+        NoteSegmentOptions {
+            a4_hz: 440.0,
+            min_clarity: 0.5,
+            pitch_tolerance_semitones: 0.7,
+            min_note_secs: 0.08,
+            max_gap_secs: 0.1,
+            onsets: vec![],
+            statistic: PitchStatistic::Mean,
+        }
+    }
+}
+
+/// A note in the making, its points and whether it's glued to the note before it (it started
+/// because the pitch moved, not after a gap or at an onset).
+struct PendingNote<'a> {
+    points: Vec<&'a PitchPoint>,
+    glued: bool,
+}
+
+/// Median of some fractional MIDI note numbers.
+fn median_midi(values: &[f32]) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    sorted[sorted.len() / 2]
+}
+
+/// Splits a monophonic pitch contour (chronological `PitchPoint`s like `analyze_pitch_contour`
+/// gives, smoothed with `smooth_contour` for best results) into discrete notes, for turning
+/// singing or humming into MIDI. A note ends at a gap in the contour (unvoiced or low clarity
+/// points), when the pitch moves more than `pitch_tolerance_semitones` away from the note's
+/// median so far, or at an onset from `options.onsets`. Glides and wobbles shorter than
+/// `min_note_secs` are merged into the note they're attached to, and isolated blips are dropped.
+/// Every note lasts from its first point to one contour hop past its last one, and is returned
+/// in order.
+pub fn segment_notes(contour: &[PitchPoint], options: &NoteSegmentOptions) -> Vec<NoteEvent> {
+    let voiced: Vec<&PitchPoint> = contour
+        .iter()
+        .filter(|p| {
+            p.frequency.is_finite() && p.frequency > 0.0 && p.clarity >= options.min_clarity
+        })
+        .collect();
+    if voiced.is_empty() {
+        return vec![];
+    }
+
+    // the contour's hop, how long every point lasts
+    let mut spacings: Vec<f64> = contour
+        .windows(2)
+        .map(|pair| pair[1].time_secs - pair[0].time_secs)
+        .filter(|spacing| *spacing > 0.0)
+        .collect();
+    spacings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let hop = spacings.get(spacings.len() / 2).copied().unwrap_or(0.0);
+
+    let midi = |p: &PitchPoint| hz_to_midi(p.frequency, options.a4_hz);
+    let mut pending: Vec<PendingNote> = vec![];
+    for (i, point) in voiced.iter().enumerate() {
+        let split = match (i.checked_sub(1).map(|prev| voiced[prev]), pending.last()) {
+            (Some(prev), Some(note)) => {
+                let gap = point.time_secs - prev.time_secs > options.max_gap_secs.max(hop * 1.5);
+                let onset = options.onsets.iter().any(|onset| {
+                    (*onset as f64 > prev.time_secs) && (*onset as f64 <= point.time_secs)
+                });
+                let center = median_midi(&note.points.iter().map(|p| midi(p)).collect::<Vec<_>>());
+                let moved = (midi(point) - center).abs() > options.pitch_tolerance_semitones;
+
+                if gap || onset {
+                    Some(false)
+                } else if moved {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            _ => Some(false),
+        };
+
+        match split {
+            Some(glued) => pending.push(PendingNote {
+                points: vec![point],
+                glued,
+            }),
+            None => pending.last_mut().unwrap().points.push(point),
+        }
+    }
+
+    let length =
+        |note: &PendingNote| note.points.last().unwrap().time_secs - note.points[0].time_secs + hop;
+
+    // fold the short notes into a neighbor they're glued to, drop the ones that stand alone
+    let mut merged: Vec<PendingNote> = vec![];
+    let mut carried: Option<PendingNote> = None;
+    let mut pending = pending.into_iter().peekable();
+    while let Some(mut note) = pending.next() {
+        if let Some(mut before) = carried.take() {
+            // glued to the short note(s) before it, which had no long note to join
+            before.points.append(&mut note.points);
+            note = before;
+        }
+        if length(&note) >= options.min_note_secs {
+            merged.push(note);
+        } else if note.glued && !merged.is_empty() {
+            merged.last_mut().unwrap().points.append(&mut note.points);
+        } else if pending.peek().is_some_and(|next| next.glued) {
+            carried = Some(note);
+        }
+    }
+
+    merged
+        .iter()
+        .map(|note| {
+            let values: Vec<f32> = note.points.iter().map(|p| midi(p)).collect();
+            let points: Vec<PitchPoint> = note.points.iter().map(|p| **p).collect();
+            let start = note.points[0].time_secs;
+            NoteEvent {
+                start_secs: start,
+                duration_secs: length(note),
+                midi_note: median_midi(&values).round().clamp(0.0, 127.0) as u8,
+                // every voiced point has a positive frequency, so there's always something to
+                // aggregate, unless the clarities of a clarity weighted note are all zero
+                frequency: aggregate_pitch(&points, options.statistic)
+                    .unwrap_or(points[0].frequency),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points every 10ms at the given frequencies, all with the same clarity.
+    fn contour(frequencies: &[f32], clarity: f32) -> Vec<PitchPoint> {
+        frequencies
+            .iter()
+            .enumerate()
+            .map(|(i, frequency)| PitchPoint {
+                time_secs: i as f64 * 0.01,
+                frequency: *frequency,
+                clarity,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn midi_numbers_names_and_cents() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        assert!(close(hz_to_midi(440.0, 440.0), 69.0));
+        assert!(close(hz_to_midi(261.6256, 440.0), 60.0));
+        assert!(close(hz_to_midi(27.5, 440.0), 21.0));
+        // a different tuning moves everything with it
+        assert!(close(hz_to_midi(432.0, 432.0), 69.0));
+        for midi in [0.0, 21.0, 60.0, 60.5, 69.0, 108.0, 127.0] {
+            assert!(close(hz_to_midi(midi_to_hz(midi, 440.0), 440.0), midi));
+        }
+
+        assert_eq!(hz_to_note_name(440.0, 440.0), "A4");
+        assert_eq!(hz_to_note_name(261.63, 440.0), "C4");
+        assert_eq!(hz_to_note_name(277.18, 440.0), "C#4");
+        assert_eq!(hz_to_note_name(246.94, 440.0), "B3");
+        assert_eq!(hz_to_note_name(16.35, 440.0), "C0");
+
+        // 10 cents sharp of A4, and 30 flat of E5
+        let sharp = nearest_note(440.0 * 2f32.powf(10.0 / 1200.0), 440.0);
+        assert_eq!((sharp.name, sharp.octave), (NoteName::A, 4));
+        assert!(close(sharp.cents, 10.0), "{}", sharp.cents);
+        assert_eq!(sharp.to_string(), "A4 +10 cents");
+        let flat = midi_to_hz(76.0 - 0.3, 440.0);
+        assert!(close(cents_offset(flat, 440.0), -30.0));
+        assert_eq!(nearest_note(flat, 440.0).to_string(), "E5 -30 cents");
+
+        // and a pitch report names the notes it measured
+        let tone: Vec<f32> = (0..44100)
+            .map(|i| (2.0 * std::f32::consts::PI * 196.0 * i as f32 / 44100.0).sin() * 0.5)
+            .collect();
+        let (report, _) = crate::analyze_pitch(&tone, 44100, 50.0, 1000.0).unwrap();
+        for note in [report.mean_note, report.median_note] {
+            assert_eq!((note.name, note.octave), (NoteName::G, 3));
+            assert!(note.cents.abs() < 5.0, "{}", note.cents);
+        }
+    }
+
+    #[test]
+    fn an_octave_jump_is_1200_cents() {
+        let cents = contour_to_cents(&contour(&[220.0, 440.0, 0.0, 880.0], 0.9), 440.0);
+        // the unvoiced point is dropped
+        assert_eq!(cents.len(), 3);
+        assert_eq!(cents[0].0, 0.0);
+        assert!((cents[0].1 + 1200.0).abs() < 1e-3, "{:?}", cents);
+        assert!(cents[1].1.abs() < 1e-3, "{:?}", cents);
+        assert!(
+            (cents[2].1 - cents[1].1 - 1200.0).abs() < 1e-3,
+            "{:?}",
+            cents
+        );
+    }
+
+    #[test]
+    fn dominant_note_survives_brief_excursions() {
+        // mostly A4, with a short dip to G4 and a blip up to C5
+        let mut frequencies = vec![440.0; 80];
+        frequencies[20..28].fill(392.0);
+        frequencies[50..53].fill(523.25);
+
+        let (name, octave, fraction) = dominant_note(&contour(&frequencies, 0.9), 440.0).unwrap();
+        assert_eq!((name, octave), (NoteName::A, 4));
+        assert!(fraction > 0.8 && fraction < 0.9, "{}", fraction);
+    }
+
+    #[test]
+    fn silence_after_a_note_is_not_credited_to_it() {
+        // a tenth of a second of A4, two seconds of nothing, then 0.3 seconds of E4
+        let mut points = contour(&[440.0; 10], 0.9);
+        points.extend(
+            contour(&[329.63; 30], 0.9)
+                .into_iter()
+                .map(|point| PitchPoint {
+                    time_secs: point.time_secs + 2.0,
+                    ..point
+                }),
+        );
+
+        let (name, octave, fraction) = dominant_note(&points, 440.0).unwrap();
+        assert_eq!((name, octave), (NoteName::E, 4));
+        assert!((fraction - 0.75).abs() < 0.01, "{}", fraction);
+    }
+
+    #[test]
+    fn note_frequency_follows_the_chosen_statistic() {
+        // a note that's mostly A4 but drifts sharp at the end, still within the tolerance
+        let mut frequencies = vec![440.0; 14];
+        frequencies.extend([452.0; 6]);
+        let points = contour(&frequencies, 0.9);
+
+        let frequency = |statistic| {
+            let options = NoteSegmentOptions {
+                statistic,
+                ..Default::default()
+            };
+            let notes = segment_notes(&points, &options);
+            assert_eq!(notes.len(), 1, "{:?}", notes);
+            notes[0].frequency
+        };
+        assert_eq!(frequency(PitchStatistic::Median), 440.0);
+        let mean = frequency(PitchStatistic::Mean);
+        assert!((mean - 443.6).abs() < 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn no_note_dominates_an_even_split() {
+        let mut frequencies = vec![440.0; 40];
+        frequencies.extend([329.63; 40]);
+        assert_eq!(dominant_note(&contour(&frequencies, 0.9), 440.0), None);
+    }
+
+    #[test]
+    fn unsure_stretches_get_wider_bands() {
+        // a steady, confident A4, then a wobbly one YIN was only half sure about
+        let steady = contour(&[440.0; 20], 0.98);
+        let wobbly: Vec<f32> = (0..20)
+            .map(|i| if i % 2 == 0 { 430.0 } else { 450.0 })
+            .collect();
+        let mut points = steady;
+        points.extend(contour(&wobbly, 0.5).into_iter().map(|point| PitchPoint {
+            time_secs: point.time_secs + 0.2,
+            ..point
+        }));
+
+        let bands = confidence_band(&points, 3);
+        assert_eq!(bands.len(), 40);
+        // away from where the two meet, the neighbors would mix them up
+        let widest_steady = bands[..16]
+            .iter()
+            .fold(0.0f32, |widest, band| widest.max(band.half_width_cents));
+        let narrowest_wobbly = bands[24..].iter().fold(f32::MAX, |narrowest, band| {
+            narrowest.min(band.half_width_cents)
+        });
+        assert!(widest_steady < 5.0, "{}", widest_steady);
+        assert!(narrowest_wobbly > 100.0, "{}", narrowest_wobbly);
+        for band in &bands {
+            assert!(band.low < band.frequency && band.frequency < band.high);
+        }
+    }
+
+    #[test]
+    fn segmenting_a_sung_phrase() {
+        // every 10ms: A4, a breath, a slide up into C5, straight on to D5, a breath with a blip of
+        // noise in it, then D5 again, sung twice with no gap between
+        let mut points = vec![];
+        let mut sing = |frequency: f32, clarity: f32, count: usize| {
+            for _ in 0..count {
+                points.push(PitchPoint {
+                    time_secs: points.len() as f64 * 0.01,
+                    frequency,
+                    clarity,
+                });
+            }
+        };
+        sing(440.0, 0.9, 30);
+        sing(150.0, 0.1, 20);
+        sing(500.0, 0.9, 3);
+        sing(523.25, 0.9, 27);
+        sing(587.33, 0.9, 30);
+        sing(150.0, 0.1, 20);
+        sing(300.0, 0.9, 2);
+        sing(150.0, 0.1, 18);
+        sing(587.33, 0.9, 50);
+        let options = NoteSegmentOptions {
+            onsets: vec![1.75],
+            ..NoteSegmentOptions::default()
+        };
+
+        let notes = segment_notes(&points, &options);
+        let summary: Vec<(u8, i64, i64)> = notes
+            .iter()
+            .map(|note| {
+                (
+                    note.midi_note,
+                    (note.start_secs * 100.0).round() as i64,
+                    (note.duration_secs * 100.0).round() as i64,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (69, 0, 30),
+                (72, 50, 30),
+                (74, 80, 30),
+                (74, 150, 25),
+                (74, 175, 25)
+            ]
+        );
+        assert!((notes[0].frequency - 440.0).abs() < 1e-3);
+        // the slide drags the mean of its note down, not the note itself
+        assert!(notes[1].frequency < 523.25 && notes[1].frequency > 515.0);
+    }
+}
@@ -0,0 +1,249 @@
+use ::ogg::{PacketWriteEndInfo, PacketWriter};
+use ::opus::{Application, Bitrate, Channels, Encoder};
+
+use crate::{
+    decode_audio, interleave_to_mono, resample, AvasaraError, ComposeStage, MediaSource, Progress,
+    ResampleQuality,
+};
+
+/// What libopus tunes its encoding for, see `OpusSettings`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpusApplication {
+    /// Speech, favors intelligibility, for voice chat, podcasts and TTS datasets.
+    Voip,
+    /// Music and anything else where fidelity to the input matters most.
+    #[default]
+    Audio,
+    /// Lowest possible delay at the cost of quality, only worth it for live monitoring.
+    LowDelay,
+}
+
+/// How `encode_opus` and `compose_to_opus` configure the encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusSettings {
+    /// Target bitrate in bits per second (6000 to 510000 make sense), `None` lets libopus pick
+    /// one based on the sample rate, which for mono is generous.
+    pub bitrate: Option<u32>,
+    /// 0 (fastest) to 10 (best quality for the bitrate), values above 10 are clamped.
+    pub complexity: u8,
+    pub application: OpusApplication,
+}
+
+impl Default for OpusSettings {
+    fn default() -> Self {
+        OpusSettings {
+            bitrate: None,
+            complexity: 10,
+            application: OpusApplication::Audio,
+        }
+    }
+}
+
+/// Sample rates libopus can take directly, anything else gets resampled to 48kHz first.
+const OPUS_RATES: [usize; 5] = [8000, 12000, 16000, 24000, 48000];
+
+/// Encodes some mono audio data into an Ogg Opus file (RFC 7845), 20ms per packet. Opus only
+/// takes 8, 12, 16, 24 or 48kHz, audio at any other rate is resampled to 48kHz with `resample`
+/// first, the original rate is still recorded in the header so players that care can resample
+/// back. Granule positions are set so a player trims the encoder's startup delay and the padding
+/// at the end, giving back exactly as many samples as went in. Returns `AvasaraError::Opus` if
+/// libopus refuses the settings.
+///
+/// # Panics
+/// Panics if `sample_rate` is zero.
+pub fn encode_opus(
+    audio: &[f32],
+    sample_rate: usize,
+    stream_serial: i32,
+    settings: &OpusSettings,
+) -> Result<Vec<u8>, AvasaraError> {
+    assert!(sample_rate > 0, "sample rate must be non-zero");
+
+    let resampled;
+    let (audio, encoder_rate) = if OPUS_RATES.contains(&sample_rate) {
+        (audio, sample_rate)
+    } else {
+        resampled = resample(audio, sample_rate, 48000, ResampleQuality::Balanced);
+        (&resampled[..], 48000)
+    };
+
+    let application = match settings.application {
+        OpusApplication::Voip => Application::Voip,
+        OpusApplication::Audio => Application::Audio,
+        OpusApplication::LowDelay => Application::LowDelay,
+    };
+    let mut encoder = Encoder::new(encoder_rate as u32, Channels::Mono, application)?;
+    encoder.set_complexity(settings.complexity.min(10) as i32)?;
+    encoder.set_bitrate(match settings.bitrate {
+        Some(bits) => Bitrate::Bits(bits as i32),
+        None => Bitrate::Auto,
+    })?;
+
+    // granule positions are always counted at 48kHz, whatever rate the encoder runs at
+    let to_48k = |samples: usize| (samples * 48000 / encoder_rate) as u64;
+    let lookahead = encoder.get_lookahead()? as usize;
+    let pre_skip = to_48k(lookahead);
+
+    let serial = stream_serial as u32;
+    let mut writer = PacketWriter::new(vec![]);
+
+    // identification header, version 1, mono, no output gain, channel mapping family 0
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(1);
+    head.extend((pre_skip as u16).to_le_bytes());
+    head.extend((sample_rate as u32).to_le_bytes());
+    head.extend(0i16.to_le_bytes());
+    head.push(0);
+    writer.write_packet(head, serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    // comment header, just the vendor string and no comments
+    let vendor = ::opus::version();
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor.as_bytes());
+    tags.extend(0u32.to_le_bytes());
+    writer.write_packet(tags, serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    // the encoder's output lags its input by `lookahead` samples, so feed that much silence past
+    // the end to get the last real samples out, then pad the final frame to a full 20ms
+    let frame_len = encoder_rate / 50;
+    let mut padded = audio.to_vec();
+    padded.resize(
+        (audio.len() + lookahead).div_ceil(frame_len) * frame_len,
+        0.0,
+    );
+    let last_granule = pre_skip + to_48k(audio.len());
+
+    let frames = padded.len() / frame_len;
+    let mut packet = vec![0; 4000];
+    for (i, frame) in padded.chunks(frame_len).enumerate() {
+        let len = encoder.encode_float(frame, &mut packet)?;
+        let (end_info, granule) = if i + 1 == frames {
+            (PacketWriteEndInfo::EndStream, last_granule)
+        } else {
+            (
+                PacketWriteEndInfo::NormalPacket,
+                to_48k((i + 1) * frame_len).min(last_granule),
+            )
+        };
+        writer.write_packet(packet[..len].to_vec(), serial, end_info, granule)?;
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// The Opus counterpart to `compose_to_ogg`, decodes `src`, interleaves it to mono and encodes it
+/// into Ogg Opus with `encode_opus`, `stream_serial` works the same way it does there.
+/// `on_progress` gets every stage as it starts, the same way `ComposeBuilder::on_progress` does
+/// (without percentages, and `Interleaving` only for stereo sources), pass `|_| {}` to ignore
+/// them. Fails if decoding or encoding does, or if the source has more than 2 channels.
+pub fn compose_to_opus<S: MediaSource + 'static>(
+    src: S,
+    stream_serial: i32,
+    settings: &OpusSettings,
+    on_progress: impl Fn(Progress),
+) -> Result<Vec<u8>, AvasaraError> {
+    let report = |stage| {
+        on_progress(Progress {
+            stage,
+            percent: None,
+        })
+    };
+
+    report(ComposeStage::Decoding);
+    let (audio, sample_rate, channels) = decode_audio(src)?.into_parts();
+    if (sample_rate == 0) || (channels == 0) {
+        return Err(AvasaraError::DecodeFailed);
+    }
+
+    if channels > 1 {
+        report(ComposeStage::Interleaving);
+    }
+    let mut audio = interleave_to_mono(audio, sample_rate as u32, channels)?;
+
+    report(ComposeStage::Encoding);
+    let out = encode_opus(audio.as_f32_slice(), sample_rate, stream_serial, settings)?;
+    report(ComposeStage::Done);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits an Ogg stream into its pages' granule positions and bodies.
+    fn pages(data: &[u8]) -> Vec<(u64, Vec<u8>)> {
+        let mut pages = vec![];
+        let mut at = 0;
+        while at < data.len() {
+            assert_eq!(&data[at..at + 4], b"OggS", "no page at byte {}", at);
+            let granule = u64::from_le_bytes(data[at + 6..at + 14].try_into().unwrap());
+            let segments = data[at + 26] as usize;
+            let body_len: usize = data[at + 27..at + 27 + segments]
+                .iter()
+                .map(|&len| len as usize)
+                .sum();
+            let body_start = at + 27 + segments;
+            pages.push((granule, data[body_start..body_start + body_len].to_vec()));
+            at = body_start + body_len;
+        }
+        pages
+    }
+
+    #[test]
+    fn the_headers_and_granules_describe_the_input() {
+        let tone: Vec<f32> = (0..44100)
+            .map(|i| (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin() * 0.5)
+            .collect();
+        let settings = OpusSettings {
+            bitrate: Some(64000),
+            ..OpusSettings::default()
+        };
+        let pages = pages(&encode_opus(&tone, 44100, 7, &settings).unwrap());
+
+        // OpusHead first on a page of its own, mono, with the original rate kept
+        let head = &pages[0].1;
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!((head[8], head[9]), (1, 1));
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]) as u64;
+        assert_eq!(u32::from_le_bytes(head[12..16].try_into().unwrap()), 44100);
+        assert_eq!(&pages[1].1[..8], b"OpusTags");
+
+        // 44.1kHz goes through 48kHz, the last granule trims back to exactly the resampled length
+        let (last_granule, _) = pages.last().unwrap();
+        let resampled = resample(&tone, 44100, 48000, ResampleQuality::Balanced).len() as u64;
+        assert_eq!(*last_granule, pre_skip + resampled);
+    }
+
+    #[test]
+    fn composing_reports_through_the_callback() {
+        let stereo: Vec<f32> = (0..48000)
+            .flat_map(|i| {
+                let s = (std::f32::consts::TAU * 440.0 * i as f32 / 48000.0).sin() * 0.5;
+                [s, s]
+            })
+            .collect();
+        let wav = crate::encode_wav(&stereo, 48000, 2, crate::WavFormat::Pcm16).unwrap();
+        let stages = std::cell::RefCell::new(vec![]);
+
+        let opus = compose_to_opus(
+            std::io::Cursor::new(wav),
+            0,
+            &OpusSettings::default(),
+            |progress| stages.borrow_mut().push(progress.stage),
+        )
+        .unwrap();
+        assert!(opus.starts_with(b"OggS"));
+        assert_eq!(
+            stages.into_inner(),
+            [
+                ComposeStage::Decoding,
+                ComposeStage::Interleaving,
+                ComposeStage::Encoding,
+                ComposeStage::Done
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::spectrum;
+
+/// Computes the onset strength envelope (log-compressed spectral flux) of some mono audio data,
+/// this is the curve that onset detection and tempo estimation look for peaks and periodicity in,
+/// exposed so you can do your own beat tracking on top of it. Every value says how much new
+/// energy appeared across all frequencies compared to the previous frame, so sudden attacks
+/// (drums, plucks, consonants) show up as sharp peaks and sustained or fading sounds stay near
+/// zero, the values are unitless and only meaningful relative to each other.
+///
+/// The analysis frames are centered every `hop` samples, so value `i` belongs to the time
+/// `i * hop / sample_rate` seconds (see `onset_envelope_times`) and there are `audio.len() / hop +
+/// 1` of them, the first value is always zero since there's nothing before it to compare to. A
+/// hop of 512 at 44.1kHz (about 11.6ms) is a good starting point.
+///
+/// # Panics
+/// Panics if `hop` is zero.
+pub fn onset_envelope(audio: &[f32], sample_rate: usize, hop: usize) -> Vec<f32> {
+    assert!(hop > 0, "hop size can't be zero");
+
+    // about twice the hop so each attack doesn't smear over more than a couple of frames, but at
+    // least ~20ms so there's still some usable frequency resolution
+    let frame_len = (2 * hop).max(sample_rate / 50).next_power_of_two();
+    let frames: Vec<Vec<f32>> = spectrum::stft_magnitudes(audio, frame_len, hop)
+        .into_iter()
+        .map(|frame| frame.into_iter().map(|m| m.ln_1p()).collect())
+        .collect();
+
+    let mut envelope = Vec::with_capacity(frames.len());
+    envelope.push(0.0);
+    for pair in frames.windows(2) {
+        let flux: f32 = pair[1]
+            .iter()
+            .zip(&pair[0])
+            .map(|(now, before)| (now - before).max(0.0))
+            .sum();
+        envelope.push(flux);
+    }
+
+    envelope
+}
+
+/// The time axis (in seconds) of an onset envelope of `len` values computed with the given
+/// `sample_rate` and `hop`, so `onset_envelope_times(env.len(), sr, hop)[i]` is when `env[i]`
+/// happened.
+pub fn onset_envelope_times(len: usize, sample_rate: usize, hop: usize) -> Vec<f64> {
+    (0..len)
+        .map(|i| (i * hop) as f64 / sample_rate as f64)
+        .collect()
+}
+
+/// How far either side of a frame (in seconds) `detect_onsets` looks when deciding whether it's
+/// a peak, and the shortest gap it allows between two onsets, closer attacks than ~30ms blur into
+/// one for the ear anyway.
+const ONSET_PEAK_RADIUS_SECS: f64 = 0.03;
+
+/// How far either side of a frame (in seconds) `detect_onsets` averages the envelope over for the
+/// local baseline a peak has to rise above, long enough to span a few notes so a dense passage
+/// raises its own bar.
+const ONSET_BASELINE_RADIUS_SECS: f64 = 0.1;
+
+/// Finds note onsets (the start of every note, drum hit, pluck or syllable) in some mono audio
+/// data and returns their times in seconds, in order. It picks peaks out of `onset_envelope`
+/// (spectral flux), scaled so its loudest frame is 1.0: a frame is an onset if it's the highest
+/// within ~30ms either side of it and rises more than `threshold` above the envelope's average
+/// over the surrounding ~100ms, and if the previous onset was at least ~30ms before it.
+///
+/// `hop` is the envelope's hop in samples and so the resolution of the returned times, 512 at
+/// 44.1kHz (about 11.6ms) is plenty for charting, 256 is tighter. `threshold` is on the scaled
+/// envelope so it's 0.0 to 1.0, around 0.1 catches most notes of clean material, raise it if
+/// noise or reverb tails come up as onsets, lower it if soft notes go missing. Silent input has no
+/// onsets.
+///
+/// # Panics
+/// Panics if `hop` is zero.
+pub fn detect_onsets(audio: &[f32], sample_rate: usize, hop: usize, threshold: f32) -> Vec<f32> {
+    let envelope = onset_envelope(audio, sample_rate, hop);
+    let loudest = envelope.iter().fold(0.0f32, |max, v| max.max(*v));
+    if loudest <= 0.0 {
+        return vec![];
+    }
+    let envelope: Vec<f32> = envelope.iter().map(|v| v / loudest).collect();
+
+    let frames_for = |secs: f64| ((secs * sample_rate as f64 / hop as f64).round() as usize).max(1);
+    let peak_radius = frames_for(ONSET_PEAK_RADIUS_SECS);
+    let baseline_radius = frames_for(ONSET_BASELINE_RADIUS_SECS);
+    let around = |i: usize, radius: usize| {
+        &envelope[i.saturating_sub(radius)..(i + radius + 1).min(envelope.len())]
+    };
+
+    let mut onsets = vec![];
+    let mut last: Option<usize> = None;
+    for (i, value) in envelope.iter().enumerate() {
+        let neighborhood = around(i, peak_radius);
+        // the first of equal neighbours counts, a flat top would otherwise give several onsets
+        let is_peak = neighborhood.iter().all(|v| v <= value)
+            && envelope[i.saturating_sub(peak_radius)..i]
+                .iter()
+                .all(|v| v < value);
+        if !is_peak {
+            continue;
+        }
+
+        let baseline = around(i, baseline_radius);
+        let mean = baseline.iter().sum::<f32>() / baseline.len() as f32;
+        if *value - mean <= threshold {
+            continue;
+        }
+
+        if last.is_none_or(|last| i - last >= peak_radius) {
+            onsets.push((i * hop) as f32 / sample_rate as f32);
+            last = Some(i);
+        }
+    }
+
+    onsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_peaks_line_up_with_clicks() {
+        let sample_rate = 44100;
+        let hop = 512;
+        let clicks = [0.25, 0.75, 1.25, 1.75];
+        let mut audio = vec![0.0; 2 * sample_rate];
+        for click in clicks {
+            let start = (click * sample_rate as f64) as usize;
+            // a short decaying tick, 2ms long
+            for (i, sample) in audio[start..start + 88].iter_mut().enumerate() {
+                *sample = (1.0 - i as f32 / 88.0) * if i % 2 == 0 { 1.0 } else { -1.0 };
+            }
+        }
+
+        let envelope = onset_envelope(&audio, sample_rate, hop);
+        let times = onset_envelope_times(envelope.len(), sample_rate, hop);
+        assert_eq!(envelope.len(), audio.len() / hop + 1);
+        let loudest = envelope.iter().fold(0.0f32, |max, v| max.max(*v));
+
+        for click in clicks {
+            // the strongest frame within 100ms of the click is at the click, give or take a hop
+            // or two for the analysis frame's length
+            let (peak, value) = times
+                .iter()
+                .zip(&envelope)
+                .filter(|(time, _)| (**time - click).abs() < 0.1)
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .unwrap();
+            assert!(
+                (peak - click).abs() <= 2.0 * hop as f64 / sample_rate as f64,
+                "{} {}",
+                peak,
+                click
+            );
+            assert!(*value > 0.5 * loudest, "{} {}", value, loudest);
+        }
+
+        // and between the clicks there's nothing at all
+        for (time, value) in times.iter().zip(&envelope) {
+            if clicks.iter().all(|click| (time - click).abs() > 0.1) {
+                assert!(*value < 0.01 * loudest, "{} at {}", value, time);
+            }
+        }
+    }
+
+    #[test]
+    fn detect_onsets_finds_every_note() {
+        let sample_rate = 44100;
+        // plucked notes, quickly decaying, the last one much softer than the rest
+        let notes = [
+            (0.2, 220.0, 0.8),
+            (0.6, 330.0, 0.8),
+            (1.0, 262.0, 0.8),
+            (1.4, 392.0, 0.1),
+        ];
+        let mut audio = vec![0.0; 2 * sample_rate];
+        for (start, frequency, level) in notes {
+            let start = (start * sample_rate as f64) as usize;
+            for (i, sample) in audio[start..start + sample_rate * 2 / 5]
+                .iter_mut()
+                .enumerate()
+            {
+                let t = i as f32 / sample_rate as f32;
+                *sample +=
+                    level * (-t * 20.0).exp() * (std::f32::consts::TAU * frequency * t).sin();
+            }
+        }
+
+        let onsets = detect_onsets(&audio, sample_rate, 512, 0.1);
+        assert_eq!(onsets.len(), notes.len(), "{:?}", onsets);
+        for (onset, (start, _, _)) in onsets.iter().zip(notes) {
+            assert!((*onset as f64 - start).abs() < 0.03, "{} {}", onset, start);
+        }
+        assert!(onsets.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // a high enough threshold loses the soft note only
+        let loud = detect_onsets(&audio, sample_rate, 512, 0.5);
+        assert_eq!(loud.len(), 3, "{:?}", loud);
+        assert!(detect_onsets(&vec![0.0; sample_rate], sample_rate, 512, 0.1).is_empty());
+    }
+}
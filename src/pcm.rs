@@ -0,0 +1,82 @@
+/// Sample format (and byte order) of raw PCM data written by `to_raw_bytes`, the names follow
+/// ffmpeg's `-f` formats so `PcmFormat::S16Le` is what you'd pipe into `ffmpeg -f s16le`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Unsigned 8 bit, silence is 128.
+    U8,
+    S16Le,
+    S16Be,
+    /// Signed 24 bit packed into 3 bytes per sample.
+    S24Le,
+    S24Be,
+    S32Le,
+    S32Be,
+    F32Le,
+    F32Be,
+}
+
+impl PcmFormat {
+    /// How many bytes a single sample takes up in this format.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmFormat::U8 => 1,
+            PcmFormat::S16Le | PcmFormat::S16Be => 2,
+            PcmFormat::S24Le | PcmFormat::S24Be => 3,
+            PcmFormat::S32Le | PcmFormat::S32Be | PcmFormat::F32Le | PcmFormat::F32Be => 4,
+        }
+    }
+}
+
+/// Scales a sample in -1.0..=1.0 to a signed integer with `bits` bits, clipping anything outside
+/// that range instead of letting it wrap around.
+fn quantize(sample: f32, bits: u32) -> i32 {
+    let max = ((1i64 << (bits - 1)) - 1) as f64;
+    (sample.clamp(-1.0, 1.0) as f64 * max).round() as i32
+}
+
+/// Turns audio data (interleaved already if it has more than one channel, it's written out in the
+/// same order) into the raw bytes of the given `PcmFormat`, no header or container of any kind,
+/// ready to be piped into ffmpeg, sox, aplay or anything else that takes raw PCM. Integer formats
+/// clip samples outside -1.0..=1.0, the float formats pass them through untouched.
+pub fn to_raw_bytes(audio: &[f32], format: PcmFormat) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(audio.len() * format.bytes_per_sample());
+
+    for sample in audio {
+        match format {
+            PcmFormat::U8 => bytes.push((quantize(*sample, 8) + 128) as u8),
+            PcmFormat::S16Le => bytes.extend((quantize(*sample, 16) as i16).to_le_bytes()),
+            PcmFormat::S16Be => bytes.extend((quantize(*sample, 16) as i16).to_be_bytes()),
+            PcmFormat::S24Le => bytes.extend(&quantize(*sample, 24).to_le_bytes()[..3]),
+            PcmFormat::S24Be => bytes.extend(&quantize(*sample, 24).to_be_bytes()[1..]),
+            PcmFormat::S32Le => bytes.extend(quantize(*sample, 32).to_le_bytes()),
+            PcmFormat::S32Be => bytes.extend(quantize(*sample, 32).to_be_bytes()),
+            PcmFormat::F32Le => bytes.extend(sample.to_le_bytes()),
+            PcmFormat::F32Be => bytes.extend(sample.to_be_bytes()),
+        }
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_samples_give_the_expected_16_bit_bytes() {
+        let audio = [0.0, 1.0, -1.0, 0.5, 2.0];
+        assert_eq!(
+            to_raw_bytes(&audio, PcmFormat::S16Le),
+            // 0, 32767, -32767, 16384 (0.5 rounds up) and 2.0 clipped to 32767
+            vec![0x00, 0x00, 0xff, 0x7f, 0x01, 0x80, 0x00, 0x40, 0xff, 0x7f]
+        );
+    }
+
+    #[test]
+    fn big_endian_24_bit_keeps_the_low_three_bytes() {
+        assert_eq!(
+            to_raw_bytes(&[1.0, -1.0], PcmFormat::S24Be),
+            vec![0x7f, 0xff, 0xff, 0x80, 0x00, 0x01]
+        );
+    }
+}
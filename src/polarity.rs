@@ -0,0 +1,86 @@
+/// Best guess of the polarity of some audio data, returned by `detect_polarity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// The strongest excursions of the waveform point upwards (positive skew).
+    Normal,
+    /// The strongest excursions of the waveform point downwards (negative skew), which usually
+    /// means the signal got flipped somewhere along the chain (a mic wired backwards, etc.)
+    Inverted,
+    /// The waveform is too symmetric (or too short/silent) to tell either way.
+    Unknown,
+}
+
+/// Skewness below this (in absolute value) is considered too symmetric to make a call.
+const SKEW_THRESHOLD: f64 = 0.1;
+
+/// Guesses the polarity of some audio data by looking at the asymmetry of its amplitude
+/// distribution (the skewness), lots of natural sounds, especially voice, are lopsided with the
+/// bigger peaks pointing in one direction, so if those peaks point downwards we assume the
+/// recording is inverted. This is a heuristic and nothing more, pure tones or heavily processed
+/// material are perfectly symmetric and will report `Polarity::Unknown`, and the convention that
+/// positive skew means "normal" is just that, a convention, so compare files against each other
+/// rather than trusting it blindly.
+pub fn detect_polarity(audio: &[f32]) -> Polarity {
+    if audio.len() < 3 {
+        return Polarity::Unknown;
+    }
+
+    let len = audio.len() as f64;
+    let mean = audio.iter().map(|s| *s as f64).sum::<f64>() / len;
+
+    let mut m2 = 0.0;
+    let mut m3 = 0.0;
+    for s in audio {
+        let d = *s as f64 - mean;
+        m2 += d * d;
+        m3 += d * d * d;
+    }
+    m2 /= len;
+    m3 /= len;
+
+    // silence (or a constant dc signal) has no shape to speak of
+    if m2 <= f64::EPSILON {
+        return Polarity::Unknown;
+    }
+
+    let skewness = m3 / m2.powf(1.5);
+    if skewness > SKEW_THRESHOLD {
+        Polarity::Normal
+    } else if skewness < -SKEW_THRESHOLD {
+        Polarity::Inverted
+    } else {
+        Polarity::Unknown
+    }
+}
+
+/// Flips the polarity of some audio data in place (every sample gets multiplied by -1), use it
+/// together with `detect_polarity` to line up recordings before mixing them together.
+pub fn invert_polarity(audio: &mut [f32]) {
+    for s in audio.iter_mut() {
+        *s = -*s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverting_an_asymmetric_waveform_flips_detection() {
+        // a pulse train, short tall peaks pointing up over a slightly negative floor
+        let mut audio: Vec<f32> = (0..4800)
+            .map(|i| if i % 48 < 4 { 0.9 } else { -0.08 })
+            .collect();
+        assert_eq!(detect_polarity(&audio), Polarity::Normal);
+
+        invert_polarity(&mut audio);
+        assert_eq!(detect_polarity(&audio), Polarity::Inverted);
+    }
+
+    #[test]
+    fn symmetric_or_silent_audio_is_unknown() {
+        let tone: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin()).collect();
+        assert_eq!(detect_polarity(&tone), Polarity::Unknown);
+        assert_eq!(detect_polarity(&[0.0; 100]), Polarity::Unknown);
+    }
+}
@@ -0,0 +1,152 @@
+use std::f64::consts::PI;
+
+/// How hard `resample` works at keeping the result clean, each step up roughly doubles the work
+/// per output sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    /// 8 zero crossings, passband up to 90% of Nyquist, good enough for speech, previews and
+    /// anything that's going to be analyzed rather than listened to.
+    Fast,
+    /// 16 zero crossings, passband up to 95% of Nyquist, around -65dB of alias rejection.
+    #[default]
+    Balanced,
+    /// 32 zero crossings, passband up to 97% of Nyquist, for material that's going to be
+    /// listened to closely.
+    Best,
+}
+
+impl ResampleQuality {
+    /// How many zero crossings of the sinc kernel are used on each side of an output sample, more
+    /// means a sharper anti-aliasing filter (and more work).
+    fn zero_crossings(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 8.0,
+            ResampleQuality::Balanced => 16.0,
+            ResampleQuality::Best => 32.0,
+        }
+    }
+
+    /// Where the passband ends, as a fraction of whichever Nyquist is lower.
+    fn passband(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 0.90,
+            ResampleQuality::Balanced => 0.95,
+            ResampleQuality::Best => 0.97,
+        }
+    }
+}
+
+/// Blackman window evaluated at `x` in -1.0..=1.0 (zero outside it).
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let x = (x + 1.0) / 2.0;
+    0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+}
+
+/// Converts some mono audio data from `src_rate` to `dst_rate` using band-limited (Blackman
+/// windowed sinc) interpolation, when going down the kernel is widened to act as the
+/// anti-aliasing filter so nothing above the new Nyquist folds back in, `quality` picks the
+/// kernel length and where the passband ends (see `ResampleQuality`). The output has
+/// `len * dst_rate / src_rate` samples (rounded), samples before the start and past the end are
+/// treated as silence. Returns the input unchanged if both rates are the same.
+///
+/// # Panics
+/// Panics if either rate is zero.
+pub fn resample(
+    audio: &[f32],
+    src_rate: usize,
+    dst_rate: usize,
+    quality: ResampleQuality,
+) -> Vec<f32> {
+    assert!(
+        src_rate > 0 && dst_rate > 0,
+        "sample rates must be non-zero"
+    );
+    if src_rate == dst_rate {
+        return audio.to_vec();
+    }
+
+    let ratio = src_rate as f64 / dst_rate as f64;
+    // cutoff in cycles per *input* sample
+    let cutoff = 0.5 * quality.passband() * (1.0 / ratio).min(1.0);
+    let half_width = quality.zero_crossings() / (2.0 * cutoff);
+    let out_len = (audio.len() as f64 / ratio).round() as usize;
+
+    (0..out_len)
+        .map(|n| {
+            let t = n as f64 * ratio;
+            let first = (t - half_width).ceil().max(0.0) as usize;
+            let last = ((t + half_width).floor() as usize).min(audio.len().saturating_sub(1));
+
+            (first..=last)
+                .map(|k| {
+                    let x = t - k as f64;
+                    let sinc = if x == 0.0 {
+                        2.0 * cutoff
+                    } else {
+                        (2.0 * PI * cutoff * x).sin() / (PI * x)
+                    };
+                    audio[k] as f64 * sinc * blackman(x / half_width)
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// RMS of the middle half, away from the edges where the kernel runs off the input.
+    fn inner_rms(audio: &[f32]) -> f32 {
+        let inner = &audio[audio.len() / 4..audio.len() * 3 / 4];
+        (inner.iter().map(|s| s * s).sum::<f32>() / inner.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn halving_the_rate_keeps_the_passband_and_drops_the_rest() {
+        for quality in [
+            ResampleQuality::Fast,
+            ResampleQuality::Balanced,
+            ResampleQuality::Best,
+        ] {
+            let kept = resample(&sine(1000.0, 48000, 0.5), 48000, 24000, quality);
+            assert_eq!(kept.len(), 12000);
+            let expected = sine(1000.0, 24000, 0.5);
+            let error = kept[3000..9000]
+                .iter()
+                .zip(&expected[3000..9000])
+                .fold(0.0f32, |worst, (a, b)| worst.max((a - b).abs()));
+            assert!(error < 0.01, "{:?} {}", quality, error);
+
+            // above the new 12kHz Nyquist, it would fold back to 9kHz
+            let dropped = resample(&sine(15000.0, 48000, 0.5), 48000, 24000, quality);
+            assert!(
+                inner_rms(&dropped) < 0.01,
+                "{:?} {}",
+                quality,
+                inner_rms(&dropped)
+            );
+        }
+    }
+
+    #[test]
+    fn the_composer_encodes_at_the_target_rate() {
+        let wav =
+            crate::encode_wav(&sine(440.0, 48000, 1.0), 48000, 1, crate::WavFormat::Pcm16).unwrap();
+        let (_, rate) = crate::ComposeBuilder::new()
+            .sample_rate(24000)
+            .on_progress(|_| {})
+            .run_reporting_rate(std::io::Cursor::new(wav))
+            .unwrap();
+        assert_eq!(rate, 24000);
+    }
+}
@@ -0,0 +1,442 @@
+use crate::{onsets, spectrum};
+
+/// Autocorrelation normalized by the energy of the two overlapping parts at every lag, so a value
+/// of 1.0 at some lag means the signal repeats perfectly after that many samples.
+fn normalized_autocorrelation(signal: &[f32]) -> Vec<f32> {
+    let raw = spectrum::autocorrelation(signal);
+
+    // prefix sums of the squared signal to get the energy of any span in O(1)
+    let mut energy = Vec::with_capacity(signal.len() + 1);
+    energy.push(0.0f64);
+    for s in signal {
+        energy.push(energy.last().unwrap() + (*s as f64) * (*s as f64));
+    }
+    let total = signal.len();
+
+    raw.iter()
+        .enumerate()
+        .map(|(lag, r)| {
+            let head = energy[total - lag];
+            let tail = energy[total] - energy[lag];
+            let norm = (head * tail).sqrt();
+            if norm > 0.0 {
+                (*r as f64 / norm) as f32
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Squared difference between the two spans (normalized by their energy) a crossfade from `end`
+/// back to `start` would blend together, 0.0 means they're identical.
+fn crossfade_error(audio: &[f32], start: usize, end: usize, fade: usize) -> f32 {
+    let mut diff = 0.0;
+    let mut energy = 0.0;
+    for i in 0..fade {
+        let (a, b) = (audio[start + i], audio[end + i]);
+        diff += (a - b) * (a - b);
+        energy += a * a + b * b;
+    }
+    if energy > 0.0 {
+        diff / energy
+    } else {
+        0.0
+    }
+}
+
+/// Finds a start and end sample (`start..end`, end exclusive) in some mono audio data that loop
+/// seamlessly, meaning that jumping from `end` back to `start` (with or without a short
+/// crossfade) is as close to inaudible as the material allows. The loop length comes from the
+/// strongest repetition in the signal's autocorrelation (at least 20ms and at most half of the
+/// input, the shortest of the near-best lags wins, so a steady tone loops over a few cycles and a
+/// drum loop over a beat or a bar), the start is put on the strongest attack in the onset envelope
+/// (snapped to a rising zero crossing), and the end is nudged around the loop length to where the
+/// waveform matches the start best.
+///
+/// Returns `None` when the audio doesn't repeat well enough to loop (speech, noise, anything
+/// evolving) or is too short to hold the loop twice.
+pub fn find_loop_points(audio: &[f32], sample_rate: usize) -> Option<(usize, usize)> {
+    let min_lag = (sample_rate / 50).max(1);
+    let max_lag = audio.len() / 2;
+    if max_lag <= min_lag {
+        return None;
+    }
+
+    let correlation = normalized_autocorrelation(audio);
+    let best = correlation[min_lag..=max_lag]
+        .iter()
+        .cloned()
+        .fold(f32::MIN, f32::max);
+    if best < 0.8 {
+        return None;
+    }
+
+    // every multiple of the period correlates about as well, the first one that's (nearly) as
+    // good as the best is the most musically sensible loop
+    let lag = (min_lag..=max_lag).find(|lag| {
+        let r = correlation[*lag];
+        r >= best - 0.01
+            && r >= correlation[lag - 1]
+            && r >= correlation.get(lag + 1).copied().unwrap_or(f32::MIN)
+    })?;
+
+    let fade = (lag / 4).clamp(1, 1024);
+    let slack = (lag / 50).max(16);
+
+    // start on the strongest attack that still leaves room for a whole loop (and fade) after it
+    let hop = 512;
+    let envelope = onsets::onset_envelope(audio, sample_rate, hop);
+    let latest_start = audio.len().checked_sub(lag + slack + fade)?;
+    let onset = envelope
+        .iter()
+        .enumerate()
+        .take_while(|(i, _)| i * hop < latest_start)
+        .fold(
+            (0, f32::MIN),
+            |acc, (i, v)| if *v > acc.1 { (i, *v) } else { acc },
+        )
+        .0
+        * hop;
+
+    let start = (onset.max(1)..latest_start)
+        .take(lag)
+        .find(|i| audio[i - 1] < 0.0 && audio[*i] >= 0.0)
+        .unwrap_or(onset);
+
+    let end = (start + lag).saturating_sub(slack)..=(start + lag + slack);
+    let (end, error) = end
+        .filter(|end| *end > start && end + fade <= audio.len())
+        .map(|end| (end, crossfade_error(audio, start, end, fade)))
+        .fold((0, f32::MAX), |acc, c| if c.1 < acc.1 { c } else { acc });
+
+    if error > 0.1 {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
+/// Measures how strongly rhythmic some mono audio data is, from 0.0 (no steady pulse at all, like
+/// speech, rubato playing or noise) to 1.0 (a perfectly regular pulse, like a drum machine). The
+/// onset envelope is autocorrelated and the strongest peak at a lag between 40 and 200 BPM is
+/// taken as the beat, the returned value is how well the envelope lines up with itself shifted by
+/// that beat (its normalized autocorrelation there), so it says nothing about *which* tempo it is,
+/// only how steady it is. Needs a few seconds of audio to mean anything, anything shorter than
+/// two beats at 200 BPM returns 0.0.
+pub fn beat_strength(audio: &[f32], sample_rate: usize) -> f32 {
+    let hop = 512;
+    let envelope = onsets::onset_envelope(audio, sample_rate, hop);
+    let frames_per_sec = sample_rate as f32 / hop as f32;
+
+    let min_lag = ((60.0 / 200.0) * frames_per_sec).round() as usize;
+    let max_lag = (((60.0 / 40.0) * frames_per_sec).round() as usize).min(envelope.len() / 2);
+    if max_lag <= min_lag {
+        return 0.0;
+    }
+
+    // only the fluctuations matter, the average onset strength would correlate with itself at
+    // every lag and make everything look rhythmic
+    let average = envelope.iter().sum::<f32>() / envelope.len() as f32;
+    let centered: Vec<f32> = envelope.iter().map(|v| v - average).collect();
+
+    normalized_autocorrelation(&centered)[min_lag..=max_lag]
+        .iter()
+        .cloned()
+        .fold(0.0, f32::max)
+        .clamp(0.0, 1.0)
+}
+
+/// Estimates the tempo of some mono audio data in beats per minute, from the strongest
+/// periodicity of its onset envelope between 40 and 200 BPM. Autocorrelation loves octave errors
+/// (a 120 BPM track repeats every 0.5s but also every 1s), so candidates are weighted towards the
+/// 120 BPM region most music sits around before picking one, and the winning lag is refined to a
+/// fraction of a frame. Assumes a roughly constant tempo, returns `None` when there's no pulse to
+/// speak of or the audio is shorter than two beats at 200 BPM.
+pub fn estimate_bpm(audio: &[f32], sample_rate: usize) -> Option<f32> {
+    tempo(audio, sample_rate).map(|(bpm, _)| bpm)
+}
+
+/// `estimate_bpm`'s tempo along with the normalized autocorrelation of the onset envelope at the
+/// winning lag, how well the envelope lines up with itself shifted by one beat.
+fn tempo(audio: &[f32], sample_rate: usize) -> Option<(f32, f32)> {
+    let hop = 512;
+    let envelope = onsets::onset_envelope(audio, sample_rate, hop);
+    let frames_per_sec = sample_rate as f32 / hop as f32;
+
+    let min_lag = ((60.0 / 200.0) * frames_per_sec).round() as usize;
+    let max_lag = (((60.0 / 40.0) * frames_per_sec).round() as usize).min(envelope.len() / 2);
+    if max_lag <= min_lag {
+        return None;
+    }
+
+    let average = envelope.iter().sum::<f32>() / envelope.len() as f32;
+    let centered: Vec<f32> = envelope.iter().map(|v| v - average).collect();
+    let correlation = normalized_autocorrelation(&centered);
+
+    // log-normal preference centered on 120 BPM, an octave away costs about 40%
+    let preference = |lag: usize| {
+        let bpm = 60.0 * frames_per_sec / lag as f32;
+        (-0.5 * (bpm / 120.0).log2().powi(2)).exp()
+    };
+    let best = (min_lag..=max_lag).max_by(|a, b| {
+        (correlation[*a] * preference(*a))
+            .partial_cmp(&(correlation[*b] * preference(*b)))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })?;
+    if correlation[best] <= 0.0 {
+        return None;
+    }
+
+    // parabolic interpolation around the peak for a fractional lag
+    let lag = match (correlation.get(best - 1), correlation.get(best + 1)) {
+        (Some(l), Some(r)) => {
+            let c = correlation[best];
+            let denom = l - 2.0 * c + r;
+            if denom.abs() > f32::EPSILON {
+                best as f32 + (0.5 * (l - r) / denom).clamp(-0.5, 0.5)
+            } else {
+                best as f32
+            }
+        }
+        _ => best as f32,
+    };
+
+    Some((60.0 * frames_per_sec / lag, correlation[best]))
+}
+
+/// Finds where the beats fall in some mono audio data, returned as timestamps in seconds. The
+/// tempo comes from `estimate_bpm`, then a grid with that spacing is slid across the onset
+/// envelope to find the offset (phase) where the grid lines hit the most onset energy, and every
+/// grid line from the start to the end of the audio is a beat. Good enough for DJ-style beat
+/// matching and cue points on electronic and most pop music, but it assumes the tempo stays
+/// (roughly) constant for the whole input, anything that speeds up, slows down or changes meter
+/// will drift off the grid. Once the phase is found, the grid's spacing and offset are fitted to
+/// the onset peaks near each line, so on steady material the beats usually land within a few
+/// milliseconds of the attacks. Returns an empty vector when no tempo could be found.
+pub fn beat_positions(audio: &[f32], sample_rate: usize) -> Vec<f64> {
+    match estimate_bpm(audio, sample_rate) {
+        Some(bpm) => beat_grid(audio, sample_rate, bpm),
+        None => vec![],
+    }
+}
+
+/// The beats of `beat_positions` for an already estimated tempo.
+fn beat_grid(audio: &[f32], sample_rate: usize, bpm: f32) -> Vec<f64> {
+    let hop = 512;
+    let envelope = onsets::onset_envelope(audio, sample_rate, hop);
+    let frames_per_sec = sample_rate as f64 / hop as f64;
+    let period = 60.0 * frames_per_sec / bpm as f64;
+
+    let grid = |phase: f64| {
+        (0..)
+            .map(move |k| phase + k as f64 * period)
+            .take_while(|position| position.round() < envelope.len() as f64)
+    };
+
+    let phases = (0..period.ceil() as usize).map(|phase| phase as f64);
+    let best_phase = phases
+        .map(|phase| {
+            let score: f32 = grid(phase)
+                .map(|position| envelope[position.round() as usize])
+                .sum();
+            (phase, score)
+        })
+        .fold(
+            (0.0, f32::MIN),
+            |best, c| if c.1 > best.1 { c } else { best },
+        )
+        .0;
+
+    // the tempo estimate is only as exact as the autocorrelation peak, over a few minutes even a
+    // tiny error drifts off the beat, so fit the grid to the actual onset peaks near each line
+    // (least squares on beat number vs. peak position) to pin down the period and phase together
+    let search = (period / 4.0).max(1.0) as usize;
+    let peaks: Vec<(f64, f64)> = grid(best_phase)
+        .enumerate()
+        .filter_map(|(k, position)| {
+            let center = position.round() as usize;
+            let lo = center.saturating_sub(search);
+            let hi = (center + search).min(envelope.len() - 1);
+            let peak = (lo..=hi).max_by(|a, b| {
+                envelope[*a]
+                    .partial_cmp(&envelope[*b])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+            (envelope[peak] > 0.0).then_some((k as f64, peak as f64))
+        })
+        .collect();
+
+    let (phase, period) = if peaks.len() >= 2 {
+        let n = peaks.len() as f64;
+        let mean_k = peaks.iter().map(|(k, _)| k).sum::<f64>() / n;
+        let mean_p = peaks.iter().map(|(_, p)| p).sum::<f64>() / n;
+        let covariance: f64 = peaks.iter().map(|(k, p)| (k - mean_k) * (p - mean_p)).sum();
+        let variance: f64 = peaks.iter().map(|(k, _)| (k - mean_k).powi(2)).sum();
+        let fitted = covariance / variance;
+        // move the intercept to the first line that still rounds to a frame inside the audio
+        let intercept = mean_p - fitted * mean_k;
+        (
+            intercept - ((intercept + 0.5) / fitted).floor() * fitted,
+            fitted,
+        )
+    } else {
+        (best_phase, period)
+    };
+
+    (0..)
+        .map(|k| phase + k as f64 * period)
+        .take_while(|position| position.round() < envelope.len() as f64)
+        .map(|position| (position / frames_per_sec).max(0.0))
+        .collect()
+}
+
+/// Everything `estimate_tempo` found out about the pulse of some audio.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoReport {
+    /// The tempo in beats per minute, same as `estimate_bpm`.
+    pub bpm: f32,
+    /// How much to trust `bpm`, 0.0 to 1.0, the normalized autocorrelation of the onset envelope
+    /// at one beat's lag. A drum machine is close to 1.0, most produced music lands somewhere
+    /// above 0.3, below about 0.1 the "tempo" is probably just the strongest of a lot of noise.
+    pub confidence: f32,
+    /// Where the beats fall, in seconds, same as `beat_positions`.
+    pub beat_times: Vec<f64>,
+}
+
+/// Estimates the tempo of some mono audio data along with how sure it is about it and where the
+/// beats are, for DJ-style auto-mixing where a wrong tempo is worse than none, so a mix can fall
+/// back to a plain crossfade when `confidence` is low. The tempo is `estimate_bpm`'s (the
+/// strongest periodicity of the onset envelope between 40 and 200 BPM) and the beats are
+/// `beat_positions`'s grid fitted to the onsets, with the same assumption of a roughly constant
+/// tempo. Returns `None` where `estimate_bpm` does.
+pub fn estimate_tempo(audio: &[f32], sample_rate: usize) -> Option<TempoReport> {
+    let (bpm, correlation) = tempo(audio, sample_rate)?;
+
+    Some(TempoReport {
+        bpm,
+        confidence: correlation.clamp(0.0, 1.0),
+        beat_times: beat_grid(audio, sample_rate, bpm),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// Deterministic white noise in -1.0..1.0, a plain LCG so the tests don't need `rand`.
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_steady_tone_loops_over_whole_periods() {
+        // 441Hz at 44.1kHz repeats every 100 samples exactly
+        let tone = sine(441.0, 44100, 1.0);
+        let (start, end) = find_loop_points(&tone, 44100).unwrap();
+
+        let length = end - start;
+        // at least the 20ms minimum, and the first whole number of periods past it
+        assert_eq!(length, 900, "{}..{}", start, end);
+        for i in 0..100 {
+            assert!((tone[start + i] - tone[end + i]).abs() < 1e-3);
+        }
+    }
+
+    /// `secs` of silence with a decaying noise burst (a kick, a syllable) of `burst` seconds at
+    /// every one of `times`.
+    fn bursts(times: &[f64], burst: f64, secs: f64) -> Vec<f32> {
+        let sample_rate = 44100;
+        let mut audio = vec![0.0; (secs * sample_rate as f64) as usize];
+        let length = (burst * sample_rate as f64) as usize;
+        for (n, time) in times.iter().enumerate() {
+            let start = (time * sample_rate as f64) as usize;
+            let hit = noise(length, n as u32 + 1);
+            for (i, sample) in hit.iter().enumerate() {
+                if let Some(out) = audio.get_mut(start + i) {
+                    *out += sample * (1.0 - i as f32 / length as f32);
+                }
+            }
+        }
+        audio
+    }
+
+    #[test]
+    fn a_drum_loop_has_a_stronger_beat_than_speech() {
+        // a hit every half second (120 BPM) against syllables at uneven gaps
+        let drums: Vec<f64> = (0..16).map(|beat| beat as f64 * 0.5).collect();
+        let mut syllables = vec![];
+        let mut time = 0.1;
+        for gap in [
+            0.23, 0.41, 0.17, 0.62, 0.29, 0.35, 0.19, 0.83, 0.27, 0.44, 0.21, 0.57,
+        ] {
+            syllables.push(time);
+            time += gap;
+        }
+
+        let steady = beat_strength(&bursts(&drums, 0.05, 8.0), 44100);
+        let speech = beat_strength(&bursts(&syllables, 0.15, 8.0), 44100);
+        assert!(steady > 0.8, "{}", steady);
+        assert!(speech < 0.5, "{}", speech);
+    }
+
+    #[test]
+    fn noise_has_no_loop() {
+        assert_eq!(find_loop_points(&noise(44100, 7), 44100), None);
+    }
+
+    #[test]
+    fn beats_land_on_the_clicks() {
+        // 128 BPM, starting 0.2 seconds in
+        let period = 60.0 / 128.0;
+        let clicks: Vec<f64> = (0..21).map(|beat| 0.2 + beat as f64 * period).collect();
+
+        let beats = beat_positions(&bursts(&clicks, 0.01, 10.0), 44100);
+        let nearest = |time: f64, among: &[f64]| {
+            among
+                .iter()
+                .map(|other| (other - time).abs())
+                .fold(f64::MAX, f64::min)
+        };
+        assert!(!beats.is_empty());
+        for beat in &beats {
+            assert!(nearest(*beat, &clicks) < 0.02, "{} in {:?}", beat, beats);
+        }
+        for click in &clicks {
+            assert!(nearest(*click, &beats) < 0.02, "{} in {:?}", click, beats);
+        }
+    }
+
+    #[test]
+    fn the_tempo_of_a_drum_loop_is_confident() {
+        let clicks: Vec<f64> = (0..20).map(|beat| beat as f64 * 0.5).collect();
+        let report = estimate_tempo(&bursts(&clicks, 0.03, 10.0), 44100).unwrap();
+
+        assert!((report.bpm - 120.0).abs() < 1.0, "{:?}", report);
+        assert!(report.confidence > 0.5, "{:?}", report);
+        assert!(report.beat_times.len() >= 18, "{:?}", report);
+        assert!(report
+            .beat_times
+            .windows(2)
+            .all(|pair| (pair[1] - pair[0] - 0.5).abs() < 0.02));
+
+        // a steady hiss has no pulse to speak of
+        let hiss: Vec<f32> = noise(44100 * 10, 3).iter().map(|s| s * 0.3).collect();
+        if let Some(report) = estimate_tempo(&hiss, 44100) {
+            assert!(report.confidence < 0.3, "{:?}", report);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::f32::consts::PI;
+
+use rustfft::{num_complex::Complex, FftPlanner};
+
+/// Periodic Hann window of `len` samples.
+pub(crate) fn hann(len: usize) -> Vec<f32> {
+    WindowFunction::Hann.coefficients(len)
+}
+
+/// The taper applied to every frame before its FFT in `stft`, trading how narrow a pure tone's
+/// peak is against how much its energy leaks into far away bins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowFunction {
+    /// The all-rounder, leakage falls off quickly away from the peak, what every analysis in
+    /// Avasara uses internally.
+    #[default]
+    Hann,
+    /// A slightly narrower peak than Hann with lower nearby sidelobes, but leakage that stays put
+    /// further out, the classic choice for speech.
+    Hamming,
+    /// A wider peak but far less leakage than either, for picking quiet tones out next to loud
+    /// ones.
+    Blackman,
+}
+
+impl WindowFunction {
+    /// The window's `len` coefficients, in the periodic form (the one meant for overlapping STFT
+    /// frames rather than filter design).
+    pub fn coefficients(&self, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let phase = 2.0 * PI * i as f32 / len as f32;
+                match self {
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Blackman => {
+                        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// A magnitude spectrogram as computed by `stft`, one row of `bins()` magnitudes per frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+    /// `magnitudes[frame][bin]`, the linear magnitude of each FFT bin (not normalized by the
+    /// frame length or window, so only meaningful relative to each other or after converting to
+    /// dB against a reference).
+    pub magnitudes: Vec<Vec<f32>>,
+    pub sample_rate: usize,
+    /// Length of every frame (and its FFT) in samples.
+    pub frame_len: usize,
+    /// Distance between consecutive frames' centers in samples.
+    pub hop: usize,
+}
+
+impl Spectrogram {
+    /// Number of frames (rows).
+    pub fn frames(&self) -> usize {
+        self.magnitudes.len()
+    }
+
+    /// Number of frequency bins in every frame (columns), `frame_len / 2 + 1`.
+    pub fn bins(&self) -> usize {
+        self.frame_len / 2 + 1
+    }
+
+    /// Center frequency of bin `bin` in Hz.
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / self.frame_len as f32
+    }
+
+    /// When frame `frame` is centered, in seconds from the start of the audio.
+    pub fn frame_time(&self, frame: usize) -> f64 {
+        (frame * self.hop) as f64 / self.sample_rate as f64
+    }
+}
+
+/// Computes the magnitude spectrogram (short-time Fourier transform) of some mono audio data,
+/// frames of `frame_len` samples tapered by `window` and centered every `hop` samples, frame `i`
+/// on sample `i * hop`, with the audio zero padded at both ends so the first and last samples get
+/// a full frame too. That's `audio.len() / hop + 1` frames of `frame_len / 2 + 1` bins, from 0Hz
+/// up to the Nyquist frequency, see `Spectrogram`'s methods for mapping them to times and
+/// frequencies.
+///
+/// Longer frames resolve frequencies more finely (`sample_rate / frame_len` Hz per bin) but smear
+/// timing, 2048 samples with a hop of 512 is the usual starting point for music at 44.1kHz, 512
+/// with a hop of 160 for 16kHz speech. Any length works but powers of two are the fastest.
+///
+/// # Panics
+/// Panics if `frame_len` or `hop` is zero.
+pub fn stft(
+    audio: &[f32],
+    sample_rate: usize,
+    frame_len: usize,
+    hop: usize,
+    window: WindowFunction,
+) -> Spectrogram {
+    assert!(
+        frame_len > 0 && hop > 0,
+        "frame length and hop size must be non-zero"
+    );
+
+    Spectrogram {
+        magnitudes: windowed_stft_magnitudes(audio, &window.coefficients(frame_len), hop),
+        sample_rate,
+        frame_len,
+        hop,
+    }
+}
+
+/// Averaged magnitude spectrum (`frame_len / 2 + 1` bins) of Hann-windowed frames taken every
+/// `frame_len / 2` samples, audio shorter than a frame gets zero padded into a single frame.
+pub(crate) fn average_magnitude_spectrum(audio: &[f32], frame_len: usize) -> Vec<f32> {
+    let window = hann(frame_len);
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(frame_len);
+    let bins = frame_len / 2 + 1;
+
+    let mut sum = vec![0.0; bins];
+    let mut frames = 0;
+    let mut buffer = vec![Complex::new(0.0, 0.0); frame_len];
+
+    let mut start = 0;
+    loop {
+        for (i, slot) in buffer.iter_mut().enumerate() {
+            let sample = audio.get(start + i).copied().unwrap_or(0.0);
+            *slot = Complex::new(sample * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+        for (acc, bin) in sum.iter_mut().zip(&buffer) {
+            *acc += bin.norm();
+        }
+        frames += 1;
+
+        start += frame_len / 2;
+        if start + frame_len > audio.len() {
+            break;
+        }
+    }
+
+    sum.iter().map(|m| m / frames as f32).collect()
+}
+
+/// Local maxima of a magnitude spectrum that are within `floor_db` of the strongest bin, returned
+/// as `(frequency in Hz, magnitude)` with parabolic interpolation of the peak position, strongest
+/// first.
+pub(crate) fn spectral_peaks(
+    magnitudes: &[f32],
+    sample_rate: usize,
+    frame_len: usize,
+    floor_db: f32,
+) -> Vec<(f32, f32)> {
+    let max = magnitudes.iter().cloned().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return vec![];
+    }
+    let floor = max * 10f32.powf(floor_db / 20.0);
+    let bin_hz = sample_rate as f32 / frame_len as f32;
+
+    let mut peaks = vec![];
+    for i in 1..magnitudes.len().saturating_sub(1) {
+        let (l, c, r) = (magnitudes[i - 1], magnitudes[i], magnitudes[i + 1]);
+        if c < floor || c <= l || c < r {
+            continue;
+        }
+
+        // parabolic interpolation on the log magnitudes for sub-bin accuracy
+        let (ll, lc, lr) = (l.max(1e-12).ln(), c.ln(), r.max(1e-12).ln());
+        let denom = ll - 2.0 * lc + lr;
+        let offset = if denom.abs() > f32::EPSILON {
+            0.5 * (ll - lr) / denom
+        } else {
+            0.0
+        };
+
+        peaks.push(((i as f32 + offset) * bin_hz, c));
+    }
+
+    peaks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    peaks
+}
+
+/// Magnitude spectra (`frame_len / 2 + 1` bins each) of Hann-windowed frames centered every `hop`
+/// samples, frame `i` is centered on sample `i * hop` and the audio is zero padded at both ends,
+/// so there are `audio.len() / hop + 1` frames.
+pub(crate) fn stft_magnitudes(audio: &[f32], frame_len: usize, hop: usize) -> Vec<Vec<f32>> {
+    windowed_stft_magnitudes(audio, &hann(frame_len), hop)
+}
+
+/// `stft_magnitudes` with any window, the frame length is the window's.
+fn windowed_stft_magnitudes(audio: &[f32], window: &[f32], hop: usize) -> Vec<Vec<f32>> {
+    let frame_len = window.len();
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(frame_len);
+    let bins = frame_len / 2 + 1;
+    let half = (frame_len / 2) as isize;
+
+    let mut buffer = vec![Complex::new(0.0, 0.0); frame_len];
+    (0..=audio.len() / hop)
+        .map(|frame| {
+            let start = (frame * hop) as isize - half;
+            for (i, slot) in buffer.iter_mut().enumerate() {
+                let index = start + i as isize;
+                let sample = if index >= 0 {
+                    audio.get(index as usize).copied().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+                *slot = Complex::new(sample * window[i], 0.0);
+            }
+            fft.process(&mut buffer);
+            buffer[..bins].iter().map(|bin| bin.norm()).collect()
+        })
+        .collect()
+}
+
+/// Raw (unnormalized) autocorrelation of `signal` for every lag from 0 to `signal.len() - 1`,
+/// computed through the FFT so it stays cheap for long inputs.
+pub(crate) fn autocorrelation(signal: &[f32]) -> Vec<f32> {
+    let len = signal.len();
+    if len == 0 {
+        return vec![];
+    }
+
+    cross_correlation(signal, signal, len - 1).split_off(len - 1)
+}
+
+/// Raw cross-correlation of two signals for lags from `-max_lag` to `max_lag`, index `max_lag + k`
+/// holds the sum of `a[i] * b[i + k]`, so a peak at a positive lag means `b` is a delayed copy of
+/// `a`. Computed through the FFT so it stays cheap for long inputs.
+pub(crate) fn cross_correlation(a: &[f32], b: &[f32], max_lag: usize) -> Vec<f32> {
+    let len = (a.len() + b.len()).max(1).next_power_of_two();
+    let mut planner = FftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(len);
+    let inverse = planner.plan_fft_inverse(len);
+
+    let padded = |signal: &[f32]| -> Vec<Complex<f32>> {
+        signal
+            .iter()
+            .map(|s| Complex::new(*s, 0.0))
+            .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+            .take(len)
+            .collect()
+    };
+    let mut fa = padded(a);
+    let mut fb = padded(b);
+    forward.process(&mut fa);
+    forward.process(&mut fb);
+
+    let mut buffer: Vec<Complex<f32>> = fa.iter().zip(&fb).map(|(x, y)| x.conj() * y).collect();
+    inverse.process(&mut buffer);
+
+    (-(max_lag as isize)..=max_lag as isize)
+        .map(|lag| {
+            if lag.unsigned_abs() >= len {
+                0.0
+            } else {
+                buffer[lag.rem_euclid(len as isize) as usize].re / len as f32
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn a_tone_peaks_in_its_bin_from_when_it_starts() {
+        // half a second of silence, then a tone halfway between bins 40 and 41
+        let sample_rate = 44100;
+        let frequency = 40.5 * sample_rate as f32 / 2048.0;
+        let mut audio = vec![0.0; sample_rate / 2];
+        audio.extend(sine(frequency, sample_rate, 1.0));
+
+        let spectrogram = stft(&audio, sample_rate, 2048, 512, WindowFunction::Hann);
+        assert_eq!(spectrogram.frames(), audio.len() / 512 + 1);
+        assert!(spectrogram
+            .magnitudes
+            .iter()
+            .all(|frame| frame.len() == 1025));
+        assert_eq!(spectrogram.bins(), 1025);
+        assert!((spectrogram.bin_frequency(1024) - 22050.0).abs() < 1e-3);
+
+        for (frame, magnitudes) in spectrogram.magnitudes.iter().enumerate() {
+            let time = spectrogram.frame_time(frame);
+            let loudest = (0..magnitudes.len())
+                .max_by(|a, b| magnitudes[*a].total_cmp(&magnitudes[*b]))
+                .unwrap();
+            // frames that reach into the tone (2048 samples wide, centered) have heard it
+            if time > 0.5 + 1024.0 / sample_rate as f64 {
+                assert!(loudest == 40 || loudest == 41, "{} at {}", loudest, time);
+            } else if time < 0.5 - 1024.0 / sample_rate as f64 {
+                assert!(magnitudes.iter().all(|m| *m == 0.0), "{}", time);
+            }
+        }
+    }
+
+    #[test]
+    fn blackman_leaks_least_and_hamming_most_far_from_the_peak() {
+        let sample_rate = 44100;
+        let tone = sine(40.5 * sample_rate as f32 / 2048.0, sample_rate, 1.0);
+        // 30 bins away, relative to the peak
+        let leakage = |window: WindowFunction| {
+            let spectrogram = stft(&tone, sample_rate, 2048, 2048, window);
+            let middle = &spectrogram.magnitudes[spectrogram.frames() / 2];
+            middle[70] / middle[40]
+        };
+
+        let (blackman, hann, hamming) = (
+            leakage(WindowFunction::Blackman),
+            leakage(WindowFunction::Hann),
+            leakage(WindowFunction::Hamming),
+        );
+        assert!(
+            blackman < hann && hann < hamming,
+            "{} {} {}",
+            blackman,
+            hann,
+            hamming
+        );
+        assert!(hamming < 0.01, "{}", hamming);
+    }
+}
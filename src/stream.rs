@@ -0,0 +1,736 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{
+    CodecParameters, Decoder as CodecDecoder, DecoderOptions, CODEC_TYPE_NULL,
+};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::{Hint, ProbeResult};
+
+use crate::{AvasaraError, DecodeWarning};
+
+/// Probes a source for its container format (and any metadata in front of it, like ID3 tags),
+/// shared by `Decoder` and `probe_info`.
+pub(crate) fn probe<S: MediaSource + 'static>(src: S) -> Result<ProbeResult, AvasaraError> {
+    probe_with_hint(src, &ProbeHint::default())
+}
+
+/// Same as `probe`, with a hint to try the likely format first.
+pub(crate) fn probe_with_hint<S: MediaSource + 'static>(
+    src: S,
+    hint: &ProbeHint,
+) -> Result<ProbeResult, AvasaraError> {
+    // more info at getting_started.md of Symphonia
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    let meta_opts: MetadataOptions = Default::default();
+    let fmt_opts: FormatOptions = Default::default();
+
+    Ok(symphonia::default::get_probe().format(&hint.to_symphonia(), mss, &fmt_opts, &meta_opts)?)
+}
+
+/// A tag as a (key, value) pair, the key exactly as the file has it, the value minus the NUL
+/// padding some formats (RIFF INFO chunks) leave at the end of strings.
+pub(crate) fn tag_pair(tag: &Tag) -> (String, String) {
+    (tag.key.clone(), tag_value(tag))
+}
+
+/// A tag's value as a string, see `tag_pair`.
+pub(crate) fn tag_value(tag: &Tag) -> String {
+    tag.value.to_string().trim_end_matches('\0').to_string()
+}
+
+/// A tag renamed to the Vorbis comment field that means the same thing, so it can be written into
+/// an Ogg Vorbis file whatever format it came from (ID3's `TPE1` and MP4's `©ART` both become
+/// `ARTIST`). Only tags symphonia recognizes (see `StandardTagKey`) and that still hold after a
+/// transcode are mapped, ReplayGain values and encoder info describe the old encode, so those
+/// (and anything unrecognized) give `None`.
+pub(crate) fn vorbis_comment(tag: &Tag) -> Option<(String, String)> {
+    use StandardTagKey::*;
+
+    let field = match tag.std_key? {
+        Album => "ALBUM",
+        AlbumArtist => "ALBUMARTIST",
+        Arranger => "ARRANGER",
+        Artist => "ARTIST",
+        Bpm => "BPM",
+        Comment => "COMMENT",
+        Compilation => "COMPILATION",
+        Composer => "COMPOSER",
+        Conductor => "CONDUCTOR",
+        Copyright => "COPYRIGHT",
+        Date => "DATE",
+        Description => "DESCRIPTION",
+        DiscNumber => "DISCNUMBER",
+        DiscTotal => "DISCTOTAL",
+        Engineer => "ENGINEER",
+        Ensemble => "ENSEMBLE",
+        Genre => "GENRE",
+        IdentBarcode => "BARCODE",
+        IdentCatalogNumber => "CATALOGNUMBER",
+        IdentIsrc => "ISRC",
+        Label => "LABEL",
+        Language => "LANGUAGE",
+        License => "LICENSE",
+        Lyricist => "LYRICIST",
+        Lyrics => "LYRICS",
+        Mood => "MOOD",
+        MusicBrainzAlbumArtistId => "MUSICBRAINZ_ALBUMARTISTID",
+        MusicBrainzAlbumId => "MUSICBRAINZ_ALBUMID",
+        MusicBrainzArtistId => "MUSICBRAINZ_ARTISTID",
+        MusicBrainzReleaseGroupId => "MUSICBRAINZ_RELEASEGROUPID",
+        MusicBrainzTrackId => "MUSICBRAINZ_TRACKID",
+        OriginalDate => "ORIGINALDATE",
+        Performer => "PERFORMER",
+        Producer => "PRODUCER",
+        Remixer => "REMIXER",
+        TrackNumber => "TRACKNUMBER",
+        TrackTitle => "TITLE",
+        TrackTotal => "TRACKTOTAL",
+        Version => "VERSION",
+        _ => return None,
+    };
+    Some((field.to_string(), tag_value(tag)))
+}
+
+/// Every tag a freshly probed source has so far, the ones found in front of the container first
+/// and then the container's own.
+pub(crate) fn initial_tags(probed: &mut ProbeResult) -> Vec<Tag> {
+    let mut tags = vec![];
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            tags.extend_from_slice(revision.tags());
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+    tags
+}
+
+/// Short name of a track's codec, like `"flac"`, `"mp3"` or `"pcm_s16le"`, shared by
+/// `probe_info`, `list_tracks` and `TrackSelector::Codec`.
+pub(crate) fn codec_name(params: &CodecParameters) -> String {
+    symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name.to_string())
+        .unwrap_or_else(|| format!("{}", params.codec))
+}
+
+/// What the source is expected to be, handed to symphonia's prober so it tries the likely
+/// format first, see `Decoder::with_hint` and `decode_audio_with_hint`. Probing works without
+/// one, formats are recognized by their content, but headerless ones (raw AAC in ADTS frames,
+/// raw MP3 with junk in front of it) probe slower and less reliably without. Both parts are
+/// optional, an empty hint is the same as none.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProbeHint {
+    /// File extension without the dot, like `"aac"` or `"mka"`.
+    pub extension: Option<String>,
+    /// MIME type, like `"audio/aac"` or `"audio/ogg"` (what an HTTP response's Content-Type
+    /// says).
+    pub mime_type: Option<String>,
+}
+
+impl ProbeHint {
+    /// A hint with just a file extension (without the dot).
+    pub fn extension(extension: &str) -> Self {
+        ProbeHint {
+            extension: Some(extension.to_string()),
+            mime_type: None,
+        }
+    }
+
+    /// A hint with just a MIME type.
+    pub fn mime_type(mime_type: &str) -> Self {
+        ProbeHint {
+            extension: None,
+            mime_type: Some(mime_type.to_string()),
+        }
+    }
+
+    /// A hint with the extension of `path`, empty if it has none.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        ProbeHint {
+            extension: path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(str::to_string),
+            mime_type: None,
+        }
+    }
+
+    /// The same thing as symphonia's own `Hint`.
+    fn to_symphonia(&self) -> Hint {
+        let mut hint = Hint::new();
+        if let Some(extension) = &self.extension {
+            hint.with_extension(extension.trim_start_matches('.'));
+        }
+        if let Some(mime_type) = &self.mime_type {
+            hint.mime_type(mime_type);
+        }
+        hint
+    }
+}
+
+/// Which audio track of a multi-track container (an MKV with a dub or a commentary, an MP4 with
+/// both AAC and ALAC) `Decoder::with_track` and `decode_track` decode. Only audio tracks count,
+/// subtitles and video are skipped, see `list_tracks` for what a file has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackSelector {
+    /// The nth audio track in the order the container lists them, `Index(0)` (the default) is
+    /// the one `decode` picks.
+    Index(usize),
+    /// The first audio track with this language tag, compared without caring about case. MKV
+    /// uses three letter ISO 639-2 codes (`"eng"`, `"jpn"`, `"ger"`), so that's usually what
+    /// to pass.
+    Language(String),
+    /// The first audio track encoded with the first of these codecs (short names, as in
+    /// `TrackInfo::codec`) that any track has, so `["flac", "alac", "aac"]` takes a lossless
+    /// track if there is one and settles for AAC otherwise.
+    Codec(Vec<String>),
+}
+
+impl Default for TrackSelector {
+    fn default() -> Self {
+        TrackSelector::Index(0)
+    }
+}
+
+impl TrackSelector {
+    /// Picks the selected track out of a container's track list, `AvasaraError::DecodeFailed` if
+    /// there's no audio track at all, `AvasaraError::TrackNotFound` if there is but none match.
+    pub(crate) fn select<'a>(&self, tracks: &'a [Track]) -> Result<&'a Track, AvasaraError> {
+        let mut audio = tracks
+            .iter()
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .peekable();
+        if audio.peek().is_none() {
+            return Err(AvasaraError::DecodeFailed);
+        }
+
+        let selected = match self {
+            TrackSelector::Index(index) => audio.nth(*index),
+            TrackSelector::Language(language) => audio.find(|t| {
+                t.language
+                    .as_deref()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(language))
+            }),
+            TrackSelector::Codec(preferences) => {
+                let audio: Vec<&Track> = audio.collect();
+                preferences.iter().find_map(|codec| {
+                    audio
+                        .iter()
+                        .find(|t| codec_name(&t.codec_params).eq_ignore_ascii_case(codec))
+                        .copied()
+                })
+            }
+        };
+        selected.ok_or(AvasaraError::TrackNotFound)
+    }
+}
+
+/// One packet's worth of decoded audio, as yielded by `Decoder`.
+#[derive(Debug, Clone)]
+pub struct DecodedBlock {
+    /// Interleaved f32 samples, `channels` of them per frame.
+    pub samples: Vec<f32>,
+    pub sample_rate: usize,
+    pub channels: usize,
+    /// Where the first sample of this block sits in the whole decoded stream (counting interleaved
+    /// samples, like `DecodeWarning`'s `after_samples`), so concatenating every block gives exactly
+    /// what `decode` returns.
+    pub offset: usize,
+}
+
+/// Decodes a file incrementally, one packet at a time, instead of building the whole track in
+/// memory like `decode` does, for hour-long recordings or anything else where you'd rather feed
+/// blocks straight into analysis or an encoder as they come. Takes the same sources and formats
+/// as `decode` and works as an iterator of `Result<DecodedBlock, AvasaraError>`, packets that fail
+/// to decode are skipped (and recorded, see `take_warnings`) just like in `decode`, an
+/// unrecoverable error is yielded once and ends the iteration.
+///
+/// Blocks are usually a few thousand samples long but their size depends entirely on the codec,
+/// buffer them up yourself if you need fixed-size chunks.
+pub struct Decoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    selector: TrackSelector,
+    track_id: u32,
+    total_frames: Option<u64>,
+    /// The frame a seek asked for, samples before it in the next decoded packets are dropped.
+    seek_target: Option<u64>,
+    sample_rate: usize,
+    channels: usize,
+    offset: usize,
+    warnings: Vec<DecodeWarning>,
+    tags: Vec<(String, String)>,
+    comments: Vec<(String, String)>,
+    finished: bool,
+}
+
+impl Decoder {
+    /// Probes the source and sets up decoding of its first audio track, fails if the format or
+    /// codec isn't supported (`AvasaraError::Decode`) or there's no audio track at all
+    /// (`AvasaraError::DecodeFailed`). Nothing is decoded until the first call to `next`.
+    pub fn new<S: MediaSource + 'static>(src: S) -> Result<Self, AvasaraError> {
+        Decoder::with_track(src, TrackSelector::default())
+    }
+
+    /// Same as `new`, but decodes the audio track picked by `selector` instead of the first one,
+    /// fails with `AvasaraError::TrackNotFound` if none of them match.
+    pub fn with_track<S: MediaSource + 'static>(
+        src: S,
+        selector: TrackSelector,
+    ) -> Result<Self, AvasaraError> {
+        Decoder::with_hint(src, selector, &ProbeHint::default())
+    }
+
+    /// Same as `new`, but opens the file at `path` itself, with its extension as a hint for the
+    /// format so probing doesn't have to guess. The file is read as it's decoded, not loaded into
+    /// memory up front. Fails with `AvasaraError::Io` if it can't be opened.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, AvasaraError> {
+        let path = path.as_ref();
+        Decoder::with_hint(
+            File::open(path)?,
+            TrackSelector::default(),
+            &ProbeHint::from_path(path),
+        )
+    }
+
+    /// Same as `with_track`, with a hint about the source's format (see `ProbeHint`), for
+    /// headerless formats and sources that aren't files (a network stream whose Content-Type is
+    /// known, a buffer that came with a file name).
+    pub fn with_hint<S: MediaSource + 'static>(
+        src: S,
+        selector: TrackSelector,
+        hint: &ProbeHint,
+    ) -> Result<Self, AvasaraError> {
+        let mut probed = probe_with_hint(src, hint)?;
+        let initial = initial_tags(&mut probed);
+        let tags = initial.iter().map(tag_pair).collect();
+        let comments = initial.iter().filter_map(vorbis_comment).collect();
+        let format = probed.format;
+
+        let track = selector.select(format.tracks())?;
+
+        let dec_opts: DecoderOptions = Default::default();
+        let decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
+        let track_id = track.id;
+        let total_frames = track.codec_params.n_frames;
+
+        Ok(Decoder {
+            format,
+            decoder,
+            selector,
+            track_id,
+            total_frames,
+            seek_target: None,
+            // will later be set in `next` using the spec of each decoded packet
+            sample_rate: 0,
+            channels: 0,
+            offset: 0,
+            warnings: vec![],
+            tags,
+            comments,
+            finished: false,
+        })
+    }
+
+    /// Sample rate of the last block decoded so far, 0 before the first one.
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Channel count of the last block decoded so far, 0 before the first one.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// How many frames (samples per channel) the track is supposed to have, as declared by the
+    /// container, for progress bars, `None` if it doesn't say (raw MP3 and ADTS streams, live
+    /// captures). Like `MediaInfo::duration_secs` this can be wrong for a truncated file.
+    pub fn total_frames(&self) -> Option<u64> {
+        self.total_frames
+    }
+
+    /// Every tag (artist, title and so on) seen so far as (key, value) pairs, keys exactly as the
+    /// file has them. Tags that show up partway through the stream (chained Ogg files, internet
+    /// radio dumps) are appended as they're decoded past, so this is only complete once the
+    /// iterator is done.
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// The tags seen so far (see `tags`) that have a standard meaning, renamed to Vorbis comment
+    /// fields (`ARTIST`, `TITLE`, `ALBUM`, `TRACKNUMBER` and so on), ready to be handed to
+    /// `ComposeBuilder::comments` so a transcode keeps them. Tags without a Vorbis equivalent,
+    /// and ones that stop being true after re-encoding (ReplayGain, the old encoder's name), are
+    /// left out.
+    pub fn vorbis_comments(&self) -> &[(String, String)] {
+        &self.comments
+    }
+
+    /// Jumps to `time` from the start of the track, the next block starts exactly there (the
+    /// container seeks to the packet at or before it and the samples in between are decoded and
+    /// thrown away), and its `offset` counts from the start of the track as if everything before
+    /// had been decoded. Works in either direction and for any container symphonia can seek in,
+    /// formats without a seek index (raw MP3, ADTS) have to be scanned to get there, which is
+    /// slower but still skips the decoding. Fails with `AvasaraError::Decode` if `time` is past
+    /// the end or the source can't seek at all (`ReadOnlySource`s can't).
+    pub fn seek(&mut self, time: Duration) -> Result<(), AvasaraError> {
+        let target = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: time.into(),
+                    track_id: Some(self.track_id),
+                },
+            )?
+            .required_ts;
+
+        // a freshly reset codec needs a packet or few to get going again (Vorbis has nothing to
+        // overlap its first block with, MP3 wants its bit reservoir back) and gives little or no
+        // audio for them, so start 200ms early and let that happen before the target
+        let preroll = self.preroll_rate() / 5;
+        if target > 0 {
+            self.format.seek(
+                SeekMode::Accurate,
+                SeekTo::TimeStamp {
+                    ts: target.saturating_sub(preroll),
+                    track_id: self.track_id,
+                },
+            )?;
+        }
+        // the codec's state belongs to wherever it was before
+        self.decoder.reset();
+        self.seek_target = Some(target);
+        self.finished = false;
+        Ok(())
+    }
+
+    /// Throws the codec away and sets up a new one for the audio track the selector picks out of
+    /// the container's tracks now, after it asked for a reset (`Error::ResetRequired`), the old
+    /// track list (and whatever the codec remembered) no longer applies. Decoding carries on with the same `offset`, a
+    /// change in sample rate or channels shows up as `SpecChanged` once the next packet decodes.
+    fn reset_track(&mut self) -> Result<(), AvasaraError> {
+        let track = self.selector.select(self.format.tracks())?;
+
+        let dec_opts: DecoderOptions = Default::default();
+        self.decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
+        self.track_id = track.id;
+        self.total_frames = track.codec_params.n_frames;
+        self.seek_target = None;
+        self.warnings.push(DecodeWarning::StreamReset {
+            after_samples: self.offset,
+        });
+        Ok(())
+    }
+
+    /// The track's sample rate for working out how long the preroll before a seek target is,
+    /// the one the codec declares if decoding hasn't started yet, 48kHz if it doesn't.
+    fn preroll_rate(&self) -> u64 {
+        if self.sample_rate > 0 {
+            return self.sample_rate as u64;
+        }
+        self.format
+            .tracks()
+            .iter()
+            .find(|t| t.id == self.track_id)
+            .and_then(|t| t.codec_params.sample_rate)
+            .unwrap_or(48000) as u64
+    }
+
+    /// Hands over every `DecodeWarning` collected since the last call, in the order they
+    /// happened.
+    pub fn take_warnings(&mut self) -> Vec<DecodeWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Result<DecodedBlock, AvasaraError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::ResetRequired) => {
+                    // a new logical stream starts here (chained ogg, an icecast dump moving on to
+                    // the next song), its track can have a whole different codec setup
+                    if let Err(err) = self.reset_track() {
+                        self.finished = true;
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+                Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    // this is called whenever audiofile finishes reading
+                    self.finished = true;
+                    return None;
+                }
+                Err(err) => {
+                    // an unrecoverable error occured, halt decoding.
+                    self.finished = true;
+                    return Some(Err(err.into()));
+                }
+            };
+
+            while !self.format.metadata().is_latest() {
+                // drop the old revision, the new one takes its place at the head of the queue
+                self.format.metadata().pop();
+                if let Some(revision) = self.format.metadata().current() {
+                    self.tags.extend(revision.tags().iter().map(tag_pair));
+                    self.comments
+                        .extend(revision.tags().iter().filter_map(vorbis_comment));
+                }
+                self.warnings.push(DecodeWarning::MetadataChanged {
+                    after_samples: self.offset,
+                });
+            }
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let rate = spec.rate as usize;
+                    let channels = spec.channels.count();
+                    if (self.sample_rate != 0)
+                        && ((rate != self.sample_rate) || (channels != self.channels))
+                    {
+                        self.warnings.push(DecodeWarning::SpecChanged {
+                            after_samples: self.offset,
+                            sample_rate: rate,
+                            channels,
+                        });
+                    }
+                    self.sample_rate = rate;
+                    self.channels = channels;
+
+                    let mut sample_buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    sample_buf.copy_interleaved_ref(decoded);
+                    let mut samples = sample_buf.samples();
+
+                    if let Some(target) = self.seek_target {
+                        // still before where the seek asked to be, skip up to it
+                        let frames = (samples.len() / channels.max(1)) as u64;
+                        if packet.ts() + frames <= target {
+                            continue;
+                        }
+                        let skip = target.saturating_sub(packet.ts()) as usize * channels;
+                        samples = &samples[skip..];
+                        self.offset = packet.ts().max(target) as usize * channels;
+                        self.seek_target = None;
+                    }
+
+                    let block = DecodedBlock {
+                        samples: samples.to_vec(),
+                        sample_rate: rate,
+                        channels,
+                        offset: self.offset,
+                    };
+                    self.offset += block.samples.len();
+                    return Some(Ok(block));
+                }
+                Err(Error::IoError(err)) => {
+                    self.warnings.push(DecodeWarning::SkippedPacket {
+                        timestamp: packet.ts(),
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+                Err(Error::DecodeError(err)) => {
+                    self.warnings.push(DecodeWarning::SkippedPacket {
+                        timestamp: packet.ts(),
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+                Err(Error::ResetRequired) => {
+                    if let Err(err) = self.reset_track() {
+                        self.finished = true;
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    // an unrecoverable error occured, halt decoding.
+                    self.finished = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Two seconds of a stereo tone as a 16 bit WAV file, and the samples it decodes to.
+    fn stereo_wav() -> (Vec<u8>, Vec<f32>) {
+        let audio: Vec<f32> = (0..88200)
+            .flat_map(|i| {
+                let s = (std::f32::consts::TAU * 220.0 * i as f32 / 44100.0).sin() * 0.5;
+                [s, -s]
+            })
+            .collect();
+        let wav = crate::encode_wav(&audio, 44100, 2, crate::WavFormat::Pcm16).unwrap();
+        let decoded = crate::decode_audio(Cursor::new(wav.clone())).unwrap();
+        (wav, decoded.samples)
+    }
+
+    #[test]
+    fn blocks_add_up_to_the_whole_decode() {
+        let (wav, whole) = stereo_wav();
+
+        let mut decoder = Decoder::new(Cursor::new(wav)).unwrap();
+        let mut joined = vec![];
+        let mut blocks = 0;
+        for block in &mut decoder {
+            let block = block.unwrap();
+            assert_eq!((block.sample_rate, block.channels), (44100, 2));
+            // every block picks up where the last one stopped
+            assert_eq!(block.offset, joined.len());
+            assert!(block.samples.len() < whole.len());
+            joined.extend(block.samples);
+            blocks += 1;
+        }
+        assert!(blocks > 1, "{}", blocks);
+        assert_eq!(joined, whole);
+        assert!(decoder.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn any_media_source_decodes_the_same() {
+        let (wav, whole) = stereo_wav();
+        let path = std::env::temp_dir().join(format!("avasara-source-{}.wav", std::process::id()));
+        std::fs::write(&path, &wav).unwrap();
+
+        // straight from disk, and from something that can only be read front to back
+        let from_file = crate::decode_audio(File::open(&path).unwrap()).unwrap();
+        let from_reader =
+            crate::decode_audio(symphonia::core::io::ReadOnlySource::new(Cursor::new(wav)))
+                .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(from_file.samples, whole);
+        assert_eq!(from_reader.samples, whole);
+    }
+
+    #[test]
+    fn a_chained_ogg_splits_where_the_stream_changes() {
+        // a second of mono at 44.1kHz, then a second of stereo at 22.05kHz, each its own logical
+        // stream, back to back like an Icecast dump
+        let tone = |sample_rate: usize, channels: usize| -> Vec<f32> {
+            (0..sample_rate)
+                .flat_map(|i| {
+                    let s = (std::f32::consts::TAU * 440.0 * i as f32 / sample_rate as f32).sin();
+                    vec![s * 0.5; channels]
+                })
+                .collect()
+        };
+        let mut chained = crate::ComposeBuilder::new()
+            .stream_serial(1)
+            .run_from_samples(tone(44100, 1), 44100, 1)
+            .unwrap();
+        chained.extend(
+            crate::ComposeBuilder::new()
+                .stream_serial(2)
+                .channels(crate::ChannelPolicy::KeepOriginal)
+                .run_from_samples(tone(22050, 2), 22050, 2)
+                .unwrap(),
+        );
+
+        let segments = crate::decode_segments(Cursor::new(chained)).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].sample_rate, segments[0].channels), (44100, 1));
+        assert_eq!((segments[1].sample_rate, segments[1].channels), (22050, 2));
+        assert_eq!(segments[1].offset, segments[0].samples.len());
+        // a Vorbis stream can hand over up to a block of padding at the end
+        for (segment, frames) in segments.iter().zip([44100, 22050]) {
+            let decoded = segment.samples.len() / segment.channels;
+            assert!(
+                decoded >= frames && decoded < frames + 2048,
+                "{} frames at {}Hz",
+                decoded,
+                segment.sample_rate
+            );
+        }
+    }
+
+    #[test]
+    fn the_selector_picks_by_index_language_and_codec() {
+        use symphonia::core::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_FLAC};
+
+        let track = |id: u32, codec, language: Option<&str>| {
+            let mut track = Track::new(id, CodecParameters::new().for_codec(codec).clone());
+            track.language = language.map(str::to_string);
+            track
+        };
+        // a video track first, which never counts
+        let tracks = [
+            track(1, CODEC_TYPE_NULL, None),
+            track(2, CODEC_TYPE_AAC, Some("eng")),
+            track(3, CODEC_TYPE_AAC, Some("jpn")),
+            track(4, CODEC_TYPE_FLAC, Some("jpn")),
+        ];
+        let pick = |selector: TrackSelector| selector.select(&tracks).map(|t| t.id);
+
+        assert_eq!(pick(TrackSelector::default()).unwrap(), 2);
+        assert_eq!(pick(TrackSelector::Index(2)).unwrap(), 4);
+        assert_eq!(pick(TrackSelector::Language("JPN".into())).unwrap(), 3);
+        let lossless_first = TrackSelector::Codec(vec!["alac".into(), "flac".into(), "aac".into()]);
+        assert_eq!(pick(lossless_first).unwrap(), 4);
+        assert!(matches!(
+            pick(TrackSelector::Index(3)),
+            Err(AvasaraError::TrackNotFound)
+        ));
+        assert!(matches!(
+            pick(TrackSelector::Language("ger".into())),
+            Err(AvasaraError::TrackNotFound)
+        ));
+        assert!(matches!(
+            TrackSelector::default().select(&tracks[..1]),
+            Err(AvasaraError::DecodeFailed)
+        ));
+    }
+
+    #[test]
+    fn hints_help_but_a_wrong_one_does_no_harm() {
+        assert_eq!(
+            ProbeHint::from_path(Path::new("/music/take 3.flac")),
+            ProbeHint::extension("flac")
+        );
+        assert_eq!(
+            ProbeHint::from_path(Path::new("no-extension")),
+            ProbeHint::default()
+        );
+
+        let (wav, samples) = stereo_wav();
+        for hint in [
+            ProbeHint::extension("wav"),
+            ProbeHint::mime_type("audio/wav"),
+            // both wrong, probing falls back to the content
+            ProbeHint {
+                extension: Some("mp3".to_string()),
+                mime_type: Some("audio/aac".to_string()),
+            },
+        ] {
+            let audio = crate::decode_audio_with_hint(Cursor::new(wav.clone()), &hint).unwrap();
+            assert_eq!(audio.samples, samples, "{:?}", hint);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+/// Resamples a `(time_secs, value)` timeline (a pitch contour from `contour_to_cents` or mapped
+/// from `PitchPoint`s, a `crest_timeline`, anything else with timestamps) onto a fixed frame rate,
+/// giving exactly one value per video frame so analysis can be overlaid on or synced with video
+/// (karaoke, subtitles, visualizers) without any further bookkeeping. `duration_secs` is the
+/// length of the clip, so a 10 second clip at 30 fps always produces 300 values, frame `i` is
+/// sampled at `i / fps` seconds.
+///
+/// Values are linearly interpolated between the two surrounding points, a frame gets `None` if it
+/// falls before the first point, after the last one, or inside a gap longer than `max_gap_secs`
+/// (so unvoiced stretches of a pitch contour stay empty instead of being bridged by a straight
+/// line). The timeline is expected to be sorted by time.
+pub fn align_to_frame_rate(
+    timeline: &[(f64, f32)],
+    duration_secs: f64,
+    fps: f64,
+    max_gap_secs: f64,
+) -> Vec<Option<f32>> {
+    let frames = (duration_secs * fps).round().max(0.0) as usize;
+    let mut next = 0;
+
+    (0..frames)
+        .map(|frame| {
+            let time = frame as f64 / fps;
+            // the first point at or after this frame, frames only move forward so neither does
+            // this
+            while next < timeline.len() && timeline[next].0 < time {
+                next += 1;
+            }
+
+            let after = timeline.get(next)?;
+            if after.0 == time {
+                return Some(after.1);
+            }
+            let before = timeline.get(next.checked_sub(1)?)?;
+            if after.0 - before.0 > max_gap_secs {
+                return None;
+            }
+
+            let t = ((time - before.0) / (after.0 - before.0)) as f32;
+            Some(before.1 + (after.1 - before.1) * t)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_pitch_contour, AnalyzeOptions};
+
+    #[test]
+    fn ten_seconds_at_30_fps_is_300_frames() {
+        let sample_rate = 44100;
+        let clip: Vec<f32> = (0..sample_rate * 10)
+            .map(|i| (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+        let timeline: Vec<(f64, f32)> =
+            analyze_pitch_contour(&clip, sample_rate, 50.0, 600.0, &AnalyzeOptions::default())
+                .iter()
+                .map(|point| (point.time_secs, point.frequency))
+                .collect();
+
+        let frames = align_to_frame_rate(&timeline, 10.0, 30.0, 0.1);
+        assert_eq!(frames.len(), 300);
+        // the last window starts a little before the end, so the very last frames have nothing
+        // after them to interpolate towards
+        for frame in &frames[..295] {
+            assert!((frame.unwrap() - 220.0).abs() < 2.0, "{:?}", frame);
+        }
+    }
+
+    #[test]
+    fn long_gaps_stay_empty() {
+        let timeline = [(0.0, 1.0), (0.5, 2.0), (2.0, 3.0)];
+        let frames = align_to_frame_rate(&timeline, 2.0, 4.0, 1.0);
+        assert_eq!(
+            frames,
+            [
+                Some(1.0),
+                Some(1.5),
+                Some(2.0),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+}
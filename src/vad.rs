@@ -0,0 +1,254 @@
+use pitch_detection::detector::yin::YINDetector;
+
+use crate::yin_pitch;
+
+/// A stretch of audio, in seconds from the start, as found by `detect_voice_activity`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Segment {
+    /// How long the segment is, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// The thresholds `detect_voice_activity` judges frames and ranges by, the defaults are what
+/// `voice_activity` uses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadOptions {
+    /// Frames with an RMS level below this (in dBFS) are never speech.
+    pub energy_floor_dbfs: f32,
+    /// Minimum YIN clarity for a frame to count as voiced.
+    pub min_clarity: f32,
+    /// Above this many zero crossings per sample a frame is hiss rather than voice.
+    pub max_zero_crossing_rate: f32,
+    /// The pitch range (in Hz) of the voices to look for.
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    /// Gaps between voiced ranges shorter than this (in seconds) are bridged, so unvoiced
+    /// consonants and short pauses don't split a sentence up.
+    pub max_gap_secs: f64,
+    /// Added to both ends of every range (in seconds), for the consonants at the edges of words.
+    pub padding_secs: f64,
+    /// Ranges shorter than this (in seconds, before padding) are dropped as blips.
+    pub min_duration_secs: f64,
+}
+
+impl Default for VadOptions {
+    fn default() -> Self {
+        VadOptions {
+            energy_floor_dbfs: -50.0,
+            min_clarity: 0.6,
+            max_zero_crossing_rate: 0.35,
+            min_frequency: 60.0,
+            max_frequency: 500.0,
+            max_gap_secs: 0.2,
+            padding_secs: 0.05,
+            min_duration_secs: 0.1,
+        }
+    }
+}
+
+fn zero_crossing_rate(frame: &[f32]) -> f32 {
+    let crossings = frame
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / frame.len().max(1) as f32
+}
+
+/// Finds the parts of some mono audio data where someone is talking, returned as `(start, end)`
+/// time ranges in seconds. Unlike plain silence detection this ignores loud non-speech too, a
+/// 40ms frame (every 10ms) only counts as speech if it is loud enough (above -50dBFS), doesn't
+/// hiss like noise (low zero-crossing rate) and has a clear pitch in the range of the human voice
+/// (60-500Hz with a YIN clarity of at least 0.6). Unvoiced consonants at the edges of words are
+/// picked up by bridging gaps shorter than 200ms and padding every range by 50ms on both ends,
+/// ranges shorter than 100ms are dropped as blips.
+///
+/// This is a simple heuristic VAD, good enough to cut the dead air out of interviews before
+/// transcription or pitch analysis, not a replacement for a trained model on noisy material. See
+/// `detect_voice_activity` to tune the thresholds.
+pub fn voice_activity(audio: &[f32], sample_rate: usize) -> Vec<(f64, f64)> {
+    detect_voice_activity(audio, sample_rate, &VadOptions::default())
+        .into_iter()
+        .map(|segment| (segment.start, segment.end))
+        .collect()
+}
+
+/// Same as `voice_activity`, but with every threshold configurable through `VadOptions` (a
+/// lower energy floor for a quiet phone recording, a higher pitch range for children's voices,
+/// longer gaps for slow speakers) and the ranges returned as `Segment`s, ready to be handed to
+/// `analyze_pitch_in_segments` so pitch analysis skips the silence and noise in between.
+pub fn detect_voice_activity(
+    audio: &[f32],
+    sample_rate: usize,
+    options: &VadOptions,
+) -> Vec<Segment> {
+    let frame_len = sample_rate * 40 / 1000;
+    let hop = (sample_rate / 100).max(1);
+    if frame_len < 2 || audio.len() < frame_len {
+        return vec![];
+    }
+
+    let mut detector = YINDetector::new(frame_len, frame_len / 2);
+    let voiced: Vec<bool> = (0..=(audio.len() - frame_len) / hop)
+        .map(|i| {
+            let frame = &audio[i * hop..i * hop + frame_len];
+
+            let mean_square = frame.iter().map(|s| s * s).sum::<f32>() / frame_len as f32;
+            if 10.0 * mean_square.max(1e-12).log10() < options.energy_floor_dbfs {
+                return false;
+            }
+            if zero_crossing_rate(frame) > options.max_zero_crossing_rate {
+                return false;
+            }
+
+            match yin_pitch(&mut detector, frame, sample_rate) {
+                Some((frequency, clarity)) => {
+                    (options.min_frequency..=options.max_frequency).contains(&frequency)
+                        && clarity >= options.min_clarity
+                }
+                None => false,
+            }
+        })
+        .collect();
+
+    let frame_secs = |i: usize| (i * hop) as f64 / sample_rate as f64;
+    let frame_duration = frame_len as f64 / sample_rate as f64;
+
+    // runs of voiced frames, as time ranges
+    let mut ranges: Vec<(f64, f64)> = vec![];
+    let mut start = None;
+    for (i, is_voiced) in voiced.iter().chain(std::iter::once(&false)).enumerate() {
+        match (is_voiced, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push((frame_secs(s), frame_secs(i - 1) + frame_duration));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    // bridge short gaps, then pad and drop blips
+    let mut merged: Vec<(f64, f64)> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.0 - last.1 < options.max_gap_secs => last.1 = range.1,
+            _ => merged.push(range),
+        }
+    }
+
+    let total = audio.len() as f64 / sample_rate as f64;
+    merged
+        .into_iter()
+        .filter(|(start, end)| end - start >= options.min_duration_secs)
+        .map(|(start, end)| Segment {
+            start: (start - options.padding_secs).max(0.0),
+            end: (end + options.padding_secs).min(total),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speech_is_found_between_stretches_of_noise() {
+        // noise, a "sentence" (a gliding voice-like harmonic tone), noise, another one, noise
+        let sample_rate = 16000;
+        let mut state = 1u32;
+        let audio: Vec<f32> = (0..sample_rate * 5)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let speaking = (1.0..2.0).contains(&t) || (3.0..4.0).contains(&t);
+                if speaking {
+                    let f0 = 140.0 + 20.0 * t.fract();
+                    (1..=5)
+                        .map(|k| (2.0 * std::f32::consts::PI * k as f32 * f0 * t).sin() / k as f32)
+                        .sum::<f32>()
+                        * 0.2
+                } else {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    ((state >> 8) as f32 / (1 << 23) as f32 - 1.0) * 0.3
+                }
+            })
+            .collect();
+
+        let ranges = voice_activity(&audio, sample_rate);
+        assert_eq!(ranges.len(), 2, "{:?}", ranges);
+        for ((start, end), expected) in ranges.iter().zip([1.0, 3.0]) {
+            assert!((start - expected).abs() < 0.1, "{:?}", ranges);
+            assert!((end - (expected + 1.0)).abs() < 0.1, "{:?}", ranges);
+        }
+    }
+
+    /// A voice-like harmonic tone at `f0` during every one of `spans` (in seconds) and silence
+    /// everywhere else.
+    fn voice(spans: &[(f32, f32)], f0: f32, sample_rate: usize, secs: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * secs) as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                if spans.iter().any(|(start, end)| (*start..*end).contains(&t)) {
+                    (1..=5)
+                        .map(|k| (2.0 * std::f32::consts::PI * k as f32 * f0 * t).sin() / k as f32)
+                        .sum::<f32>()
+                        * 0.2
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_options_decide_what_gets_bridged_and_dropped() {
+        // two words 150ms apart, then a 60ms blip
+        let audio = voice(&[(0.5, 1.0), (1.15, 1.6), (2.5, 2.56)], 150.0, 16000, 3.0);
+
+        let default = detect_voice_activity(&audio, 16000, &VadOptions::default());
+        assert_eq!(default.len(), 1, "{:?}", default);
+        assert!((default[0].start - 0.45).abs() < 0.05, "{:?}", default);
+        assert!((default[0].end - 1.65).abs() < 0.05, "{:?}", default);
+
+        let strict = VadOptions {
+            max_gap_secs: 0.05,
+            padding_secs: 0.0,
+            min_duration_secs: 0.02,
+            ..VadOptions::default()
+        };
+        let split = detect_voice_activity(&audio, 16000, &strict);
+        assert_eq!(split.len(), 3, "{:?}", split);
+        assert!(split[2].duration() < 0.15, "{:?}", split);
+
+        // a voice above the pitch range isn't a voice at all
+        let high = VadOptions {
+            max_frequency: 120.0,
+            ..VadOptions::default()
+        };
+        assert!(detect_voice_activity(&audio, 16000, &high).is_empty());
+    }
+
+    #[test]
+    fn pitch_analysis_stays_inside_the_segments() {
+        // a 150Hz voice, with a louder 400Hz whistle before it
+        let mut audio = voice(&[(0.0, 1.0)], 400.0, 16000, 1.0);
+        audio.extend(voice(&[(0.0, 1.0)], 150.0, 16000, 1.0));
+        let segments = [Segment {
+            start: 1.0,
+            end: 2.0,
+        }];
+
+        let (report, _) =
+            crate::analyze_pitch_in_segments(&audio, 16000, 60.0, 500.0, &segments).unwrap();
+        assert!((report.mean - 150.0).abs() < 2.0, "{}", report.mean);
+        let (everything, _) = crate::analyze_pitch(&audio, 16000, 60.0, 500.0).unwrap();
+        assert!(everything.mean > 200.0, "{}", everything.mean);
+    }
+}
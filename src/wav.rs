@@ -0,0 +1,436 @@
+use crate::pcm::{to_raw_bytes, PcmFormat};
+use crate::{
+    decode_audio, downmix_to_mono, AvasaraError, ComposeStage, DownmixStrategy, MediaSource,
+    Progress,
+};
+
+/// Sample format of the WAV files `encode_wav` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    /// 16 bit integer PCM, what every player on earth reads, samples are rounded to the nearest
+    /// step and clipped to -1.0..=1.0.
+    Pcm16,
+    /// 16 bit integer PCM with TPDF dither (±1 step of triangular noise added before rounding),
+    /// which trades the distortion rounding leaves on quiet passages and fades for a constant,
+    /// inaudible noise floor around -96dBFS. The noise comes from a fixed seed, so the same audio
+    /// always gives the same file.
+    Pcm16Dithered,
+    /// 24 bit integer PCM, rounded and clipped like `Pcm16` but with 256 times finer steps, for
+    /// masters and anything else headed for more processing.
+    Pcm24,
+    /// 32 bit IEEE float, lossless for anything Avasara decodes or computes and keeps samples
+    /// past full scale as they are, the one for intermediate files.
+    Float32,
+}
+
+/// How `encode_wav_with` writes a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavOptions {
+    pub format: WavFormat,
+    /// Whether to write a `WAVE_FORMAT_EXTENSIBLE` header instead of the plain PCM (or IEEE
+    /// float) one. Some tools insist on it for more than two channels or more than 16 bits, since
+    /// it's the only header that says which speaker every channel belongs to, while a few old
+    /// ones can't read it at all.
+    pub extensible: bool,
+    /// Which speakers the channels go to in an extensible header, the usual `SPEAKER_*` bits
+    /// (`0x1` front left, `0x2` front right, `0x4` front center, `0x8` LFE and so on), one per
+    /// channel in order. `None` picks the standard layout for the channel count, the same order
+    /// the rest of Avasara assumes (FL, FR, FC, LFE, then the surrounds, see
+    /// `downmix_to_stereo`), and no speakers at all past 8 channels. Ignored without
+    /// `extensible`.
+    pub channel_mask: Option<u32>,
+}
+
+impl Default for WavOptions {
+    /// 16 bit PCM with the plain header, the simplest WAV file there is.
+    fn default() -> Self {
+        WavOptions {
+            format: WavFormat::Pcm16,
+            extensible: false,
+            channel_mask: None,
+        }
+    }
+}
+
+/// The speakers the channels of a file with this many channels usually go to, in
+/// `WAVE_FORMAT_EXTENSIBLE` channel mask bits.
+fn standard_channel_mask(channels: usize) -> u32 {
+    match channels {
+        // FC
+        1 => 0x4,
+        // FL FR
+        2 => 0x3,
+        // FL FR FC
+        3 => 0x7,
+        // FL FR BL BR
+        4 => 0x33,
+        // FL FR FC BL BR
+        5 => 0x37,
+        // FL FR FC LFE BL BR
+        6 => 0x3f,
+        // FL FR FC LFE BC SL SR
+        7 => 0x70f,
+        // FL FR FC LFE BL BR SL SR
+        8 => 0x63f,
+        _ => 0,
+    }
+}
+
+/// A tiny xorshift generator for the dither noise, seeded the same every time so dithered output
+/// stays reproducible.
+struct DitherNoise(u32);
+
+impl DitherNoise {
+    /// Uniform noise in -0.5..0.5.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 - 0.5
+    }
+}
+
+/// Writes some audio data (interleaved already if it has more than one channel) into a complete
+/// WAV file in memory, header and all, ready to be written to disk. Integer files use a plain PCM
+/// header and float files an IEEE float one (with the `fact` chunk the spec asks for), neither
+/// uses the extensible header, so files with more than two channels carry no speaker layout and
+/// players assume the usual order (FL, FR, FC, LFE, ...), see `encode_wav_with` for that.
+/// Trailing samples that don't make up a whole frame are dropped.
+///
+/// # Errors
+/// `AvasaraError::UnsupportedChannels` if `channels` is zero or above 65535,
+/// `AvasaraError::UnsupportedSampleRate` if `sample_rate` is zero or doesn't fit in 32 bits, and
+/// `AvasaraError::TooLong` if the audio is too long for a WAV file (4GiB of data).
+pub fn encode_wav(
+    audio: &[f32],
+    sample_rate: usize,
+    channels: usize,
+    format: WavFormat,
+) -> Result<Vec<u8>, AvasaraError> {
+    encode_wav_with(
+        audio,
+        sample_rate,
+        channels,
+        &WavOptions {
+            format,
+            ..Default::default()
+        },
+    )
+}
+
+/// Same as `encode_wav`, but with a choice of header, see `WavOptions`. An extensible header
+/// replaces the plain `fmt ` chunk with the 40 byte `WAVE_FORMAT_EXTENSIBLE` one, carrying the
+/// channel mask and the PCM (or IEEE float) subformat GUID, float files still get their `fact`
+/// chunk.
+///
+/// # Errors
+/// Same as `encode_wav`.
+pub fn encode_wav_with(
+    audio: &[f32],
+    sample_rate: usize,
+    channels: usize,
+    options: &WavOptions,
+) -> Result<Vec<u8>, AvasaraError> {
+    let format = options.format;
+    if channels == 0 || channels > u16::MAX as usize {
+        return Err(AvasaraError::UnsupportedChannels(channels));
+    }
+    let sample_rate = u32::try_from(sample_rate)
+        .ok()
+        .filter(|rate| *rate > 0)
+        .ok_or(AvasaraError::UnsupportedSampleRate(sample_rate))?;
+    let audio = &audio[..audio.len() / channels * channels];
+
+    let data = match format {
+        WavFormat::Pcm16 => to_raw_bytes(audio, PcmFormat::S16Le),
+        WavFormat::Pcm16Dithered => {
+            let step = 1.0 / i16::MAX as f32;
+            let mut noise = DitherNoise(0x2545_f491);
+            let dithered: Vec<f32> = audio
+                .iter()
+                .map(|sample| sample + (noise.next() + noise.next()) * step)
+                .collect();
+            to_raw_bytes(&dithered, PcmFormat::S16Le)
+        }
+        WavFormat::Pcm24 => to_raw_bytes(audio, PcmFormat::S24Le),
+        WavFormat::Float32 => to_raw_bytes(audio, PcmFormat::F32Le),
+    };
+    // the RIFF size counts the header too, so that has to fit as well
+    let data_len = u32::try_from(data.len())
+        .ok()
+        .filter(|len| len.checked_add(60).is_some())
+        .ok_or(AvasaraError::TooLong)?;
+
+    let (format_tag, bits): (u16, u16) = match format {
+        WavFormat::Pcm16 | WavFormat::Pcm16Dithered => (1, 16),
+        WavFormat::Pcm24 => (1, 24),
+        WavFormat::Float32 => (3, 32),
+    };
+    let is_float = format_tag == 3;
+    let block_align = channels as u16 * bits / 8;
+
+    // plain PCM has no cbSize, float has an empty one and extensible 22 bytes of extra fields
+    let fmt_len: u32 = match (options.extensible, is_float) {
+        (true, _) => 40,
+        (false, true) => 18,
+        (false, false) => 16,
+    };
+    // fmt, a fact chunk for float, then the data chunk
+    let header_len: u32 = 4 + 8 + fmt_len + if is_float { 12 } else { 0 } + 8;
+    let mut wav = Vec::with_capacity(header_len as usize + 8 + data.len());
+    wav.extend(b"RIFF");
+    wav.extend((header_len + data_len).to_le_bytes());
+    wav.extend(b"WAVE");
+
+    wav.extend(b"fmt ");
+    wav.extend(fmt_len.to_le_bytes());
+    let tag = if options.extensible {
+        0xfffe
+    } else {
+        format_tag
+    };
+    wav.extend(tag.to_le_bytes());
+    wav.extend((channels as u16).to_le_bytes());
+    wav.extend(sample_rate.to_le_bytes());
+    wav.extend((sample_rate * block_align as u32).to_le_bytes());
+    wav.extend(block_align.to_le_bytes());
+    wav.extend(bits.to_le_bytes());
+    if options.extensible {
+        wav.extend(22u16.to_le_bytes());
+        // every bit of the container is used
+        wav.extend(bits.to_le_bytes());
+        let mask = options
+            .channel_mask
+            .unwrap_or_else(|| standard_channel_mask(channels));
+        wav.extend(mask.to_le_bytes());
+        // KSDATAFORMAT_SUBTYPE_PCM or _IEEE_FLOAT, the old format tag followed by the same 12
+        // bytes every one of these GUIDs ends in
+        wav.extend((format_tag as u32).to_le_bytes());
+        wav.extend([
+            0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+        ]);
+    } else if is_float {
+        wav.extend(0u16.to_le_bytes());
+    }
+    if is_float {
+        wav.extend(b"fact");
+        wav.extend(4u32.to_le_bytes());
+        wav.extend(((audio.len() / channels) as u32).to_le_bytes());
+    }
+
+    wav.extend(b"data");
+    wav.extend(data_len.to_le_bytes());
+    wav.extend(data);
+
+    Ok(wav)
+}
+
+/// The WAV counterpart to `compose_to_ogg`, for when you need a lossless intermediate file
+/// instead of a small one, decodes `src`, folds it down to mono (the plain average of every
+/// channel, so unlike `compose_to_ogg` any channel count works) and writes it with `encode_wav`
+/// at the source's sample rate. `on_progress` gets every stage as it starts, the same way
+/// `ComposeBuilder::on_progress` does (without percentages, and `Interleaving` only for sources
+/// with more than one channel), pass `|_| {}` to ignore them. Fails if decoding or encoding does.
+pub fn compose_to_wav<S: MediaSource + 'static>(
+    src: S,
+    format: WavFormat,
+    on_progress: impl Fn(Progress),
+) -> Result<Vec<u8>, AvasaraError> {
+    let report = |stage| {
+        on_progress(Progress {
+            stage,
+            percent: None,
+        })
+    };
+
+    report(ComposeStage::Decoding);
+    let (audio, sample_rate, channels) = decode_audio(src)?.into_parts();
+    if (sample_rate == 0) || (channels == 0) {
+        return Err(AvasaraError::DecodeFailed);
+    }
+
+    if channels > 1 {
+        report(ComposeStage::Interleaving);
+    }
+    let audio = downmix_to_mono(&audio, channels, DownmixStrategy::Average);
+
+    report(ComposeStage::Encoding);
+    let out = encode_wav(&audio, sample_rate, 1, format)?;
+    report(ComposeStage::Done);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(wav: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(wav[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(wav: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(wav[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn six_channel_24_bit_extensible_header() {
+        let audio = vec![0.25; 6 * 100];
+        let options = WavOptions {
+            format: WavFormat::Pcm24,
+            extensible: true,
+            channel_mask: None,
+        };
+        let wav = encode_wav_with(&audio, 48000, 6, &options).unwrap();
+
+        assert_eq!(&wav[12..16], b"fmt ");
+        assert_eq!(u32_at(&wav, 16), 40);
+        assert_eq!(u16_at(&wav, 20), 0xfffe);
+        assert_eq!(u16_at(&wav, 22), 6);
+        assert_eq!(u32_at(&wav, 24), 48000);
+        assert_eq!(u32_at(&wav, 28), 48000 * 18);
+        assert_eq!(u16_at(&wav, 32), 18);
+        assert_eq!(u16_at(&wav, 34), 24);
+        assert_eq!(u16_at(&wav, 36), 22);
+        assert_eq!(u16_at(&wav, 38), 24);
+        // 5.1: FL FR FC LFE BL BR
+        assert_eq!(u32_at(&wav, 40), 0x3f);
+        assert_eq!(
+            wav[44..60],
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38,
+                0x9b, 0x71
+            ]
+        );
+
+        assert_eq!(&wav[60..64], b"data");
+        assert_eq!(u32_at(&wav, 64), 6 * 100 * 3);
+        assert_eq!(wav.len(), 68 + 6 * 100 * 3);
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        // 0.25 of full scale, little endian
+        assert_eq!(wav[68..71], [0x00, 0x00, 0x20]);
+    }
+
+    #[test]
+    fn the_default_header_stays_plain() {
+        let wav = encode_wav_with(&[0.0; 6 * 10], 48000, 6, &WavOptions::default()).unwrap();
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), 1);
+        assert_eq!(&wav[36..40], b"data");
+    }
+
+    #[test]
+    fn plain_16_bit_header() {
+        let wav = encode_wav(&[0.5, -0.5, 1.5, 0.0, 0.25], 44100, 2, WavFormat::Pcm16).unwrap();
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), 1);
+        assert_eq!(u16_at(&wav, 22), 2);
+        assert_eq!(u32_at(&wav, 24), 44100);
+        assert_eq!(u32_at(&wav, 28), 44100 * 4);
+        assert_eq!(u16_at(&wav, 32), 4);
+        assert_eq!(u16_at(&wav, 34), 16);
+        // the half frame at the end is dropped
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), 8);
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        // rounded to the nearest step, and clipped
+        let samples: Vec<i16> = wav[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, [16384, -16384, i16::MAX, 0]);
+    }
+
+    #[test]
+    fn dither_stays_within_a_step_and_is_reproducible() {
+        let ramp: Vec<f32> = (0..4000).map(|i| i as f32 / 4000.0 - 0.5).collect();
+        let plain = encode_wav(&ramp, 48000, 1, WavFormat::Pcm16).unwrap();
+        let dithered = encode_wav(&ramp, 48000, 1, WavFormat::Pcm16Dithered).unwrap();
+
+        // same header, it's still plain 16 bit PCM
+        assert_eq!(plain[..44], dithered[..44]);
+        assert_ne!(plain, dithered);
+        let samples = |wav: &[u8]| -> Vec<i32> {
+            wav[44..]
+                .chunks(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as i32)
+                .collect()
+        };
+        for (plain, dithered) in samples(&plain).iter().zip(samples(&dithered)) {
+            assert!((plain - dithered).abs() <= 2, "{} {}", plain, dithered);
+        }
+        assert_eq!(
+            dithered,
+            encode_wav(&ramp, 48000, 1, WavFormat::Pcm16Dithered).unwrap()
+        );
+    }
+
+    #[test]
+    fn float_header_has_a_fact_chunk() {
+        let audio = [0.5, -2.0, 0.125, 1.0, 0.0, 0.75];
+        let wav = encode_wav(&audio, 96000, 3, WavFormat::Float32).unwrap();
+
+        assert_eq!(u32_at(&wav, 16), 18);
+        assert_eq!(u16_at(&wav, 20), 3);
+        assert_eq!(u16_at(&wav, 22), 3);
+        assert_eq!(u32_at(&wav, 24), 96000);
+        assert_eq!(u32_at(&wav, 28), 96000 * 12);
+        assert_eq!(u16_at(&wav, 32), 12);
+        assert_eq!(u16_at(&wav, 34), 32);
+        assert_eq!(u16_at(&wav, 36), 0);
+        assert_eq!(&wav[38..42], b"fact");
+        assert_eq!(u32_at(&wav, 42), 4);
+        assert_eq!(u32_at(&wav, 46), 2);
+        assert_eq!(&wav[50..54], b"data");
+        assert_eq!(u32_at(&wav, 54), 6 * 4);
+        assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+        // samples past full scale survive as they are
+        let samples: Vec<f32> = wav[58..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, audio);
+    }
+
+    #[test]
+    fn bad_parameters_are_errors() {
+        assert!(matches!(
+            encode_wav(&[0.0; 4], 44100, 0, WavFormat::Pcm16),
+            Err(AvasaraError::UnsupportedChannels(0))
+        ));
+        assert!(matches!(
+            encode_wav(&[0.0; 4], 0, 1, WavFormat::Pcm16),
+            Err(AvasaraError::UnsupportedSampleRate(0))
+        ));
+    }
+
+    #[test]
+    fn composing_folds_to_mono_and_reports_through_the_callback() {
+        let stereo: Vec<f32> = (0..4410).flat_map(|_| [0.5, 0.0]).collect();
+        let source = encode_wav(&stereo, 44100, 2, WavFormat::Float32).unwrap();
+
+        let stages = std::cell::RefCell::new(vec![]);
+        let wav = compose_to_wav(
+            std::io::Cursor::new(source),
+            WavFormat::Float32,
+            |progress| stages.borrow_mut().push(progress.stage),
+        )
+        .unwrap();
+        assert_eq!(
+            stages.into_inner(),
+            [
+                ComposeStage::Decoding,
+                ComposeStage::Interleaving,
+                ComposeStage::Encoding,
+                ComposeStage::Done
+            ]
+        );
+        assert_eq!(u16_at(&wav, 22), 1);
+        assert_eq!(u32_at(&wav, 54), 4410 * 4);
+        assert_eq!(f32::from_le_bytes(wav[58..62].try_into().unwrap()), 0.25);
+    }
+}
@@ -0,0 +1,194 @@
+/// The lowest and highest sample in one pixel column of a waveform, what `compute_peaks` gives,
+/// drawn as a vertical line from `min` to `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeakPair {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl PeakPair {
+    /// Both values scaled to 8 bits (-128 to 127), a quarter of the size for sending to a
+    /// browser and still more resolution than a waveform a few hundred pixels tall can show.
+    pub fn to_8bit(&self) -> (i8, i8) {
+        (quantize(self.min, 8) as i8, quantize(self.max, 8) as i8)
+    }
+
+    /// Both values scaled to 16 bits (-32768 to 32767).
+    pub fn to_16bit(&self) -> (i16, i16) {
+        (quantize(self.min, 16) as i16, quantize(self.max, 16) as i16)
+    }
+}
+
+/// Scales a sample to a signed integer of `bits` bits, clipped to full scale.
+fn quantize(sample: f32, bits: u32) -> i32 {
+    let scale = (1i32 << (bits - 1)) as f32;
+    ((sample * scale).round() as i32).clamp(-(scale as i32), scale as i32 - 1)
+}
+
+/// How many bits every value of a `.dat` file written by `encode_waveform_dat` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeakBits {
+    /// Half the size, plenty for drawing, what audiowaveform's `-b 8` writes.
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+/// Boils some audio data (interleaved if it has more than one channel) down to the min/max pairs
+/// a waveform view draws, one per `samples_per_pixel` frames per channel, so a zoom level is just
+/// a choice of `samples_per_pixel` (the audio's length in frames divided by the view's width in
+/// pixels to fit it all in). The pairs come interleaved the same way the audio is, `channels` of
+/// them per column in channel order, and the last column covers whatever frames are left over.
+///
+/// # Panics
+/// Panics if `channels` or `samples_per_pixel` is zero.
+pub fn compute_peaks(audio: &[f32], channels: usize, samples_per_pixel: usize) -> Vec<PeakPair> {
+    assert!(
+        channels > 0 && samples_per_pixel > 0,
+        "channels and samples per pixel must be non-zero"
+    );
+
+    let frames = audio.len() / channels;
+    audio[..frames * channels]
+        .chunks(samples_per_pixel * channels)
+        .flat_map(|column| {
+            (0..channels).map(move |channel| {
+                column.iter().skip(channel).step_by(channels).fold(
+                    PeakPair {
+                        min: f32::INFINITY,
+                        max: f32::NEG_INFINITY,
+                    },
+                    |peak, sample| PeakPair {
+                        min: peak.min.min(*sample),
+                        max: peak.max.max(*sample),
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+/// Writes peaks from `compute_peaks` as an audiowaveform binary `.dat` file, the format BBC's
+/// audiowaveform tool writes and peaks.js and waveform-data.js read, so a web player can load
+/// precomputed waveforms instead of decoding the audio in the browser. `sample_rate` and
+/// `samples_per_pixel` go in the header (players use them to map pixels back to time) and have to
+/// be the ones the peaks were computed with. Mono files are written as version 1 (which every
+/// reader takes), anything with more channels as version 2, which adds the channel count.
+///
+/// # Panics
+/// Panics if `channels` is zero or `peaks` doesn't hold a whole number of columns.
+pub fn encode_waveform_dat(
+    peaks: &[PeakPair],
+    channels: usize,
+    sample_rate: u32,
+    samples_per_pixel: u32,
+    bits: PeakBits,
+) -> Vec<u8> {
+    assert!(
+        channels > 0 && peaks.len().is_multiple_of(channels),
+        "peaks must hold a pair per channel for every column"
+    );
+
+    let version: i32 = if channels == 1 { 1 } else { 2 };
+    let flags: u32 = match bits {
+        PeakBits::Eight => 1,
+        PeakBits::Sixteen => 0,
+    };
+
+    let mut dat = vec![];
+    dat.extend(version.to_le_bytes());
+    dat.extend(flags.to_le_bytes());
+    dat.extend((sample_rate as i32).to_le_bytes());
+    dat.extend((samples_per_pixel as i32).to_le_bytes());
+    dat.extend(((peaks.len() / channels) as u32).to_le_bytes());
+    if version == 2 {
+        dat.extend((channels as i32).to_le_bytes());
+    }
+
+    for peak in peaks {
+        match bits {
+            PeakBits::Eight => {
+                let (min, max) = peak.to_8bit();
+                dat.extend([min as u8, max as u8]);
+            }
+            PeakBits::Sixteen => {
+                let (min, max) = peak.to_16bit();
+                dat.extend(min.to_le_bytes());
+                dat.extend(max.to_le_bytes());
+            }
+        }
+    }
+    dat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_per_column_and_channel() {
+        // stereo, the left a ramp up and the right its negative, 10 frames
+        let audio: Vec<f32> = (0..10)
+            .flat_map(|i| [i as f32 / 10.0, -(i as f32) / 10.0])
+            .collect();
+
+        let peaks = compute_peaks(&audio, 2, 4);
+        let pairs: Vec<(f32, f32)> = peaks.iter().map(|p| (p.min, p.max)).collect();
+        assert_eq!(
+            pairs,
+            [
+                (0.0, 0.3),
+                (-0.3, 0.0),
+                (0.4, 0.7),
+                (-0.7, -0.4),
+                // the leftover two frames
+                (0.8, 0.9),
+                (-0.9, -0.8)
+            ]
+        );
+
+        // full scale clips to the largest positive value instead of wrapping
+        let loud = PeakPair {
+            min: -1.0,
+            max: 1.0,
+        };
+        assert_eq!(loud.to_8bit(), (-128, 127));
+        assert_eq!(loud.to_16bit(), (-32768, 32767));
+    }
+
+    fn i32_at(dat: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(dat[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn dat_headers_follow_audiowaveform() {
+        let peaks = [
+            PeakPair {
+                min: -0.5,
+                max: 0.25,
+            },
+            PeakPair { min: 0.0, max: 1.0 },
+        ];
+
+        // mono, 8 bit, version 1
+        let dat = encode_waveform_dat(&peaks, 1, 44100, 256, PeakBits::Eight);
+        assert_eq!(
+            [0, 4, 8, 12, 16].map(|at| i32_at(&dat, at)),
+            [1, 1, 44100, 256, 2]
+        );
+        assert_eq!(dat[20..], [(-64i8) as u8, 32, 0, 127]);
+
+        // stereo, 16 bit, version 2 with the channel count
+        let dat = encode_waveform_dat(&peaks, 2, 48000, 512, PeakBits::Sixteen);
+        assert_eq!(
+            [0, 4, 8, 12, 16, 20].map(|at| i32_at(&dat, at)),
+            [2, 0, 48000, 512, 1, 2]
+        );
+        let values: Vec<i16> = dat[24..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(values, [-16384, 8192, 0, 32767]);
+    }
+}